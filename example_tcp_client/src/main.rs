@@ -41,7 +41,18 @@ fn main() {
     )
     .expect("connect to kanata");
     log::info!("successfully connected");
-    let writer_stream = kanata_conn.try_clone().expect("clone writer");
+    let mut writer_stream = kanata_conn.try_clone().expect("clone writer");
+    let hello = serde_json::to_string(&ClientRequest {
+        request_id: Some(0),
+        msg: ClientMessage::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: vec![],
+        },
+    })
+    .expect("deserializable");
+    writer_stream
+        .write_all(hello.as_bytes())
+        .expect("stream writable");
     let reader_stream = kanata_conn;
     std::thread::spawn(move || write_to_kanata(writer_stream));
     read_from_kanata(reader_stream);
@@ -127,7 +138,7 @@ fn read_from_kanata(s: TcpStream) {
     loop {
         msg.clear();
         reader.read_line(&mut msg).expect("stream readable");
-        let parsed_msg: ServerMessage = match serde_json::from_str(&msg) {
+        let parsed_msg: ServerResponse = match serde_json::from_str(&msg) {
             Ok(msg) => msg,
             Err(e) => {
                 log::warn!("could not parse server message {msg}: {e:?}");
@@ -135,10 +146,19 @@ fn read_from_kanata(s: TcpStream) {
             }
         };
         match parsed_msg {
-            ServerMessage::LayerChange { new } => {
+            ServerResponse {
+                msg: ServerMessage::LayerChange { new },
+                ..
+            } => {
                 log::info!("reader: kanata changed layers to \"{new}\"");
             }
-            msg => {
+            ServerResponse {
+                request_id: Some(id),
+                msg,
+            } => {
+                log::info!("got response to request {id}: {msg:?}");
+            }
+            ServerResponse { msg, .. } => {
                 log::info!("got msg: {msg:?}");
             }
         }
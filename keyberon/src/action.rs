@@ -1,7 +1,7 @@
 //! The different actions that can be executed via any given key.
 
 use crate::key_code::KeyCode;
-use crate::layout::{QueuedIter, WaitingAction};
+use crate::layout::{KCoord, QueuedIter, WaitingAction};
 use core::fmt::Debug;
 
 pub mod switch;
@@ -69,6 +69,14 @@ pub enum HoldTapConfig<'a> {
     /// events than on timing. Be aware that doing the good succession
     /// of key might require some training.
     PermissiveHold,
+    /// Like PermissiveHold, but only keys typed with the opposite hand can activate the hold
+    /// action. If the next key pressed is typed with the same hand, the tap action is activated
+    /// right away.
+    ///
+    /// This behavior is meant for home row modifiers: a modifier is usually combined with a key
+    /// of the other hand, while rolls within one hand are typing. The slice gives the hand of
+    /// every key that has one. Keys without a hand are treated as keys of the opposite hand.
+    OppositeHand(&'a [(KCoord, Hand)]),
     /// A custom configuration. Allows the behavior to be controlled by a caller
     /// supplied handler function.
     ///
@@ -96,6 +104,9 @@ impl Debug for HoldTapConfig<'_> {
             HoldTapConfig::Default => f.write_str("Default"),
             HoldTapConfig::HoldOnOtherKeyPress => f.write_str("HoldOnOtherKeyPress"),
             HoldTapConfig::PermissiveHold => f.write_str("PermissiveHold"),
+            HoldTapConfig::OppositeHand(hands) => {
+                f.debug_tuple("OppositeHand").field(hands).finish()
+            }
             HoldTapConfig::Custom(_) => f.write_str("Custom"),
         }
     }
@@ -108,11 +119,29 @@ impl PartialEq for HoldTapConfig<'_> {
             (HoldTapConfig::Default, HoldTapConfig::Default)
             | (HoldTapConfig::HoldOnOtherKeyPress, HoldTapConfig::HoldOnOtherKeyPress)
             | (HoldTapConfig::PermissiveHold, HoldTapConfig::PermissiveHold) => true,
+            (HoldTapConfig::OppositeHand(a), HoldTapConfig::OppositeHand(b)) => a == b,
             _ => false,
         }
     }
 }
 
+/// The hand that types a key, see [HoldTapConfig::OppositeHand].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+impl Hand {
+    /// Returns the hand of the key at the coordinate, if it has one.
+    pub fn of(coord: KCoord, hands: &[(KCoord, Hand)]) -> Option<Hand> {
+        hands
+            .iter()
+            .find(|(c, _)| *c == coord)
+            .map(|(_, hand)| *hand)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 /// A state that that can be released from the active states via the ReleaseState action.
 pub enum ReleasableState {
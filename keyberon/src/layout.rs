@@ -80,6 +80,7 @@ const REAL_KEY_ROW: u8 = 0;
 
 const HISTORICAL_EVENT_LEN: usize = 8;
 const EXTRA_WAITING_LEN: usize = 8;
+const HOLD_TAP_RESOLUTION_LEN: usize = EXTRA_WAITING_LEN + 1;
 #[test]
 fn extra_waiting_size_constraint() {
     assert!(EXTRA_WAITING_LEN < i8::MAX as usize);
@@ -109,7 +110,15 @@ where
     pub rpt_action: Option<&'a Action<'a, T>>,
     pub historical_keys: History<KeyCode>,
     pub historical_inputs: History<KCoord>,
+    /// Hold-tap keys that were resolved since the user of the layout last drained this queue.
+    /// Old entries are overwritten if it is never drained.
+    pub hold_tap_resolutions:
+        ArrayDeque<HoldTapResolution, HOLD_TAP_RESOLUTION_LEN, arraydeque::behavior::Wrapping>,
     pub quick_tap_hold_timeout: bool,
+    /// If a HoldTap key is pressed less than this many ticks after the previous key press, its
+    /// tap action is activated right away, so that HoldTap keys act as plain keys while typing.
+    /// 0 disables this.
+    pub tap_hold_require_prior_idle: u16,
     pub chords_v2: Option<ChordsV2<'a, T>>,
     rpt_multikey_key_buffer: MultiKeyBuffer<'a, T>,
     trans_resolution_behavior_v2: bool,
@@ -456,6 +465,32 @@ pub enum WaitingAction {
     NoOp,
 }
 
+/// The outcome of a HoldTap key that was waiting for its action to be decided.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HoldTapResolution {
+    /// Coordinate of the HoldTap key.
+    pub coord: KCoord,
+    /// The action that was triggered.
+    pub action: WaitingAction,
+    /// Number of ticks that the key was waiting before being resolved.
+    pub ticks: u16,
+}
+
+/// Returns whether a key was pressed and released while queued.
+fn is_press_and_release(queued: &Queue) -> bool {
+    let mut queued = queued.iter();
+    while let Some(q) = queued.next() {
+        if q.event.is_press() {
+            let (i, j) = q.event.coord();
+            let target = Event::Release(i, j);
+            if queued.clone().any(|q| q.event == target) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
 impl<'a, T: std::fmt::Debug> WaitingState<'a, T> {
     fn tick_wt(
         &mut self,
@@ -516,16 +551,21 @@ impl<'a, T: std::fmt::Debug> WaitingState<'a, T> {
                 }
             }
             HoldTapConfig::PermissiveHold => {
-                let mut queued = queued.iter();
-                while let Some(q) = queued.next() {
-                    if q.event.is_press() {
-                        let (i, j) = q.event.coord();
-                        let target = Event::Release(i, j);
-                        if queued.clone().any(|q| q.event == target) {
-                            return Some(WaitingAction::Hold);
-                        }
+                if is_press_and_release(queued) {
+                    return Some(WaitingAction::Hold);
+                }
+            }
+            HoldTapConfig::OppositeHand(hands) => {
+                let own_hand = Hand::of(self.coord, hands);
+                let first_press = queued.iter().find(|q| q.event.is_press());
+                if let (Some(own_hand), Some(q)) = (own_hand, first_press) {
+                    if Hand::of(q.event.coord(), hands) == Some(own_hand) {
+                        return Some(WaitingAction::Tap);
                     }
                 }
+                if is_press_and_release(queued) {
+                    return Some(WaitingAction::Hold);
+                }
             }
             HoldTapConfig::Custom(func) => {
                 let (waiting_action, local_skip) = (func)(QueuedIter(queued.iter()));
@@ -1093,8 +1133,10 @@ impl<'a, const C: usize, const R: usize, T: 'a + Copy + std::fmt::Debug> Layout<
             rpt_action: None,
             historical_keys: History::new(),
             historical_inputs: History::new(),
+            hold_tap_resolutions: ArrayDeque::new(),
             rpt_multikey_key_buffer: unsafe { MultiKeyBuffer::new() },
             quick_tap_hold_timeout: false,
+            tap_hold_require_prior_idle: 0,
             trans_resolution_behavior_v2: true,
             delegate_to_first_layer: false,
             chords_v2: None,
@@ -1131,11 +1173,20 @@ impl<'a, const C: usize, const R: usize, T: 'a + Copy + std::fmt::Debug> Layout<
                 WaitingConfig::TapDance(_) => 0,
             };
             let layer_stack = w.layer_stack.clone();
+            let resolution =
+                matches!(w.config, WaitingConfig::HoldTap(..)).then_some(HoldTapResolution {
+                    coord,
+                    action: WaitingAction::Hold,
+                    ticks: w.ticks,
+                });
             if idx < 0 {
                 self.waiting = None;
             } else {
                 self.extra_waiting.remove(idx as usize);
             }
+            if let Some(resolution) = resolution {
+                self.hold_tap_resolutions.push_back(resolution);
+            }
             if coord == self.last_press_tracker.coord {
                 self.last_press_tracker.tap_hold_timeout = 0;
             }
@@ -1162,11 +1213,20 @@ impl<'a, const C: usize, const R: usize, T: 'a + Copy + std::fmt::Debug> Layout<
                 WaitingConfig::TapDance(_) => 0,
             };
             let layer_stack = w.layer_stack.clone();
+            let resolution =
+                matches!(w.config, WaitingConfig::HoldTap(..)).then_some(HoldTapResolution {
+                    coord,
+                    action: WaitingAction::Tap,
+                    ticks: w.ticks,
+                });
             if idx < 0 {
                 self.waiting = None;
             } else {
                 self.extra_waiting.remove(idx as usize);
             }
+            if let Some(resolution) = resolution {
+                self.hold_tap_resolutions.push_back(resolution);
+            }
             let ret = self.do_action(
                 tap,
                 coord,
@@ -1243,11 +1303,20 @@ impl<'a, const C: usize, const R: usize, T: 'a + Copy + std::fmt::Debug> Layout<
                 WaitingConfig::TapDance(_) => 0,
             };
             let layer_stack = w.layer_stack.clone();
+            let resolution =
+                matches!(w.config, WaitingConfig::HoldTap(..)).then_some(HoldTapResolution {
+                    coord,
+                    action: WaitingAction::Timeout,
+                    ticks: w.ticks,
+                });
             if idx < 0 {
                 self.waiting = None;
             } else {
                 self.extra_waiting.remove(idx as usize);
             }
+            if let Some(resolution) = resolution {
+                self.hold_tap_resolutions.push_back(resolution);
+            }
             if coord == self.last_press_tracker.coord {
                 self.last_press_tracker.tap_hold_timeout = 0;
             }
@@ -1537,6 +1606,35 @@ impl<'a, const C: usize, const R: usize, T: 'a + Copy + std::fmt::Debug> Layout<
             }
         }
     }
+    /// Returns whether the press of the key at `coord` came less than
+    /// `tap_hold_require_prior_idle` ticks after the press of another real key.
+    ///
+    /// Only keys of the real key row are considered. Users of the layout that stop ticking while
+    /// idle must keep ticking until the latest real key press is older than
+    /// `tap_hold_require_prior_idle`, so that the ticks in between are counted.
+    fn is_prior_press_recent(&self, coord: KCoord) -> bool {
+        if self.tap_hold_require_prior_idle == 0 || coord.0 != REAL_KEY_ROW {
+            return false;
+        }
+        // The press of this key is the most recent one with its coordinate, since presses are
+        // added to the history when they are queued.
+        let mut presses = self
+            .historical_inputs
+            .iter_hevents()
+            .skip_while(|press| press.event != coord);
+        let Some(press) = presses.next() else {
+            return false;
+        };
+        presses
+            .find(|prior| prior.event.0 == REAL_KEY_ROW)
+            .is_some_and(|prior| {
+                prior
+                    .ticks_since_occurrence
+                    .saturating_sub(press.ticks_since_occurrence)
+                    < self.tap_hold_require_prior_idle
+            })
+    }
+
     /// Register a key event.
     pub fn event(&mut self, event: Event) {
         if let Event::Press(x, y) = event {
@@ -1655,7 +1753,15 @@ impl<'a, const C: usize, const R: usize, T: 'a + Copy + std::fmt::Debug> Layout<
                 tap_hold_interval,
             }) => {
                 let mut custom = CustomEvent::NoEvent;
-                if *tap_hold_interval == 0
+                if self.is_prior_press_recent(coord) {
+                    self.hold_tap_resolutions.push_back(HoldTapResolution {
+                        coord,
+                        action: WaitingAction::Tap,
+                        ticks: 0,
+                    });
+                    self.last_press_tracker.tap_hold_timeout = *tap_hold_interval;
+                    custom.update(self.do_action(tap, coord, delay, is_oneshot, layer_stack));
+                } else if *tap_hold_interval == 0
                     || coord != self.last_press_tracker.coord
                     || self.last_press_tracker.tap_hold_timeout == 0
                 {
@@ -2279,6 +2385,69 @@ mod test {
         assert_keys(&[], layout.keycodes());
     }
 
+    #[test]
+    fn hold_tap_resolutions() {
+        static LAYERS: Layers<2, 1> = &[[[
+            HoldTap(&HoldTapAction {
+                timeout: 200,
+                hold: k(LAlt),
+                timeout_action: k(LAlt),
+                tap: k(Space),
+                config: HoldTapConfig::HoldOnOtherKeyPress,
+                tap_hold_interval: 0,
+            }),
+            k(Enter),
+        ]]];
+        let mut layout = Layout::new(LAYERS);
+
+        layout.event(Press(0, 0));
+        layout.tick();
+        layout.event(Release(0, 0));
+        layout.tick();
+        assert_eq!(
+            layout.hold_tap_resolutions.pop_front(),
+            Some(HoldTapResolution {
+                coord: (0, 0),
+                action: WaitingAction::Tap,
+                ticks: 1,
+            })
+        );
+        assert_eq!(layout.hold_tap_resolutions.pop_front(), None);
+        layout.tick();
+
+        layout.event(Press(0, 0));
+        layout.tick();
+        layout.event(Press(0, 1));
+        layout.tick();
+        assert_eq!(
+            layout.hold_tap_resolutions.pop_front(),
+            Some(HoldTapResolution {
+                coord: (0, 0),
+                action: WaitingAction::Hold,
+                ticks: 1,
+            })
+        );
+        layout.event(Release(0, 0));
+        layout.event(Release(0, 1));
+        for _ in 0..4 {
+            layout.tick();
+        }
+
+        layout.event(Press(0, 0));
+        for _ in 0..201 {
+            layout.tick();
+        }
+        assert_eq!(
+            layout.hold_tap_resolutions.pop_front(),
+            Some(HoldTapResolution {
+                coord: (0, 0),
+                action: WaitingAction::Timeout,
+                ticks: 200,
+            })
+        );
+        assert_eq!(layout.hold_tap_resolutions.pop_front(), None);
+    }
+
     #[test]
     fn permissive_hold() {
         static LAYERS: Layers<2, 1> = &[[[
@@ -2319,6 +2488,134 @@ mod test {
         assert_keys(&[], layout.keycodes());
     }
 
+    #[test]
+    fn opposite_hand_hold() {
+        static HANDS: &[(KCoord, Hand)] = &[
+            ((0, 0), Hand::Left),
+            ((0, 1), Hand::Left),
+            ((0, 2), Hand::Right),
+        ];
+        static LAYERS: Layers<3, 1> = &[[[
+            HoldTap(&HoldTapAction {
+                timeout: 200,
+                hold: k(LAlt),
+                timeout_action: k(LAlt),
+                tap: k(Space),
+                config: HoldTapConfig::OppositeHand(HANDS),
+                tap_hold_interval: 0,
+            }),
+            k(Enter),
+            k(Tab),
+        ]]];
+        let mut layout = Layout::new(LAYERS);
+
+        // Press of a key of the same hand resolves to tap right away
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[Space], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[Space, Enter], layout.keycodes());
+        layout.event(Release(0, 0));
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        assert_eq!(
+            layout.hold_tap_resolutions.pop_front().map(|r| r.action),
+            Some(WaitingAction::Tap)
+        );
+
+        // Press and release of a key of the opposite hand resolves to hold
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        layout.event(Release(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LAlt], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LAlt, Tab], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        assert_eq!(
+            layout.hold_tap_resolutions.pop_front().map(|r| r.action),
+            Some(WaitingAction::Hold)
+        );
+
+        // A roll to a key of the opposite hand resolves to tap
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[Space], layout.keycodes());
+        assert_eq!(
+            layout.hold_tap_resolutions.pop_front().map(|r| r.action),
+            Some(WaitingAction::Tap)
+        );
+    }
+
+    #[test]
+    fn tap_hold_require_prior_idle() {
+        static LAYERS: Layers<2, 1> = &[[[
+            HoldTap(&HoldTapAction {
+                timeout: 200,
+                hold: k(LAlt),
+                timeout_action: k(LAlt),
+                tap: k(Space),
+                config: HoldTapConfig::PermissiveHold,
+                tap_hold_interval: 0,
+            }),
+            k(Enter),
+        ]]];
+        let mut layout = Layout::new(LAYERS);
+        layout.tap_hold_require_prior_idle = 100;
+
+        // Press soon after another key activates the tap action right away
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Release(0, 1));
+        for _ in 0..50 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+        }
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[Space], layout.keycodes());
+        assert_eq!(
+            layout.hold_tap_resolutions.pop_front().map(|r| r.action),
+            Some(WaitingAction::Tap)
+        );
+        for _ in 0..300 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[Space], layout.keycodes());
+        }
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // Press after enough idle time waits as usual
+        for _ in 0..100 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+        }
+        layout.event(Press(0, 0));
+        for _ in 0..200 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[], layout.keycodes());
+        }
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LAlt], layout.keycodes());
+        assert_eq!(
+            layout.hold_tap_resolutions.pop_front().map(|r| r.action),
+            Some(WaitingAction::Timeout)
+        );
+    }
+
     #[test]
     fn simultaneous_hold() {
         static LAYERS: Layers<3, 1> = &[[[
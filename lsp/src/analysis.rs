@@ -0,0 +1,348 @@
+//! Parses a configuration document and converts the results of the parser into the information
+//! served to the editor.
+
+use kanata_parser::cfg::sexpr::{Position as SpanPosition, Span};
+use kanata_parser::cfg::{parse_cfg_raw_string, FileContentProvider, ParseError, ParserState};
+use kanata_parser::lsp_hints::{LspHints, ReferencesMap};
+use lsp_types::{Diagnostic, DiagnosticSeverity, DiagnosticTag, Location, Position, Range, Url};
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// The kinds of named items that can be defined and referenced in a configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SymbolKind {
+    Alias,
+    Variable,
+    VirtualKey,
+    Layer,
+    Template,
+    Include,
+}
+
+/// A definition or reference of a named item.
+#[derive(Debug, Clone)]
+struct Symbol {
+    kind: SymbolKind,
+    name: String,
+    location: Location,
+    is_definition: bool,
+}
+
+/// The result of parsing a document.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    /// Diagnostics for every file with any, which may include files other than the document
+    /// itself if they are included by the document.
+    pub diagnostics: HashMap<Url, Vec<Diagnostic>>,
+    /// The files included by the document.
+    pub included_files: HashSet<Url>,
+    symbols: Vec<Symbol>,
+}
+
+/// Parse the document at `uri` and collect its diagnostics, definitions and references.
+///
+/// Included files are read from `open_documents` if the editor has them open, otherwise from the
+/// file system.
+pub fn analyze(
+    uri: &Url,
+    text: &str,
+    local_keys_variant: &str,
+    open_documents: &HashMap<Url, String>,
+) -> Analysis {
+    let path = uri
+        .to_file_path()
+        .unwrap_or_else(|_| PathBuf::from(uri.path()));
+    let files = FileResolver::new(uri, &path);
+
+    let mut get_file_content = |include_path: &Path| {
+        let include_uri = files.uri(&include_path.to_string_lossy());
+        match open_documents.get(&include_uri) {
+            Some(text) => Ok(text.clone()),
+            None => include_uri
+                .to_file_path()
+                .map_err(|_| format!("invalid file path: {include_uri}"))
+                .and_then(|p| std::fs::read_to_string(p).map_err(|e| e.to_string())),
+        }
+    };
+    let env_vars = Ok(std::env::vars().collect());
+    let mut s = ParserState::default();
+    let result = parse_cfg_raw_string(
+        text,
+        &mut s,
+        &path,
+        &mut FileContentProvider::new(&mut get_file_content),
+        local_keys_variant,
+        env_vars,
+    );
+
+    let mut analysis = Analysis::default();
+    if let Err(e) = result {
+        analysis.push_error(&files, e);
+    }
+    analysis.push_hints(&files, &s.lsp_hints.borrow());
+    analysis
+}
+
+impl Analysis {
+    fn push_diagnostic(&mut self, uri: Url, diagnostic: Diagnostic) {
+        self.diagnostics.entry(uri).or_default().push(diagnostic);
+    }
+
+    fn push_error(&mut self, files: &FileResolver, e: ParseError) {
+        let (uri, range) = match &e.span {
+            Some(span) => (files.uri(&span.file_name), span_range(span)),
+            None => (files.main_uri.clone(), Range::default()),
+        };
+        self.push_diagnostic(
+            uri,
+            Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("kanata".to_owned()),
+                message: e.msg,
+                ..Default::default()
+            },
+        );
+    }
+
+    fn push_hints(&mut self, files: &FileResolver, hints: &LspHints) {
+        for inactive in hints.inactive_code.iter() {
+            self.push_diagnostic(
+                files.uri(&inactive.span.file_name),
+                Diagnostic {
+                    range: span_range(&inactive.span),
+                    severity: Some(DiagnosticSeverity::HINT),
+                    source: Some("kanata".to_owned()),
+                    message: inactive.reason.clone(),
+                    tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let defs = &hints.definition_locations;
+        for (kind, locations) in [
+            (SymbolKind::Alias, &defs.alias),
+            (SymbolKind::Variable, &defs.variable),
+            (SymbolKind::VirtualKey, &defs.virtual_key),
+            (SymbolKind::Layer, &defs.layer),
+            (SymbolKind::Template, &defs.template),
+        ] {
+            for (name, span) in locations.iter() {
+                self.symbols.push(Symbol {
+                    kind,
+                    name: name.clone(),
+                    location: files.location(span),
+                    is_definition: true,
+                });
+            }
+        }
+
+        let refs = &hints.reference_locations;
+        for (kind, references) in [
+            (SymbolKind::Alias, &refs.alias),
+            (SymbolKind::Variable, &refs.variable),
+            (SymbolKind::VirtualKey, &refs.virtual_key),
+            (SymbolKind::Layer, &refs.layer),
+            (SymbolKind::Template, &refs.template),
+            (SymbolKind::Include, &refs.include),
+        ] {
+            self.push_references(files, kind, references);
+        }
+        for included in refs.include.0.keys() {
+            self.included_files.insert(files.uri(included));
+        }
+    }
+
+    fn push_references(&mut self, files: &FileResolver, kind: SymbolKind, refs: &ReferencesMap) {
+        for (name, spans) in refs.0.iter() {
+            for span in spans.iter() {
+                self.symbols.push(Symbol {
+                    kind,
+                    name: name.clone(),
+                    location: files.location(span),
+                    is_definition: false,
+                });
+            }
+        }
+    }
+
+    /// If the document failed to parse, the parser produces no definitions or references. Keep
+    /// the previous ones instead so that navigation still works while the document is edited.
+    pub fn keep_symbols_on_error(&mut self, previous: &Analysis) {
+        if self.symbols.is_empty() && self.has_errors() {
+            self.symbols = previous.symbols.clone();
+            self.included_files = previous.included_files.clone();
+        }
+    }
+
+    fn has_errors(&self) -> bool {
+        self.diagnostics
+            .values()
+            .flatten()
+            .any(|d| d.severity == Some(DiagnosticSeverity::ERROR))
+    }
+
+    /// Returns the symbol at the position, if any.
+    fn symbol_at(&self, uri: &Url, pos: Position) -> Option<&Symbol> {
+        self.symbols.iter().find(|sym| {
+            &sym.location.uri == uri
+                && sym.location.range.start <= pos
+                && pos <= sym.location.range.end
+        })
+    }
+
+    /// Returns the definitions of the item at the position.
+    pub fn definitions(&self, uri: &Url, pos: Position) -> Vec<Location> {
+        let Some(sym) = self.symbol_at(uri, pos) else {
+            return vec![];
+        };
+        if sym.kind == SymbolKind::Include {
+            // The definition of an include is the included file itself.
+            return sym
+                .location
+                .uri
+                .join(&sym.name)
+                .map(|uri| vec![Location::new(uri, Range::default())])
+                .unwrap_or_default();
+        }
+        self.symbols
+            .iter()
+            .filter(|other| other.is_definition && other.kind == sym.kind && other.name == sym.name)
+            .map(|other| other.location.clone())
+            .collect()
+    }
+
+    /// Returns the references to the item at the position, and its definition if
+    /// `include_declaration` is true.
+    pub fn references(&self, uri: &Url, pos: Position, include_declaration: bool) -> Vec<Location> {
+        let Some(sym) = self.symbol_at(uri, pos) else {
+            return vec![];
+        };
+        self.symbols
+            .iter()
+            .filter(|other| {
+                other.kind == sym.kind
+                    && other.name == sym.name
+                    && (include_declaration || !other.is_definition)
+            })
+            .map(|other| other.location.clone())
+            .collect()
+    }
+}
+
+/// Converts the file names used by the parser into URIs. The main document is named by its path
+/// and included files are named by their path relative to the main document's directory.
+struct FileResolver {
+    main_uri: Url,
+    main_path: PathBuf,
+}
+
+impl FileResolver {
+    fn new(main_uri: &Url, main_path: &Path) -> Self {
+        Self {
+            main_uri: main_uri.clone(),
+            main_path: main_path.to_owned(),
+        }
+    }
+
+    fn uri(&self, file_name: &str) -> Url {
+        if Path::new(file_name) == self.main_path {
+            return self.main_uri.clone();
+        }
+        let path = match self.main_path.parent() {
+            Some(dir) => dir.join(file_name),
+            None => PathBuf::from(file_name),
+        };
+        Url::from_file_path(&path)
+            .ok()
+            .or_else(|| self.main_uri.join(file_name).ok())
+            .unwrap_or_else(|| self.main_uri.clone())
+    }
+
+    fn location(&self, span: &Span) -> Location {
+        Location::new(self.uri(&span.file_name), span_range(span))
+    }
+}
+
+fn span_range(span: &Span) -> Range {
+    Range::new(
+        lsp_position(&span.file_content, &span.start),
+        lsp_position(&span.file_content, &span.end),
+    )
+}
+
+/// LSP positions count characters in UTF-16 code units, while the parser counts bytes.
+fn lsp_position(content: &str, pos: &SpanPosition) -> Position {
+    let line_prefix = content
+        .get(pos.line_beginning..pos.absolute)
+        .unwrap_or_default();
+    Position::new(
+        pos.line as u32,
+        line_prefix.chars().map(char::len_utf16).sum::<usize>() as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri() -> Url {
+        Url::from_file_path(std::env::temp_dir().join("kanata.kbd")).unwrap()
+    }
+
+    #[test]
+    fn reports_parse_error_with_range() {
+        let analysis = analyze(
+            &uri(),
+            "(defsrc a)\n(deflayer base notakey)",
+            "deflocalkeys-linux",
+            &HashMap::new(),
+        );
+        let diagnostics = &analysis.diagnostics[&uri()];
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(1, 15), Position::new(1, 22))
+        );
+    }
+
+    #[test]
+    fn finds_definitions_and_references() {
+        let text = "(defsrc a b)\n(deflayer base @é (layer-switch other))\n\
+                    (deflayer other @é _)\n(defalias é a)";
+        let analysis = analyze(&uri(), text, "deflocalkeys-linux", &HashMap::new());
+        assert!(
+            analysis.diagnostics.is_empty(),
+            "{:?}",
+            analysis.diagnostics
+        );
+
+        // On the first @é reference.
+        let defs = analysis.definitions(&uri(), Position::new(1, 16));
+        assert_eq!(defs.len(), 1);
+        assert_eq!(defs[0].range.start, Position::new(3, 10));
+
+        let refs = analysis.references(&uri(), Position::new(3, 10), false);
+        assert_eq!(refs.len(), 2);
+        let refs = analysis.references(&uri(), Position::new(3, 10), true);
+        assert_eq!(refs.len(), 3);
+
+        // On the layer name within layer-switch.
+        let defs = analysis.definitions(&uri(), Position::new(1, 33));
+        assert_eq!(defs.len(), 1);
+        assert_eq!(defs[0].range.start.line, 2);
+    }
+
+    #[test]
+    fn reports_inactive_platform_blocks() {
+        let text = "(defsrc a)\n(deflayer base a)\n(platform (macos) (defalias x b))";
+        let analysis = analyze(&uri(), text, "deflocalkeys-linux", &HashMap::new());
+        let diagnostics = &analysis.diagnostics[&uri()];
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].tags, Some(vec![DiagnosticTag::UNNECESSARY]));
+        assert_eq!(diagnostics[0].range.start.line, 2);
+    }
+}
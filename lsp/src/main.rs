@@ -0,0 +1,284 @@
+//! A language server for kanata configuration files.
+//!
+//! The server communicates over stdio and provides:
+//!
+//! - diagnostics for errors reported by the kanata parser
+//! - go-to-definition and find-references for aliases, variables, layers, templates, virtual keys
+//!   and included files
+//! - hints that mark platform-specific and environment-specific code as inactive
+
+use anyhow::Result;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{GotoDefinition, References, Request as _};
+use lsp_types::{
+    GotoDefinitionResponse, InitializeParams, Location, OneOf, PublishDiagnosticsParams,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+
+use std::collections::{HashMap, HashSet};
+
+mod analysis;
+use analysis::*;
+
+#[cfg(target_os = "windows")]
+const DEFAULT_LOCAL_KEYS_VARIANT: &str = "deflocalkeys-win";
+#[cfg(target_os = "macos")]
+const DEFAULT_LOCAL_KEYS_VARIANT: &str = "deflocalkeys-macos";
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const DEFAULT_LOCAL_KEYS_VARIANT: &str = "deflocalkeys-linux";
+
+fn main() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    let init_params: InitializeParams =
+        serde_json::from_value(connection.initialize(serde_json::to_value(capabilities)?)?)?;
+
+    // The platform whose deflocalkeys and platform blocks are active can be chosen by the
+    // client, e.g. to edit a Windows configuration on Linux.
+    let local_keys_variant = init_params
+        .initialization_options
+        .as_ref()
+        .and_then(|opts| opts.get("localKeysVariant"))
+        .and_then(|v| v.as_str())
+        .unwrap_or(DEFAULT_LOCAL_KEYS_VARIANT)
+        .to_owned();
+
+    let server = Server {
+        connection,
+        local_keys_variant,
+        documents: HashMap::new(),
+        analyses: HashMap::new(),
+        published: HashSet::new(),
+    };
+    // The server owns the connection, which must be dropped before joining the IO threads.
+    server.run()?;
+    io_threads.join()?;
+    Ok(())
+}
+
+struct Server {
+    connection: Connection,
+    local_keys_variant: String,
+    /// Text of the documents that are open in the editor.
+    documents: HashMap<Url, String>,
+    /// Analysis of every open document.
+    analyses: HashMap<Url, Analysis>,
+    /// Files that diagnostics were last published for.
+    published: HashSet<Url>,
+}
+
+impl Server {
+    fn run(mut self) -> Result<()> {
+        let receiver = self.connection.receiver.clone();
+        for msg in &receiver {
+            match msg {
+                Message::Request(req) => {
+                    if self.connection.handle_shutdown(&req)? {
+                        return Ok(());
+                    }
+                    self.handle_request(req)?;
+                }
+                Message::Notification(not) => self.handle_notification(not)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&mut self, req: Request) -> Result<()> {
+        let response = match req.method.as_str() {
+            GotoDefinition::METHOD => match request_params::<GotoDefinition>(req.params) {
+                Ok(params) => {
+                    let pos = params.text_document_position_params;
+                    let locations = self.collect_locations(|analysis| {
+                        analysis.definitions(&pos.text_document.uri, pos.position)
+                    });
+                    Response::new_ok(req.id, GotoDefinitionResponse::Array(locations))
+                }
+                Err(e) => Response::new_err(req.id, ErrorCode::InvalidParams as i32, e.to_string()),
+            },
+            References::METHOD => match request_params::<References>(req.params) {
+                Ok(params) => {
+                    let pos = params.text_document_position;
+                    let include_declaration = params.context.include_declaration;
+                    let locations = self.collect_locations(|analysis| {
+                        analysis.references(
+                            &pos.text_document.uri,
+                            pos.position,
+                            include_declaration,
+                        )
+                    });
+                    Response::new_ok(req.id, locations)
+                }
+                Err(e) => Response::new_err(req.id, ErrorCode::InvalidParams as i32, e.to_string()),
+            },
+            _ => Response::new_err(
+                req.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unsupported request: {}", req.method),
+            ),
+        };
+        self.send(response)
+    }
+
+    fn handle_notification(&mut self, not: Notification) -> Result<()> {
+        match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Some(params) = notification_params::<DidOpenTextDocument>(not) else {
+                    return Ok(());
+                };
+                self.documents
+                    .insert(params.text_document.uri, params.text_document.text);
+            }
+            DidChangeTextDocument::METHOD => {
+                let Some(params) = notification_params::<DidChangeTextDocument>(not) else {
+                    return Ok(());
+                };
+                // With full document sync, the last change holds the whole text.
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents.insert(params.text_document.uri, change.text);
+                }
+            }
+            // Included files that are not open may have been changed on disk.
+            DidSaveTextDocument::METHOD => {}
+            DidCloseTextDocument::METHOD => {
+                let Some(params) = notification_params::<DidCloseTextDocument>(not) else {
+                    return Ok(());
+                };
+                self.documents.remove(&params.text_document.uri);
+                self.analyses.remove(&params.text_document.uri);
+            }
+            _ => return Ok(()),
+        }
+        self.analyze_all()
+    }
+
+    /// Analyze every open document and publish the diagnostics.
+    ///
+    /// Any open document can affect the others through includes, so all of them are analyzed
+    /// again whenever one changes.
+    fn analyze_all(&mut self) -> Result<()> {
+        for (uri, text) in self.documents.iter() {
+            let mut analysis = analyze(uri, text, &self.local_keys_variant, &self.documents);
+            if let Some(previous) = self.analyses.get(uri) {
+                analysis.keep_symbols_on_error(previous);
+            }
+            self.analyses.insert(uri.clone(), analysis);
+        }
+
+        // A file included by another open document is reported on by its includer only;
+        // on its own it is usually not a complete configuration.
+        let included: HashSet<&Url> = self
+            .analyses
+            .values()
+            .flat_map(|analysis| analysis.included_files.iter())
+            .collect();
+        let mut diagnostics: HashMap<Url, Vec<_>> = HashMap::new();
+        for (uri, analysis) in self.analyses.iter() {
+            if included.contains(uri) {
+                continue;
+            }
+            for (file, file_diagnostics) in analysis.diagnostics.iter() {
+                diagnostics
+                    .entry(file.clone())
+                    .or_default()
+                    .extend(file_diagnostics.iter().cloned());
+            }
+        }
+
+        // Files that no longer have diagnostics are sent an empty list to clear them.
+        let published: HashSet<Url> = diagnostics.keys().cloned().collect();
+        for uri in self.published.difference(&published) {
+            diagnostics.entry(uri.clone()).or_default();
+        }
+        self.published = published;
+        for (uri, diagnostics) in diagnostics {
+            let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+            self.send(Notification::new(
+                PublishDiagnostics::METHOD.to_owned(),
+                params,
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn collect_locations(&self, f: impl Fn(&Analysis) -> Vec<Location>) -> Vec<Location> {
+        let mut locations: Vec<Location> = vec![];
+        for location in self.analyses.values().flat_map(f) {
+            if !locations.contains(&location) {
+                locations.push(location);
+            }
+        }
+        locations
+    }
+
+    fn send(&self, msg: impl Into<Message>) -> Result<()> {
+        self.connection.sender.send(msg.into())?;
+        Ok(())
+    }
+}
+
+fn request_params<R: lsp_types::request::Request>(
+    params: serde_json::Value,
+) -> serde_json::Result<R::Params> {
+    serde_json::from_value(params)
+}
+
+/// Returns the params of a notification. A notification can't be answered with an error, so
+/// one with invalid params is reported on stderr and otherwise ignored.
+fn notification_params<N: lsp_types::notification::Notification>(
+    not: Notification,
+) -> Option<N::Params> {
+    serde_json::from_value(not.params)
+        .map_err(|e| eprintln!("ignoring {} with invalid params: {e}", not.method))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> (Server, Connection) {
+        let (connection, client) = Connection::memory();
+        let server = Server {
+            connection,
+            local_keys_variant: DEFAULT_LOCAL_KEYS_VARIANT.to_owned(),
+            documents: HashMap::new(),
+            analyses: HashMap::new(),
+            published: HashSet::new(),
+        };
+        (server, client)
+    }
+
+    fn error_code(client: &Connection) -> Option<i32> {
+        match client.receiver.try_recv() {
+            Ok(Message::Response(response)) => response.error.map(|e| e.code),
+            msg => panic!("expected a response, got {msg:?}"),
+        }
+    }
+
+    #[test]
+    fn bad_requests_get_errors() {
+        let (mut server, client) = server();
+        let invalid = Request::new(1.into(), GotoDefinition::METHOD.to_owned(), "nope");
+        server.handle_request(invalid).unwrap();
+        assert_eq!(error_code(&client), Some(ErrorCode::InvalidParams as i32));
+
+        let unknown = Request::new(2.into(), "kanata/unknown".to_owned(), ());
+        server.handle_request(unknown).unwrap();
+        assert_eq!(error_code(&client), Some(ErrorCode::MethodNotFound as i32));
+
+        let invalid = Notification::new(DidOpenTextDocument::METHOD.to_owned(), "nope");
+        server.handle_notification(invalid).unwrap();
+        assert!(client.receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! Parsing of standalone action expressions after the configuration has already been parsed,
+//! e.g. actions received from a TCP client.
+
+use super::*;
+
+use crate::{bail, bail_span};
+
+/// The maximum number of distinct expressions that can be parsed. The allocations of parsed
+/// actions are only freed together with the layout, so this bounds the memory that clients can
+/// make kanata use until the next reload.
+pub(crate) const MAX_PARSED_ACTIONS: usize = 1024;
+
+/// The parts of [`ParserState`] needed to parse a single action expression outside of a
+/// configuration file. Unlike `ParserState` this can be sent across threads.
+///
+/// Variables from `defvar` and `defchords` groups are not available to these expressions.
+#[derive(Debug)]
+pub struct ActionExprParser {
+    aliases: Aliases,
+    layer_idxs: LayerIndexes,
+    virtual_keys: HashMap<String, (usize, &'static KanataAction)>,
+    is_cmd_enabled: bool,
+    delegate_to_first_layer: bool,
+    default_sequence_timeout: u16,
+    default_sequence_input_mode: SequenceInputMode,
+    block_unmapped_keys: bool,
+    a: Arc<Allocations>,
+    /// Actions that were already parsed, by their expression text. This holds at most
+    /// [`MAX_PARSED_ACTIONS`] actions.
+    parsed: HashMap<String, &'static KanataAction>,
+}
+
+impl ActionExprParser {
+    pub(super) fn new(s: &ParserState) -> Self {
+        Self {
+            aliases: s.aliases.clone(),
+            layer_idxs: s.layer_idxs.clone(),
+            virtual_keys: s.virtual_keys.clone(),
+            is_cmd_enabled: s.is_cmd_enabled,
+            delegate_to_first_layer: s.delegate_to_first_layer,
+            default_sequence_timeout: s.default_sequence_timeout,
+            default_sequence_input_mode: s.default_sequence_input_mode,
+            block_unmapped_keys: s.block_unmapped_keys,
+            a: s.a.clone(),
+            parsed: HashMap::default(),
+        }
+    }
+
+    /// Parse a single action expression, e.g. `(macro h i)` or `@my-alias`.
+    ///
+    /// The returned action is allocated together with the layout of the configuration this parser
+    /// was created from, so it is freed when that layout is dropped. Parsing the same expression
+    /// again returns the same action without allocating. New expressions are refused once
+    /// [`MAX_PARSED_ACTIONS`] distinct expressions have been parsed.
+    pub fn parse(&mut self, expr: &str) -> MResult<&'static KanataAction> {
+        if let Some(action) = self.parsed.get(expr) {
+            return Ok(action);
+        }
+        let action = self.parse_raw(expr)?;
+        self.parsed.insert(expr.to_owned(), action);
+        Ok(action)
+    }
+
+    fn parse_raw(&mut self, expr: &str) -> Result<&'static KanataAction> {
+        if self.parsed.len() >= MAX_PARSED_ACTIONS {
+            bail!(
+                "Too many distinct actions were executed. \
+                 Reuse previous actions or reload the configuration to execute new ones"
+            );
+        }
+        // Only lists are allowed at the top level, so wrap the expression to also allow atoms
+        // such as keys or aliases.
+        let exprs = sexpr::parse(&format!("({expr})"), "action")?;
+        let action_expr = match exprs.as_slice() {
+            [tl] => match tl.t.as_slice() {
+                [action_expr] => action_expr.clone(),
+                _ => bail_span!(tl, "Expected exactly one action"),
+            },
+            _ => bail!("Expected exactly one action"),
+        };
+        // Lend the maps to the parser state instead of cloning them for every expression.
+        let s = ParserState {
+            aliases: std::mem::take(&mut self.aliases),
+            layer_idxs: std::mem::take(&mut self.layer_idxs),
+            virtual_keys: std::mem::take(&mut self.virtual_keys),
+            is_cmd_enabled: self.is_cmd_enabled,
+            delegate_to_first_layer: self.delegate_to_first_layer,
+            default_sequence_timeout: self.default_sequence_timeout,
+            default_sequence_input_mode: self.default_sequence_input_mode,
+            block_unmapped_keys: self.block_unmapped_keys,
+            a: self.a.clone(),
+            ..Default::default()
+        };
+        let action = parse_action(&action_expr, &s);
+        self.aliases = s.aliases;
+        self.layer_idxs = s.layer_idxs;
+        self.virtual_keys = s.virtual_keys;
+        action
+    }
+}
@@ -0,0 +1,133 @@
+use super::*;
+
+use crate::bail;
+#[cfg(feature = "cmd")]
+use crate::{anyhow_expr, bail_expr};
+
+#[cfg(feature = "cmd")]
+const CMD_ASYNC_ERR: &str = "cmd-async expects lists of options followed by the command, e.g.\n\
+    (cmd-async (var weather) (timeout 2000) (on-failure vk-fail) (cmd curl wttr.in))\n\
+    options: (var <name>) (timeout <ms>) (on-success <vkey>) (on-failure <vkey>) \
+    (on-exit <code> <vkey>)";
+
+pub(crate) fn parse_cmd_async(
+    ac_params: &[SExpr],
+    s: &ParserState,
+) -> Result<&'static KanataAction> {
+    #[cfg(not(feature = "cmd"))]
+    {
+        let _ = (ac_params, s);
+        bail!(
+            "cmd is not enabled for this kanata executable. Use a cmd_allowed prebuilt executable or compile with the feature: cmd."
+        );
+    }
+    #[cfg(feature = "cmd")]
+    {
+        if !s.is_cmd_enabled {
+            bail!("To use cmd you must put in defcfg: danger-enable-cmd yes.");
+        }
+        let mut cmd_async = CmdAsync {
+            cmd: vec![],
+            var: None,
+            timeout: None,
+            on_exit: vec![],
+            on_success: None,
+            on_failure: None,
+        };
+        for param in ac_params {
+            let Some(option) = param.list(s.vars()) else {
+                bail_expr!(param, "{CMD_ASYNC_ERR}");
+            };
+            let name = option.first().and_then(|o| o.atom(s.vars()));
+            match (name, &option[option.len().min(1)..]) {
+                (Some("cmd"), cmd) => {
+                    if !cmd_async.cmd.is_empty() {
+                        bail_expr!(param, "cmd is specified more than once");
+                    }
+                    collect_strings(cmd, &mut cmd_async.cmd, s);
+                    if cmd_async.cmd.is_empty() {
+                        bail_expr!(param, "cmd expects at least one string");
+                    }
+                }
+                (Some("var"), [var]) => {
+                    cmd_async.var = Some(parse_cmd_var_name(var, s)?);
+                }
+                (Some("timeout"), [timeout]) => {
+                    cmd_async.timeout = Some(parse_non_zero_u16(timeout, s, "timeout")?);
+                }
+                (Some("on-success"), [vkey]) => {
+                    cmd_async.on_success = Some(parse_vkey_name(vkey, s)?);
+                }
+                (Some("on-failure"), [vkey]) => {
+                    cmd_async.on_failure = Some(parse_vkey_name(vkey, s)?);
+                }
+                (Some("on-exit"), [code, vkey]) => {
+                    let code = code
+                        .atom(s.vars())
+                        .and_then(|c| c.parse::<i32>().ok())
+                        .ok_or_else(|| anyhow_expr!(code, "exit code must be a number"))?;
+                    if cmd_async.on_exit.iter().any(|(c, _)| *c == code) {
+                        bail_expr!(param, "exit code {code} is specified more than once");
+                    }
+                    cmd_async.on_exit.push((code, parse_vkey_name(vkey, s)?));
+                }
+                _ => bail_expr!(param, "{CMD_ASYNC_ERR}"),
+            }
+        }
+        if cmd_async.cmd.is_empty() {
+            bail!("{CMD_ASYNC_ERR}");
+        }
+        Ok(s.a.sref(Action::Custom(
+            s.a.sref(s.a.sref_slice(CustomAction::CmdAsync(Box::new(cmd_async)))),
+        )))
+    }
+}
+
+/// Parses an action that uses the cmd variable set by `cmd-async`.
+pub(crate) fn parse_cmd_var_action(
+    action_name: &str,
+    ac_params: &[SExpr],
+    s: &ParserState,
+) -> Result<&'static KanataAction> {
+    #[cfg(not(feature = "cmd"))]
+    {
+        let _ = (ac_params, s);
+        bail!(
+            "{action_name} is not enabled for this kanata executable. Use a cmd_allowed prebuilt executable or compile with the feature: cmd."
+        );
+    }
+    #[cfg(feature = "cmd")]
+    {
+        if ac_params.len() != 1 {
+            bail!(
+                "{action_name} expects 1 parameter: <cmd variable name>, found {}",
+                ac_params.len()
+            );
+        }
+        let var = parse_cmd_var_name(&ac_params[0], s)?;
+        Ok(s.a.sref(Action::Custom(s.a.sref(s.a.sref_slice(
+            match action_name {
+                VAR_OUTPUT_KEYS => CustomAction::VarOutputKeys(var),
+                CLIPBOARD_VAR_SET => CustomAction::ClipboardVarSet(var),
+                _ => unreachable!("unknown cmd variable action {action_name}"),
+            },
+        )))))
+    }
+}
+
+#[cfg(feature = "cmd")]
+fn parse_cmd_var_name(expr: &SExpr, s: &ParserState) -> Result<String> {
+    expr.atom(s.vars())
+        .map(|name| name.trim_atom_quotes().to_owned())
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| anyhow_expr!(expr, "cmd variable name must be a non-empty string"))
+}
+
+#[cfg(feature = "cmd")]
+fn parse_vkey_name(expr: &SExpr, s: &ParserState) -> Result<String> {
+    parse_vkey_coord(expr, s)?;
+    Ok(expr
+        .atom(s.vars())
+        .expect("parse_vkey_coord accepts only atoms")
+        .to_owned())
+}
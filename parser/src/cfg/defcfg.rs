@@ -137,6 +137,7 @@ pub struct CfgOptions {
     pub allow_hardware_repeat: bool,
     pub start_alias: Option<String>,
     pub enable_cmd: bool,
+    pub enable_tcp_actions: bool,
     pub sequence_timeout: u16,
     pub sequence_input_mode: SequenceInputMode,
     pub sequence_backtrack_modcancel: bool,
@@ -148,10 +149,14 @@ pub struct CfgOptions {
     pub override_release_on_activation: bool,
     pub dynamic_macro_max_presses: u16,
     pub dynamic_macro_replay_delay_behaviour: ReplayDelayBehaviour,
+    pub dynamic_macro_file: Option<String>,
     pub concurrent_tap_hold: bool,
+    pub tap_hold_require_prior_idle: u16,
     pub rapid_event_delay: u16,
     pub trans_resolution_behavior_v2: bool,
     pub chords_v2_min_idle: u16,
+    pub statistics_file: Option<String>,
+    pub hold_tap_analysis_file: Option<String>,
     #[cfg(any(target_os = "linux", target_os = "unknown"))]
     pub linux_opts: CfgLinuxOptions,
     #[cfg(any(target_os = "macos", target_os = "unknown"))]
@@ -176,6 +181,7 @@ impl Default for CfgOptions {
             allow_hardware_repeat: true,
             start_alias: None,
             enable_cmd: false,
+            enable_tcp_actions: false,
             sequence_timeout: 1000,
             sequence_input_mode: SequenceInputMode::HiddenSuppressed,
             sequence_backtrack_modcancel: true,
@@ -187,10 +193,14 @@ impl Default for CfgOptions {
             override_release_on_activation: false,
             dynamic_macro_max_presses: 128,
             dynamic_macro_replay_delay_behaviour: ReplayDelayBehaviour::Recorded,
+            dynamic_macro_file: None,
             concurrent_tap_hold: false,
+            tap_hold_require_prior_idle: 0,
             rapid_event_delay: 5,
             trans_resolution_behavior_v2: true,
             chords_v2_min_idle: 5,
+            statistics_file: None,
+            hold_tap_analysis_file: None,
             #[cfg(any(target_os = "linux", target_os = "unknown"))]
             linux_opts: Default::default(),
             #[cfg(any(target_os = "windows", target_os = "unknown"))]
@@ -250,6 +260,9 @@ pub fn parse_defcfg(expr: &[SExpr]) -> Result<CfgOptions> {
                     "dynamic-macro-max-presses" => {
                         cfg.dynamic_macro_max_presses = parse_cfg_val_u16(val, label, false)?;
                     }
+                    "dynamic-macro-file" => {
+                        cfg.dynamic_macro_file = Some(sexpr_to_str_or_err(val, label)?.to_string());
+                    }
                     "dynamic-macro-replay-delay-behaviour" => {
                         cfg.dynamic_macro_replay_delay_behaviour = val
                             .atom(None)
@@ -737,9 +750,19 @@ pub fn parse_defcfg(expr: &[SExpr]) -> Result<CfgOptions> {
                         cfg.start_alias = parse_defcfg_val_string(val, label)?
                     }
                     "danger-enable-cmd" => cfg.enable_cmd = parse_defcfg_val_bool(val, label)?,
+                    "danger-enable-tcp-actions" => {
+                        cfg.enable_tcp_actions = parse_defcfg_val_bool(val, label)?
+                    }
                     "sequence-backtrack-modcancel" => {
                         cfg.sequence_backtrack_modcancel = parse_defcfg_val_bool(val, label)?
                     }
+                    "statistics-file" => {
+                        cfg.statistics_file = Some(sexpr_to_str_or_err(val, label)?.to_string());
+                    }
+                    "hold-tap-analysis-file" => {
+                        cfg.hold_tap_analysis_file =
+                            Some(sexpr_to_str_or_err(val, label)?.to_string());
+                    }
                     "log-layer-changes" => {
                         cfg.log_layer_changes = parse_defcfg_val_bool(val, label)?
                     }
@@ -768,6 +791,9 @@ pub fn parse_defcfg(expr: &[SExpr]) -> Result<CfgOptions> {
                     "concurrent-tap-hold" => {
                         cfg.concurrent_tap_hold = parse_defcfg_val_bool(val, label)?
                     }
+                    "tap-hold-require-prior-idle" => {
+                        cfg.tap_hold_require_prior_idle = parse_cfg_val_u16(val, label, false)?
+                    }
                     "rapid-event-delay" => {
                         cfg.rapid_event_delay = parse_cfg_val_u16(val, label, false)?
                     }
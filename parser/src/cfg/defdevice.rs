@@ -0,0 +1,280 @@
+//! Parsing of `defdevice`, which maps the keys of specific input devices separately from the
+//! main `defsrc` and layers.
+//!
+//! Example:
+//!
+//! ```text
+//! (defdevice macropad
+//!   (names "My Macropad")
+//!   (defsrc kp1 kp2 kp3)
+//!   (deflayer base  a b c)
+//!   (deflayer nav   left down right))
+//! ```
+//!
+//! The device keys are placed in the fake key row after the virtual keys, so they have their own
+//! layout coordinates and do not collide with the same keys on other devices.
+
+use super::*;
+
+use crate::{anyhow_expr, bail, bail_expr};
+
+const DEFDEVICE: &str = "defdevice";
+
+/// The keys of an input device that are mapped by a `defdevice` block.
+#[derive(Debug, Clone, Default)]
+pub struct DefDevice {
+    /// Name of the `defdevice` block.
+    pub name: String,
+    /// Input device names that this block applies to.
+    pub device_names: Vec<String>,
+    /// Input device paths that this block applies to.
+    pub device_paths: Vec<String>,
+    /// Mapping of the keys in the block's `defsrc` to their column in the fake key row.
+    pub keys: HashMap<OsCode, u16>,
+    /// Column in the fake key row of the action to tap when a matching device is connected.
+    pub on_connect: Option<u16>,
+    /// Column in the fake key row of the action to tap when a matching device is disconnected.
+    pub on_disconnect: Option<u16>,
+}
+
+impl DefDevice {
+    /// Returns the number of columns in the fake key row used by this `defdevice`.
+    pub fn column_count(&self) -> usize {
+        self.keys.len()
+            + usize::from(self.on_connect.is_some())
+            + usize::from(self.on_disconnect.is_some())
+    }
+}
+
+/// Parse all `defdevice` blocks and put their actions into the fake key row of every layer.
+///
+/// `first_column` is the first column in the fake key row that is not used by virtual keys.
+pub(super) fn parse_defdevices(
+    exprs: &[&Vec<SExpr>],
+    s: &ParserState,
+    first_column: usize,
+    layers: &mut IntermediateLayers,
+    mapped_keys: &mut MappedKeys,
+) -> Result<Vec<DefDevice>> {
+    let mut next_column = first_column;
+    let mut devices: Vec<DefDevice> = vec![];
+    for expr in exprs {
+        let device = parse_defdevice(expr, s, &mut next_column, layers)?;
+        if devices.iter().any(|d| d.name == device.name) {
+            bail_expr!(&expr[1], "Duplicate defdevice name: {}", device.name);
+        }
+        mapped_keys.extend(device.keys.keys().copied());
+        devices.push(device);
+    }
+    if devices.len() > usize::from(u8::MAX) {
+        bail!("Maximum number of defdevice blocks is {}", u8::MAX);
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "unknown")))]
+    if !devices.is_empty() {
+        log::warn!("defdevice is only supported on Linux; its keys will never be activated");
+    }
+    Ok(devices)
+}
+
+fn parse_defdevice(
+    expr: &[SExpr],
+    s: &ParserState,
+    next_column: &mut usize,
+    layers: &mut IntermediateLayers,
+) -> Result<DefDevice> {
+    const ERR: &str = "defdevice expects a name followed by lists of: \
+        (names ...), (paths ...), (defsrc ...), (deflayer <layer name> ...), \
+        (on-connect <action>), (on-disconnect <action>)";
+    let mut subexprs = check_first_expr(expr.iter(), DEFDEVICE)?;
+    let name_expr = subexprs
+        .next()
+        .ok_or_else(|| anyhow_expr!(&expr[0], "{ERR}\nMissing the name"))?;
+    let name = name_expr
+        .atom(s.vars())
+        .ok_or_else(|| anyhow_expr!(name_expr, "{ERR}\nThe name must not be a list"))?
+        .to_owned();
+
+    let mut device = DefDevice {
+        name,
+        ..Default::default()
+    };
+    let mut defsrc: Option<Vec<OsCode>> = None;
+    let mut layer_exprs: Vec<(usize, &SExpr, &[SExpr])> = vec![];
+    let mut on_connect: Option<&'static KanataAction> = None;
+    let mut on_disconnect: Option<&'static KanataAction> = None;
+    for subexpr in subexprs {
+        let list = subexpr
+            .list(s.vars())
+            .ok_or_else(|| anyhow_expr!(subexpr, "{ERR}\nFound an atom instead of a list"))?;
+        let label_expr = list
+            .first()
+            .ok_or_else(|| anyhow_expr!(subexpr, "{ERR}\nFound an empty list"))?;
+        match label_expr.atom(s.vars()) {
+            Some("names") => {
+                device
+                    .device_names
+                    .extend(parse_device_strings(&list[1..], s)?);
+            }
+            Some("paths") => {
+                device
+                    .device_paths
+                    .extend(parse_device_strings(&list[1..], s)?);
+            }
+            Some("defsrc") => {
+                if defsrc.is_some() {
+                    bail_expr!(label_expr, "Only one defsrc is allowed within a defdevice");
+                }
+                let mut keys = vec![];
+                for key_expr in &list[1..] {
+                    let key = key_expr
+                        .atom(s.vars())
+                        .and_then(str_to_oscode)
+                        .ok_or_else(|| anyhow_expr!(key_expr, "Unknown key in defsrc"))?;
+                    if keys.contains(&key) {
+                        bail_expr!(key_expr, "Repeat declaration of key in defsrc");
+                    }
+                    keys.push(key);
+                }
+                defsrc = Some(keys);
+            }
+            Some(DEFLAYER) => {
+                let layer_name_expr = list
+                    .get(1)
+                    .ok_or_else(|| anyhow_expr!(label_expr, "deflayer requires a layer name"))?;
+                let layer_name = layer_name_expr.atom(s.vars()).ok_or_else(|| {
+                    anyhow_expr!(layer_name_expr, "The layer name must not be a list")
+                })?;
+                let layer_idx = *s.layer_idxs.get(layer_name).ok_or_else(|| {
+                    anyhow_expr!(layer_name_expr, "Layer {layer_name} is not defined")
+                })?;
+                if layer_exprs.iter().any(|(idx, _, _)| *idx == layer_idx) {
+                    bail_expr!(
+                        layer_name_expr,
+                        "Layer {layer_name} is repeated within this defdevice"
+                    );
+                }
+                layer_exprs.push((layer_idx, layer_name_expr, &list[2..]));
+            }
+            Some(label @ ("on-connect" | "on-disconnect")) => {
+                let action = match &list[1..] {
+                    [action_expr] => parse_action(action_expr, s)?,
+                    _ => bail_expr!(label_expr, "{label} expects exactly one action"),
+                };
+                let dest = match label {
+                    "on-connect" => &mut on_connect,
+                    _ => &mut on_disconnect,
+                };
+                if dest.replace(action).is_some() {
+                    bail_expr!(label_expr, "Only one {label} is allowed within a defdevice");
+                }
+            }
+            _ => bail_expr!(label_expr, "{ERR}\nUnknown item in defdevice"),
+        }
+    }
+
+    if device.device_names.is_empty() && device.device_paths.is_empty() {
+        bail_expr!(
+            name_expr,
+            "{ERR}\nAt least one device name or path must be given"
+        );
+    }
+    let defsrc = match defsrc {
+        Some(defsrc) => {
+            if layer_exprs.is_empty() {
+                bail_expr!(name_expr, "{ERR}\nAt least one deflayer must be given");
+            }
+            defsrc
+        }
+        None => {
+            if let Some((_, layer_name_expr, _)) = layer_exprs.first() {
+                bail_expr!(layer_name_expr, "{ERR}\ndeflayer requires a defsrc");
+            }
+            if on_connect.is_none() && on_disconnect.is_none() {
+                bail_expr!(
+                    name_expr,
+                    "{ERR}\nEither a defsrc or on-connect/on-disconnect must be given"
+                );
+            }
+            vec![]
+        }
+    };
+    let event_column_count =
+        usize::from(on_connect.is_some()) + usize::from(on_disconnect.is_some());
+    if *next_column + defsrc.len() + event_column_count > KEYS_IN_ROW {
+        bail_expr!(
+            name_expr,
+            "Too many keys: virtual keys and defdevice keys must not exceed {KEYS_IN_ROW} in total"
+        );
+    }
+
+    let columns: Vec<usize> = (*next_column..*next_column + defsrc.len()).collect();
+    *next_column += defsrc.len();
+
+    // The first listed layer is used for layers that the defdevice does not list. Within it,
+    // `_` outputs the key itself, similar to the main defsrc.
+    let mut base_actions: Vec<KanataAction> = vec![];
+    for (i, (layer_idx, layer_name_expr, actions)) in layer_exprs.iter().enumerate() {
+        if actions.len() != defsrc.len() {
+            bail_expr!(
+                layer_name_expr,
+                "Expected {} actions to match the defdevice defsrc, found {}",
+                defsrc.len(),
+                actions.len()
+            );
+        }
+        let mut layer_actions = Vec::with_capacity(actions.len());
+        for (key_idx, action_expr) in actions.iter().enumerate() {
+            let action = match *parse_action(action_expr, s)? {
+                Action::Trans if i == 0 => Action::KeyCode(defsrc[key_idx].into()),
+                Action::Trans => base_actions[key_idx],
+                action => action,
+            };
+            layer_actions.push(action);
+        }
+        for (column, action) in columns.iter().zip(layer_actions.iter()) {
+            layers[*layer_idx][usize::from(FAKE_KEY_ROW)][*column] = *action;
+        }
+        if i == 0 {
+            base_actions = layer_actions;
+        }
+    }
+    for (layer_idx, layer) in layers.iter_mut().enumerate() {
+        if layer_exprs.iter().any(|(idx, _, _)| *idx == layer_idx) {
+            continue;
+        }
+        for (column, action) in columns.iter().zip(base_actions.iter()) {
+            layer[usize::from(FAKE_KEY_ROW)][*column] = *action;
+        }
+    }
+
+    device.keys = defsrc
+        .into_iter()
+        .zip(columns.into_iter().map(|c| c as u16))
+        .collect();
+
+    // Device events tap their actions from a column of their own, the same on every layer.
+    for (action, dest) in [
+        (on_connect, &mut device.on_connect),
+        (on_disconnect, &mut device.on_disconnect),
+    ] {
+        if let Some(action) = action {
+            for layer in layers.iter_mut() {
+                layer[usize::from(FAKE_KEY_ROW)][*next_column] = *action;
+            }
+            *dest = Some(*next_column as u16);
+            *next_column += 1;
+        }
+    }
+    Ok(device)
+}
+
+fn parse_device_strings(exprs: &[SExpr], s: &ParserState) -> Result<Vec<String>> {
+    exprs
+        .iter()
+        .map(|expr| {
+            expr.atom(s.vars())
+                .map(|a| a.trim_atom_quotes().to_owned())
+                .ok_or_else(|| anyhow_expr!(expr, "Expected a device name or path, found a list"))
+        })
+        .collect()
+}
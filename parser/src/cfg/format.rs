@@ -0,0 +1,310 @@
+//! Formatting of configuration files.
+//!
+//! The formatter works on the output of the S-expression parser with comments and whitespace kept,
+//! so only whitespace is ever changed. It:
+//!
+//! - aligns the keys of `defsrc` and of every `deflayer` to a common grid
+//! - puts top-level items on their own line with at most one blank line between them
+//! - removes trailing whitespace
+//!
+//! The grid is taken from `defsrc`: every distinct starting column of a key in `defsrc` becomes a
+//! grid column, which keeps its position unless an action placed in the grid column before it by
+//! any layer is too wide. This keeps gaps such as the ones around `spc` in a keyboard-shaped
+//! `defsrc`.
+//!
+//! A `deflayer` is left as written if it contains comments, has an action spanning multiple lines
+//! or does not have the same number of items as `defsrc`, e.g. because it uses a template.
+
+use super::sexpr::{self, SExpr, SExprMetaData, Spanned, TopLevel};
+use super::{ParseError, Result};
+
+use std::ops::Range;
+use unicode_width::UnicodeWidthChar;
+
+const INDENT: &str = "  ";
+
+/// Format the text of a configuration file.
+///
+/// Returns an error if the text is not a valid S-expression; the configuration items themselves
+/// are not validated.
+pub fn format_cfg(text: &str) -> Result<String> {
+    let (bom, text) = match text.strip_prefix('\u{feff}') {
+        Some(stripped) => ("\u{feff}", stripped),
+        None => ("", text),
+    };
+    let line_ending = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let text = text.replace("\r\n", "\n");
+
+    let (exprs, metadata) = sexpr::parse_(&text, "", false)?;
+    let comments: Vec<Range<usize>> = metadata
+        .iter()
+        .filter_map(|m| match m {
+            SExprMetaData::LineComment(c) => Some(line_comment_range(&text, c)),
+            SExprMetaData::BlockComment(c) => Some(c.span.start()..c.span.end()),
+            SExprMetaData::Whitespace(_) => None,
+        })
+        .collect();
+    let layers = AlignedLayers::new(&text, &exprs, &comments);
+
+    // Top-level items are the lists and the comments that are not within any list.
+    let mut items: Vec<(Range<usize>, Option<&TopLevel>)> = exprs
+        .iter()
+        .map(|e| (e.span.start()..e.span.end(), Some(e)))
+        .collect();
+    items.extend(
+        comments
+            .iter()
+            .filter(|c| !items.iter().any(|(r, _)| r.contains(&c.start)))
+            .map(|c| (c.clone(), None))
+            .collect::<Vec<_>>(),
+    );
+    items.sort_by_key(|(r, _)| r.start);
+
+    let mut out = String::new();
+    let mut prev_end = None;
+    for (range, expr) in items.into_iter() {
+        if let Some(prev_end) = prev_end {
+            match text[prev_end..range.start].matches('\n').count() {
+                0 => out.push(' '),
+                1 => out.push('\n'),
+                _ => out.push_str("\n\n"),
+            }
+        }
+        match expr.and_then(|e| layers.format(e)) {
+            Some(aligned) => out.push_str(&aligned),
+            None => out.push_str(&trim_line_ends(&text, range.clone(), expr)),
+        }
+        prev_end = Some(range.end);
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+
+    // Guard against changing the meaning of the configuration.
+    let (formatted_exprs, _) = sexpr::parse_(&out, "", false)?;
+    if !same_exprs(
+        exprs.iter().map(|e| &e.t[..]),
+        formatted_exprs.iter().map(|e| &e.t[..]),
+    ) {
+        return Err(ParseError::new_without_span(
+            "Formatting would change the meaning of the configuration. This is a bug in kanata.",
+        ));
+    }
+
+    Ok(format!("{bom}{}", out.replace('\n', line_ending)))
+}
+
+/// The range of a line comment, excluding its newline.
+fn line_comment_range(text: &str, comment: &Spanned<String>) -> Range<usize> {
+    let range = comment.span.start()..comment.span.end();
+    match text[range.clone()].ends_with('\n') {
+        true => range.start..range.end - 1,
+        false => range,
+    }
+}
+
+/// Copy the text in `range` with the whitespace at the end of each line removed, except within
+/// multi-line strings of `expr`.
+fn trim_line_ends(text: &str, range: Range<usize>, expr: Option<&TopLevel>) -> String {
+    let mut strings = vec![];
+    if let Some(expr) = expr {
+        collect_multiline_atoms(&expr.t, &mut strings);
+    }
+    let mut out = String::new();
+    let mut line_start = range.start;
+    for (i, _) in text[range.clone()].match_indices('\n') {
+        let newline = range.start + i;
+        let line = &text[line_start..newline];
+        if strings.iter().any(|s| s.contains(&newline)) {
+            out.push_str(line);
+        } else {
+            out.push_str(line.trim_end());
+        }
+        out.push('\n');
+        line_start = newline + 1;
+    }
+    out.push_str(text[line_start..range.end].trim_end());
+    out
+}
+
+fn collect_multiline_atoms(exprs: &[SExpr], ranges: &mut Vec<Range<usize>>) {
+    for expr in exprs {
+        match expr {
+            SExpr::Atom(a) if a.t.contains('\n') => ranges.push(a.span.start()..a.span.end()),
+            SExpr::Atom(_) => {}
+            SExpr::List(l) => collect_multiline_atoms(&l.t, ranges),
+        }
+    }
+}
+
+fn same_exprs<'a>(
+    a: impl ExactSizeIterator<Item = &'a [SExpr]>,
+    b: impl ExactSizeIterator<Item = &'a [SExpr]>,
+) -> bool {
+    a.len() == b.len() && a.zip(b).all(|(a, b)| same_expr_list(a, b))
+}
+
+fn same_expr_list(a: &[SExpr], b: &[SExpr]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|pair| match pair {
+            (SExpr::Atom(a), SExpr::Atom(b)) => a.t == b.t,
+            (SExpr::List(a), SExpr::List(b)) => same_expr_list(&a.t, &b.t),
+            _ => false,
+        })
+}
+
+/// Places keys on a grid by the column that they start at in `defsrc`: each distinct starting
+/// column is a grid column. Returns the distinct starting columns in order and the grid column of
+/// each key.
+pub fn grid_columns(key_columns: &[usize]) -> (Vec<usize>, Vec<usize>) {
+    let mut columns = key_columns.to_vec();
+    columns.sort_unstable();
+    columns.dedup();
+    let key_grid_columns = key_columns
+        .iter()
+        .map(|column| columns.binary_search(column).expect("column exists"))
+        .collect();
+    (columns, key_grid_columns)
+}
+
+/// The layout of `defsrc` and of the layers that can be aligned to it.
+struct AlignedLayers<'a> {
+    text: &'a str,
+    comments: &'a [Range<usize>],
+    /// For each row of `defsrc`, the grid column of each key.
+    rows: Vec<Vec<usize>>,
+    /// Starting column of every grid column, relative to the indentation.
+    positions: Vec<usize>,
+}
+
+impl<'a> AlignedLayers<'a> {
+    fn new(text: &'a str, exprs: &[TopLevel], comments: &'a [Range<usize>]) -> Self {
+        let mut layers = Self {
+            text,
+            comments,
+            rows: vec![],
+            positions: vec![],
+        };
+        let mut defsrcs = exprs.iter().filter(|e| first_atom(e) == Some("defsrc"));
+        let (Some(defsrc), None) = (defsrcs.next(), defsrcs.next()) else {
+            return layers;
+        };
+        let keys = &defsrc.t[1..];
+        if keys.is_empty()
+            || layers.contains_comment(defsrc)
+            || keys.iter().any(|k| !matches!(k, SExpr::Atom(_)))
+        {
+            return layers;
+        }
+
+        let key_columns: Vec<usize> = keys
+            .iter()
+            .map(|k| {
+                let span = k.span();
+                display_width(&text[span.start.line_beginning..span.start()])
+            })
+            .collect();
+        let (grid_columns, key_grid_columns) = grid_columns(&key_columns);
+        let mut prev_line = None;
+        for (key, grid_column) in keys.iter().zip(key_grid_columns) {
+            let line = key.span().start.line;
+            if prev_line != Some(line) {
+                layers.rows.push(vec![]);
+                prev_line = Some(line);
+            }
+            layers
+                .rows
+                .last_mut()
+                .expect("row exists")
+                .push(grid_column);
+        }
+
+        // Move every grid column as far as needed to fit the widest item placed before it.
+        let mut widths = vec![0; grid_columns.len()];
+        for expr in exprs.iter() {
+            let Some((_, items)) = layers.layer_items(expr) else {
+                continue;
+            };
+            for (item, grid_column) in items.iter().zip(layers.rows.iter().flatten()) {
+                let width = &mut widths[*grid_column];
+                *width = (*width).max(display_width(item));
+            }
+        }
+        let mut min_position = 0;
+        for (column, width) in grid_columns.iter().zip(widths) {
+            let position = min_position.max(column - grid_columns[0]);
+            layers.positions.push(position);
+            min_position = position + width + 1;
+        }
+        layers
+    }
+
+    /// Returns the header and the item texts of `expr` if it can be aligned.
+    fn layer_items(&self, expr: &TopLevel) -> Option<(String, Vec<&'a str>)> {
+        let (header, items) = match first_atom(expr)? {
+            "defsrc" => ("defsrc".to_owned(), &expr.t[1..]),
+            "deflayer" => {
+                let name = self.source(expr.t.get(1)?);
+                (format!("deflayer {name}"), expr.t.get(2..)?)
+            }
+            _ => return None,
+        };
+        if self.rows.is_empty()
+            || header.contains('\n')
+            || items.len() != self.rows.iter().map(Vec::len).sum::<usize>()
+            || self.contains_comment(expr)
+        {
+            return None;
+        }
+        let items: Vec<&str> = items.iter().map(|item| self.source(item)).collect();
+        if items.iter().any(|item| item.contains('\n')) {
+            return None;
+        }
+        Some((header, items))
+    }
+
+    /// Returns the aligned text of `expr` if it is `defsrc` or a `deflayer` that can be aligned.
+    fn format(&self, expr: &TopLevel) -> Option<String> {
+        let (header, items) = self.layer_items(expr)?;
+        let mut items = items.into_iter();
+        let mut out = format!("({header}\n");
+        for row in self.rows.iter() {
+            let mut line = String::from(INDENT);
+            for grid_column in row.iter() {
+                let item = items.next().expect("item count matches");
+                let position = INDENT.len() + self.positions[*grid_column];
+                line.push_str(&" ".repeat(position - display_width(&line)));
+                line.push_str(item);
+            }
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out.push(')');
+        Some(out)
+    }
+
+    fn contains_comment(&self, expr: &TopLevel) -> bool {
+        let range = expr.span.start()..expr.span.end();
+        self.comments.iter().any(|c| range.contains(&c.start))
+    }
+
+    fn source(&self, expr: &SExpr) -> &'a str {
+        let span = expr.span();
+        &self.text[span.start()..span.end()]
+    }
+}
+
+fn first_atom(expr: &TopLevel) -> Option<&str> {
+    match expr.t.first()? {
+        SExpr::Atom(a) => Some(&a.t),
+        SExpr::List(_) => None,
+    }
+}
+
+/// The width of the text in terminal columns, counting tabs as advancing to the next multiple of 8.
+fn display_width(s: &str) -> usize {
+    s.chars().fold(0, |width, c| match c {
+        '\t' => (width / 8 + 1) * 8,
+        c => width + c.width().unwrap_or(0),
+    })
+}
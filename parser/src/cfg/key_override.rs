@@ -5,7 +5,10 @@ use rustc_hash::FxHashMap as HashMap;
 
 use crate::keys::*;
 
+use super::FAKE_KEY_ROW;
+
 use kanata_keyberon::key_code::KeyCode;
+use kanata_keyberon::layout::Event;
 use kanata_keyberon::layout::State;
 use kanata_keyberon::layout::NORMAL_KEY_FLAG_CLEAR_ON_NEXT_ACTION;
 use kanata_keyberon::layout::NORMAL_KEY_FLAG_CLEAR_ON_NEXT_RELEASE;
@@ -17,6 +20,15 @@ pub struct OverrideStates {
     mods_pressed: u8,
     oscs_to_remove: Vec<OsCode>,
     oscs_to_add: Vec<OsCode>,
+    /// Input keys of active action overrides. These are kept apart from `oscs_to_remove` because
+    /// their layout states must not be cleared; the action stays pressed while they are held.
+    action_oscs_to_remove: Vec<OsCode>,
+    /// Input non-modifier keys of the action overrides that are active, with the fake key row
+    /// column of their action. An action override stays active until its input key is released,
+    /// even if its modifiers are released first, e.g. because they were one-shot modifiers.
+    active_actions: Vec<(OsCode, u16)>,
+    /// Fake key row columns of the action overrides whose actions have been pressed.
+    pressed_action_columns: Vec<u16>,
 }
 
 impl Default for OverrideStates {
@@ -31,30 +43,29 @@ impl OverrideStates {
             mods_pressed: 0,
             oscs_to_add: Vec::new(),
             oscs_to_remove: Vec::new(),
+            action_oscs_to_remove: Vec::new(),
+            active_actions: Vec::new(),
+            pressed_action_columns: Vec::new(),
         }
     }
 
     fn cleanup(&mut self) {
         self.oscs_to_add.clear();
         self.oscs_to_remove.clear();
+        self.action_oscs_to_remove.clear();
         self.mods_pressed = 0;
     }
 
-    fn update(&mut self, osc: OsCode, overrides: &Overrides) {
+    fn update<T>(&mut self, osc: OsCode, overrides: &Overrides, ctx: &OverrideContext<T>) {
         if let Some(mod_mask) = mask_for_key(osc) {
             self.mods_pressed |= mod_mask;
         } else {
-            overrides.update_keys(
-                osc,
-                self.mods_pressed,
-                &mut self.oscs_to_add,
-                &mut self.oscs_to_remove,
-            );
+            overrides.update_keys(osc, self, ctx);
         }
     }
 
     fn is_key_overridden(&self, osc: OsCode) -> bool {
-        self.oscs_to_remove.contains(&osc)
+        self.oscs_to_remove.contains(&osc) || self.action_oscs_to_remove.contains(&osc)
     }
 
     fn add_overrides(&self, oscs: &mut Vec<KeyCode>) {
@@ -64,6 +75,43 @@ impl OverrideStates {
     pub fn removed_oscs(&self) -> impl Iterator<Item = OsCode> + '_ {
         self.oscs_to_remove.iter().copied()
     }
+
+    /// Calls `handle_event` with a press for every action override that became active and a
+    /// release for every action override that is no longer active.
+    pub fn handle_action_changes(&mut self, mut handle_event: impl FnMut(Event)) {
+        let (active, pressed) = (&self.active_actions, &mut self.pressed_action_columns);
+        pressed.retain(|column| {
+            let keep = active.iter().any(|(_, c)| c == column);
+            if !keep {
+                handle_event(Event::Release(FAKE_KEY_ROW, *column));
+            }
+            keep
+        });
+        for column in active.iter().map(|(_, c)| *c) {
+            if !pressed.contains(&column) {
+                handle_event(Event::Press(FAKE_KEY_ROW, column));
+                pressed.push(column);
+            }
+        }
+    }
+}
+
+/// Runtime information that overrides can be restricted by.
+#[derive(Debug, Clone, Copy)]
+pub struct OverrideContext<'a, T: 'a> {
+    /// The layer that is currently active.
+    pub layer: usize,
+    /// The states of the keyberon layout, used to check whether virtual keys are active.
+    pub kb_states: &'a [State<'a, T>],
+}
+
+impl<T> OverrideContext<'_, T> {
+    fn is_vkey_active(&self, vkey: u16) -> bool {
+        self.kb_states
+            .iter()
+            .filter_map(State::coord)
+            .any(|coord| coord == (FAKE_KEY_ROW, vkey))
+    }
 }
 
 /// A collection of global key overrides.
@@ -88,13 +136,21 @@ impl Overrides {
         Self { overrides_by_osc }
     }
 
-    pub fn override_keys(&self, kcs: &mut Vec<KeyCode>, states: &mut OverrideStates) {
+    pub fn override_keys<T>(
+        &self,
+        kcs: &mut Vec<KeyCode>,
+        states: &mut OverrideStates,
+        ctx: OverrideContext<T>,
+    ) {
         if self.is_empty() {
             return;
         }
         states.cleanup();
+        states
+            .active_actions
+            .retain(|(osc, _)| kcs.contains(&KeyCode::from(*osc)));
         for kc in kcs.iter().copied() {
-            states.update(kc.into(), self);
+            states.update(kc.into(), self, &ctx);
         }
         kcs.retain(|kc| !states.is_key_overridden((*kc).into()));
         states.add_overrides(kcs);
@@ -103,33 +159,56 @@ impl Overrides {
     pub fn output_non_mods_for_input_non_mod(&self, in_osc: OsCode) -> Vec<OsCode> {
         let mut ret = Vec::new();
         if let Some(ovds) = self.overrides_by_osc.get(&in_osc) {
-            for out_osc in ovds.iter().map(|ovd| ovd.out_non_mod_osc) {
-                ret.push(out_osc);
+            for ovd in ovds.iter() {
+                if let OverrideOutput::Keys { non_mod_osc, .. } = ovd.output {
+                    ret.push(non_mod_osc);
+                }
             }
         }
         ret
     }
 
+    /// Returns the number of fake key row columns used by action overrides.
+    pub fn action_column_count(&self) -> usize {
+        self.overrides_by_osc
+            .values()
+            .flatten()
+            .filter(|ovd| matches!(ovd.output, OverrideOutput::Action(_)))
+            .count()
+    }
+
     fn is_empty(&self) -> bool {
         self.overrides_by_osc.is_empty()
     }
 
-    fn update_keys(
+    fn update_keys<T>(
         &self,
         active_osc: OsCode,
-        active_mod_mask: u8,
-        oscs_to_add: &mut Vec<OsCode>,
-        oscs_to_remove: &mut Vec<OsCode>,
+        states: &mut OverrideStates,
+        ctx: &OverrideContext<T>,
     ) {
         let Some(ovds) = self.overrides_by_osc.get(&active_osc) else {
             return;
         };
+        if let Some((_, column)) = states
+            .active_actions
+            .iter()
+            .find(|(osc, _)| *osc == active_osc)
+        {
+            let ovd = ovds
+                .iter()
+                .find(|ovd| ovd.output == OverrideOutput::Action(*column))
+                .expect("active action belongs to an override of its input key");
+            ovd.add_removed_keys(&mut states.action_oscs_to_remove);
+            return;
+        }
+        let active_mod_mask = states.mods_pressed;
         let mut cur_chord_size = 0;
         if let Some(ovd) = ovds
             .iter()
             .filter(|ovd| {
                 let mask = ovd.get_mod_mask();
-                if mask & active_mod_mask == mask {
+                if mask & active_mod_mask == mask && ovd.is_enabled(active_mod_mask, ctx) {
                     // keep only the longest matching prefix.
                     let chord_size = ovd.in_mod_oscs.len() + 1;
                     if chord_size <= cur_chord_size {
@@ -145,8 +224,16 @@ impl Overrides {
             .next_back()
         {
             log::debug!("using override {ovd:?}");
-            ovd.add_override_keys(oscs_to_add);
-            ovd.add_removed_keys(oscs_to_remove);
+            match ovd.output {
+                OverrideOutput::Keys { .. } => {
+                    ovd.add_override_keys(&mut states.oscs_to_add);
+                    ovd.add_removed_keys(&mut states.oscs_to_remove);
+                }
+                OverrideOutput::Action(column) => {
+                    states.active_actions.push((active_osc, column));
+                    ovd.add_removed_keys(&mut states.action_oscs_to_remove);
+                }
+            }
         }
     }
 }
@@ -155,53 +242,120 @@ impl Overrides {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Override {
     in_non_mod_osc: OsCode,
-    out_non_mod_osc: OsCode,
     in_mod_oscs: Vec<OsCode>,
-    out_mod_oscs: Vec<OsCode>,
+    output: OverrideOutput,
+    /// Modifiers that must not be held for the override to activate.
+    negative_mod_mask: u8,
+    /// Layers the override is restricted to. Empty means all layers.
+    layers: Vec<u16>,
+    /// Virtual key that disables the override while it is active.
+    disabled_by_vkey: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum OverrideOutput {
+    Keys {
+        non_mod_osc: OsCode,
+        mod_oscs: Vec<OsCode>,
+    },
+    /// Column in the fake key row containing the action that is pressed while the override is
+    /// active.
+    Action(u16),
 }
 
 impl Override {
     pub fn try_new(in_oscs: &[OsCode], out_oscs: &[OsCode]) -> Result<Self> {
-        let mut in_nmoscs = in_oscs
-            .iter()
-            .copied()
-            .filter(|osc| mask_for_key(*osc).is_none());
-        let in_non_mod_osc = in_nmoscs.next().ok_or_else(|| {
-            anyhow!("override must contain exactly one input non-modifier key; found none")
-        })?;
-        if in_nmoscs.next().is_some() {
-            bail!("override must contain exactly one input non-modifier key; found multiple");
-        }
         let mut out_nmoscs = out_oscs
             .iter()
             .copied()
             .filter(|osc| mask_for_key(*osc).is_none());
-        let out_non_mod_osc = out_nmoscs.next().ok_or_else(|| {
+        let non_mod_osc = out_nmoscs.next().ok_or_else(|| {
             anyhow!("override must contain exactly one output non-modifier key; found none")
         })?;
         if out_nmoscs.next().is_some() {
             bail!("override must contain exactly one output non-modifier key; found multiple");
         }
-        let mut in_mod_oscs = in_oscs
+        let mut mod_oscs = out_oscs
             .iter()
             .copied()
             .filter(|osc| mask_for_key(*osc).is_some())
             .collect::<Vec<_>>();
-        let mut out_mod_oscs = out_oscs
+        mod_oscs.shrink_to_fit();
+        Self::try_new_with_output(
+            in_oscs,
+            OverrideOutput::Keys {
+                non_mod_osc,
+                mod_oscs,
+            },
+        )
+    }
+
+    /// Create an override that presses the action in the fake key row at `column` while active,
+    /// instead of outputting keys.
+    pub fn try_new_action(in_oscs: &[OsCode], column: u16) -> Result<Self> {
+        Self::try_new_with_output(in_oscs, OverrideOutput::Action(column))
+    }
+
+    fn try_new_with_output(in_oscs: &[OsCode], output: OverrideOutput) -> Result<Self> {
+        let mut in_nmoscs = in_oscs
+            .iter()
+            .copied()
+            .filter(|osc| mask_for_key(*osc).is_none());
+        let in_non_mod_osc = in_nmoscs.next().ok_or_else(|| {
+            anyhow!("override must contain exactly one input non-modifier key; found none")
+        })?;
+        if in_nmoscs.next().is_some() {
+            bail!("override must contain exactly one input non-modifier key; found multiple");
+        }
+        let mut in_mod_oscs = in_oscs
             .iter()
             .copied()
             .filter(|osc| mask_for_key(*osc).is_some())
             .collect::<Vec<_>>();
         in_mod_oscs.shrink_to_fit();
-        out_mod_oscs.shrink_to_fit();
         Ok(Self {
             in_non_mod_osc,
-            out_non_mod_osc,
             in_mod_oscs,
-            out_mod_oscs,
+            output,
+            negative_mod_mask: 0,
+            layers: vec![],
+            disabled_by_vkey: None,
         })
     }
 
+    /// Set modifiers that prevent the override from activating while any of them is held.
+    pub fn set_negative_mods(&mut self, negative_mod_oscs: &[OsCode]) -> Result<()> {
+        let mut mask = 0;
+        for osc in negative_mod_oscs.iter().copied() {
+            if self.in_mod_oscs.contains(&osc) {
+                bail!("negative modifier {osc} is also an input modifier of the override");
+            }
+            mask |= mask_for_key(osc)
+                .ok_or_else(|| anyhow!("negative modifiers must be modifier keys; found {osc}"))?;
+        }
+        self.negative_mod_mask = mask;
+        Ok(())
+    }
+
+    /// Restrict the override to the given layer indices.
+    pub fn set_layers(&mut self, mut layers: Vec<u16>) {
+        layers.shrink_to_fit();
+        self.layers = layers;
+    }
+
+    /// Disable the override while the virtual key at the given fake key row column is active.
+    pub fn set_disabled_by_vkey(&mut self, vkey: u16) {
+        self.disabled_by_vkey = Some(vkey);
+    }
+
+    fn is_enabled<T>(&self, active_mod_mask: u8, ctx: &OverrideContext<T>) -> bool {
+        active_mod_mask & self.negative_mod_mask == 0
+            && (self.layers.is_empty() || self.layers.iter().any(|l| usize::from(*l) == ctx.layer))
+            && !self
+                .disabled_by_vkey
+                .is_some_and(|vk| ctx.is_vkey_active(vk))
+    }
+
     fn get_mod_mask(&self) -> u8 {
         let mut mask = 0;
         for osc in self.in_mod_oscs.iter().copied() {
@@ -211,13 +365,20 @@ impl Override {
     }
 
     fn add_override_keys(&self, oscs_to_add: &mut Vec<OsCode>) {
-        for osc in self.out_mod_oscs.iter().copied() {
+        let OverrideOutput::Keys {
+            non_mod_osc,
+            mod_oscs,
+        } = &self.output
+        else {
+            return;
+        };
+        for osc in mod_oscs.iter().copied() {
             if !oscs_to_add.contains(&osc) {
                 oscs_to_add.push(osc);
             }
         }
-        if !oscs_to_add.contains(&self.out_non_mod_osc) {
-            oscs_to_add.push(self.out_non_mod_osc);
+        if !oscs_to_add.contains(non_mod_osc) {
+            oscs_to_add.push(*non_mod_osc);
         }
     }
 
@@ -18,6 +18,7 @@ pub const TAP_HOLD_RELEASE_KEYS: &str = "tap-hold-release-keys";
 pub const TAP_HOLD_RELEASE_KEYS_A: &str = "tap⬓↑keys";
 pub const TAP_HOLD_EXCEPT_KEYS: &str = "tap-hold-except-keys";
 pub const TAP_HOLD_EXCEPT_KEYS_A: &str = "tap⬓⤫keys";
+pub const TAP_HOLD_OPPOSITE_HAND: &str = "tap-hold-opposite-hand";
 pub const MULTI: &str = "multi";
 pub const MACRO: &str = "macro";
 pub const MACRO_REPEAT: &str = "macro-repeat";
@@ -92,11 +93,15 @@ pub const SETMOUSE: &str = "setmouse";
 pub const SETMOUSE_A: &str = "set🖱";
 pub const DYNAMIC_MACRO_RECORD: &str = "dynamic-macro-record";
 pub const DYNAMIC_MACRO_PLAY: &str = "dynamic-macro-play";
+pub const DYNAMIC_MACRO_PLAY_REPEAT: &str = "dynamic-macro-play-repeat";
 pub const ARBITRARY_CODE: &str = "arbitrary-code";
 pub const CMD: &str = "cmd";
 pub const CMD_LOG: &str = "cmd-log";
 pub const PUSH_MESSAGE: &str = "push-msg";
 pub const CMD_OUTPUT_KEYS: &str = "cmd-output-keys";
+pub const CMD_ASYNC: &str = "cmd-async";
+pub const VAR_OUTPUT_KEYS: &str = "var-output-keys";
+pub const CLIPBOARD_VAR_SET: &str = "clipboard-var-set";
 pub const FORK: &str = "fork";
 pub const CAPS_WORD: &str = "caps-word";
 pub const CAPS_WORD_A: &str = "word⇪";
@@ -147,6 +152,7 @@ pub fn is_list_action(ac: &str) -> bool {
         TAP_HOLD_RELEASE_KEYS_A,
         TAP_HOLD_EXCEPT_KEYS,
         TAP_HOLD_EXCEPT_KEYS_A,
+        TAP_HOLD_OPPOSITE_HAND,
         MULTI,
         MACRO,
         MACRO_REPEAT,
@@ -214,9 +220,13 @@ pub fn is_list_action(ac: &str) -> bool {
         SETMOUSE_A,
         DYNAMIC_MACRO_RECORD,
         DYNAMIC_MACRO_PLAY,
+        DYNAMIC_MACRO_PLAY_REPEAT,
         ARBITRARY_CODE,
         CMD,
         CMD_OUTPUT_KEYS,
+        CMD_ASYNC,
+        VAR_OUTPUT_KEYS,
+        CLIPBOARD_VAR_SET,
         CMD_LOG,
         PUSH_MESSAGE,
         FORK,
@@ -64,6 +64,14 @@ pub use defcfg::*;
 mod deftemplate;
 pub use deftemplate::*;
 
+mod defdevice;
+pub use defdevice::*;
+
+pub mod format;
+
+mod resolved;
+pub use resolved::*;
+
 mod switch;
 pub use switch::*;
 
@@ -77,8 +85,14 @@ pub use error::*;
 mod chord;
 use chord::*;
 
+mod action_expr;
+pub use action_expr::*;
+
 mod fake_key;
 use fake_key::*;
+
+mod cmd_async;
+use cmd_async::*;
 pub use fake_key::{FAKE_KEY_ROW, NORMAL_KEY_ROW};
 
 mod platform;
@@ -268,6 +282,8 @@ pub struct Cfg {
     pub layout: KanataLayout,
     /// Sequences defined in `defseq`.
     pub sequences: KeySeqsToFKeys,
+    /// Mapping of the fake key row column of a `defseq` action to the text of the action.
+    pub sequence_action_names: HashMap<u16, String>,
     /// Overrides defined in `defoverrides`.
     pub overrides: Overrides,
     /// Mapping of fake key name to its column in the fake key row.
@@ -276,6 +292,19 @@ pub struct Cfg {
     pub switch_max_key_timing: u16,
     /// Zipchord-like configuration.
     pub zippy: Option<(ZchPossibleChords, ZchConfig)>,
+    /// Parser for action expressions received at runtime.
+    pub action_expr_parser: ActionExprParser,
+    /// Actions defined in `defalias` by their alias name.
+    pub aliases: HashMap<String, &'static KanataAction>,
+    /// Devices with their own keys defined in `defdevice`.
+    pub devices: Vec<DefDevice>,
+    /// The first column of the fake key row that is not used by virtual keys, `defdevice` keys
+    /// or the actions of `defoverrides` and `defseq`.
+    pub first_free_fake_key_column: usize,
+    /// Keys of `defsrc` in order, with their position in the configuration.
+    pub defsrc: Vec<DefsrcKey>,
+    /// The configuration items after expansion of templates, variables and conditionals.
+    pub resolved: ResolvedCfg,
 }
 
 /// Parse a new configuration from a file.
@@ -314,6 +343,7 @@ pub fn new_from_str(cfg_text: &str, file_content: HashMap<String, String>) -> MR
     );
     layout.bm().chords_v2 = icfg.chords_v2;
     layout.bm().quick_tap_hold_timeout = icfg.options.concurrent_tap_hold;
+    layout.bm().tap_hold_require_prior_idle = icfg.options.tap_hold_require_prior_idle;
     layout.bm().oneshot.pause_input_processing_delay = icfg.options.rapid_event_delay;
     let mut fake_keys: HashMap<String, usize> = s
         .virtual_keys
@@ -321,6 +351,8 @@ pub fn new_from_str(cfg_text: &str, file_content: HashMap<String, String>) -> MR
         .map(|(k, v)| (k.clone(), v.0))
         .collect();
     fake_keys.shrink_to_fit();
+    let action_expr_parser = ActionExprParser::new(&s);
+    let aliases = s.aliases.clone();
     log::info!("config file is valid");
     Ok(Cfg {
         options: icfg.options,
@@ -329,10 +361,17 @@ pub fn new_from_str(cfg_text: &str, file_content: HashMap<String, String>) -> MR
         key_outputs,
         layout,
         sequences: icfg.sequences,
+        sequence_action_names: icfg.sequence_action_names,
         overrides: icfg.overrides,
         fake_keys,
         switch_max_key_timing,
         zippy: icfg.zippy,
+        action_expr_parser,
+        aliases,
+        devices: icfg.devices,
+        first_free_fake_key_column: icfg.first_free_fake_key_column,
+        defsrc: icfg.defsrc,
+        resolved: icfg.resolved,
     })
 }
 
@@ -345,6 +384,17 @@ pub struct LayerInfo {
     pub icon: Option<String>,
 }
 
+/// A key of `defsrc` and where it was written, which can be used to draw the layers in the same
+/// shape as `defsrc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefsrcKey {
+    pub code: OsCode,
+    /// Line of the key, counted from the first line with a key.
+    pub row: usize,
+    /// Column of the key within its line, in characters.
+    pub column: usize,
+}
+
 #[allow(clippy::type_complexity)] // return type is not pub
 fn parse_cfg(p: &Path) -> MResult<Cfg> {
     let mut s = ParserState::default();
@@ -363,6 +413,7 @@ fn parse_cfg(p: &Path) -> MResult<Cfg> {
     );
     layout.bm().chords_v2 = icfg.chords_v2;
     layout.bm().quick_tap_hold_timeout = icfg.options.concurrent_tap_hold;
+    layout.bm().tap_hold_require_prior_idle = icfg.options.tap_hold_require_prior_idle;
     layout.bm().oneshot.pause_input_processing_delay = icfg.options.rapid_event_delay;
     if let Some(s) = icfg.start_action {
         layout.bm().action_queue.push_front(Some(((1, 0), 0, s)));
@@ -373,6 +424,8 @@ fn parse_cfg(p: &Path) -> MResult<Cfg> {
         .map(|(k, v)| (k.clone(), v.0))
         .collect();
     fake_keys.shrink_to_fit();
+    let action_expr_parser = ActionExprParser::new(&s);
+    let aliases = s.aliases.clone();
     log::info!("config file is valid");
     Ok(Cfg {
         options: icfg.options,
@@ -381,10 +434,17 @@ fn parse_cfg(p: &Path) -> MResult<Cfg> {
         key_outputs,
         layout,
         sequences: icfg.sequences,
+        sequence_action_names: icfg.sequence_action_names,
         overrides: icfg.overrides,
         fake_keys,
         switch_max_key_timing,
         zippy: icfg.zippy,
+        action_expr_parser,
+        aliases,
+        devices: icfg.devices,
+        first_free_fake_key_column: icfg.first_free_fake_key_column,
+        defsrc: icfg.defsrc,
+        resolved: icfg.resolved,
     })
 }
 
@@ -418,10 +478,15 @@ pub struct IntermediateCfg {
     pub layer_info: Vec<LayerInfo>,
     pub klayers: KanataLayers,
     pub sequences: KeySeqsToFKeys,
+    pub sequence_action_names: HashMap<u16, String>,
     pub overrides: Overrides,
     pub chords_v2: Option<ChordsV2<'static, KanataCustom>>,
     pub start_action: Option<&'static KanataAction>,
     pub zippy: Option<(ZchPossibleChords, ZchConfig)>,
+    pub devices: Vec<DefDevice>,
+    pub first_free_fake_key_column: usize,
+    pub defsrc: Vec<DefsrcKey>,
+    pub resolved: ResolvedCfg,
 }
 
 // A snapshot of enviroment variables, or an error message with an explanation
@@ -558,6 +623,9 @@ pub fn parse_cfg_raw_string(
     env_vars: EnvVars,
 ) -> Result<IntermediateCfg> {
     let mut lsp_hints: LspHints = Default::default();
+    // Variable references left over from a previous parse that failed must not leak into this one.
+    #[cfg(feature = "lsp")]
+    LSP_VARIABLE_REFERENCES.with_borrow_mut(|refs| refs.0.clear());
 
     let spanned_root_exprs = sexpr::parse(text, &cfg_path.to_string_lossy())
         .and_then(|xs| expand_includes(xs, file_content_provider, &mut lsp_hints))
@@ -657,6 +725,7 @@ pub fn parse_cfg_raw_string(
         )
     }
     let (mut mapped_keys, mapping_order, _mouse_in_defsrc) = parse_defsrc(src_expr, &cfg)?;
+    let defsrc = defsrc_keys(src_expr, &mapping_order);
     #[cfg(any(target_os = "linux", target_os = "unknown"))]
     if cfg.linux_opts.linux_device_detect_mode.is_none() {
         cfg.linux_opts.linux_device_detect_mode = Some(match _mouse_in_defsrc {
@@ -783,6 +852,19 @@ pub fn parse_cfg_raw_string(
         ..Default::default()
     };
 
+    let hands_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("defhands"))
+        .collect::<Vec<_>>();
+    match hands_exprs.as_slice() {
+        [] => {}
+        [hands_expr] => s.hands = parse_hands(&hands_expr.t, s)?,
+        [_, extra, ..] => bail_span!(
+            extra,
+            "Only one defhands is allowed, found more. Delete the extras."
+        ),
+    }
+
     let chords_exprs = spanned_root_exprs
         .iter()
         .filter(gen_first_atom_filter_spanned("defchords"))
@@ -801,12 +883,6 @@ pub fn parse_cfg_raw_string(
         .collect::<Vec<_>>();
     parse_virtual_keys(&vkeys_exprs, s)?;
 
-    let sequence_exprs = root_exprs
-        .iter()
-        .filter(gen_first_atom_filter("defseq"))
-        .collect::<Vec<_>>();
-    let sequences = parse_sequences(&sequence_exprs, s)?;
-
     let alias_exprs = spanned_root_exprs
         .iter()
         .filter(gen_first_atom_start_filter_spanned("defalias"))
@@ -823,16 +899,29 @@ pub fn parse_cfg_raw_string(
 
     let mut klayers = parse_layers(s, &mut mapped_keys, &cfg)?;
 
-    resolve_chord_groups(&mut klayers, s)?;
-    let layers = s.a.bref_slice(klayers);
-    s.layers = layers;
+    let device_exprs = root_exprs
+        .iter()
+        .filter(gen_first_atom_filter("defdevice"))
+        .collect::<Vec<_>>();
+    // Columns of the fake key row are used by virtual keys first, then by the items below in
+    // order. The remaining columns are free to be used at runtime.
+    let mut fake_key_column = s.virtual_keys.len();
+    let devices = parse_defdevices(
+        &device_exprs,
+        s,
+        fake_key_column,
+        &mut klayers,
+        &mut mapped_keys,
+    )?;
+    fake_key_column += devices.iter().map(|d| d.column_count()).sum::<usize>();
+
     let override_exprs = root_exprs
         .iter()
         .filter(gen_first_atom_filter("defoverrides"))
         .collect::<Vec<_>>();
     let overrides = match override_exprs.len() {
         0 => Overrides::new(&[]),
-        1 => parse_overrides(override_exprs[0], s)?,
+        1 => parse_overrides(override_exprs[0], s, fake_key_column, &mut klayers)?,
         _ => {
             let spanned = spanned_root_exprs
                 .iter()
@@ -845,6 +934,19 @@ pub fn parse_cfg_raw_string(
             )
         }
     };
+    fake_key_column += overrides.action_column_count();
+
+    let sequence_exprs = root_exprs
+        .iter()
+        .filter(gen_first_atom_filter("defseq"))
+        .collect::<Vec<_>>();
+    let (sequences, sequence_action_names) =
+        parse_sequences(&sequence_exprs, s, fake_key_column, &mut klayers)?;
+    fake_key_column += sequence_action_names.len();
+
+    resolve_chord_groups(&mut klayers, s)?;
+    let layers = s.a.bref_slice(klayers);
+    s.layers = layers;
 
     let defchordsv2_filter = |exprs: &&Vec<SExpr>| -> bool {
         if exprs.is_empty() {
@@ -927,6 +1029,16 @@ pub fn parse_cfg_raw_string(
         }
     };
 
+    let resolved = resolve_cfg(
+        &root_exprs,
+        &s.vars,
+        &layer_info
+            .iter()
+            .map(|l| l.name.clone())
+            .collect::<Vec<_>>(),
+        &s.alias_exprs,
+    );
+
     #[cfg(feature = "lsp")]
     LSP_VARIABLE_REFERENCES.with_borrow_mut(|refs| {
         s.lsp_hints
@@ -944,10 +1056,15 @@ pub fn parse_cfg_raw_string(
         layer_info,
         klayers,
         sequences,
+        sequence_action_names,
         overrides,
         chords_v2,
         start_action,
         zippy,
+        devices,
+        first_free_fake_key_column: fake_key_column,
+        defsrc,
+        resolved,
     })
 }
 
@@ -984,6 +1101,8 @@ fn error_on_unknown_top_level_atoms(exprs: &[Spanned<Vec<SExpr>>]) -> Result<()>
                 | "defchordsv2-experimental"
                 | "defzippy"
                 | "defzippy-experimental"
+                | "defdevice"
+                | "defhands"
                 | "defseq" => Ok(()),
                 _ => err_span!(expr, "Found unknown configuration item"),
             })
@@ -1137,6 +1256,9 @@ fn parse_defsrc(
                 | OsCode::BTN_MIDDLE
                 | OsCode::BTN_SIDE
                 | OsCode::BTN_EXTRA
+                | OsCode::BTN_FORWARD
+                | OsCode::BTN_BACK
+                | OsCode::BTN_TASK
                 | OsCode::MouseWheelUp
                 | OsCode::MouseWheelDown
                 | OsCode::MouseWheelLeft
@@ -1189,6 +1311,29 @@ fn parse_defsrc(
     Ok((mkeys, ordered_codes, is_mouse_used))
 }
 
+/// Returns the positions of the keys of an already validated `defsrc`.
+fn defsrc_keys(expr: &[SExpr], mapping_order: &[usize]) -> Vec<DefsrcKey> {
+    let first_line = expr.get(1).map(|e| e.span().start.line).unwrap_or_default();
+    expr.iter()
+        .skip(1)
+        .zip(mapping_order)
+        .filter_map(|(key, code)| {
+            let span = key.span();
+            Some(DefsrcKey {
+                code: OsCode::from_u16(*code as u16)?,
+                // Items expanded from a template keep the position of the template.
+                row: span.start.line.saturating_sub(first_line),
+                column: span
+                    .file_content
+                    .get(span.start.line_beginning..span.start())
+                    .unwrap_or_default()
+                    .chars()
+                    .count(),
+            })
+        })
+        .collect()
+}
+
 type LayerIndexes = HashMap<String, usize>;
 type Aliases = HashMap<String, &'static KanataAction>;
 
@@ -1323,10 +1468,14 @@ pub struct ParserState {
     layers: KLayers,
     layer_exprs: Vec<LayerExprs>,
     aliases: Aliases,
+    /// Names and actions of the active aliases in the order they are defined.
+    alias_exprs: Vec<(String, SExpr)>,
     layer_idxs: LayerIndexes,
     mapping_order: Vec<usize>,
     virtual_keys: HashMap<String, (usize, &'static KanataAction)>,
     chord_groups: HashMap<String, ChordGroup>,
+    /// Hands of the keys from defhands, used by `tap-hold-opposite-hand`.
+    hands: &'static [(KCoord, Hand)],
     defsrc_layer: [KanataAction; KEYS_IN_ROW],
     vars: HashMap<String, SExpr>,
     is_cmd_enabled: bool,
@@ -1354,11 +1503,13 @@ impl Default for ParserState {
             layers: Default::default(),
             layer_exprs: Default::default(),
             aliases: Default::default(),
+            alias_exprs: Default::default(),
             layer_idxs: Default::default(),
             mapping_order: Default::default(),
             defsrc_layer: [KanataAction::NoOp; KEYS_IN_ROW],
             virtual_keys: Default::default(),
             chord_groups: Default::default(),
+            hands: &[],
             vars: Default::default(),
             is_cmd_enabled: default_cfg.enable_cmd,
             delegate_to_first_layer: default_cfg.delegate_to_first_layer,
@@ -1562,10 +1713,12 @@ fn read_alias_name_action_pairs<'a>(
             Some(v) => v,
             None => bail_expr!(alias_expr, "Found alias without an action - add an action"),
         };
-        let action = parse_action(action, s)?;
+        let action_expr = action;
+        let action = parse_action(action_expr, s)?;
         if s.aliases.insert(alias.into(), action).is_some() {
             bail_expr!(alias_expr, "Duplicate alias: {}", alias);
         }
+        s.alias_exprs.push((alias.into(), action_expr.clone()));
         #[cfg(feature = "lsp")]
         s.lsp_hints
             .borrow_mut()
@@ -1786,6 +1939,12 @@ fn parse_action_list(ac: &[SExpr], s: &ParserState) -> Result<&'static KanataAct
         TAP_HOLD_EXCEPT_KEYS | TAP_HOLD_EXCEPT_KEYS_A => {
             parse_tap_hold_keys(&ac[1..], s, "except", custom_tap_hold_except)
         }
+        TAP_HOLD_OPPOSITE_HAND => {
+            if s.hands.is_empty() {
+                bail!("{TAP_HOLD_OPPOSITE_HAND} requires defhands to define the hand of each key")
+            }
+            parse_tap_hold(&ac[1..], s, HoldTapConfig::OppositeHand(s.hands))
+        }
         MULTI => parse_multi(&ac[1..], s),
         MACRO => parse_macro(&ac[1..], s, RepeatMacro::No),
         MACRO_REPEAT | MACRO_REPEAT_A => parse_macro(&ac[1..], s, RepeatMacro::Yes),
@@ -1863,10 +2022,14 @@ fn parse_action_list(ac: &[SExpr], s: &ParserState) -> Result<&'static KanataAct
         SETMOUSE | SETMOUSE_A => parse_set_mouse(&ac[1..], s),
         DYNAMIC_MACRO_RECORD => parse_dynamic_macro_record(&ac[1..], s),
         DYNAMIC_MACRO_PLAY => parse_dynamic_macro_play(&ac[1..], s),
+        DYNAMIC_MACRO_PLAY_REPEAT => parse_dynamic_macro_play_repeat(&ac[1..], s),
         ARBITRARY_CODE => parse_arbitrary_code(&ac[1..], s),
         CMD => parse_cmd(&ac[1..], s, CmdType::Standard),
         CMD_OUTPUT_KEYS => parse_cmd(&ac[1..], s, CmdType::OutputKeys),
         CMD_LOG => parse_cmd_log(&ac[1..], s),
+        CMD_ASYNC => parse_cmd_async(&ac[1..], s),
+        VAR_OUTPUT_KEYS => parse_cmd_var_action(VAR_OUTPUT_KEYS, &ac[1..], s),
+        CLIPBOARD_VAR_SET => parse_cmd_var_action(CLIPBOARD_VAR_SET, &ac[1..], s),
         PUSH_MESSAGE => parse_push_message(&ac[1..], s),
         FORK => parse_fork(&ac[1..], s),
         CAPS_WORD | CAPS_WORD_A => {
@@ -2040,6 +2203,44 @@ Params in order:
     }))))
 }
 
+/// Parse `(defhands (left <keys>) (right <keys>))` into the hand of every listed `defsrc` key.
+fn parse_hands(exprs: &[SExpr], s: &ParserState) -> Result<&'static [(KCoord, Hand)]> {
+    const ERR_MSG: &str = "defhands expects lists of keys starting with left or right";
+    let subexprs = check_first_expr(exprs.iter(), "defhands")?;
+    let mut hands: Vec<(KCoord, Hand)> = vec![];
+    for hand_expr in subexprs {
+        let list = hand_expr
+            .list(s.vars())
+            .ok_or_else(|| anyhow_expr!(hand_expr, "{ERR_MSG}"))?;
+        let hand = match list.first().and_then(|e| e.atom(s.vars())) {
+            Some("left") => Hand::Left,
+            Some("right") => Hand::Right,
+            _ => bail_expr!(hand_expr, "{ERR_MSG}"),
+        };
+        for key_expr in list[1..].iter() {
+            let keys = match key_expr.list(s.vars()) {
+                Some(keys) => keys,
+                None => std::slice::from_ref(key_expr),
+            };
+            for key_expr in keys {
+                let key = key_expr
+                    .atom(s.vars())
+                    .and_then(str_to_oscode)
+                    .ok_or_else(|| anyhow_expr!(key_expr, "string of a known key is expected"))?;
+                if !s.mapping_order.contains(&usize::from(key)) {
+                    bail_expr!(key_expr, "defhands can only contain keys of defsrc");
+                }
+                let coord = (NORMAL_KEY_ROW, u16::from(key));
+                if hands.iter().any(|(c, _)| *c == coord) {
+                    bail_expr!(key_expr, "the key is already given a hand");
+                }
+                hands.push((coord, hand));
+            }
+        }
+    }
+    Ok(s.a.sref_vec(hands))
+}
+
 fn parse_u8_with_range(expr: &SExpr, s: &ParserState, label: &str, min: u8, max: u8) -> Result<u8> {
     expr.atom(s.vars())
         .map(str::parse::<u8>)
@@ -3279,6 +3480,27 @@ fn parse_dynamic_macro_play(ac_params: &[SExpr], s: &ParserState) -> Result<&'st
     )))
 }
 
+fn parse_dynamic_macro_play_repeat(
+    ac_params: &[SExpr],
+    s: &ParserState,
+) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str = "dynamic-macro-play-repeat expects 3 parameters: \
+        <macro ID (number 0-65535)> <repeat count (1-65535)> <speed % (1-65535)>";
+    if ac_params.len() != 3 {
+        bail!("{ERR_MSG}, found {}", ac_params.len());
+    }
+    let macro_id = parse_u16(&ac_params[0], s, "macro ID")?;
+    let count = parse_non_zero_u16(&ac_params[1], s, "repeat count")?;
+    let speed = parse_non_zero_u16(&ac_params[2], s, "speed %")?;
+    Ok(s.a.sref(Action::Custom(s.a.sref(s.a.sref_slice(
+        CustomAction::DynamicMacroPlayRepeat {
+            macro_id,
+            count,
+            speed,
+        },
+    )))))
+}
+
 fn parse_live_reload_num(ac_params: &[SExpr], s: &ParserState) -> Result<&'static KanataAction> {
     const ERR_MSG: &str = "expects 1 parameter: <config argument position (1-65535)>";
     if ac_params.len() != 1 {
@@ -3520,32 +3742,68 @@ fn parse_layers(
     Ok(layers_cfg)
 }
 
-const SEQ_ERR: &str = "defseq expects pairs of parameters: <virtual_key_name> <key_list>";
+const SEQ_ERR: &str = "defseq expects pairs of parameters: <virtual_key_name or action> <key_list>";
 
-fn parse_sequences(exprs: &[&Vec<SExpr>], s: &ParserState) -> Result<KeySeqsToFKeys> {
+/// Parse all `defseq` items. Sequences that activate an action instead of a virtual key have their
+/// action put into the fake key row of every layer, starting at `first_column`. Also returns the
+/// text of these actions by their column.
+fn parse_sequences(
+    exprs: &[&Vec<SExpr>],
+    s: &ParserState,
+    first_column: usize,
+    layers: &mut IntermediateLayers,
+) -> Result<(KeySeqsToFKeys, HashMap<u16, String>)> {
     let mut sequences = Trie::new();
+    let mut action_names = HashMap::default();
+    let mut next_column = first_column;
     for expr in exprs {
         let mut subexprs = check_first_expr(expr.iter(), "defseq")?.peekable();
 
-        while let Some(vkey_expr) = subexprs.next() {
-            let vkey = vkey_expr.atom(s.vars()).ok_or_else(|| {
-                anyhow_expr!(vkey_expr, "{SEQ_ERR}\nvirtual_key_name must not be a list")
+        while let Some(target_expr) = subexprs.next() {
+            let vkey_coord = match target_expr.atom(s.vars()) {
+                Some(vkey) if s.virtual_keys.contains_key(vkey) => {
+                    #[cfg(feature = "lsp")]
+                    s.lsp_hints
+                        .borrow_mut()
+                        .reference_locations
+                        .virtual_key
+                        .push(vkey, target_expr.span());
+                    get_fake_key_coords(s.virtual_keys[vkey].0)
+                }
+                atom => {
+                    let action = parse_action(target_expr, s).map_err(|e| match atom {
+                        Some(vkey) => anyhow_expr!(
+                            target_expr,
+                            "{SEQ_ERR}\nThe referenced key does not exist \
+                            and is not a valid action: {vkey}"
+                        ),
+                        None => e,
+                    })?;
+                    if matches!(action, Action::Trans) {
+                        bail_expr!(target_expr, "Transparent action is forbidden in defseq");
+                    }
+                    if next_column >= KEYS_IN_ROW {
+                        bail_expr!(
+                            target_expr,
+                            "Too many keys: virtual keys, defdevice keys and actions of \
+                            defoverrides and defseq must not exceed {KEYS_IN_ROW} in total"
+                        );
+                    }
+                    for layer in layers.iter_mut() {
+                        layer[usize::from(FAKE_KEY_ROW)][next_column] = *action;
+                    }
+                    let column = next_column as u16;
+                    next_column += 1;
+                    action_names.insert(column, ResolvedExpr::new(target_expr, &s.vars).to_text());
+                    (FAKE_KEY_ROW, column)
+                }
+            };
+            let key_seq_expr = subexprs.next().ok_or_else(|| {
+                anyhow_expr!(
+                    target_expr,
+                    "{SEQ_ERR}\nMissing key_list for {target_expr:?}"
+                )
             })?;
-            #[cfg(feature = "lsp")]
-            s.lsp_hints
-                .borrow_mut()
-                .reference_locations
-                .virtual_key
-                .push(vkey, vkey_expr.span());
-            if !s.virtual_keys.contains_key(vkey) {
-                bail_expr!(
-                    vkey_expr,
-                    "{SEQ_ERR}\nThe referenced key does not exist: {vkey}"
-                );
-            }
-            let key_seq_expr = subexprs
-                .next()
-                .ok_or_else(|| anyhow_expr!(vkey_expr, "{SEQ_ERR}\nMissing key_list for {vkey}"))?;
             let key_seq = key_seq_expr.list(s.vars()).ok_or_else(|| {
                 anyhow_expr!(key_seq_expr, "{SEQ_ERR}\nGot a non-list for key_list")
             })?;
@@ -3607,26 +3865,20 @@ fn parse_sequences(exprs: &[&Vec<SExpr>], s: &ParserState) -> Result<KeySeqsToFK
             }
 
             for p in permutations.into_iter() {
-                if sequences.ancestor_exists(&p) {
+                // A sequence may be the beginning of another sequence;
+                // only exact duplicates are ambiguous.
+                if sequences.get(&p).is_some() {
                     bail_expr!(
                         key_seq_expr,
-                        "Sequence has a conflict: its sequence contains an earlier defined sequence"
-                        );
-                }
-                if sequences.descendant_exists(&p) {
-                    bail_expr!(key_seq_expr, "Sequence has a conflict: its sequence is contained within an earlier defined seqence");
+                        "Sequence has a conflict: it is the same as an earlier defined sequence"
+                    );
                 }
-                sequences.insert(
-                    p,
-                    s.virtual_keys
-                        .get(vkey)
-                        .map(|(y, _)| get_fake_key_coords(*y))
-                        .expect("vk exists, checked earlier"),
-                );
+                sequences.insert(p, vkey_coord);
             }
         }
     }
-    Ok(sequences)
+    action_names.shrink_to_fit();
+    Ok((sequences, action_names))
 }
 
 fn parse_sequence_keys(exprs: &[SExpr], s: &ParserState) -> Result<Vec<u16>> {
@@ -3742,55 +3994,129 @@ fn parse_arbitrary_code(ac_params: &[SExpr], s: &ParserState) -> Result<&'static
     )))
 }
 
-fn parse_overrides(exprs: &[SExpr], s: &ParserState) -> Result<Overrides> {
+/// Parse `defoverrides`. The actions of overrides that output an action are put into the fake key
+/// row of every layer, starting at `first_column`.
+fn parse_overrides(
+    exprs: &[SExpr],
+    s: &ParserState,
+    first_column: usize,
+    layers: &mut IntermediateLayers,
+) -> Result<Overrides> {
     const ERR_MSG: &str =
-        "defoverrides expects pairs of parameters: <input key list> <output key list>";
+        "defoverrides expects pairs of parameters: <input key list> <output key list or action>";
     let mut subexprs = check_first_expr(exprs.iter(), "defoverrides")?;
 
     let mut overrides = Vec::<Override>::new();
+    let mut next_column = first_column;
     while let Some(in_keys_expr) = subexprs.next() {
         let in_keys = in_keys_expr
             .list(s.vars())
             .ok_or_else(|| anyhow_expr!(in_keys_expr, "Input keys must be a list"))?;
-        let out_keys_expr = subexprs
+        let out_expr = subexprs
             .next()
             .ok_or_else(|| anyhow_expr!(in_keys_expr, "Missing output keys for input keys"))?;
-        let out_keys = out_keys_expr
-            .list(s.vars())
-            .ok_or_else(|| anyhow_expr!(out_keys_expr, "Output keys must be a list"))?;
-        let in_keys =
-            in_keys
-                .iter()
-                .try_fold(vec![], |mut keys, key_expr| -> Result<Vec<OsCode>> {
-                    let key = key_expr
-                        .atom(s.vars())
-                        .and_then(str_to_oscode)
-                        .ok_or_else(|| {
-                            anyhow_expr!(key_expr, "Unknown input key name, must use known keys")
-                        })?;
-                    keys.push(key);
-                    Ok(keys)
-                })?;
-        let out_keys =
-            out_keys
-                .iter()
-                .try_fold(vec![], |mut keys, key_expr| -> Result<Vec<OsCode>> {
-                    let key = key_expr
-                        .atom(s.vars())
-                        .and_then(str_to_oscode)
-                        .ok_or_else(|| {
-                            anyhow_expr!(key_expr, "Unknown output key name, must use known keys")
-                        })?;
-                    keys.push(key);
-                    Ok(keys)
-                })?;
-        overrides
-            .push(Override::try_new(&in_keys, &out_keys).map_err(|e| anyhow!("{ERR_MSG}: {e}"))?);
+        let (in_key_exprs, option_exprs): (Vec<_>, Vec<_>) = in_keys
+            .iter()
+            .partition(|key_expr| key_expr.atom(s.vars()).is_some());
+        let in_keys = parse_override_keys(in_key_exprs.into_iter(), s, "input")?;
+        // An output list that begins with the name of a list action, or an output that is not a
+        // list, is an action. Any other output is a list of keys.
+        let out_keys = out_expr.list(s.vars()).filter(|out_keys| {
+            !out_keys
+                .first()
+                .and_then(|ac| ac.atom(s.vars()))
+                .is_some_and(is_list_action)
+        });
+        let mut ovd = match out_keys {
+            Some(out_keys) => {
+                let out_keys = parse_override_keys(out_keys.iter(), s, "output")?;
+                Override::try_new(&in_keys, &out_keys)
+            }
+            None => {
+                let action = parse_action(out_expr, s)?;
+                if matches!(action, Action::Trans) {
+                    bail_expr!(
+                        out_expr,
+                        "Transparent action is forbidden as override output"
+                    );
+                }
+                if next_column >= KEYS_IN_ROW {
+                    bail_expr!(
+                        out_expr,
+                        "Too many keys: virtual keys, defdevice keys and override actions \
+                        must not exceed {KEYS_IN_ROW} in total"
+                    );
+                }
+                for layer in layers.iter_mut() {
+                    layer[usize::from(FAKE_KEY_ROW)][next_column] = *action;
+                }
+                let column = next_column as u16;
+                next_column += 1;
+                Override::try_new_action(&in_keys, column)
+            }
+        }
+        .map_err(|e| anyhow_expr!(in_keys_expr, "{ERR_MSG}: {e}"))?;
+        for option_expr in option_exprs {
+            parse_override_option(option_expr, &mut ovd, s)?;
+        }
+        overrides.push(ovd);
     }
     log::debug!("All overrides:\n{overrides:#?}");
     Ok(Overrides::new(&overrides))
 }
 
+fn parse_override_keys<'a>(
+    mut key_exprs: impl Iterator<Item = &'a SExpr>,
+    s: &ParserState,
+    direction: &str,
+) -> Result<Vec<OsCode>> {
+    key_exprs.try_fold(vec![], |mut keys, key_expr| {
+        let key = key_expr
+            .atom(s.vars())
+            .and_then(str_to_oscode)
+            .ok_or_else(|| {
+                anyhow_expr!(
+                    key_expr,
+                    "Unknown {direction} key name, must use known keys"
+                )
+            })?;
+        keys.push(key);
+        Ok(keys)
+    })
+}
+
+fn parse_override_option(option_expr: &SExpr, ovd: &mut Override, s: &ParserState) -> Result<()> {
+    const ERR_MSG: &str = "Lists within the input keys of an override must be one of:\n\
+        (layers <layer>...) | (negative-mods <modifier>...) | (disabled-by-vkey <virtual key>)";
+    let option = option_expr.list(s.vars()).expect("not an atom");
+    match (
+        option.first().and_then(|o| o.atom(s.vars())),
+        &option[option.len().min(1)..],
+    ) {
+        (Some("layers"), layer_exprs) if !layer_exprs.is_empty() => {
+            let layers = layer_exprs.iter().try_fold(vec![], |mut layers, layer| {
+                let l_idx = layer
+                    .atom(s.vars())
+                    .and_then(|l| s.layer_idxs.get(l))
+                    .ok_or_else(|| anyhow_expr!(layer, "Not a known layer name."))?;
+                layers.push(*l_idx as u16);
+                Ok::<_, ParseError>(layers)
+            })?;
+            ovd.set_layers(layers);
+        }
+        (Some("negative-mods"), mod_exprs) if !mod_exprs.is_empty() => {
+            let mods = parse_override_keys(mod_exprs.iter(), s, "negative modifier")?;
+            ovd.set_negative_mods(&mods)
+                .map_err(|e| anyhow_expr!(option_expr, "{e}"))?;
+        }
+        (Some("disabled-by-vkey"), [vkey]) => {
+            ovd.set_disabled_by_vkey(parse_vkey_coord(vkey, s)?.y);
+        }
+        _ => bail_expr!(option_expr, "{ERR_MSG}"),
+    }
+    Ok(())
+}
+
 fn parse_fork(ac_params: &[SExpr], s: &ParserState) -> Result<&'static KanataAction> {
     const ERR_STR: &str =
         "fork expects 3 params: <left-action> <right-action> <right-trigger-keys>";
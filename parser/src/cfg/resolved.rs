@@ -0,0 +1,202 @@
+//! The configuration items as kanata understands them after `include`, `platform`,
+//! `environment`, `deftemplate` and `defvar` have been applied, e.g. to compare configurations
+//! across machines or to feed them to other tools.
+//!
+//! Actions are kept as written apart from the substitution of variables; they are not converted
+//! to any other representation.
+
+use super::sexpr::SExpr;
+
+use rustc_hash::FxHashMap as HashMap;
+
+/// A configuration expression with the variables substituted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolvedExpr {
+    Atom(String),
+    List(Vec<ResolvedExpr>),
+}
+
+/// The fully expanded configuration items.
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedCfg {
+    /// Options of `defcfg` in the order they are written.
+    pub defcfg: Vec<(String, ResolvedExpr)>,
+    /// Layers in the order of their index.
+    pub layers: Vec<ResolvedLayer>,
+    /// Aliases that are active, i.e. not in a `defaliasenvcond` whose condition does not match.
+    pub aliases: Vec<(String, ResolvedExpr)>,
+    /// Keys of `defvirtualkeys` and `deffakekeys`.
+    pub virtual_keys: Vec<(String, ResolvedExpr)>,
+    pub chord_groups: Vec<ResolvedChordGroup>,
+    pub chords_v2: Vec<ResolvedChordV2>,
+    /// Targets of `defseq`, i.e. virtual key names or actions, with their key sequence.
+    pub sequences: Vec<(ResolvedExpr, ResolvedExpr)>,
+    /// Input and output keys of `defoverrides`.
+    pub overrides: Vec<(ResolvedExpr, ResolvedExpr)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedLayer {
+    pub name: String,
+    /// The input key of every item with its action. For `deflayer` the input keys are the ones of
+    /// `defsrc`, for `deflayermap` they are written as in the configuration.
+    pub keys: Vec<(String, ResolvedExpr)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedChordGroup {
+    pub name: String,
+    pub timeout: ResolvedExpr,
+    /// The keys of every chord with its action.
+    pub chords: Vec<(ResolvedExpr, ResolvedExpr)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedChordV2 {
+    pub keys: ResolvedExpr,
+    pub action: ResolvedExpr,
+    pub timeout: ResolvedExpr,
+    pub release_behaviour: ResolvedExpr,
+    pub disabled_layers: ResolvedExpr,
+}
+
+impl ResolvedExpr {
+    /// Convert the expression, substituting variable references by their value.
+    pub fn new(expr: &SExpr, vars: &HashMap<String, SExpr>) -> Self {
+        match expr {
+            SExpr::Atom(a) => match a.t.strip_prefix('$').and_then(|name| vars.get(name)) {
+                Some(var) => Self::new(var, vars),
+                None => Self::Atom(a.t.clone()),
+            },
+            SExpr::List(l) => Self::List(l.t.iter().map(|e| Self::new(e, vars)).collect()),
+        }
+    }
+
+    /// The atom, or the text of the list as it would be written in the configuration.
+    pub fn to_text(&self) -> String {
+        match self {
+            Self::Atom(a) => a.clone(),
+            Self::List(l) => {
+                let items: Vec<String> = l.iter().map(Self::to_text).collect();
+                format!("({})", items.join(" "))
+            }
+        }
+    }
+}
+
+/// The items after the first one of every configuration item named by one of `names`, in pairs.
+fn pairs<'a>(
+    root_exprs: &'a [Vec<SExpr>],
+    names: &'a [&str],
+) -> impl Iterator<Item = (&'a SExpr, &'a SExpr)> + 'a {
+    root_exprs
+        .iter()
+        .filter(move |expr| first_atom(expr).is_some_and(|first| names.contains(&first)))
+        .flat_map(|expr| expr[1..].chunks_exact(2).map(|pair| (&pair[0], &pair[1])))
+}
+
+fn first_atom(expr: &[SExpr]) -> Option<&str> {
+    match expr.first()? {
+        SExpr::Atom(a) => Some(&a.t),
+        SExpr::List(_) => None,
+    }
+}
+
+/// Collect the resolved configuration from the expanded top-level configuration items.
+///
+/// The items must have been parsed successfully already, so that e.g. every `deflayer` has the
+/// same number of items as `defsrc`.
+pub(super) fn resolve_cfg(
+    root_exprs: &[Vec<SExpr>],
+    vars: &HashMap<String, SExpr>,
+    layer_names: &[String],
+    alias_exprs: &[(String, SExpr)],
+) -> ResolvedCfg {
+    let resolve = |expr: &SExpr| ResolvedExpr::new(expr, vars);
+    let name = |expr: &SExpr| resolve(expr).to_text();
+    let named_pairs = |names: &'static [&'static str]| -> Vec<_> {
+        pairs(root_exprs, names)
+            .map(|(k, v)| (name(k), resolve(v)))
+            .collect()
+    };
+
+    let defsrc_keys: Vec<String> = root_exprs
+        .iter()
+        .find(|expr| first_atom(expr) == Some("defsrc"))
+        .map(|expr| expr[1..].iter().map(name).collect())
+        .unwrap_or_default();
+    let layers = root_exprs
+        .iter()
+        .filter_map(|expr| match first_atom(expr)? {
+            super::DEFLAYER => Some(
+                defsrc_keys
+                    .iter()
+                    .cloned()
+                    .zip(expr[2..].iter().map(resolve))
+                    .collect(),
+            ),
+            super::DEFLAYER_MAPPED => Some(
+                expr[2..]
+                    .chunks_exact(2)
+                    .map(|pair| (name(&pair[0]), resolve(&pair[1])))
+                    .collect(),
+            ),
+            _ => None,
+        })
+        .zip(layer_names)
+        .map(|(keys, name)| ResolvedLayer {
+            name: name.clone(),
+            keys,
+        })
+        .collect();
+
+    let chord_groups = root_exprs
+        .iter()
+        .filter(|expr| first_atom(expr) == Some("defchords"))
+        .filter_map(|expr| {
+            Some(ResolvedChordGroup {
+                name: name(expr.get(1)?),
+                timeout: resolve(expr.get(2)?),
+                chords: expr[3..]
+                    .chunks_exact(2)
+                    .map(|pair| (resolve(&pair[0]), resolve(&pair[1])))
+                    .collect(),
+            })
+        })
+        .collect();
+    let chords_v2 = root_exprs
+        .iter()
+        .filter(|expr| {
+            matches!(
+                first_atom(expr),
+                Some("defchordsv2" | "defchordsv2-experimental")
+            )
+        })
+        .flat_map(|expr| expr[1..].chunks_exact(5))
+        .map(|chunk| ResolvedChordV2 {
+            keys: resolve(&chunk[0]),
+            action: resolve(&chunk[1]),
+            timeout: resolve(&chunk[2]),
+            release_behaviour: resolve(&chunk[3]),
+            disabled_layers: resolve(&chunk[4]),
+        })
+        .collect();
+
+    ResolvedCfg {
+        defcfg: named_pairs(&["defcfg"]),
+        layers,
+        aliases: alias_exprs
+            .iter()
+            .map(|(alias, action)| (alias.clone(), resolve(action)))
+            .collect(),
+        virtual_keys: named_pairs(&["defvirtualkeys", "deffakekeys"]),
+        chord_groups,
+        chords_v2,
+        sequences: pairs(root_exprs, &["defseq"])
+            .map(|(target, keys)| (resolve(target), resolve(keys)))
+            .collect(),
+        overrides: pairs(root_exprs, &["defoverrides"])
+            .map(|(input, output)| (resolve(input), resolve(output)))
+            .collect(),
+    }
+}
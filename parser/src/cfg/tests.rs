@@ -9,6 +9,7 @@ mod ambiguous;
 mod defcfg;
 mod device_detect;
 mod environment;
+mod format;
 mod macros;
 
 static CFG_PARSE_LOCK: Mutex<()> = Mutex::new(());
@@ -346,25 +347,31 @@ fn disallow_nested_tap_hold() {
 }
 
 #[test]
-fn disallow_ancestor_seq() {
+fn allow_ancestor_seq() {
     let _lk = lock(&CFG_PARSE_LOCK);
-    match new_from_file(&std::path::PathBuf::from("./test_cfgs/ancestor_seq.kbd"))
+    new_from_file(&std::path::PathBuf::from("./test_cfgs/ancestor_seq.kbd"))
         .map_err(|e| format!("{e:?}"))
-    {
-        Ok(_) => panic!("invalid ancestor seq was Ok'd"),
-        Err(e) => assert!(e.contains("is contained")),
-    }
+        .expect("a sequence may begin with an earlier defined sequence");
 }
 
 #[test]
-fn disallow_descendent_seq() {
+fn allow_descendent_seq() {
     let _lk = lock(&CFG_PARSE_LOCK);
-    match new_from_file(&std::path::PathBuf::from("./test_cfgs/descendant_seq.kbd"))
+    new_from_file(&std::path::PathBuf::from("./test_cfgs/descendant_seq.kbd"))
         .map_err(|e| format!("{e:?}"))
-    {
-        Ok(_) => panic!("invalid descendant seq was Ok'd"),
-        Err(e) => assert!(e.contains("contains")),
-    }
+        .expect("a sequence may be the beginning of an earlier defined sequence");
+}
+
+#[test]
+fn disallow_duplicate_seq() {
+    let e = parse_cfg(
+        "(defsrc a b)
+         (deflayer base a b)
+         (defvirtualkeys x a y b)
+         (defseq x (a b) y (a b))",
+    )
+    .expect_err("duplicate sequence should err");
+    assert!(e.msg.contains("same as an earlier"), "{}", e.msg);
 }
 
 #[test]
@@ -1567,6 +1574,52 @@ fn parse_cmd_log() {
         .expect("parses");
 }
 
+#[test]
+#[cfg(feature = "cmd")]
+fn parse_cmd_async() {
+    let source = r#"
+(defcfg danger-enable-cmd yes)
+(defsrc a)
+(deflayer base a)
+(defvirtualkeys ok a fail b two c)
+(defalias
+    1 (cmd-async (cmd date))
+    2 (cmd-async (var today) (timeout 2000) (cmd date "+%F"))
+    3 (cmd-async (on-success ok) (on-failure fail) (on-exit 2 two) (cmd (sh -c "exit 2")))
+    4 (clipboard-var-set today)
+    5 (var-output-keys today)
+)
+"#;
+    parse_cfg(source)
+        .map_err(|e| eprintln!("{:?}", miette::Error::from(e)))
+        .expect("parses");
+
+    for (alias, err) in [
+        ("(cmd-async (var x))", "cmd-async expects"),
+        ("(cmd-async date)", "cmd-async expects"),
+        (
+            "(cmd-async (timeout 0) (cmd date))",
+            "timeout must be 1-65535",
+        ),
+        (
+            "(cmd-async (on-success nope) (cmd date))",
+            "unknown virtual key name",
+        ),
+        (
+            "(cmd-async (on-exit x ok) (cmd date))",
+            "exit code must be a number",
+        ),
+        ("(var-output-keys)", "var-output-keys expects 1 parameter"),
+    ] {
+        let source = format!(
+            "(defcfg danger-enable-cmd yes)\n(defsrc a)\n(deflayer base a)\n\
+             (defvirtualkeys ok a)\n(defalias 1 {alias})"
+        );
+        let e = parse_cfg(&source).expect_err("should err");
+        assert!(e.msg.contains(err), "{alias}: {}", e.msg);
+    }
+}
+
 #[test]
 fn parse_defvar_concat() {
     let _lk = lock(&CFG_PARSE_LOCK);
@@ -2106,3 +2159,257 @@ fn parse_clipboard_actions() {
 ";
     parse_cfg(source).map(|_| ()).expect("success");
 }
+
+#[test]
+fn parse_action_expr_after_cfg() {
+    init_log();
+    let _lk = lock(&CFG_PARSE_LOCK);
+    let mut cfg = new_from_str(
+        "
+(defsrc a)
+(deflayer base @hi)
+(deflayer other b)
+(defalias hi (macro h i))
+(defvirtualkeys vk c)
+",
+        Default::default(),
+    )
+    .expect("parses");
+    let parser = &mut cfg.action_expr_parser;
+    assert!(matches!(
+        parser.parse("@hi").expect("alias parses"),
+        Action::Sequence { .. }
+    ));
+    assert_eq!(
+        parser.parse("a").expect("key parses"),
+        &Action::KeyCode(KeyCode::A)
+    );
+    assert!(matches!(
+        parser.parse("(layer-switch other)").expect("layer parses"),
+        Action::DefaultLayer(1)
+    ));
+    parser
+        .parse("(on-press tap-vkey vk)")
+        .expect("virtual key parses");
+    let first = parser.parse("(macro a b)").expect("macro parses");
+    let second = parser.parse("(macro a b)").expect("macro parses");
+    assert!(std::ptr::eq(first, second), "repeated expression is cached");
+    parser
+        .parse("(layer-switch nope)")
+        .map(|_| ())
+        .expect_err("unknown layer");
+    parser
+        .parse("a b")
+        .map(|_| ())
+        .expect_err("only one action");
+    parser.parse("").map(|_| ()).expect_err("no action");
+    parser
+        .parse("(cmd rm -rf /)")
+        .map(|_| ())
+        .expect_err("cmd disabled");
+}
+
+#[test]
+fn parse_action_expr_refuses_new_expressions_when_full() {
+    init_log();
+    let _lk = lock(&CFG_PARSE_LOCK);
+    let mut cfg = new_from_str("(defsrc a) (deflayer base a)", Default::default()).expect("parses");
+    let parser = &mut cfg.action_expr_parser;
+    for i in 0..MAX_PARSED_ACTIONS {
+        parser
+            .parse(&format!("(macro a {})", i + 1))
+            .expect("macro parses");
+    }
+    parser
+        .parse("(macro b)")
+        .map(|_| ())
+        .expect_err("new expression is refused");
+    parser
+        .parse("(macro a 1)")
+        .expect("parsed expression is still available");
+}
+
+#[test]
+fn parse_defdevice() {
+    init_log();
+    let _lk = lock(&CFG_PARSE_LOCK);
+    let mut s = ParserState::default();
+    let res = parse_cfg_raw_string(
+        "
+(defsrc a)
+(deflayer base b)
+(deflayer nav c)
+(deflayer other d)
+(defvirtualkeys vk e)
+(defdevice pad
+  (names \"My Macropad\")
+  (paths /dev/input/by-id/pad)
+  (defsrc kp1 kp2)
+  (deflayer base x _)
+  (deflayer nav _ y))
+",
+        &mut s,
+        &PathBuf::from("test"),
+        &mut FileContentProvider {
+            get_file_content_fn: &mut |_| unimplemented!(),
+        },
+        DEF_LOCAL_KEYS,
+        Err("env vars not implemented".into()),
+    )
+    .map_err(|e| {
+        eprintln!("{:?}", miette::Error::from(e));
+        ""
+    })
+    .unwrap();
+    assert_eq!(res.devices.len(), 1);
+    let pad = &res.devices[0];
+    assert_eq!(pad.device_names, vec!["My Macropad".to_owned()]);
+    assert_eq!(pad.device_paths, vec!["/dev/input/by-id/pad".to_owned()]);
+    // Device keys come after the virtual keys.
+    assert_eq!(pad.keys[&OsCode::KEY_KP1], 1);
+    assert_eq!(pad.keys[&OsCode::KEY_KP2], 2);
+    assert!(res.mapped_keys.contains(&OsCode::KEY_KP1));
+
+    let (klayers, _) = res.klayers.get();
+    let row = usize::from(FAKE_KEY_ROW);
+    let kc = |k| Action::KeyCode(k);
+    assert_eq!(klayers[0][row][1], kc(KeyCode::X));
+    assert_eq!(klayers[0][row][2], kc(KeyCode::Kp2));
+    assert_eq!(klayers[1][row][1], kc(KeyCode::X));
+    assert_eq!(klayers[1][row][2], kc(KeyCode::Y));
+    // Unlisted layers use the first listed layer.
+    assert_eq!(klayers[2][row][1], kc(KeyCode::X));
+    assert_eq!(klayers[2][row][2], kc(KeyCode::Kp2));
+    // The main keys are unchanged.
+    assert_eq!(
+        klayers[0][0][OsCode::KEY_A.as_u16() as usize],
+        kc(KeyCode::B)
+    );
+}
+
+#[test]
+fn parse_defdevice_errors() {
+    init_log();
+    let _lk = lock(&CFG_PARSE_LOCK);
+    for (cfg, reason) in [
+        (
+            "(defdevice pad (defsrc kp1) (deflayer base a))",
+            "no names or paths",
+        ),
+        ("(defdevice pad (names x) (deflayer base a))", "no defsrc"),
+        ("(defdevice pad (names x) (defsrc kp1))", "no deflayer"),
+        ("(defdevice pad (names x))", "no defsrc or device actions"),
+        (
+            "(defdevice pad (names x) (on-connect a b))",
+            "more than one action",
+        ),
+        (
+            "(defdevice pad (names x) (defsrc kp1) (deflayer nope a))",
+            "unknown layer",
+        ),
+        (
+            "(defdevice pad (names x) (defsrc kp1) (deflayer base a b))",
+            "action count",
+        ),
+        (
+            "(defdevice pad (names x) (defsrc kp1) (deflayer base a))
+             (defdevice pad (names y) (defsrc kp1) (deflayer base a))",
+            "duplicate name",
+        ),
+    ] {
+        let mut s = ParserState::default();
+        let res = parse_cfg_raw_string(
+            &format!("(defsrc a) (deflayer base b) {cfg}"),
+            &mut s,
+            &PathBuf::from("test"),
+            &mut FileContentProvider {
+                get_file_content_fn: &mut |_| unimplemented!(),
+            },
+            DEF_LOCAL_KEYS,
+            Err("env vars not implemented".into()),
+        );
+        assert!(res.is_err(), "expected error: {reason}");
+    }
+}
+
+#[test]
+fn parse_defoverrides_options() {
+    let source = r#"
+(defsrc a b)
+(deflayer base a b)
+(deflayer other a b)
+(defvirtualkeys vk nop0)
+(defoverrides
+  (lsft a (layers other) (negative-mods lctl ralt) (disabled-by-vkey vk)) (b)
+  (lctl a) (macro h i)
+  (lctl b (layers base)) (layer-while-held other)
+)
+"#;
+    let cfg = parse_cfg(source)
+        .map_err(|e| eprintln!("{:?}", miette::Error::from(e)))
+        .expect("parses");
+    assert_eq!(cfg.overrides.action_column_count(), 2);
+
+    for (overrides, err) in [
+        ("(a (layers nope)) (b)", "Not a known layer name"),
+        (
+            "(lsft a (negative-mods lsft)) (b)",
+            "also an input modifier",
+        ),
+        ("(a (negative-mods b)) (c)", "must be modifier keys"),
+        (
+            "(a (disabled-by-vkey nope)) (b)",
+            "unknown virtual key name",
+        ),
+        ("(a (bogus)) (b)", "must be one of"),
+        ("(a) _", "Transparent action is forbidden"),
+        ("(a) (lsft nope)", "Unknown output key name"),
+        ("(a) (nope lsft)", "Unknown output key name"),
+    ] {
+        let source = format!(
+            "(defsrc a)\n(deflayer base a)\n(defvirtualkeys vk a)\n(defoverrides {overrides})"
+        );
+        let e = parse_cfg(&source).expect_err("should err");
+        assert!(e.msg.contains(err), "{overrides}: {}", e.msg);
+    }
+}
+
+#[test]
+fn parse_defseq_actions() {
+    let source = r#"
+(defsrc a b)
+(deflayer base a b)
+(defvirtualkeys vk a)
+(defalias hi (macro h i))
+(defvar smiley 🙂)
+(defseq
+  vk (a b)
+  @hi (b a)
+  (unicode $smiley) (a a)
+  C-c (b b)
+)
+"#;
+    let cfg = parse_cfg(source)
+        .map_err(|e| eprintln!("{:?}", miette::Error::from(e)))
+        .expect("parses");
+    let mut names = cfg
+        .sequence_action_names
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    names.sort();
+    // Variables are replaced by their value in the names.
+    assert_eq!(names, ["(unicode 🙂)", "@hi", "C-c"]);
+    // One virtual key and three sequence actions.
+    assert_eq!(cfg.first_free_fake_key_column, 4);
+
+    for (seq, err) in [
+        ("nope (a b)", "not a valid action: nope"),
+        ("_ (a b)", "Transparent action is forbidden"),
+        ("(bogus-action) (a b)", "Unknown action type"),
+    ] {
+        let source = format!("(defsrc a b)\n(deflayer base a b)\n(defseq {seq})");
+        let e = parse_cfg(&source).expect_err("should err");
+        assert!(e.msg.contains(err), "{seq}: {}", e.msg);
+    }
+}
@@ -46,6 +46,15 @@ mod linux {
             Some(DeviceDetectMode::Any)
         );
 
+        let source = r#"(defsrc mtsk mfwd2 mbck2) (deflayer base 1 mlft mrgt)"#;
+        let icfg = parse_cfg(source)
+            .map_err(|e| log::info!("{:?}", miette::Error::from(e)))
+            .expect("no error");
+        assert_eq!(
+            icfg.options.linux_opts.linux_device_detect_mode,
+            Some(DeviceDetectMode::Any)
+        );
+
         let source = r#"(defsrc a) (deflayer base b)"#;
         let icfg = parse_cfg(source)
             .map_err(|e| log::info!("{:?}", miette::Error::from(e)))
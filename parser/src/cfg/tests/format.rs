@@ -0,0 +1,95 @@
+use crate::cfg::format::format_cfg;
+
+#[test]
+fn format_aligns_layers_to_defsrc() {
+    let cfg = "
+(defcfg)   ;; options
+
+
+
+(defsrc
+  grv  1  2
+  caps a  s
+  lctl   spc   ralt
+)
+(deflayer base @grl 1 2 @cap a s lctl spc (layer-switch base))
+(deflayer (other) _
+  _ _ _ _ _ _ _ _)
+";
+    let expected = "(defcfg) ;; options
+
+(defsrc
+  grv  1     2
+  caps a     s
+  lctl   spc   ralt
+)
+(deflayer base
+  @grl 1     2
+  @cap a     s
+  lctl   spc   (layer-switch base)
+)
+(deflayer (other)
+  _    _     _
+  _    _     _
+  _      _     _
+)
+";
+    assert_eq!(format_cfg(cfg).unwrap(), expected);
+    assert_eq!(format_cfg(expected).unwrap(), expected);
+}
+
+#[test]
+fn format_keeps_comments_and_strings() {
+    let cfg = "\r
+  ;; leading comment   \r
+(defsrc a b)\r
+(deflayer base\r
+  a ;; comment within a layer\r
+  b)\r
+(deflayer other x  \r
+  y)  \r
+#| block   \r
+comment |#\r
+(defalias m (macro r#\"multi   \r
+line\"#))   \r
+";
+    let expected = ";; leading comment\r
+(defsrc\r
+  a b\r
+)\r
+(deflayer base\r
+  a ;; comment within a layer\r
+  b)\r
+(deflayer other\r
+  x y\r
+)\r
+#| block\r
+comment |#\r
+(defalias m (macro r#\"multi   \r
+line\"#))\r
+";
+    assert_eq!(format_cfg(cfg).unwrap(), expected);
+    assert_eq!(format_cfg(expected).unwrap(), expected);
+}
+
+#[test]
+fn format_skips_layers_that_do_not_match_defsrc() {
+    let cfg = "(defsrc a b c)
+(deflayer base a b)
+(deflayer other (tap-hold 200 200
+  a b) b c)
+";
+    let expected = "(defsrc
+  a b c
+)
+(deflayer base a b)
+(deflayer other (tap-hold 200 200
+  a b) b c)
+";
+    assert_eq!(format_cfg(cfg).unwrap(), expected);
+}
+
+#[test]
+fn format_reports_syntax_errors() {
+    assert!(format_cfg("(defsrc a").is_err());
+}
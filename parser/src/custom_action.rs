@@ -14,6 +14,11 @@ pub enum CustomAction {
     Cmd(Vec<String>),
     CmdLog(LogLevel, LogLevel, Vec<String>),
     CmdOutputKeys(Vec<String>),
+    CmdAsync(Box<CmdAsync>),
+    /// Set the clipboard to the content of a cmd variable.
+    ClipboardVarSet(String),
+    /// Type the content of a cmd variable, which is parsed like the output of `cmd-output-keys`.
+    VarOutputKeys(String),
     PushMessage(Vec<SimpleSExpr>),
     Unicode(char),
     Mouse(Btn),
@@ -75,6 +80,12 @@ pub enum CustomAction {
     DynamicMacroRecord(u16),
     DynamicMacroRecordStop(u16),
     DynamicMacroPlay(u16),
+    /// Play a dynamic macro `count` times at `speed` percent of the recorded speed.
+    DynamicMacroPlayRepeat {
+        macro_id: u16,
+        count: u16,
+        speed: u16,
+    },
     SendArbitraryCode(u16),
     CapsWord(CapsWordCfg),
     SetMouse {
@@ -98,6 +109,42 @@ pub enum CustomAction {
     ClipboardSaveSwap(u16, u16),
 }
 
+/// A command that runs in the background without blocking kanata.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CmdAsync {
+    pub cmd: Vec<String>,
+    /// Name of the cmd variable that is set to the standard output of the command.
+    pub var: Option<String>,
+    /// Time in ms after which the command is killed.
+    pub timeout: Option<u16>,
+    /// Names of the virtual keys that are tapped when the command exits with the code.
+    ///
+    /// Names are resolved when the command finishes, since the configuration may have been
+    /// reloaded while it was running.
+    pub on_exit: Vec<(i32, String)>,
+    /// Virtual key that is tapped when the command exits with code 0.
+    pub on_success: Option<String>,
+    /// Virtual key that is tapped when the command exits with another code, cannot be started or
+    /// times out.
+    pub on_failure: Option<String>,
+}
+
+impl CmdAsync {
+    /// Returns the virtual key to tap for the exit code, which is `None` if the command did not
+    /// exit by itself.
+    pub fn vkey_for_exit_code(&self, code: Option<i32>) -> Option<&str> {
+        if let Some(code) = code {
+            if let Some((_, vkey)) = self.on_exit.iter().find(|(c, _)| *c == code) {
+                return Some(vkey);
+            }
+        }
+        match code {
+            Some(0) => self.on_success.as_deref(),
+            _ => self.on_failure.as_deref(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Btn {
     Left,
@@ -161,6 +161,58 @@ fn add_default_str_osc_mappings(mapping: &mut HashMap<String, OsCode>) {
     }
 }
 
+/// Returns a short name for the key, which is the name used in configurations for common keys.
+pub fn key_name(osc: OsCode) -> String {
+    let name = match osc {
+        OsCode::KEY_LEFTSHIFT => "lsft",
+        OsCode::KEY_RIGHTSHIFT => "rsft",
+        OsCode::KEY_LEFTCTRL => "lctl",
+        OsCode::KEY_RIGHTCTRL => "rctl",
+        OsCode::KEY_LEFTALT => "lalt",
+        OsCode::KEY_RIGHTALT => "ralt",
+        OsCode::KEY_LEFTMETA => "lmet",
+        OsCode::KEY_RIGHTMETA => "rmet",
+        OsCode::KEY_SPACE => "spc",
+        OsCode::KEY_ENTER => "ret",
+        OsCode::KEY_BACKSPACE => "bspc",
+        OsCode::KEY_CAPSLOCK => "caps",
+        OsCode::KEY_DELETE => "del",
+        OsCode::KEY_INSERT => "ins",
+        OsCode::KEY_PAGEUP => "pgup",
+        OsCode::KEY_PAGEDOWN => "pgdn",
+        OsCode::KEY_RIGHT => "rght",
+        OsCode::KEY_COMPOSE => "menu",
+        OsCode::KEY_GRAVE => "grv",
+        OsCode::KEY_MINUS => "-",
+        OsCode::KEY_EQUAL => "=",
+        OsCode::KEY_LEFTBRACE => "[",
+        OsCode::KEY_RIGHTBRACE => "]",
+        OsCode::KEY_BACKSLASH => "\\",
+        OsCode::KEY_SEMICOLON => ";",
+        OsCode::KEY_APOSTROPHE => "'",
+        OsCode::KEY_COMMA => ",",
+        OsCode::KEY_DOT => ".",
+        OsCode::KEY_SLASH => "/",
+        OsCode::KEY_VOLUMEUP => "volu",
+        OsCode::KEY_VOLUMEDOWN => "vold",
+        OsCode::KEY_MUTE => "mute",
+        OsCode::BTN_LEFT => "mlft",
+        OsCode::BTN_RIGHT => "mrgt",
+        OsCode::BTN_MIDDLE => "mmid",
+        OsCode::BTN_SIDE => "mbck",
+        OsCode::BTN_EXTRA => "mfwd",
+        OsCode::BTN_FORWARD => "mfwd2",
+        OsCode::BTN_BACK => "mbck2",
+        OsCode::BTN_TASK => "mtsk",
+        OsCode::MouseWheelUp => "mwu",
+        OsCode::MouseWheelDown => "mwd",
+        OsCode::MouseWheelLeft => "mwl",
+        OsCode::MouseWheelRight => "mwr",
+        _ => return osc.to_string().to_lowercase(),
+    };
+    name.into()
+}
+
 /// Convert a `&str` to an `OsCode`.
 ///
 /// kmonad's str to key mapping is found here as a reference:
@@ -336,6 +388,13 @@ pub fn str_to_oscode(s: &str) -> Option<OsCode> {
         "mmid" | "mousemid" | "🖰3" => OsCode::BTN_MIDDLE,
         "mbck" | "mousebackward" | "🖰4" => OsCode::BTN_SIDE,
         "mfwd" | "mouseforward" | "🖰5" => OsCode::BTN_EXTRA,
+        // NOTE: these are linux-only; trackballs and some mice send them for their extra buttons.
+        #[cfg(any(target_os = "linux", target_os = "unknown"))]
+        "mfwd2" | "mouseforward2" => OsCode::BTN_FORWARD,
+        #[cfg(any(target_os = "linux", target_os = "unknown"))]
+        "mbck2" | "mousebackward2" => OsCode::BTN_BACK,
+        #[cfg(any(target_os = "linux", target_os = "unknown"))]
+        "mtsk" | "mousetask" => OsCode::BTN_TASK,
         "mwu" | "mousewheelup" => OsCode::MouseWheelUp,
         "mwd" | "mousewheeldown" => OsCode::MouseWheelDown,
         "mwl" | "mousewheelleft" => OsCode::MouseWheelLeft,
@@ -15,6 +15,8 @@ pub enum GetOrDescendentExistsResult<T> {
     NotInTrie,
     InTrie,
     HasValue(T),
+    /// The key has a value but is also the prefix of longer keys.
+    HasValueWithDescendants(T),
 }
 
 use GetOrDescendentExistsResult::*;
@@ -37,12 +39,6 @@ impl<T> Trie<T> {
         }
     }
 
-    pub fn ancestor_exists(&self, key: impl AsRef<[u16]>) -> bool {
-        self.inner
-            .get_longest_common_prefix(cast_slice(key.as_ref()))
-            .is_some()
-    }
-
     pub fn descendant_exists(&self, key: impl AsRef<[u16]>) -> bool {
         // Length of the [u8] interpretation of the [u16] key is doubled.
         self.inner
@@ -63,7 +59,11 @@ impl<T> Trie<T> {
             None => NotInTrie,
             Some(descendant) => {
                 if descendant.0.len() == key_len(key.as_ref()) {
-                    HasValue(descendant.1.clone())
+                    if descendants.next().is_some() {
+                        HasValueWithDescendants(descendant.1.clone())
+                    } else {
+                        HasValue(descendant.1.clone())
+                    }
                 } else {
                     InTrie
                 }
@@ -71,6 +71,27 @@ impl<T> Trie<T> {
         }
     }
 
+    pub fn get(&self, key: impl AsRef<[u16]>) -> Option<&T> {
+        self.inner.get(cast_slice(key.as_ref()))
+    }
+
+    /// Iterates over the keys that begin with `prefix`, including `prefix` itself, with their
+    /// values.
+    pub fn iter_prefix<'a>(
+        &'a self,
+        prefix: &'a [TrieKeyElement],
+    ) -> impl Iterator<Item = (Vec<TrieKeyElement>, &'a T)> + 'a {
+        self.inner
+            .iter_prefix(cast_slice(prefix))
+            .map(|(key, val)| {
+                let key = key
+                    .chunks_exact(2)
+                    .map(|bytes| TrieKeyElement::from_ne_bytes([bytes[0], bytes[1]]))
+                    .collect();
+                (key, val)
+            })
+    }
+
     pub fn is_empty(&self) -> bool {
         self.inner.is_empty()
     }
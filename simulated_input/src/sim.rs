@@ -131,6 +131,8 @@ fn cli_init_fsim() -> Result<(ValidatedArgs, Vec<PathBuf>, Option<String>)> {
             paths: cfg_paths,
             #[cfg(feature = "tcp_server")]
             tcp_server_address: None::<SocketAddrWrapper>,
+            #[cfg(all(feature = "tcp_server", unix))]
+            ipc_socket_path: None,
             #[cfg(target_os = "linux")]
             symlink_path: None,
             nodelay: true,
@@ -204,115 +206,260 @@ fn main_impl() -> Result<()> {
         }
     }
 
+    let mut failed_sim_count = 0;
     for config_sim_file in &sim_paths {
         let mut k = Kanata::new(&args)?;
         log::info!("Evaluating simulation file = {:?}", config_sim_file);
         let s = std::fs::read_to_string(config_sim_file)?;
-        for l in s.lines() {
-            for pair in l.split_whitespace() {
-                match pair.split_once(':') {
-                    Some((kind, val)) => match kind {
-                        "tick" | "🕐" | "t" => {
+        let expectations = run_simulation(&mut k, &s)?;
+        #[cfg(all(
+            not(feature = "simulated_input"),
+            not(feature = "passthru_ahk"),
+            feature = "simulated_output"
+        ))]
+        println!("{}", k.kbd_out.outputs.events.join("\n"));
+        #[cfg(all(
+            not(feature = "simulated_input"),
+            not(feature = "passthru_ahk"),
+            feature = "simulated_output"
+        ))]
+        k.kbd_out.log.end(config_sim_file, _sim_appendix.clone());
+
+        if expectations.failures.is_empty() {
+            if expectations.count > 0 {
+                log::info!(
+                    "{:?}: all {} expectations passed",
+                    config_sim_file,
+                    expectations.count
+                );
+            }
+        } else {
+            failed_sim_count += 1;
+            for failure in expectations.failures.iter() {
+                log::error!("{:?}: {failure}", config_sim_file);
+            }
+        }
+    }
+
+    if failed_sim_count > 0 {
+        bail!(
+            "{failed_sim_count} of {} simulation files failed their expectations",
+            sim_paths.len()
+        );
+    }
+    Ok(())
+}
+
+/// Runs the items of a simulation file and checks its `expect-*` items.
+fn run_simulation(k: &mut Kanata, s: &str) -> Result<Expectations> {
+    let mut expectations = Expectations::default();
+    for (line_idx, l) in s.lines().enumerate() {
+        let line = line_idx + 1;
+        for pair in l.split_whitespace() {
+            match pair.split_once(':') {
+                Some((kind, val)) => match kind {
+                    "tick" | "🕐" | "t" => {
+                        let tick = str::parse::<u128>(val)?;
+                        kbd_out_log(&mut k.kbd_out, LogFmtT::InTick, None, Some(tick));
+                        k.tick_ms(tick, &None)?;
+                    }
+                    "expect-out" => expectations.check_outputs(k, line, val)?,
+                    "expect-layer" => expectations.check_layer(k, line, val),
+                    "expect-ticks" => expectations.check_ticks(k, line, val)?,
+                    "press" | "↓" | "d" | "down" => {
+                        let key_code =
+                            str_to_oscode(val).ok_or_else(|| anyhow!("unknown key: {val}"))?;
+                        kbd_out_log(&mut k.kbd_out, LogFmtT::InKeyDown, Some(key_code), None);
+                        expectations.input(k);
+                        k.handle_input_event(&KeyEvent::new(key_code, KeyValue::Press))?;
+                    }
+                    "release" | "↑" | "u" | "up" => {
+                        let key_code =
+                            str_to_oscode(val).ok_or_else(|| anyhow!("unknown key: {val}"))?;
+                        kbd_out_log(&mut k.kbd_out, LogFmtT::InKeyUp, Some(key_code), None);
+                        expectations.input(k);
+                        k.handle_input_event(&KeyEvent::new(key_code, KeyValue::Release))?;
+                    }
+                    "repeat" | "⟳" | "r" => {
+                        let key_code =
+                            str_to_oscode(val).ok_or_else(|| anyhow!("unknown key: {val}"))?;
+                        kbd_out_log(&mut k.kbd_out, LogFmtT::InKeyRep, Some(key_code), None);
+                        expectations.input(k);
+                        k.handle_input_event(&KeyEvent::new(key_code, KeyValue::Repeat))?;
+                    }
+                    _ => bail!("invalid pair prefix: {kind}"),
+                },
+                None => {
+                    let (kind, val) = split_at_1(pair);
+                    match kind {
+                        //allow skipping : separator for unique non-key symbols
+                        "🕐" => {
                             let tick = str::parse::<u128>(val)?;
                             kbd_out_log(&mut k.kbd_out, LogFmtT::InTick, None, Some(tick));
                             k.tick_ms(tick, &None)?;
                         }
-                        "press" | "↓" | "d" | "down" => {
+                        "↓" => {
                             let key_code =
                                 str_to_oscode(val).ok_or_else(|| anyhow!("unknown key: {val}"))?;
                             kbd_out_log(&mut k.kbd_out, LogFmtT::InKeyDown, Some(key_code), None);
-                            k.handle_input_event(&KeyEvent {
-                                code: key_code,
-                                value: KeyValue::Press,
-                            })?;
+                            expectations.input(k);
+                            expectations.input(k);
+                            k.handle_input_event(&KeyEvent::new(key_code, KeyValue::Press))?;
                         }
-                        "release" | "↑" | "u" | "up" => {
+                        "↑" => {
                             let key_code =
                                 str_to_oscode(val).ok_or_else(|| anyhow!("unknown key: {val}"))?;
                             kbd_out_log(&mut k.kbd_out, LogFmtT::InKeyUp, Some(key_code), None);
-                            k.handle_input_event(&KeyEvent {
-                                code: key_code,
-                                value: KeyValue::Release,
-                            })?;
+                            expectations.input(k);
+                            expectations.input(k);
+                            k.handle_input_event(&KeyEvent::new(key_code, KeyValue::Release))?;
                         }
-                        "repeat" | "⟳" | "r" => {
+                        "⟳" => {
                             let key_code =
                                 str_to_oscode(val).ok_or_else(|| anyhow!("unknown key: {val}"))?;
                             kbd_out_log(&mut k.kbd_out, LogFmtT::InKeyRep, Some(key_code), None);
-                            k.handle_input_event(&KeyEvent {
-                                code: key_code,
-                                value: KeyValue::Repeat,
-                            })?;
-                        }
-                        _ => bail!("invalid pair prefix: {kind}"),
-                    },
-                    None => {
-                        let (kind, val) = split_at_1(pair);
-                        match kind {
-                            //allow skipping : separator for unique non-key symbols
-                            "🕐" => {
-                                let tick = str::parse::<u128>(val)?;
-                                kbd_out_log(&mut k.kbd_out, LogFmtT::InTick, None, Some(tick));
-                                k.tick_ms(tick, &None)?;
-                            }
-                            "↓" => {
-                                let key_code = str_to_oscode(val)
-                                    .ok_or_else(|| anyhow!("unknown key: {val}"))?;
-                                kbd_out_log(
-                                    &mut k.kbd_out,
-                                    LogFmtT::InKeyDown,
-                                    Some(key_code),
-                                    None,
-                                );
-                                k.handle_input_event(&KeyEvent {
-                                    code: key_code,
-                                    value: KeyValue::Press,
-                                })?;
-                            }
-                            "↑" => {
-                                let key_code = str_to_oscode(val)
-                                    .ok_or_else(|| anyhow!("unknown key: {val}"))?;
-                                kbd_out_log(&mut k.kbd_out, LogFmtT::InKeyUp, Some(key_code), None);
-                                k.handle_input_event(&KeyEvent {
-                                    code: key_code,
-                                    value: KeyValue::Release,
-                                })?;
-                            }
-                            "⟳" => {
-                                let key_code = str_to_oscode(val)
-                                    .ok_or_else(|| anyhow!("unknown key: {val}"))?;
-                                kbd_out_log(
-                                    &mut k.kbd_out,
-                                    LogFmtT::InKeyRep,
-                                    Some(key_code),
-                                    None,
-                                );
-                                k.handle_input_event(&KeyEvent {
-                                    code: key_code,
-                                    value: KeyValue::Repeat,
-                                })?;
-                            }
-                            _ => bail!("invalid pair: {l}"),
+                            expectations.input(k);
+                            expectations.input(k);
+                            k.handle_input_event(&KeyEvent::new(key_code, KeyValue::Repeat))?;
                         }
+                        _ => bail!("invalid pair: {l}"),
                     }
                 }
             }
         }
+    }
+    Ok(expectations)
+}
+
+/// Tracks the `expect-*` items of a simulation file and the failed ones.
+#[derive(Default)]
+struct Expectations {
+    /// Number of expectations that were checked.
+    count: usize,
+    failures: Vec<String>,
+    /// Number of output events that were already checked by a previous `expect-out`.
+    checked_outputs: usize,
+    /// Number of output events and ticks since the last output event when the most recent
+    /// input event was handled.
+    last_input: (usize, u64),
+}
+
+impl Expectations {
+    fn check(&mut self, line: usize, passed: bool, failure: impl FnOnce() -> String) {
+        self.count += 1;
+        if !passed {
+            self.failures.push(format!("line {line}: {}", failure()));
+        }
+    }
+
+    /// Checks the key outputs since the previous `expect-out`, e.g. `expect-out:↓a,↑a`.
+    /// Outputs other than key presses and releases, such as delays or mouse events, are ignored.
+    fn check_outputs(&mut self, _k: &Kanata, line: usize, val: &str) -> Result<()> {
+        let mut expected = vec![];
+        for item in val.split(',').filter(|item| !item.is_empty()) {
+            let (kind, key) = item.split_once(':').unwrap_or_else(|| split_at_1(item));
+            let value = match kind {
+                "↓" | "d" | "down" | "press" => KeyValue::Press,
+                "↑" | "u" | "up" | "release" => KeyValue::Release,
+                _ => bail!("line {line}: invalid expected output: {item}"),
+            };
+            let key_code = str_to_oscode(key).ok_or_else(|| anyhow!("unknown key: {key}"))?;
+            expected.push(format!("out:{}", KeyEvent::new(key_code, value)));
+        }
         #[cfg(all(
             not(feature = "simulated_input"),
             not(feature = "passthru_ahk"),
             feature = "simulated_output"
         ))]
-        println!("{}", k.kbd_out.outputs.events.join("\n"));
+        {
+            let events = &_k.kbd_out.outputs.events;
+            let actual: Vec<String> = events[self.checked_outputs..]
+                .iter()
+                .filter(|event| event.starts_with("out:"))
+                .cloned()
+                .collect();
+            self.checked_outputs = events.len();
+            self.check(line, actual == expected, || {
+                format!("expected outputs {expected:?} but got {actual:?}")
+            });
+            Ok(())
+        }
+        #[cfg(not(all(
+            not(feature = "simulated_input"),
+            not(feature = "passthru_ahk"),
+            feature = "simulated_output"
+        )))]
+        {
+            let _ = expected;
+            bail!("expect-out requires the program to be compiled with simulated output")
+        }
+    }
+
+    /// Records the outputs before an input event, to measure the time from the input event to
+    /// the outputs after it.
+    fn input(&mut self, _k: &Kanata) {
         #[cfg(all(
             not(feature = "simulated_input"),
             not(feature = "passthru_ahk"),
             feature = "simulated_output"
         ))]
-        k.kbd_out.log.end(config_sim_file, _sim_appendix.clone());
+        {
+            let outputs = &_k.kbd_out.outputs;
+            self.last_input = (outputs.events.len(), outputs.ticks());
+        }
     }
 
-    Ok(())
+    /// Checks the time in ms from the most recent input event to the first key output after
+    /// it, e.g. `expect-ticks:200` for a hold action that is output 200 ms after its key was
+    /// pressed.
+    fn check_ticks(&mut self, _k: &Kanata, line: usize, val: &str) -> Result<()> {
+        let expected = str::parse::<u64>(val)?;
+        #[cfg(all(
+            not(feature = "simulated_input"),
+            not(feature = "passthru_ahk"),
+            feature = "simulated_output"
+        ))]
+        {
+            let (input_idx, ticks_at_input) = self.last_input;
+            let mut ticks: u64 = 0;
+            let mut output_ticks = None;
+            for event in _k.kbd_out.outputs.events[input_idx..].iter() {
+                if let Some(t) = event.strip_prefix("t:").and_then(|t| t.strip_suffix("ms")) {
+                    ticks += str::parse::<u64>(t)?;
+                } else if event.starts_with("out:") {
+                    // The first gap also counts the ticks before the input event.
+                    output_ticks = Some(ticks.saturating_sub(ticks_at_input));
+                    break;
+                }
+            }
+            self.check(line, output_ticks == Some(expected), || match output_ticks {
+                Some(ticks) => format!(
+                    "expected the first output {expected} ms after the last input but got {ticks} ms"
+                ),
+                None => format!(
+                    "expected the first output {expected} ms after the last input but got no output"
+                ),
+            });
+            Ok(())
+        }
+        #[cfg(not(all(
+            not(feature = "simulated_input"),
+            not(feature = "passthru_ahk"),
+            feature = "simulated_output"
+        )))]
+        {
+            let _ = expected;
+            bail!("expect-ticks requires the program to be compiled with simulated output")
+        }
+    }
+
+    /// Checks the name of the active layer, e.g. `expect-layer:nav`.
+    fn check_layer(&mut self, k: &Kanata, line: usize, val: &str) {
+        let layer = &k.layer_info[k.layout.b().current_layer()].name;
+        self.check(line, layer == val, || {
+            format!("expected active layer {val} but got {layer}")
+        });
+    }
 }
 
 fn main() -> Result<()> {
@@ -322,3 +469,61 @@ fn main() -> Result<()> {
     }
     ret
 }
+
+#[cfg(all(
+    test,
+    not(feature = "simulated_input"),
+    not(feature = "passthru_ahk"),
+    feature = "simulated_output"
+))]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Kanata has global state, so only one instance can be simulated at a time.
+    static SIM_LOCK: Mutex<()> = Mutex::new(());
+
+    const CFG: &str = "
+(defsrc a b c)
+(deflayer base a (layer-while-held nav) (tap-hold 100 100 c d))
+(deflayer nav c _ _)
+";
+
+    #[test]
+    fn expectations_pass() {
+        let _lk = SIM_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut k = Kanata::new_from_str(CFG, Default::default()).unwrap();
+        let expectations = run_simulation(
+            &mut k,
+            "d:a t:10 u:a t:10 expect-out:↓a,↑a expect-ticks:0\n\
+             d:c t:50 expect-out: t:100 expect-out:↓d expect-ticks:100\n\
+             d:b t:10 expect-layer:nav d:a t:10 expect-out:d:c u:b t:10 expect-layer:base",
+        )
+        .unwrap();
+        assert_eq!(expectations.count, 8);
+        assert_eq!(expectations.failures, Vec::<String>::new());
+    }
+
+    #[test]
+    fn expectations_fail() {
+        let _lk = SIM_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut k = Kanata::new_from_str(CFG, Default::default()).unwrap();
+        let expectations = run_simulation(
+            &mut k,
+            "d:a t:10\nexpect-out:↓b expect-layer:nav\nt:10 expect-out:↑b expect-ticks:5\n\
+             u:a expect-ticks:0",
+        )
+        .unwrap();
+        assert_eq!(expectations.count, 5);
+        assert_eq!(
+            expectations.failures,
+            [
+                r#"line 2: expected outputs ["out:↓B"] but got ["out:↓A"]"#,
+                "line 2: expected active layer nav but got base",
+                r#"line 3: expected outputs ["out:↑B"] but got []"#,
+                "line 3: expected the first output 5 ms after the last input but got 0 ms",
+                "line 4: expected the first output 0 ms after the last input but got no output",
+            ]
+        );
+    }
+}
@@ -52,6 +52,8 @@ fn cli_init(cfg_path: &str) -> Result<ValidatedArgs> {
         paths: vec![cfg_file],
         #[cfg(feature = "tcp_server")]
         tcp_server_address: None, //todo: any need in a dll?
+        #[cfg(all(feature = "tcp_server", unix))]
+        ipc_socket_path: None,
         nodelay: true,
     })
 }
@@ -0,0 +1,94 @@
+//! Output of the configuration as the parser understands it, after includes, platform and
+//! environment conditionals, templates and variables have been expanded.
+//!
+//! Named items such as aliases are written as objects keyed by name, so that the output of two
+//! configurations can be compared with a text diff. Actions are written as nested arrays of
+//! strings, e.g. `(tap-hold 200 200 a lctl)` becomes `["tap-hold","200","200","a","lctl"]`.
+//! Alias references such as `@h` are replaced by the action of the alias.
+
+use kanata_parser::cfg::{Cfg, ResolvedCfg, ResolvedExpr};
+use rustc_hash::FxHashMap as HashMap;
+use serde_json::{json, Map, Value};
+
+/// Output formats of [`dump_cfg`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DumpFormat {
+    Json,
+}
+
+/// Write the resolved configuration in the given format.
+pub fn dump_cfg(cfg: &Cfg, format: DumpFormat) -> String {
+    match format {
+        DumpFormat::Json => {
+            let mut json = serde_json::to_string_pretty(&cfg_json(&cfg.resolved))
+                .expect("a JSON value can be serialized");
+            json.push('\n');
+            json
+        }
+    }
+}
+
+fn cfg_json(cfg: &ResolvedCfg) -> Value {
+    let aliases = Aliases(
+        cfg.aliases
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+            .collect(),
+    );
+    json!({
+        "defcfg": aliases.named(&cfg.defcfg),
+        "layers": cfg.layers.iter().map(|layer| json!({
+            "name": layer.name,
+            "actions": aliases.named(&layer.keys),
+        })).collect::<Vec<_>>(),
+        "aliases": aliases.named(&cfg.aliases),
+        "virtual_keys": aliases.named(&cfg.virtual_keys),
+        "chord_groups": cfg.chord_groups.iter().map(|group| json!({
+            "name": group.name,
+            "timeout": aliases.expr(&group.timeout),
+            "chords": group.chords.iter().map(|(keys, action)| json!({
+                "keys": aliases.expr(keys),
+                "action": aliases.expr(action),
+            })).collect::<Vec<_>>(),
+        })).collect::<Vec<_>>(),
+        "chords_v2": cfg.chords_v2.iter().map(|chord| json!({
+            "keys": aliases.expr(&chord.keys),
+            "action": aliases.expr(&chord.action),
+            "timeout": aliases.expr(&chord.timeout),
+            "release_behaviour": aliases.expr(&chord.release_behaviour),
+            "disabled_layers": aliases.expr(&chord.disabled_layers),
+        })).collect::<Vec<_>>(),
+        "sequences": cfg.sequences.iter().map(|(target, keys)| json!({
+            "target": aliases.expr(target),
+            "keys": aliases.expr(keys),
+        })).collect::<Vec<_>>(),
+        "overrides": cfg.overrides.iter().map(|(input, output)| json!({
+            "input": aliases.expr(input),
+            "output": aliases.expr(output),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// The aliases of the configuration by name, used to replace alias references.
+struct Aliases<'a>(HashMap<&'a str, &'a ResolvedExpr>);
+
+impl Aliases<'_> {
+    fn named(&self, items: &[(String, ResolvedExpr)]) -> Value {
+        Value::Object(
+            items
+                .iter()
+                .map(|(name, value)| (name.clone(), self.expr(value)))
+                .collect::<Map<_, _>>(),
+        )
+    }
+
+    fn expr(&self, e: &ResolvedExpr) -> Value {
+        match e {
+            ResolvedExpr::Atom(a) => match a.strip_prefix('@').and_then(|name| self.0.get(name)) {
+                Some(action) => self.expr(action),
+                None => Value::String(a.clone()),
+            },
+            ResolvedExpr::List(l) => Value::Array(l.iter().map(|e| self.expr(e)).collect()),
+        }
+    }
+}
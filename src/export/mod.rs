@@ -0,0 +1,413 @@
+//! Conversion of the layers of a configuration into keymaps of programmable keyboard firmware.
+//!
+//! Supported are QMK `keymap.json` files, as used by `qmk json2c` and the QMK configurator, and
+//! ZMK devicetree keymaps. The keys of `defsrc` are exported in the order of `defsrc`, so the
+//! layout of the keyboard must list the same keys in the same order. Actions and configuration
+//! items without a firmware equivalent are reported as warnings; their keys do nothing.
+
+use kanata_keyberon::action::Action;
+use kanata_parser::cfg::Cfg;
+use kanata_parser::custom_action::{Btn, CustomAction};
+use kanata_parser::keys::{key_name, OsCode};
+
+use crate::layer_diagram::{custom_action_names, LayoutAction};
+
+use std::collections::BTreeSet;
+
+mod qmk;
+mod zmk;
+
+/// The tapping term of QMK and ZMK if it is not configured.
+const DEFAULT_TAPPING_TERM: u16 = 200;
+
+/// Output formats of [`export`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// A QMK keymap.json.
+    QmkJson,
+    /// A ZMK .keymap devicetree file.
+    Zmk,
+}
+
+/// An exported keymap.
+#[derive(Debug)]
+pub struct Export {
+    /// The text of the keymap file.
+    pub keymap: String,
+    /// Actions and configuration items that could not be exported or only approximately.
+    pub warnings: Vec<String>,
+}
+
+/// Export every layer of the configuration in the given format.
+pub fn export(cfg: &Cfg, format: ExportFormat) -> Export {
+    let mut keymap = Keymap::new(cfg);
+    let mut warnings = std::mem::take(&mut keymap.warnings);
+    let keymap = match format {
+        ExportFormat::QmkJson => qmk::write(&keymap, &mut warnings),
+        ExportFormat::Zmk => zmk::write(&keymap, &mut warnings),
+    };
+    let mut seen = BTreeSet::new();
+    warnings.retain(|warning| seen.insert(warning.clone()));
+    Export { keymap, warnings }
+}
+
+/// What a key does, in terms that both QMK and ZMK have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Binding {
+    Transparent,
+    None,
+    Keys(Keys),
+    /// The layer is active while the key is held.
+    Momentary(usize),
+    /// Switches the base layer.
+    ToLayer(usize),
+    OneShotLayer(usize),
+    OneShotKeys(Keys),
+    ModTap {
+        mods: Keys,
+        tap: Keys,
+    },
+    LayerTap {
+        layer: usize,
+        tap: Keys,
+    },
+    TapDance {
+        timeout: u16,
+        bindings: Vec<Binding>,
+    },
+    MouseButton(Btn),
+}
+
+/// Keys that are pressed together: modifiers and a final key, which may be a modifier too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Keys {
+    mods: Vec<OsCode>,
+    key: OsCode,
+}
+
+impl Keys {
+    fn is_modifiers_only(&self) -> bool {
+        self.key.is_modifier()
+    }
+
+    /// All modifiers, including the final key.
+    fn all_mods(&self) -> impl Iterator<Item = OsCode> + '_ {
+        self.mods.iter().copied().chain(std::iter::once(self.key))
+    }
+}
+
+/// The bindings of every key of `defsrc` in every layer.
+struct Keymap {
+    layer_names: Vec<String>,
+    /// Names of the keys of `defsrc`, used in warnings.
+    key_names: Vec<String>,
+    /// The line of `defsrc` of every key, used to break the keymap into rows.
+    rows: Vec<usize>,
+    /// Bindings per layer, in the order of `defsrc`.
+    layers: Vec<Vec<Binding>>,
+    warnings: Vec<String>,
+}
+
+impl Keymap {
+    fn new(cfg: &Cfg) -> Self {
+        let layer_names: Vec<String> = cfg.layer_info.iter().map(|l| l.name.clone()).collect();
+        let mut keymap = Self {
+            key_names: cfg.defsrc.iter().map(|k| key_name(k.code)).collect(),
+            rows: cfg.defsrc.iter().map(|k| k.row).collect(),
+            layer_names,
+            layers: vec![],
+            warnings: vec![],
+        };
+        let mut timeouts = BTreeSet::new();
+        let layers = cfg.layout.b().layers;
+        for (layer, name) in layers.iter().zip(keymap.layer_names.clone()) {
+            let bindings = cfg
+                .defsrc
+                .iter()
+                .zip(keymap.key_names.clone())
+                .map(|(k, key)| {
+                    let at = format!("Layer {name}, key {key}");
+                    keymap.binding(&layer[0][usize::from(k.code)], &at, &mut timeouts)
+                })
+                .collect();
+            keymap.layers.push(bindings);
+        }
+
+        let timeouts: Vec<String> = timeouts
+            .into_iter()
+            .filter(|t| *t != DEFAULT_TAPPING_TERM)
+            .map(|t| format!("{t} ms"))
+            .collect();
+        if !timeouts.is_empty() {
+            keymap.warnings.push(format!(
+                "Hold-tap keys use the tapping term of the firmware \
+                 ({DEFAULT_TAPPING_TERM} ms unless configured) instead of their timeouts of {}",
+                timeouts.join(", ")
+            ));
+        }
+        let unsupported = [
+            (cfg.zippy.is_some(), "defzippy"),
+            (!cfg.resolved.chords_v2.is_empty(), "defchordsv2"),
+            (!cfg.resolved.sequences.is_empty(), "defseq"),
+            (!cfg.resolved.overrides.is_empty(), "defoverrides"),
+        ];
+        for (_, item) in unsupported.iter().filter(|(used, _)| *used) {
+            keymap.warnings.push(format!("{item} is not exported"));
+        }
+        keymap
+    }
+
+    fn binding(
+        &mut self,
+        action: &LayoutAction<'_>,
+        at: &str,
+        timeouts: &mut BTreeSet<u16>,
+    ) -> Binding {
+        match action {
+            Action::Trans => Binding::Transparent,
+            Action::NoOp => Binding::None,
+            Action::Layer(idx) => Binding::Momentary(*idx),
+            Action::DefaultLayer(idx) => Binding::ToLayer(*idx),
+            Action::HoldTap(ht) => {
+                timeouts.insert(ht.timeout);
+                let tap = self.binding(&ht.tap, at, timeouts);
+                let Binding::Keys(tap) = tap else {
+                    self.warn(
+                        at,
+                        "hold-tap without a key to tap, the hold action was dropped",
+                    );
+                    return tap;
+                };
+                match &ht.hold {
+                    Action::Layer(layer) => Binding::LayerTap { layer: *layer, tap },
+                    hold => match keys(hold) {
+                        Some(mods) if mods.is_modifiers_only() => Binding::ModTap { mods, tap },
+                        _ => {
+                            self.warn(
+                                at,
+                                "hold-tap that holds neither a layer nor modifiers, \
+                                 the hold action was dropped",
+                            );
+                            Binding::Keys(tap)
+                        }
+                    },
+                }
+            }
+            Action::OneShot(os) => match os.action {
+                Action::Layer(idx) => Binding::OneShotLayer(*idx),
+                a => match keys(a) {
+                    Some(keys) => Binding::OneShotKeys(keys),
+                    None => {
+                        self.unsupported(at, "one-shot of an action other than keys or a layer")
+                    }
+                },
+            },
+            Action::TapDance(td) => Binding::TapDance {
+                timeout: td.timeout,
+                bindings: td
+                    .actions
+                    .iter()
+                    .map(|a| self.binding(a, at, timeouts))
+                    .collect(),
+            },
+            Action::Custom(cas) => match cas {
+                [CustomAction::Mouse(btn) | CustomAction::MouseTap(btn)] => {
+                    Binding::MouseButton(*btn)
+                }
+                _ => self.unsupported(at, &custom_action_names(cas)),
+            },
+            Action::Chords(_) => self.unsupported(at, "defchords"),
+            Action::Switch(_) => self.unsupported(at, "switch"),
+            Action::Fork(_) => self.unsupported(at, "fork"),
+            Action::Repeat => self.unsupported(at, "rpt"),
+            Action::Sequence { .. } | Action::RepeatableSequence { .. } => {
+                self.unsupported(at, "macro")
+            }
+            _ => match keys(action) {
+                Some(keys) => Binding::Keys(keys),
+                None => self.unsupported(at, "the action"),
+            },
+        }
+    }
+
+    /// Split the items of a layer into the rows of `defsrc`.
+    fn split_rows<T>(&self, items: Vec<T>) -> Vec<Vec<T>> {
+        let mut rows: Vec<Vec<T>> = vec![];
+        let mut last_row = None;
+        for (item, row) in items.into_iter().zip(self.rows.iter()) {
+            if last_row != Some(row) {
+                rows.push(vec![]);
+                last_row = Some(row);
+            }
+            rows.last_mut().expect("pushed").push(item);
+        }
+        rows
+    }
+
+    fn warn(&mut self, at: &str, message: &str) {
+        self.warnings.push(format!("{at}: {message}"));
+    }
+
+    fn unsupported(&mut self, at: &str, what: &str) -> Binding {
+        self.warn(
+            at,
+            &format!("{what} has no firmware equivalent and was exported as no-op"),
+        );
+        Binding::None
+    }
+}
+
+/// Returns the keys pressed by the action if it only presses keys: at most one key that is not a
+/// modifier, and any number of modifiers.
+fn keys(action: &LayoutAction<'_>) -> Option<Keys> {
+    let mut codes = vec![];
+    collect_keys(action, &mut codes)?;
+    let (others, mut mods): (Vec<OsCode>, Vec<OsCode>) =
+        codes.into_iter().partition(|k| !k.is_modifier());
+    let key = match others[..] {
+        [] => mods.pop()?,
+        [key] => key,
+        _ => return None,
+    };
+    Some(Keys { mods, key })
+}
+
+fn collect_keys(action: &LayoutAction<'_>, codes: &mut Vec<OsCode>) -> Option<()> {
+    match action {
+        Action::KeyCode(kc) => codes.push(OsCode::from(*kc)),
+        Action::MultipleKeyCodes(kcs) => codes.extend(kcs.iter().map(|kc| OsCode::from(*kc))),
+        Action::MultipleActions(acs) => {
+            for ac in acs.iter() {
+                collect_keys(ac, codes)?;
+            }
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Modifiers with their QMK modifier function and mod-tap bit and their ZMK modifier function.
+const MODIFIERS: &[(OsCode, &str, &str, &str)] = &[
+    (OsCode::KEY_LEFTCTRL, "LCTL", "MOD_LCTL", "LC"),
+    (OsCode::KEY_LEFTSHIFT, "LSFT", "MOD_LSFT", "LS"),
+    (OsCode::KEY_LEFTALT, "LALT", "MOD_LALT", "LA"),
+    (OsCode::KEY_LEFTMETA, "LGUI", "MOD_LGUI", "LG"),
+    (OsCode::KEY_RIGHTCTRL, "RCTL", "MOD_RCTL", "RC"),
+    (OsCode::KEY_RIGHTSHIFT, "RSFT", "MOD_RSFT", "RS"),
+    (OsCode::KEY_RIGHTALT, "RALT", "MOD_RALT", "RA"),
+    (OsCode::KEY_RIGHTMETA, "RGUI", "MOD_RGUI", "RG"),
+];
+
+fn modifier(key: OsCode) -> &'static (OsCode, &'static str, &'static str, &'static str) {
+    MODIFIERS
+        .iter()
+        .find(|(code, ..)| *code == key)
+        .expect("only called for modifiers")
+}
+
+/// Keys with their QMK and ZMK names, apart from letters, digits and function keys.
+const KEY_NAMES: &[(OsCode, &str, &str)] = &[
+    (OsCode::KEY_ESC, "KC_ESC", "ESC"),
+    (OsCode::KEY_MINUS, "KC_MINS", "MINUS"),
+    (OsCode::KEY_EQUAL, "KC_EQL", "EQUAL"),
+    (OsCode::KEY_BACKSPACE, "KC_BSPC", "BSPC"),
+    (OsCode::KEY_TAB, "KC_TAB", "TAB"),
+    (OsCode::KEY_LEFTBRACE, "KC_LBRC", "LBKT"),
+    (OsCode::KEY_RIGHTBRACE, "KC_RBRC", "RBKT"),
+    (OsCode::KEY_ENTER, "KC_ENT", "RET"),
+    (OsCode::KEY_SEMICOLON, "KC_SCLN", "SEMI"),
+    (OsCode::KEY_APOSTROPHE, "KC_QUOT", "SQT"),
+    (OsCode::KEY_GRAVE, "KC_GRV", "GRAVE"),
+    (OsCode::KEY_BACKSLASH, "KC_BSLS", "BSLH"),
+    (OsCode::KEY_COMMA, "KC_COMM", "COMMA"),
+    (OsCode::KEY_DOT, "KC_DOT", "DOT"),
+    (OsCode::KEY_SLASH, "KC_SLSH", "FSLH"),
+    (OsCode::KEY_SPACE, "KC_SPC", "SPACE"),
+    (OsCode::KEY_CAPSLOCK, "KC_CAPS", "CAPS"),
+    (OsCode::KEY_LEFTCTRL, "KC_LCTL", "LCTRL"),
+    (OsCode::KEY_LEFTSHIFT, "KC_LSFT", "LSHFT"),
+    (OsCode::KEY_LEFTALT, "KC_LALT", "LALT"),
+    (OsCode::KEY_LEFTMETA, "KC_LGUI", "LGUI"),
+    (OsCode::KEY_RIGHTCTRL, "KC_RCTL", "RCTRL"),
+    (OsCode::KEY_RIGHTSHIFT, "KC_RSFT", "RSHFT"),
+    (OsCode::KEY_RIGHTALT, "KC_RALT", "RALT"),
+    (OsCode::KEY_RIGHTMETA, "KC_RGUI", "RGUI"),
+    (OsCode::KEY_COMPOSE, "KC_APP", "K_APP"),
+    (OsCode::KEY_102ND, "KC_NUBS", "NON_US_BSLH"),
+    (OsCode::KEY_RO, "KC_INT1", "INTL_RO"),
+    (OsCode::KEY_YEN, "KC_INT3", "INTL_YEN"),
+    (OsCode::KEY_SYSRQ, "KC_PSCR", "PSCRN"),
+    (OsCode::KEY_PRINT, "KC_PSCR", "PSCRN"),
+    (OsCode::KEY_SCROLLLOCK, "KC_SCRL", "SLCK"),
+    (OsCode::KEY_PAUSE, "KC_PAUS", "PAUSE_BREAK"),
+    (OsCode::KEY_INSERT, "KC_INS", "INS"),
+    (OsCode::KEY_DELETE, "KC_DEL", "DEL"),
+    (OsCode::KEY_HOME, "KC_HOME", "HOME"),
+    (OsCode::KEY_END, "KC_END", "END"),
+    (OsCode::KEY_PAGEUP, "KC_PGUP", "PG_UP"),
+    (OsCode::KEY_PAGEDOWN, "KC_PGDN", "PG_DN"),
+    (OsCode::KEY_UP, "KC_UP", "UP"),
+    (OsCode::KEY_DOWN, "KC_DOWN", "DOWN"),
+    (OsCode::KEY_LEFT, "KC_LEFT", "LEFT"),
+    (OsCode::KEY_RIGHT, "KC_RGHT", "RIGHT"),
+    (OsCode::KEY_NUMLOCK, "KC_NUM", "KP_NUM"),
+    (OsCode::KEY_KPSLASH, "KC_PSLS", "KP_DIVIDE"),
+    (OsCode::KEY_KPASTERISK, "KC_PAST", "KP_MULTIPLY"),
+    (OsCode::KEY_KPMINUS, "KC_PMNS", "KP_MINUS"),
+    (OsCode::KEY_KPPLUS, "KC_PPLS", "KP_PLUS"),
+    (OsCode::KEY_KPENTER, "KC_PENT", "KP_ENTER"),
+    (OsCode::KEY_KPDOT, "KC_PDOT", "KP_DOT"),
+    (OsCode::KEY_KPEQUAL, "KC_PEQL", "KP_EQUAL"),
+    (OsCode::KEY_KPCOMMA, "KC_PCMM", "KP_COMMA"),
+    (OsCode::KEY_KP0, "KC_P0", "KP_N0"),
+    (OsCode::KEY_KP1, "KC_P1", "KP_N1"),
+    (OsCode::KEY_KP2, "KC_P2", "KP_N2"),
+    (OsCode::KEY_KP3, "KC_P3", "KP_N3"),
+    (OsCode::KEY_KP4, "KC_P4", "KP_N4"),
+    (OsCode::KEY_KP5, "KC_P5", "KP_N5"),
+    (OsCode::KEY_KP6, "KC_P6", "KP_N6"),
+    (OsCode::KEY_KP7, "KC_P7", "KP_N7"),
+    (OsCode::KEY_KP8, "KC_P8", "KP_N8"),
+    (OsCode::KEY_KP9, "KC_P9", "KP_N9"),
+    (OsCode::KEY_MUTE, "KC_MUTE", "C_MUTE"),
+    (OsCode::KEY_VOLUMEUP, "KC_VOLU", "C_VOL_UP"),
+    (OsCode::KEY_VOLUMEDOWN, "KC_VOLD", "C_VOL_DN"),
+    (OsCode::KEY_PLAYPAUSE, "KC_MPLY", "C_PP"),
+    (OsCode::KEY_NEXTSONG, "KC_MNXT", "C_NEXT"),
+    (OsCode::KEY_PREVIOUSSONG, "KC_MPRV", "C_PREV"),
+    (OsCode::KEY_STOPCD, "KC_MSTP", "C_STOP"),
+    (OsCode::KEY_BRIGHTNESSUP, "KC_BRIU", "C_BRI_UP"),
+    (OsCode::KEY_BRIGHTNESSDOWN, "KC_BRID", "C_BRI_DN"),
+    (OsCode::KEY_POWER, "KC_PWR", "C_PWR"),
+];
+
+/// Returns the QMK and ZMK names of the key, if the firmware have it.
+fn key_names(key: OsCode) -> Option<(String, String)> {
+    if let Some((_, qmk, zmk)) = KEY_NAMES.iter().find(|(code, ..)| *code == key) {
+        return Some((qmk.to_string(), zmk.to_string()));
+    }
+    // OsCode names of letters, digits and function keys are e.g. A, 1 and F1.
+    let name = key.to_string();
+    let is_letter = name.len() == 1 && name.chars().all(|c| c.is_ascii_uppercase());
+    let is_digit = name.len() == 1 && name.chars().all(|c| c.is_ascii_digit());
+    let is_function = name
+        .strip_prefix('F')
+        .and_then(|n| n.parse::<u8>().ok())
+        .is_some_and(|n| (1..=24).contains(&n));
+    match (is_letter || is_function, is_digit) {
+        (true, _) => Some((format!("KC_{name}"), name)),
+        (_, true) => Some((format!("KC_{name}"), format!("N{name}"))),
+        _ => None,
+    }
+}
+
+/// Returns the index of the mouse button, counting from 1 as both firmware do.
+fn mouse_button_number(btn: Btn) -> u8 {
+    match btn {
+        Btn::Left => 1,
+        Btn::Right => 2,
+        Btn::Mid => 3,
+        Btn::Backward => 4,
+        Btn::Forward => 5,
+    }
+}
@@ -0,0 +1,121 @@
+//! Writing of QMK `keymap.json` files.
+
+use super::{key_names, modifier, mouse_button_number, Binding, Keymap, Keys};
+use kanata_parser::keys::OsCode;
+
+pub(super) fn write(keymap: &Keymap, warnings: &mut Vec<String>) -> String {
+    let mut out = String::from("{\n");
+    out.push_str("  \"version\": 1,\n");
+    out.push_str(
+        "  \"notes\": \"Exported by kanata --export. Set keyboard and layout; the layout must \
+         have the keys of defsrc in the same order.\",\n",
+    );
+    out.push_str("  \"keyboard\": \"\",\n");
+    out.push_str("  \"keymap\": \"kanata\",\n");
+    out.push_str("  \"layout\": \"LAYOUT\",\n");
+    out.push_str("  \"layers\": [\n");
+    for (i, (layer, name)) in keymap
+        .layers
+        .iter()
+        .zip(keymap.layer_names.iter())
+        .enumerate()
+    {
+        let codes: Vec<String> = layer
+            .iter()
+            .zip(keymap.key_names.iter())
+            .map(|(binding, key)| {
+                let at = format!("Layer {name}, key {key}");
+                format!("\"{}\"", keycode(binding, &at, warnings))
+            })
+            .collect();
+        let rows: Vec<String> = keymap
+            .split_rows(codes)
+            .iter()
+            .map(|row| format!("      {}", row.join(", ")))
+            .collect();
+        out.push_str("    [\n");
+        out.push_str(&rows.join(",\n"));
+        out.push_str("\n    ]");
+        if i + 1 < keymap.layers.len() {
+            out.push(',');
+        }
+        out.push('\n');
+    }
+    out.push_str("  ]\n}\n");
+    out
+}
+
+fn keycode(binding: &Binding, at: &str, warnings: &mut Vec<String>) -> String {
+    match binding {
+        Binding::Transparent => "KC_TRNS".into(),
+        Binding::None => "KC_NO".into(),
+        Binding::Keys(keys) => keys_code(keys, at, warnings),
+        Binding::Momentary(layer) => format!("MO({layer})"),
+        Binding::ToLayer(layer) => format!("DF({layer})"),
+        Binding::OneShotLayer(layer) => format!("OSL({layer})"),
+        Binding::OneShotKeys(keys) if keys.is_modifiers_only() => {
+            format!("OSM({})", mod_bits(keys))
+        }
+        Binding::OneShotKeys(keys) => {
+            warnings.push(format!(
+                "{at}: QMK has one-shot keys only for modifiers, exported as a normal key"
+            ));
+            keys_code(keys, at, warnings)
+        }
+        Binding::ModTap { mods, tap } => {
+            format!("MT({}, {})", mod_bits(mods), basic_code(tap, at, warnings))
+        }
+        Binding::LayerTap { layer, tap } => {
+            format!("LT({layer}, {})", basic_code(tap, at, warnings))
+        }
+        Binding::TapDance { bindings, .. } => {
+            warnings.push(format!(
+                "{at}: tap dance needs C code in QMK, exported as its first action"
+            ));
+            match bindings.first() {
+                Some(first) => keycode(first, at, warnings),
+                None => "KC_NO".into(),
+            }
+        }
+        Binding::MouseButton(btn) => format!("KC_BTN{}", mouse_button_number(*btn)),
+    }
+}
+
+/// Returns the keycode of the keys, e.g. `LCTL(LSFT(KC_A))`.
+fn keys_code(keys: &Keys, at: &str, warnings: &mut Vec<String>) -> String {
+    let mut code = key_code(keys.key, at, warnings);
+    for key in keys.mods.iter().rev() {
+        code = format!("{}({code})", modifier(*key).1);
+    }
+    code
+}
+
+/// Returns the keycode of the final key only, since mod-tap and layer-tap keys of QMK can only
+/// tap basic keycodes.
+fn basic_code(keys: &Keys, at: &str, warnings: &mut Vec<String>) -> String {
+    if !keys.mods.is_empty() {
+        warnings.push(format!(
+            "{at}: QMK taps only basic keycodes in mod-tap and layer-tap keys, \
+             modifiers of the tap action were dropped"
+        ));
+    }
+    key_code(keys.key, at, warnings)
+}
+
+fn key_code(key: OsCode, at: &str, warnings: &mut Vec<String>) -> String {
+    match key_names(key) {
+        Some((qmk, _)) => qmk,
+        None => {
+            warnings.push(format!("{at}: {key} has no QMK keycode, exported as KC_NO"));
+            "KC_NO".into()
+        }
+    }
+}
+
+/// Returns the modifier bits of the keys, e.g. `MOD_LCTL | MOD_LSFT`.
+fn mod_bits(keys: &Keys) -> String {
+    keys.all_mods()
+        .map(|key| modifier(key).2)
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
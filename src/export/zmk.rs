@@ -0,0 +1,159 @@
+//! Writing of ZMK devicetree keymaps.
+
+use super::{key_names, modifier, mouse_button_number, Binding, Keymap, Keys};
+use kanata_parser::keys::OsCode;
+use unicode_width::UnicodeWidthStr;
+
+use std::fmt::Write;
+
+pub(super) fn write(keymap: &Keymap, warnings: &mut Vec<String>) -> String {
+    let mut writer = Writer {
+        warnings,
+        tap_dances: vec![],
+        uses_mouse: false,
+    };
+    let layers: Vec<Vec<Vec<String>>> = keymap
+        .layers
+        .iter()
+        .zip(keymap.layer_names.iter())
+        .map(|(layer, name)| {
+            let bindings = layer
+                .iter()
+                .zip(keymap.key_names.iter())
+                .map(|(binding, key)| writer.binding(binding, &format!("Layer {name}, key {key}")))
+                .collect();
+            keymap.split_rows(bindings)
+        })
+        .collect();
+
+    // Bindings are padded to the widest binding at their position in a row, so that the keys of
+    // every layer line up.
+    let mut widths: Vec<usize> = vec![];
+    for row in layers.iter().flatten() {
+        if widths.len() < row.len() {
+            widths.resize(row.len(), 0);
+        }
+        for (width, binding) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(binding.width());
+        }
+    }
+
+    let mut out = String::from(
+        "/*\n * Exported by kanata --export. The keys are in the order of defsrc, the keyboard\n \
+         * must have the same keys in the same order.\n */\n\n",
+    );
+    out.push_str("#include <behaviors.dtsi>\n");
+    out.push_str("#include <dt-bindings/zmk/keys.h>\n");
+    if writer.uses_mouse {
+        out.push_str("#include <dt-bindings/zmk/pointing.h>\n");
+    }
+    out.push_str("\n/ {\n");
+    if !writer.tap_dances.is_empty() {
+        out.push_str("    behaviors {\n");
+        for (i, (timeout, bindings)) in writer.tap_dances.iter().enumerate() {
+            let bindings: Vec<String> = bindings.iter().map(|b| format!("<{b}>")).collect();
+            let _ = writeln!(out, "        td{i}: tap_dance_{i} {{");
+            out.push_str("            compatible = \"zmk,behavior-tap-dance\";\n");
+            out.push_str("            #binding-cells = <0>;\n");
+            let _ = writeln!(out, "            tapping-term-ms = <{timeout}>;");
+            let _ = writeln!(out, "            bindings = {};", bindings.join(", "));
+            out.push_str("        };\n");
+        }
+        out.push_str("    };\n\n");
+    }
+    out.push_str("    keymap {\n");
+    out.push_str("        compatible = \"zmk,keymap\";\n");
+    for (rows, name) in layers.iter().zip(keymap.layer_names.iter()) {
+        let _ = writeln!(out, "\n        {} {{", node_name(name));
+        let _ = writeln!(
+            out,
+            "            display-name = \"{}\";",
+            name.replace('"', "'")
+        );
+        out.push_str("            bindings = <\n");
+        for row in rows.iter() {
+            let mut line = String::from("               ");
+            for (binding, width) in row.iter().zip(widths.iter()) {
+                let _ = write!(line, " {binding}{}", " ".repeat(width - binding.width()));
+            }
+            let _ = writeln!(out, "{}", line.trim_end());
+        }
+        out.push_str("            >;\n");
+        out.push_str("        };\n");
+    }
+    out.push_str("    };\n};\n");
+    out
+}
+
+struct Writer<'a> {
+    warnings: &'a mut Vec<String>,
+    /// The timeout and bindings of every tap dance behavior.
+    tap_dances: Vec<(u16, Vec<String>)>,
+    uses_mouse: bool,
+}
+
+impl Writer<'_> {
+    fn binding(&mut self, binding: &Binding, at: &str) -> String {
+        match binding {
+            Binding::Transparent => "&trans".into(),
+            Binding::None => "&none".into(),
+            Binding::Keys(keys) => format!("&kp {}", self.keys(keys, at)),
+            Binding::Momentary(layer) => format!("&mo {layer}"),
+            Binding::ToLayer(layer) => format!("&to {layer}"),
+            Binding::OneShotLayer(layer) => format!("&sl {layer}"),
+            Binding::OneShotKeys(keys) => format!("&sk {}", self.keys(keys, at)),
+            Binding::ModTap { mods, tap } => {
+                format!("&mt {} {}", self.keys(mods, at), self.keys(tap, at))
+            }
+            Binding::LayerTap { layer, tap } => format!("&lt {layer} {}", self.keys(tap, at)),
+            Binding::TapDance { timeout, bindings } => {
+                let bindings: Vec<String> = bindings.iter().map(|b| self.binding(b, at)).collect();
+                let tap_dance = (*timeout, bindings);
+                let idx = match self.tap_dances.iter().position(|td| *td == tap_dance) {
+                    Some(idx) => idx,
+                    None => {
+                        self.tap_dances.push(tap_dance);
+                        self.tap_dances.len() - 1
+                    }
+                };
+                format!("&td{idx}")
+            }
+            Binding::MouseButton(btn) => {
+                self.uses_mouse = true;
+                format!("&mkp MB{}", mouse_button_number(*btn))
+            }
+        }
+    }
+
+    /// Returns the keycode of the keys, e.g. `LC(LS(A))`.
+    fn keys(&mut self, keys: &Keys, at: &str) -> String {
+        let mut code = self.key(keys.key, at);
+        for key in keys.mods.iter().rev() {
+            code = format!("{}({code})", modifier(*key).3);
+        }
+        code
+    }
+
+    fn key(&mut self, key: OsCode, at: &str) -> String {
+        match key_names(key) {
+            Some((_, zmk)) => zmk,
+            None => {
+                self.warnings
+                    .push(format!("{at}: {key} has no ZMK keycode, exported as NONE"));
+                "NONE".into()
+            }
+        }
+    }
+}
+
+/// Returns a devicetree node name for the layer.
+fn node_name(layer: &str) -> String {
+    let name: String = layer
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_lowercase(),
+            false => '_',
+        })
+        .collect();
+    format!("{name}_layer")
+}
@@ -1268,6 +1268,7 @@ impl SystemTray {
         for handler in handlers.iter() {
             nwg::unbind_event_handler(handler);
         }
+        crate::kanata::save_files_on_exit();
         nwg::stop_thread_dispatch();
     }
 
@@ -0,0 +1,361 @@
+//! Conversion of KMonad configurations.
+//!
+//! The syntax of KMonad is close to kanata's, so `defsrc`, `deflayer` and `defalias` keep their
+//! shape and most key names are the same. The actions that differ are rewritten:
+//!
+//! - `tap-hold`, `tap-hold-next(-release)` and `tap-next(-release)` become `tap-hold` and
+//!   `tap-hold-press`/`tap-hold-release`
+//! - `layer-toggle` becomes `layer-while-held` and `layer-next` becomes a one-shot layer
+//! - `around` becomes `multi`, `sticky-key` becomes `one-shot`, `multi-tap` becomes `tap-dance`
+//! - `tap-macro` and `#( )` become `macro`, `cmd-button` becomes `cmd`
+//! - shifted symbols such as `!` become `S-1`
+
+use super::*;
+use kanata_parser::cfg::sexpr::{self, SExpr};
+
+pub(super) fn import(text: &str) -> Result<Imported> {
+    // Escaped parentheses are keys in KMonad, but they would end an atom in kanata's parser.
+    let text = text.replace("\\(", "S-9").replace("\\)", "S-0");
+    let exprs = sexpr::parse(&text, "").map_err(|e| anyhow!("{}", e.msg))?;
+    let mut imported = Imported::default();
+    let mut has_fallthrough = false;
+    for expr in exprs.iter() {
+        let items = &expr.t;
+        let Some(SExpr::Atom(head)) = items.first() else {
+            continue;
+        };
+        match head.t.as_str() {
+            "defcfg" => has_fallthrough = import_defcfg(&items[1..], &mut imported),
+            "defsrc" => {
+                if !imported.defsrc.is_empty() {
+                    imported.warn("Only the first defsrc was converted");
+                    continue;
+                }
+                let keys = skip_options(&items[1..], "defsrc", &mut imported);
+                imported.defsrc = rows(keys, &mut imported);
+            }
+            "defalias" => {
+                for pair in self::items(&items[1..]).chunks(2) {
+                    let (Item::Expr(SExpr::Atom(name)), Some(action)) = (&pair[0], pair.get(1))
+                    else {
+                        imported.warn("A defalias item without a name or action was skipped");
+                        continue;
+                    };
+                    let action = convert_item(action, &mut imported);
+                    imported.aliases.push((name.t.clone(), action));
+                }
+            }
+            "deflayer" => {
+                let name = match items.get(1) {
+                    Some(SExpr::Atom(name)) => name.t.clone(),
+                    Some(SExpr::List(l)) => {
+                        imported.warn("Options of deflayer such as :source were not converted");
+                        match l.t.first() {
+                            Some(SExpr::Atom(name)) => name.t.clone(),
+                            _ => continue,
+                        }
+                    }
+                    None => continue,
+                };
+                let actions = items.get(2..).unwrap_or_default();
+                let rows = rows(actions, &mut imported);
+                imported.layers.push(Layer { name, rows });
+            }
+            other => imported.warn(format!("{other} was not converted")),
+        }
+    }
+    // KMonad does not process keys that are not in defsrc unless fallthrough is enabled.
+    if !has_fallthrough {
+        imported
+            .defcfg
+            .push(("process-unmapped-keys".into(), "no".into()));
+    }
+    Ok(imported)
+}
+
+/// Returns whether `fallthrough` was converted.
+fn import_defcfg(options: &[SExpr], imported: &mut Imported) -> bool {
+    let mut has_fallthrough = false;
+    for pair in options.chunks(2) {
+        let (SExpr::Atom(option), Some(value)) = (&pair[0], pair.get(1)) else {
+            continue;
+        };
+        let is_true = matches!(value, SExpr::Atom(v) if v.t == "true");
+        let yes_no = if is_true { "yes" } else { "no" };
+        match option.t.as_str() {
+            "fallthrough" => {
+                has_fallthrough = true;
+                imported
+                    .defcfg
+                    .push(("process-unmapped-keys".into(), yes_no.into()));
+            }
+            "allow-cmd" => {
+                imported
+                    .defcfg
+                    .push(("danger-enable-cmd".into(), yes_no.into()));
+            }
+            "input" => match value.list(None) {
+                Some([SExpr::Atom(kind), SExpr::Atom(path)]) if kind.t == "device-file" => {
+                    imported.defcfg.push(("linux-dev".into(), path.t.clone()));
+                }
+                // Other inputs are the defaults of kanata on their platform.
+                _ => {}
+            },
+            // Kanata always creates its own output device.
+            "output" => {}
+            other => imported.warn(format!("defcfg option {other} was not converted")),
+        }
+    }
+    has_fallthrough
+}
+
+/// Returns the items after KMonad's `:name value` options, which are reported as not converted.
+fn skip_options<'a>(items: &'a [SExpr], item: &str, imported: &mut Imported) -> &'a [SExpr] {
+    let mut items = items;
+    while let Some(SExpr::Atom(a)) = items.first() {
+        if !a.t.starts_with(':') {
+            break;
+        }
+        imported.warn(format!("Option {} of {item} was not converted", a.t));
+        items = items.get(2..).unwrap_or_default();
+    }
+    items
+}
+
+/// An item of a list in KMonad: either an expression or a `#( )` tap-macro.
+enum Item<'a> {
+    Expr(&'a SExpr),
+    TapMacro(&'a [SExpr]),
+}
+
+impl Item<'_> {
+    fn line(&self) -> usize {
+        match self {
+            Item::Expr(e) => e.span().start.line,
+            Item::TapMacro(keys) => keys.first().map(|k| k.span().start.line).unwrap_or(0),
+        }
+    }
+}
+
+/// Combine `#` with the list after it, which the S-expression parser keeps apart.
+fn items(exprs: &[SExpr]) -> Vec<Item<'_>> {
+    let mut items = vec![];
+    let mut exprs = exprs.iter().peekable();
+    while let Some(expr) = exprs.next() {
+        match (expr, exprs.peek()) {
+            (SExpr::Atom(a), Some(SExpr::List(l))) if a.t == "#" => {
+                items.push(Item::TapMacro(&l.t));
+                exprs.next();
+            }
+            _ => items.push(Item::Expr(expr)),
+        }
+    }
+    items
+}
+
+/// Convert the items, keeping the line breaks of the source as rows.
+fn rows(exprs: &[SExpr], imported: &mut Imported) -> Vec<Vec<String>> {
+    let mut rows: Vec<Vec<String>> = vec![];
+    let mut prev_line = None;
+    for item in items(exprs).iter() {
+        let line = item.line();
+        if prev_line != Some(line) {
+            rows.push(vec![]);
+            prev_line = Some(line);
+        }
+        let converted = convert_item(item, imported);
+        rows.last_mut().expect("row exists").push(converted);
+    }
+    rows
+}
+
+fn convert_item(item: &Item, imported: &mut Imported) -> String {
+    match item {
+        Item::Expr(e) => convert(e, imported),
+        Item::TapMacro(keys) => tap_macro(keys, imported),
+    }
+}
+
+/// KMonad's names for shifted symbols.
+const SHIFTED: &[(&str, &str)] = &[
+    ("!", "S-1"),
+    ("@", "S-2"),
+    ("#", "S-3"),
+    ("$", "S-4"),
+    ("%", "S-5"),
+    ("^", "S-6"),
+    ("&", "S-7"),
+    ("*", "S-8"),
+    ("+", "S-="),
+    ("{", "S-["),
+    ("}", "S-]"),
+    ("|", "S-\\"),
+    (":", "S-;"),
+    ("<", "S-,"),
+    (">", "S-."),
+    ("?", "S-/"),
+    ("~", "S-grv"),
+];
+
+/// Convert an action.
+fn convert(expr: &SExpr, imported: &mut Imported) -> String {
+    let items = match expr {
+        SExpr::Atom(a) => {
+            return match SHIFTED.iter().find(|(symbol, _)| *symbol == a.t) {
+                Some((_, shifted)) => shifted.to_string(),
+                None => a.t.clone(),
+            }
+        }
+        SExpr::List(l) => items(&l.t),
+    };
+    let atom = |i: usize| match items.get(i) {
+        Some(Item::Expr(SExpr::Atom(a))) => Some(a.t.as_str()),
+        _ => None,
+    };
+    let Some(name) = atom(0) else {
+        imported.warn("A list without an action name was replaced by XX");
+        return "XX".into();
+    };
+    let args = |imported: &mut Imported| -> Vec<String> {
+        items[1..]
+            .iter()
+            .map(|item| convert_item(item, imported))
+            .collect()
+    };
+    match name {
+        "tap-hold" | "tap-hold-next" | "tap-hold-next-release" => {
+            let args = args(imported);
+            if let Some(option) = args.get(3) {
+                imported.warn(format!("Option {option} of {name} was not converted"));
+            }
+            let kanata_name = match name {
+                "tap-hold" => "tap-hold",
+                "tap-hold-next" => "tap-hold-press",
+                _ => "tap-hold-release",
+            };
+            match &args[..] {
+                [timeout, tap, hold, ..] => {
+                    format!("({kanata_name} {timeout} {timeout} {tap} {hold})")
+                }
+                _ => invalid(name, imported),
+            }
+        }
+        "tap-next" | "tap-next-release" => {
+            let kanata_name = match name {
+                "tap-next" => "tap-hold-press",
+                _ => "tap-hold-release",
+            };
+            match &args(imported)[..] {
+                [tap, hold] => {
+                    format!("({kanata_name} {NO_TIMEOUT} {NO_TIMEOUT} {tap} {hold})")
+                }
+                _ => invalid(name, imported),
+            }
+        }
+        "layer-toggle" => match &args(imported)[..] {
+            [layer] => format!("(layer-while-held {layer})"),
+            _ => invalid(name, imported),
+        },
+        "layer-switch" => match &args(imported)[..] {
+            [layer] => format!("(layer-switch {layer})"),
+            _ => invalid(name, imported),
+        },
+        "layer-next" => match &args(imported)[..] {
+            [layer] => format!("(one-shot {NO_TIMEOUT} (layer-while-held {layer}))"),
+            _ => invalid(name, imported),
+        },
+        "around" => {
+            // Nested arounds become a single multi.
+            let args: Vec<String> = args(imported)
+                .into_iter()
+                .map(|arg| match arg.strip_prefix("(multi ") {
+                    Some(inner) => inner.strip_suffix(')').unwrap_or(inner).to_owned(),
+                    None => arg,
+                })
+                .collect();
+            format!("(multi {})", args.join(" "))
+        }
+        "sticky-key" => match &args(imported)[..] {
+            [timeout, key] => format!("(one-shot {timeout} {key})"),
+            _ => invalid(name, imported),
+        },
+        "multi-tap" => {
+            let args = args(imported);
+            let (pairs, last) = args.split_at(args.len().saturating_sub(1));
+            let timeouts: Vec<&String> = pairs.iter().step_by(2).collect();
+            let mut actions: Vec<&String> = pairs.iter().skip(1).step_by(2).collect();
+            actions.extend(last);
+            let Some(timeout) = timeouts.iter().filter_map(|t| t.parse::<u16>().ok()).max() else {
+                return invalid(name, imported);
+            };
+            if timeouts.iter().any(|t| t.parse() != Ok(timeout)) {
+                imported.warn(format!(
+                    "multi-tap with different timeouts uses the longest one, {timeout}"
+                ));
+            }
+            let actions: Vec<&str> = actions.iter().map(|a| a.as_str()).collect();
+            format!("(tap-dance {timeout} ({}))", actions.join(" "))
+        }
+        "tap-macro" | "tap-macro-release" => {
+            if name == "tap-macro-release" {
+                imported
+                    .warn("tap-macro-release was converted to macro, which taps all keys on press");
+            }
+            match expr {
+                SExpr::List(l) => tap_macro(&l.t[1..], imported),
+                SExpr::Atom(_) => unreachable!("expr is a list"),
+            }
+        }
+        "cmd-button" => {
+            let args = args(imported);
+            if args.len() > 1 {
+                imported.warn("The release command of cmd-button was not converted");
+            }
+            match args.first() {
+                Some(cmd) => format!("(cmd sh -c {cmd})"),
+                None => invalid(name, imported),
+            }
+        }
+        other => {
+            imported.warn(format!("Action {other} was replaced by XX"));
+            "XX".into()
+        }
+    }
+}
+
+/// Convert the keys of a `tap-macro` or `#( )`. KMonad's pauses `P<ms>` and `:delay` are
+/// written as the delays of `macro`.
+fn tap_macro(keys: &[SExpr], imported: &mut Imported) -> String {
+    let mut delay = None;
+    let mut keys = keys;
+    if let [.., SExpr::Atom(option), SExpr::Atom(ms)] = keys {
+        if option.t == ":delay" {
+            delay = Some(ms.t.clone());
+            keys = &keys[..keys.len() - 2];
+        }
+    }
+    let mut out = vec![];
+    for (i, item) in items(keys).iter().enumerate() {
+        if let (Some(delay), true) = (&delay, i > 0) {
+            out.push(delay.clone());
+        }
+        match item {
+            Item::Expr(SExpr::Atom(a))
+                if a.t.len() > 1
+                    && a.t.starts_with('P')
+                    && a.t[1..].bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                out.push(a.t[1..].to_owned())
+            }
+            item => out.push(convert_item(item, imported)),
+        }
+    }
+    format!("(macro {})", out.join(" "))
+}
+
+fn invalid(name: &str, imported: &mut Imported) -> String {
+    imported.warn(format!(
+        "{name} with unexpected parameters was replaced by XX"
+    ));
+    "XX".into()
+}
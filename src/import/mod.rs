@@ -0,0 +1,188 @@
+//! Conversion of the configurations of other keyboard tools into kanata configurations.
+//!
+//! Supported are KMonad configurations and QMK keymaps, either as `keymap.c` or as the
+//! `keymap.json` exported by the QMK configurator. Constructs without a kanata equivalent are
+//! reported as warnings; their keys become `XX`. Comments of the source are not kept.
+
+use anyhow::{anyhow, bail, Result};
+use kanata_parser::cfg;
+use unicode_width::UnicodeWidthStr;
+
+use std::fmt::Write;
+use std::path::Path;
+
+mod kmonad;
+mod qmk;
+
+/// Kanata has no actions that wait forever, so this timeout is used instead where the source
+/// has no timeout, e.g. for one-shot keys.
+const NO_TIMEOUT: u16 = 65535;
+
+/// The formats that can be converted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Kmonad,
+    QmkC,
+    QmkJson,
+}
+
+impl ImportFormat {
+    /// Returns the format of a file by its extension: `.kbd` for KMonad, `.c` and `.json` for QMK.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("kbd") => Ok(Self::Kmonad),
+            Some("c") => Ok(Self::QmkC),
+            Some("json") => Ok(Self::QmkJson),
+            _ => bail!(
+                "Unknown file type of {}, expected a KMonad .kbd file or a QMK keymap.c or keymap.json",
+                path.display()
+            ),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Kmonad => "KMonad configuration",
+            Self::QmkC => "QMK keymap.c",
+            Self::QmkJson => "QMK keymap.json",
+        }
+    }
+}
+
+/// A converted configuration.
+#[derive(Debug)]
+pub struct Conversion {
+    /// The text of the kanata configuration.
+    pub cfg: String,
+    /// Constructs of the source that could not be converted or only approximately.
+    pub warnings: Vec<String>,
+}
+
+/// Convert the file at `path`, with the format given by its extension.
+pub fn import_file(path: &Path) -> Result<Conversion> {
+    let format = ImportFormat::from_path(path)?;
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read {}: {e}", path.display()))?;
+    import(&text, format)
+}
+
+/// Convert the text of a configuration in the given format.
+pub fn import(text: &str, format: ImportFormat) -> Result<Conversion> {
+    let imported = match format {
+        ImportFormat::Kmonad => kmonad::import(text)?,
+        ImportFormat::QmkC => qmk::import_c(text)?,
+        ImportFormat::QmkJson => qmk::import_json(text)?,
+    };
+    let mut warnings = imported.warnings.clone();
+    let mut cfg = imported.write(format);
+    match cfg::format::format_cfg(&cfg) {
+        Ok(formatted) => cfg = formatted,
+        Err(e) => warnings.push(format!(
+            "The converted configuration is not valid: {}",
+            e.msg
+        )),
+    }
+    if let Err(e) = cfg::new_from_str(&cfg, Default::default()) {
+        warnings.push(format!(
+            "The converted configuration has errors that need to be fixed by hand:\n{e:?}"
+        ));
+    }
+    Ok(Conversion { cfg, warnings })
+}
+
+/// The configuration items collected by an importer, with every action already in kanata syntax.
+#[derive(Debug, Default)]
+struct Imported {
+    defcfg: Vec<(String, String)>,
+    /// Rows of `defsrc`.
+    defsrc: Vec<Vec<String>>,
+    aliases: Vec<(String, String)>,
+    layers: Vec<Layer>,
+    warnings: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Layer {
+    name: String,
+    /// Rows of actions, in the same shape as the rows of `defsrc`.
+    rows: Vec<Vec<String>>,
+}
+
+impl Imported {
+    fn warn(&mut self, warning: impl Into<String>) {
+        self.warnings.push(warning.into());
+    }
+
+    fn write(&self, format: ImportFormat) -> String {
+        let mut out = format!(
+            ";; Converted from a {} by kanata --import.\n",
+            format.name()
+        );
+        if !self.warnings.is_empty() {
+            out.push_str(";; Not converted or converted approximately:\n");
+            for warning in self.warnings.iter() {
+                for line in warning.lines() {
+                    let _ = writeln!(out, ";; - {line}");
+                }
+            }
+        }
+        out.push('\n');
+
+        if !self.defcfg.is_empty() {
+            out.push_str("(defcfg\n");
+            for (option, value) in self.defcfg.iter() {
+                let _ = writeln!(out, "  {option} {value}");
+            }
+            out.push_str(")\n\n");
+        }
+        write_rows(&mut out, "defsrc", &self.defsrc);
+        if !self.aliases.is_empty() {
+            out.push_str("(defalias\n");
+            for (name, action) in self.aliases.iter() {
+                let _ = writeln!(out, "  {name} {action}");
+            }
+            out.push_str(")\n\n");
+        }
+        for layer in self.layers.iter() {
+            let header = format!("deflayer {}", layer.name);
+            write_rows(&mut out, &header, &layer.rows);
+        }
+        out.truncate(out.trim_end().len());
+        out.push('\n');
+        out
+    }
+}
+
+/// Writes the rows of `defsrc` or of a layer. Every item is padded to the widest item at its
+/// position in the rows, so that the formatter finds the columns of `defsrc` and aligns the
+/// layers to them.
+fn write_rows(out: &mut String, header: &str, rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = vec![];
+    for row in rows.iter() {
+        if widths.len() < row.len() {
+            widths.resize(row.len(), 0);
+        }
+        for (width, item) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(item.width());
+        }
+    }
+    let _ = writeln!(out, "({header}");
+    for row in rows.iter().filter(|row| !row.is_empty()) {
+        let mut line = String::from(" ");
+        for (item, width) in row.iter().zip(widths.iter()) {
+            let _ = write!(line, " {item}{}", " ".repeat(width - item.width()));
+        }
+        let _ = writeln!(out, "{}", line.trim_end());
+    }
+    out.push_str(")\n\n");
+}
+
+/// Returns the name as a valid kanata layer or alias name: lowercase, with leading underscores
+/// removed.
+fn item_name(name: &str) -> String {
+    let name = name.trim_start_matches('_').to_lowercase();
+    match name.is_empty() {
+        true => "_".to_owned(),
+        false => name,
+    }
+}
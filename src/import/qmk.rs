@@ -0,0 +1,845 @@
+//! Conversion of QMK keymaps.
+//!
+//! The layers are read from the `keymaps` array of a `keymap.c`, or from the `layers` of a
+//! `keymap.json`. QMK keymaps describe the keys of the keyboard's matrix rather than the keys an
+//! operating system receives, so the plain keys of the first layer are used as `defsrc`.
+//! Positions of the first layer without a plain key, e.g. `MO(1)`, are left out of all layers.
+//!
+//! In a `keymap.c`, object-like `#define`s are expanded and layers may be referred to by the
+//! names of an `enum`. Line breaks of the `LAYOUT` macros are kept as rows. A `keymap.json` has
+//! no rows, so its layers are split into rows by the size of the key matrix in the layout name.
+
+use super::*;
+
+use std::collections::HashMap;
+
+/// QMK's default `TAPPING_TERM`.
+const DEFAULT_TAPPING_TERM: u16 = 200;
+
+/// A layer as written in the keymap.
+struct QmkLayer {
+    /// The designator of the layer in the `keymaps` array, e.g. `_BASE` in `[_BASE] = ...`.
+    name: Option<String>,
+    /// Rows of keycodes.
+    rows: Vec<Vec<String>>,
+}
+
+pub(super) fn import_c(text: &str) -> Result<Imported> {
+    let text = strip_comments(text);
+    let defines = defines(&text);
+    let tapping_term = defines
+        .get("TAPPING_TERM")
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(DEFAULT_TAPPING_TERM);
+    let enums = enum_values(&text);
+
+    let start = keymaps_position(&text).ok_or_else(|| anyhow!("No keymaps array was found"))?;
+    let open = start
+        + text[start..]
+            .find('{')
+            .ok_or_else(|| anyhow!("The keymaps array has no content"))?;
+    let body = &text[open + 1..matching_close(&text, open)?];
+
+    let mut layers = vec![];
+    for (entry, entry_line) in split_top_level(body, line_of(&text, open + 1)) {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+        let (name, layout) = match entry.strip_prefix('[') {
+            Some(rest) => {
+                let (name, layout) = rest
+                    .split_once(']')
+                    .ok_or_else(|| anyhow!("Invalid layer designator: {entry}"))?;
+                let layout = layout.trim_start().trim_start_matches('=').trim_start();
+                (Some(name.trim().to_owned()), layout)
+            }
+            None => (None, entry),
+        };
+        let args_start = layout
+            .find('(')
+            .ok_or_else(|| anyhow!("Expected a LAYOUT macro, found: {layout}"))?;
+        let args_end = layout
+            .rfind(')')
+            .ok_or_else(|| anyhow!("Unterminated LAYOUT macro: {layout}"))?;
+        let layout_start = layout.as_ptr() as usize - entry.as_ptr() as usize;
+        let args_line = entry_line + line_of(entry, layout_start + args_start);
+        let mut rows: Vec<Vec<String>> = vec![];
+        let mut prev_line = None;
+        for (keycode, line) in split_top_level(&layout[args_start + 1..args_end], args_line) {
+            let keycode = keycode.trim();
+            if keycode.is_empty() {
+                continue;
+            }
+            if prev_line != Some(line) {
+                rows.push(vec![]);
+                prev_line = Some(line);
+            }
+            let keycode = expand_defines(keycode, &defines);
+            rows.last_mut().expect("row exists").push(keycode);
+        }
+        layers.push(QmkLayer { name, rows });
+    }
+    Ok(convert_layers(layers, &enums, tapping_term))
+}
+
+#[cfg(not(feature = "json"))]
+pub(super) fn import_json(_text: &str) -> Result<Imported> {
+    bail!("Importing keymap.json requires kanata to be built with the json feature")
+}
+
+#[cfg(feature = "json")]
+pub(super) fn import_json(text: &str) -> Result<Imported> {
+    let json: serde_json::Value = serde_json::from_str(text)?;
+    let row_len = json["layout"].as_str().and_then(json_row_len);
+    let layers = json["layers"]
+        .as_array()
+        .ok_or_else(|| anyhow!("The keymap has no layers"))?;
+    let layers = layers
+        .iter()
+        .map(|layer| {
+            let keycodes = layer
+                .as_array()
+                .ok_or_else(|| anyhow!("A layer is not an array"))?
+                .iter()
+                .map(|kc| {
+                    kc.as_str()
+                        .map(str::to_owned)
+                        .ok_or_else(|| anyhow!("A keycode is not a string: {kc}"))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let rows = match row_len {
+                Some(len) => keycodes.chunks(len).map(<[_]>::to_vec).collect(),
+                None => vec![keycodes],
+            };
+            Ok(QmkLayer { name: None, rows })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(convert_layers(
+        layers,
+        &HashMap::new(),
+        DEFAULT_TAPPING_TERM,
+    ))
+}
+
+/// The number of keys in a row of a `LAYOUT` macro whose name gives the size of its key matrix,
+/// e.g. 12 for `LAYOUT_ortho_4x12` and for `LAYOUT_split_3x6_3`, whose halves have 6 columns.
+///
+/// A `keymap.json` has no line breaks between rows, so layers of other layouts are kept as a
+/// single row.
+#[cfg(feature = "json")]
+fn json_row_len(layout: &str) -> Option<usize> {
+    let columns = layout.split('_').find_map(|part| {
+        let (rows, columns) = part.split_once('x')?;
+        rows.parse::<usize>().ok()?;
+        columns.parse::<usize>().ok().filter(|c| *c > 0)
+    })?;
+    match layout.split('_').any(|part| part == "split") {
+        true => Some(2 * columns),
+        false => Some(columns),
+    }
+}
+
+fn convert_layers(
+    layers: Vec<QmkLayer>,
+    enums: &HashMap<String, usize>,
+    tapping_term: u16,
+) -> Imported {
+    let mut imported = Imported::default();
+    imported
+        .defcfg
+        .push(("process-unmapped-keys".into(), "no".into()));
+    let names: Vec<String> = layers
+        .iter()
+        .enumerate()
+        .map(|(i, layer)| match &layer.name {
+            Some(name) if name.parse::<usize>().is_err() => item_name(name),
+            _ => format!("layer{i}"),
+        })
+        .collect();
+    let mut converter = Converter {
+        imported: &mut imported,
+        layer_names: &names,
+        designators: layers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, layer)| Some((layer.name.clone()?, i)))
+            .collect(),
+        enums,
+        tapping_term,
+    };
+
+    // The first layer gives the keys of defsrc: its plain keys and the tap keys of its
+    // mod-taps and layer-taps.
+    let src_keys: Vec<Vec<Option<String>>> = match layers.first() {
+        Some(first) => first
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|kc| converter.tap_key(kc)).collect())
+            .collect(),
+        None => vec![],
+    };
+    let converted: Vec<Vec<Vec<String>>> = layers
+        .iter()
+        .map(|layer| {
+            layer
+                .rows
+                .iter()
+                .map(|row| row.iter().map(|kc| converter.convert(kc)).collect())
+                .collect()
+        })
+        .collect();
+
+    if layers.is_empty() {
+        imported.warn("The keymap has no layers");
+        return imported;
+    }
+    let mut used = vec![];
+    let keep: Vec<Vec<bool>> = src_keys
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|key| match key {
+                    Some(key) if !used.contains(key) => {
+                        used.push(key.clone());
+                        true
+                    }
+                    _ => false,
+                })
+                .collect()
+        })
+        .collect();
+    let skipped = keep.iter().flatten().filter(|keep| !**keep).count();
+    if skipped > 0 {
+        imported.warn(format!(
+            "{skipped} key position(s) without a key to tap or with a duplicate key \
+             in the first layer were left out"
+        ));
+    }
+    let keep_keys = |rows: &[Vec<String>]| -> Vec<Vec<String>> {
+        rows.iter()
+            .zip(keep.iter())
+            .map(|(row, keep)| {
+                row.iter()
+                    .zip(keep.iter())
+                    .filter(|(_, keep)| **keep)
+                    .map(|(key, _)| key.clone())
+                    .collect()
+            })
+            .collect()
+    };
+    let src_keys: Vec<Vec<String>> = src_keys
+        .into_iter()
+        .map(|row| row.into_iter().map(Option::unwrap_or_default).collect())
+        .collect();
+    imported.defsrc = keep_keys(&src_keys);
+    for (layer, name) in converted.iter().zip(names) {
+        let mut rows = keep_keys(layer);
+        // Layers may be shorter than the first one; fill them up with transparent keys.
+        rows.resize_with(imported.defsrc.len(), Vec::new);
+        for (row, src_row) in rows.iter_mut().zip(imported.defsrc.iter()) {
+            row.resize(src_row.len(), "_".into());
+        }
+        imported.layers.push(Layer { name, rows });
+    }
+    imported
+}
+
+struct Converter<'a> {
+    imported: &'a mut Imported,
+    layer_names: &'a [String],
+    /// Layer indexes by the designators of the `keymaps` array.
+    designators: HashMap<String, usize>,
+    /// Values of the `enum` members of the keymap, which may name layers.
+    enums: &'a HashMap<String, usize>,
+    tapping_term: u16,
+}
+
+/// Keycodes whose kanata name is not their QMK name in lowercase without `KC_`.
+const KEYCODES: &[(&str, &str)] = &[
+    ("KC_ENT", "ret"),
+    ("KC_ENTER", "ret"),
+    ("KC_ESCAPE", "esc"),
+    ("KC_BACKSPACE", "bspc"),
+    ("KC_SPACE", "spc"),
+    ("KC_MINS", "-"),
+    ("KC_MINUS", "-"),
+    ("KC_EQL", "="),
+    ("KC_EQUAL", "="),
+    ("KC_LBRC", "["),
+    ("KC_LEFT_BRACKET", "["),
+    ("KC_RBRC", "]"),
+    ("KC_RIGHT_BRACKET", "]"),
+    ("KC_BSLS", "\\"),
+    ("KC_BACKSLASH", "\\"),
+    ("KC_SCLN", ";"),
+    ("KC_SEMICOLON", ";"),
+    ("KC_QUOT", "'"),
+    ("KC_QUOTE", "'"),
+    ("KC_GRV", "grv"),
+    ("KC_GRAVE", "grv"),
+    ("KC_COMM", ","),
+    ("KC_COMMA", ","),
+    ("KC_DOT", "."),
+    ("KC_SLSH", "/"),
+    ("KC_SLASH", "/"),
+    ("KC_NUBS", "102d"),
+    ("KC_CAPS_LOCK", "caps"),
+    ("KC_PSCR", "prnt"),
+    ("KC_PRINT_SCREEN", "prnt"),
+    ("KC_SCRL", "slck"),
+    ("KC_SCROLL_LOCK", "slck"),
+    ("KC_PAUS", "pause"),
+    ("KC_BRK", "pause"),
+    ("KC_INSERT", "ins"),
+    ("KC_PAGE_UP", "pgup"),
+    ("KC_DELETE", "del"),
+    ("KC_PAGE_DOWN", "pgdn"),
+    ("KC_RIGHT", "rght"),
+    ("KC_NUM", "nlck"),
+    ("KC_NUM_LOCK", "nlck"),
+    ("KC_PSLS", "kp/"),
+    ("KC_KP_SLASH", "kp/"),
+    ("KC_PAST", "kp*"),
+    ("KC_KP_ASTERISK", "kp*"),
+    ("KC_PMNS", "kp-"),
+    ("KC_KP_MINUS", "kp-"),
+    ("KC_PPLS", "kp+"),
+    ("KC_KP_PLUS", "kp+"),
+    ("KC_PENT", "kprt"),
+    ("KC_KP_ENTER", "kprt"),
+    ("KC_PDOT", "kp."),
+    ("KC_KP_DOT", "kp."),
+    ("KC_PEQL", "kp="),
+    ("KC_KP_EQUAL", "kp="),
+    ("KC_PCMM", "kp,"),
+    ("KC_KP_COMMA", "kp,"),
+    ("KC_P0", "kp0"),
+    ("KC_KP_0", "kp0"),
+    ("KC_P1", "kp1"),
+    ("KC_KP_1", "kp1"),
+    ("KC_P2", "kp2"),
+    ("KC_KP_2", "kp2"),
+    ("KC_P3", "kp3"),
+    ("KC_KP_3", "kp3"),
+    ("KC_P4", "kp4"),
+    ("KC_KP_4", "kp4"),
+    ("KC_P5", "kp5"),
+    ("KC_KP_5", "kp5"),
+    ("KC_P6", "kp6"),
+    ("KC_KP_6", "kp6"),
+    ("KC_P7", "kp7"),
+    ("KC_KP_7", "kp7"),
+    ("KC_P8", "kp8"),
+    ("KC_KP_8", "kp8"),
+    ("KC_P9", "kp9"),
+    ("KC_KP_9", "kp9"),
+    ("KC_APP", "menu"),
+    ("KC_APPLICATION", "menu"),
+    ("KC_LCMD", "lmet"),
+    ("KC_LWIN", "lmet"),
+    ("KC_LGUI", "lmet"),
+    ("KC_RCMD", "rmet"),
+    ("KC_RWIN", "rmet"),
+    ("KC_RGUI", "rmet"),
+    ("KC_LOPT", "lalt"),
+    ("KC_ROPT", "ralt"),
+    ("KC_ALGR", "ralt"),
+    ("KC_AUDIO_MUTE", "mute"),
+    ("KC_AUDIO_VOL_UP", "volu"),
+    ("KC_VOLD", "voldwn"),
+    ("KC_AUDIO_VOL_DOWN", "voldwn"),
+    ("KC_MNXT", "next"),
+    ("KC_MEDIA_NEXT_TRACK", "next"),
+    ("KC_MPRV", "prev"),
+    ("KC_MEDIA_PREV_TRACK", "prev"),
+    ("KC_MPLY", "pp"),
+    ("KC_MEDIA_PLAY_PAUSE", "pp"),
+    ("KC_BRIU", "brup"),
+    ("KC_BRIGHTNESS_UP", "brup"),
+    ("KC_BRID", "brdown"),
+    ("KC_BRIGHTNESS_DOWN", "brdown"),
+    ("KC_BTN1", "mlft"),
+    ("KC_MS_BTN1", "mlft"),
+    ("MS_BTN1", "mlft"),
+    ("KC_BTN2", "mrgt"),
+    ("KC_MS_BTN2", "mrgt"),
+    ("MS_BTN2", "mrgt"),
+    ("KC_BTN3", "mmid"),
+    ("KC_MS_BTN3", "mmid"),
+    ("MS_BTN3", "mmid"),
+    ("KC_WH_U", "(mwheel-up 50 120)"),
+    ("KC_MS_WH_UP", "(mwheel-up 50 120)"),
+    ("MS_WHLU", "(mwheel-up 50 120)"),
+    ("KC_WH_D", "(mwheel-down 50 120)"),
+    ("KC_MS_WH_DOWN", "(mwheel-down 50 120)"),
+    ("MS_WHLD", "(mwheel-down 50 120)"),
+    ("KC_MS_U", "(movemouse-up 4 4)"),
+    ("KC_MS_UP", "(movemouse-up 4 4)"),
+    ("MS_UP", "(movemouse-up 4 4)"),
+    ("KC_MS_D", "(movemouse-down 4 4)"),
+    ("KC_MS_DOWN", "(movemouse-down 4 4)"),
+    ("MS_DOWN", "(movemouse-down 4 4)"),
+    ("KC_MS_L", "(movemouse-left 4 4)"),
+    ("KC_MS_LEFT", "(movemouse-left 4 4)"),
+    ("MS_LEFT", "(movemouse-left 4 4)"),
+    ("KC_MS_R", "(movemouse-right 4 4)"),
+    ("KC_MS_RIGHT", "(movemouse-right 4 4)"),
+    ("MS_RGHT", "(movemouse-right 4 4)"),
+    ("KC_TRNS", "_"),
+    ("KC_TRANSPARENT", "_"),
+    ("_______", "_"),
+    ("KC_NO", "XX"),
+    ("XXXXXXX", "XX"),
+    // Shifted symbols.
+    ("KC_EXLM", "S-1"),
+    ("KC_AT", "S-2"),
+    ("KC_HASH", "S-3"),
+    ("KC_DLR", "S-4"),
+    ("KC_PERC", "S-5"),
+    ("KC_CIRC", "S-6"),
+    ("KC_AMPR", "S-7"),
+    ("KC_ASTR", "S-8"),
+    ("KC_LPRN", "S-9"),
+    ("KC_RPRN", "S-0"),
+    ("KC_UNDS", "S--"),
+    ("KC_PLUS", "S-="),
+    ("KC_LCBR", "S-["),
+    ("KC_RCBR", "S-]"),
+    ("KC_PIPE", "S-\\"),
+    ("KC_COLN", "S-;"),
+    ("KC_DQUO", "S-'"),
+    ("KC_DQT", "S-'"),
+    ("KC_TILD", "S-grv"),
+    ("KC_LABK", "S-,"),
+    ("KC_LT", "S-,"),
+    ("KC_RABK", "S-."),
+    ("KC_GT", "S-."),
+    ("KC_QUES", "S-/"),
+];
+
+/// Modifier functions such as `LCTL(kc)`, with the kanata prefix for the key.
+const MOD_FUNCTIONS: &[(&str, &str)] = &[
+    ("LCTL", "C-"),
+    ("C", "C-"),
+    ("LSFT", "S-"),
+    ("S", "S-"),
+    ("LALT", "A-"),
+    ("A", "A-"),
+    ("LOPT", "A-"),
+    ("LGUI", "M-"),
+    ("G", "M-"),
+    ("LCMD", "M-"),
+    ("LWIN", "M-"),
+    ("RCTL", "RC-"),
+    ("RSFT", "RS-"),
+    ("RALT", "RA-"),
+    ("ALGR", "RA-"),
+    ("ROPT", "RA-"),
+    ("RGUI", "RM-"),
+    ("RCMD", "RM-"),
+    ("RWIN", "RM-"),
+    ("C_S", "C-S-"),
+    ("LCA", "C-A-"),
+    ("LSA", "S-A-"),
+    ("LCAG", "C-A-M-"),
+    ("MEH", "C-S-A-"),
+    ("HYPR", "C-S-A-M-"),
+];
+
+/// Mod-tap functions such as `LCTL_T(kc)`, with the kanata keys to hold.
+const MOD_TAPS: &[(&str, &[&str])] = &[
+    ("LCTL_T", &["lctl"]),
+    ("CTL_T", &["lctl"]),
+    ("LSFT_T", &["lsft"]),
+    ("SFT_T", &["lsft"]),
+    ("LALT_T", &["lalt"]),
+    ("ALT_T", &["lalt"]),
+    ("LOPT_T", &["lalt"]),
+    ("OPT_T", &["lalt"]),
+    ("LGUI_T", &["lmet"]),
+    ("GUI_T", &["lmet"]),
+    ("LCMD_T", &["lmet"]),
+    ("CMD_T", &["lmet"]),
+    ("LWIN_T", &["lmet"]),
+    ("WIN_T", &["lmet"]),
+    ("RCTL_T", &["rctl"]),
+    ("RSFT_T", &["rsft"]),
+    ("RALT_T", &["ralt"]),
+    ("ROPT_T", &["ralt"]),
+    ("ALGR_T", &["ralt"]),
+    ("RGUI_T", &["rmet"]),
+    ("RCMD_T", &["rmet"]),
+    ("RWIN_T", &["rmet"]),
+    ("C_S_T", &["lctl", "lsft"]),
+    ("LCA_T", &["lctl", "lalt"]),
+    ("MEH_T", &["lctl", "lsft", "lalt"]),
+    ("HYPR_T", &["lctl", "lsft", "lalt", "lmet"]),
+    ("ALL_T", &["lctl", "lsft", "lalt", "lmet"]),
+];
+
+/// The kanata prefixes of modifier keys in chords.
+const MOD_PREFIXES: &[(&str, &str)] = &[
+    ("lctl", "C-"),
+    ("lsft", "S-"),
+    ("lalt", "A-"),
+    ("lmet", "M-"),
+    ("rctl", "RC-"),
+    ("rsft", "RS-"),
+    ("ralt", "RA-"),
+    ("rmet", "RM-"),
+];
+
+/// The `MOD_*` constants of `MT`, `OSM` and `LM`.
+const MOD_BITS: &[(&str, &[&str])] = &[
+    ("MOD_LCTL", &["lctl"]),
+    ("MOD_LSFT", &["lsft"]),
+    ("MOD_LALT", &["lalt"]),
+    ("MOD_LGUI", &["lmet"]),
+    ("MOD_RCTL", &["rctl"]),
+    ("MOD_RSFT", &["rsft"]),
+    ("MOD_RALT", &["ralt"]),
+    ("MOD_RGUI", &["rmet"]),
+    ("MOD_MEH", &["lctl", "lsft", "lalt"]),
+    ("MOD_HYPR", &["lctl", "lsft", "lalt", "lmet"]),
+];
+
+impl Converter<'_> {
+    /// Convert a keycode to a kanata action.
+    fn convert(&mut self, keycode: &str) -> String {
+        let keycode = keycode.trim();
+        let Some((function, args)) = function_call(keycode) else {
+            return self.basic(keycode);
+        };
+        let args: Vec<&str> = args.iter().map(|a| a.trim()).collect();
+        let tapping_term = self.tapping_term;
+        match (function, &args[..]) {
+            ("MO", [layer]) => match self.layer(layer) {
+                Some(layer) => format!("(layer-while-held {layer})"),
+                None => self.unknown(keycode),
+            },
+            ("TO" | "DF" | "PDF", [layer]) => match self.layer(layer) {
+                Some(layer) => format!("(layer-switch {layer})"),
+                None => self.unknown(keycode),
+            },
+            ("OSL", [layer]) => match self.layer(layer) {
+                Some(layer) => format!("(one-shot {NO_TIMEOUT} (layer-while-held {layer}))"),
+                None => self.unknown(keycode),
+            },
+            ("LT", [layer, kc]) => {
+                match self.layer(layer) {
+                    Some(layer) => {
+                        let tap = self.convert(kc);
+                        format!("(tap-hold {tapping_term} {tapping_term} {tap} (layer-while-held {layer}))")
+                    }
+                    None => self.unknown(keycode),
+                }
+            }
+            ("LM", [layer, mods]) => match (self.layer(layer), self.mods(mods)) {
+                (Some(layer), Some(mods)) => {
+                    format!("(multi (layer-while-held {layer}) {})", mods.join(" "))
+                }
+                _ => self.unknown(keycode),
+            },
+            ("MT", [mods, kc]) => match self.mods(mods) {
+                Some(mods) => self.mod_tap(kc, &mods),
+                None => self.unknown(keycode),
+            },
+            // One-shot keys can only hold multiple modifiers as a chord such as C-lalt.
+            ("OSM", [mods]) => match self.mods(mods).as_deref() {
+                Some([prefix_mods @ .., last]) => {
+                    let prefix: String = prefix_mods
+                        .iter()
+                        .filter_map(|m| MOD_PREFIXES.iter().find(|(key, _)| key == m))
+                        .map(|(_, prefix)| *prefix)
+                        .collect();
+                    format!("(one-shot {NO_TIMEOUT} {prefix}{last})")
+                }
+                _ => self.unknown(keycode),
+            },
+            (function, [kc]) => {
+                if let Some((_, hold)) = MOD_TAPS.iter().find(|(f, _)| *f == function) {
+                    let hold: Vec<String> = hold.iter().map(|h| h.to_string()).collect();
+                    return self.mod_tap(kc, &hold);
+                }
+                let Some((_, prefix)) = MOD_FUNCTIONS.iter().find(|(f, _)| *f == function) else {
+                    return self.unsupported(keycode);
+                };
+                let key = self.convert(kc);
+                match key.starts_with('(') || key == "_" || key == "XX" {
+                    true => self.unknown(keycode),
+                    false => format!("{prefix}{key}"),
+                }
+            }
+            _ => self.unsupported(keycode),
+        }
+    }
+
+    /// The kanata key that is tapped by the keycode, if it is a plain key, a mod-tap or a
+    /// layer-tap.
+    fn tap_key(&self, keycode: &str) -> Option<String> {
+        let call = function_call(keycode.trim());
+        let tap = match call.as_ref().map(|(f, args)| (*f, &args[..])) {
+            Some(("LT" | "MT", [_, kc])) => kc.trim(),
+            Some((function, [kc])) if MOD_TAPS.iter().any(|(f, _)| *f == function) => kc.trim(),
+            Some(_) => return None,
+            None => keycode.trim(),
+        };
+        let key = match KEYCODES.iter().find(|(kc, _)| *kc == tap) {
+            Some((_, key)) => key.to_string(),
+            None => tap.strip_prefix("KC_")?.to_lowercase(),
+        };
+        kanata_parser::keys::str_to_oscode(&key).map(|_| key)
+    }
+
+    fn basic(&mut self, keycode: &str) -> String {
+        if let Some((_, key)) = KEYCODES.iter().find(|(kc, _)| *kc == keycode) {
+            return key.to_string();
+        }
+        let key = keycode
+            .strip_prefix("KC_")
+            .unwrap_or(keycode)
+            .to_lowercase();
+        match kanata_parser::keys::str_to_oscode(&key) {
+            Some(_) if keycode.starts_with("KC_") => key,
+            _ => self.unsupported(keycode),
+        }
+    }
+
+    fn mod_tap(&mut self, kc: &str, hold: &[String]) -> String {
+        let tap = self.convert(kc);
+        let hold = match hold {
+            [hold] => hold.clone(),
+            _ => format!("(multi {})", hold.join(" ")),
+        };
+        let t = self.tapping_term;
+        format!("(tap-hold {t} {t} {tap} {hold})")
+    }
+
+    /// The kanata keys of `MOD_*` constants combined with `|`.
+    fn mods(&self, mods: &str) -> Option<Vec<String>> {
+        let mut keys = vec![];
+        for m in mods.split('|').map(str::trim) {
+            let (_, mod_keys) = MOD_BITS.iter().find(|(name, _)| *name == m)?;
+            keys.extend(mod_keys.iter().map(|k| k.to_string()));
+        }
+        Some(keys)
+    }
+
+    /// The kanata name of a layer given by number, designator or enum member.
+    fn layer(&self, layer: &str) -> Option<String> {
+        let index = layer
+            .parse::<usize>()
+            .ok()
+            .or_else(|| self.designators.get(layer).copied())
+            .or_else(|| self.enums.get(layer).copied())?;
+        self.layer_names.get(index).cloned()
+    }
+
+    fn unsupported(&mut self, keycode: &str) -> String {
+        self.imported.warn(format!(
+            "{keycode} has no kanata equivalent and was replaced by XX"
+        ));
+        "XX".into()
+    }
+
+    fn unknown(&mut self, keycode: &str) -> String {
+        self.imported.warn(format!(
+            "{keycode} could not be converted and was replaced by XX"
+        ));
+        "XX".into()
+    }
+}
+
+/// The position of the declaration of the `keymaps` array, i.e. of `keymaps` followed by `[`.
+/// Other occurrences, such as in an `#include` or in a string, are skipped.
+fn keymaps_position(text: &str) -> Option<usize> {
+    let mut line_start = 0;
+    for line in text.split_inclusive('\n') {
+        if !line.trim_start().starts_with('#') {
+            let mut in_string = false;
+            for (i, c) in line.char_indices() {
+                match c {
+                    '"' => in_string = !in_string,
+                    'k' if !in_string && line[i..].starts_with("keymaps") => {
+                        let is_word_start =
+                            !line[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_');
+                        let rest = text[line_start + i + "keymaps".len()..].trim_start();
+                        if is_word_start && rest.starts_with('[') {
+                            return Some(line_start + i);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        line_start += line.len();
+    }
+    None
+}
+
+/// Splits `NAME(arg, ...)` into its name and arguments.
+fn function_call(keycode: &str) -> Option<(&str, Vec<&str>)> {
+    let (name, rest) = keycode.split_once('(')?;
+    let args = rest.strip_suffix(')')?;
+    let args = split_top_level(args, 0)
+        .into_iter()
+        .map(|(a, _)| a)
+        .collect();
+    Some((name.trim(), args))
+}
+
+/// Replaces the comments with spaces, keeping the line breaks.
+fn strip_comments(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                    }
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                out.push(' ');
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Object-like `#define`s with their replacement.
+fn defines(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter_map(|line| {
+            let rest = line.trim().strip_prefix("#define")?;
+            let rest = rest.trim_start();
+            let name_end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let (name, value) = rest.split_at(name_end);
+            // Function-like macros are not expanded.
+            if name.is_empty() || value.starts_with('(') {
+                return None;
+            }
+            Some((name.to_owned(), value.trim().to_owned()))
+        })
+        .collect()
+}
+
+fn expand_defines(keycode: &str, defines: &HashMap<String, String>) -> String {
+    let mut keycode = keycode.to_owned();
+    // A limit on the depth guards against recursive definitions.
+    for _ in 0..8 {
+        match defines.get(&keycode) {
+            Some(value) => keycode = value.clone(),
+            None => break,
+        }
+    }
+    match function_call(&keycode) {
+        Some((name, args)) => {
+            let args: Vec<String> = args
+                .iter()
+                .map(|a| expand_defines(a.trim(), defines))
+                .collect();
+            format!("{name}({})", args.join(", "))
+        }
+        None => keycode,
+    }
+}
+
+/// Values of the members of every `enum`.
+fn enum_values(text: &str) -> HashMap<String, usize> {
+    let mut values = HashMap::new();
+    let mut rest = text;
+    while let Some(pos) = rest.find("enum") {
+        rest = &rest[pos + "enum".len()..];
+        let (Some(open), Some(close)) = (rest.find('{'), rest.find('}')) else {
+            break;
+        };
+        if close < open {
+            continue;
+        }
+        let mut next = 0;
+        for member in rest[open + 1..close].split(',') {
+            let (name, value) = match member.split_once('=') {
+                Some((name, value)) => (name.trim(), value.trim().parse().ok()),
+                None => (member.trim(), None),
+            };
+            if name.is_empty() {
+                continue;
+            }
+            let value = value.unwrap_or(next);
+            values.insert(name.to_owned(), value);
+            next = value + 1;
+        }
+        rest = &rest[close..];
+    }
+    values
+}
+
+/// The index of the bracket that closes the one at `open`.
+fn matching_close(text: &str, open: usize) -> Result<usize> {
+    let mut depth = 0;
+    for (i, c) in text[open..].char_indices() {
+        match c {
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    bail!("Unbalanced brackets in the keymaps array")
+}
+
+/// Splits the text at the commas that are not within brackets. Returns every part with the line
+/// it starts on, counting from `first_line`.
+fn split_top_level(text: &str, first_line: usize) -> Vec<(&str, usize)> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    let mut line = first_line;
+    let mut part_line = None;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push((&text[start..i], part_line.unwrap_or(line)));
+                start = i + 1;
+                part_line = None;
+                continue;
+            }
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            _ => {}
+        }
+        if part_line.is_none() && !c.is_whitespace() {
+            part_line = Some(line);
+        }
+    }
+    parts.push((&text[start..], part_line.unwrap_or(line)));
+    parts
+}
+
+fn line_of(text: &str, pos: usize) -> usize {
+    text[..pos].matches('\n').count()
+}
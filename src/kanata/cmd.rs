@@ -1,10 +1,15 @@
 #![cfg_attr(feature = "simulated_output", allow(dead_code, unused_imports))]
 
 use std::fmt::Write;
+use std::sync::mpsc;
+
+use crate::oskbd::{KeyEvent, KeyValue};
 
 use kanata_parser::cfg::parse_mod_prefix;
 use kanata_parser::cfg::sexpr::*;
+use kanata_parser::custom_action::CmdAsync;
 use kanata_parser::keys::*;
+use rustc_hash::FxHashMap as HashMap;
 
 // local log prefix
 const LP: &str = "cmd-out:";
@@ -204,8 +209,12 @@ pub(super) fn keys_for_cmd_output(cmd_and_args: &[String]) -> impl Iterator<Item
         }
     };
     log::debug!("{LP} stderr: {}", String::from_utf8_lossy(&output.stderr));
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    match parse(&stdout, "cmd") {
+    keys_for_text(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parses text in the format of the output of `cmd-output-keys` into the keys to type.
+pub(super) fn keys_for_text(stdout: &str) -> std::vec::IntoIter<Item> {
+    match parse(stdout, "cmd") {
         Ok(lists) => match lists.len() {
             0 => {
                 log::warn!("{LP} got zero top-level S-expression from cmd, expected 1:\n{stdout}");
@@ -245,3 +254,192 @@ pub(super) fn run_cmd_in_thread(
         println!("cmd:{cmd_and_args:?}");
     })
 }
+
+/// The result of a command run by `cmd-async`, which is applied by the processing loop.
+pub(super) struct CmdAsyncResult {
+    var: Option<String>,
+    /// The standard output, if the command exited by itself.
+    output: Option<String>,
+    vkey: Option<String>,
+}
+
+/// Cmd variables and the results of `cmd-async` commands that were not applied yet.
+pub(super) struct CmdAsyncState {
+    pub vars: HashMap<String, String>,
+    /// The last key that was pressed, which is given to commands as `KANATA_KEY`.
+    pub last_pressed_key: OsCode,
+    tx: mpsc::Sender<CmdAsyncResult>,
+    rx: mpsc::Receiver<CmdAsyncResult>,
+    /// Wakes up the processing loop when a command finishes, so that its result is applied while
+    /// the loop is blocked waiting for input.
+    wakeup: Option<mpsc::SyncSender<KeyEvent>>,
+}
+
+impl CmdAsyncState {
+    pub(super) fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            vars: HashMap::default(),
+            last_pressed_key: OsCode::KEY_RESERVED,
+            tx,
+            rx,
+            wakeup: None,
+        }
+    }
+
+    pub(super) fn set_wakeup_channel(&mut self, wakeup: mpsc::SyncSender<KeyEvent>) {
+        self.wakeup = Some(wakeup);
+    }
+
+    /// Runs the command in a new thread.
+    pub(super) fn run(&mut self, cmd_async: &CmdAsync, layer: &str) {
+        let env = [
+            ("KANATA_LAYER", layer.to_owned()),
+            ("KANATA_KEY", self.last_pressed_key.to_string()),
+        ];
+        run_cmd_async_in_thread(cmd_async.clone(), env, self.tx.clone(), self.wakeup.clone());
+    }
+
+    /// Stores the output of the commands that finished and returns the names of the virtual keys
+    /// to tap.
+    pub(super) fn take_finished(&mut self) -> Vec<String> {
+        let mut vkeys = vec![];
+        while let Ok(result) = self.rx.try_recv() {
+            if let (Some(var), Some(output)) = (result.var, result.output) {
+                log::debug!("{LP} setting cmd variable {var} to: {output}");
+                self.vars.insert(var, output);
+            }
+            vkeys.extend(result.vkey);
+        }
+        vkeys
+    }
+
+    /// Returns the content of the cmd variable, or an empty string if it is not set.
+    pub(super) fn var(&self, name: &str) -> &str {
+        match self.vars.get(name) {
+            Some(content) => content,
+            None => {
+                log::warn!("{LP} cmd variable {name} is not set");
+                ""
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "simulated_output"))]
+fn run_cmd_async_in_thread(
+    cmd_async: CmdAsync,
+    env: [(&'static str, String); 2],
+    tx: mpsc::Sender<CmdAsyncResult>,
+    wakeup: Option<mpsc::SyncSender<KeyEvent>>,
+) {
+    use std::io::Read;
+    use std::process::{Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    /// How long the output is waited for after the command exits, if it has no timeout.
+    const OUTPUT_WAIT: Duration = Duration::from_secs(1);
+
+    std::thread::spawn(move || {
+        let mut args = cmd_async.cmd.iter();
+        let executable = args
+            .next()
+            .expect("parsing should have forbidden empty cmd");
+        let mut cmd = Command::new(executable);
+        cmd.args(args)
+            .envs(env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        log::info!("Running cmd-async: {:?}", cmd_async.cmd);
+        let (code, output) = match cmd.spawn() {
+            Ok(mut child) => {
+                // Read the output in its own thread, so that a command writing more output than
+                // fits into the pipe does not block while kanata waits for it to exit.
+                let mut stdout = child.stdout.take().expect("stdout is piped");
+                let (output_tx, output_rx) = mpsc::channel();
+                std::thread::spawn(move || {
+                    let mut output = vec![];
+                    let _ = stdout.read_to_end(&mut output);
+                    let _ = output_tx.send(output);
+                });
+                let deadline = cmd_async
+                    .timeout
+                    .map(|ms| Instant::now() + Duration::from_millis(u64::from(ms)));
+                loop {
+                    match child.try_wait() {
+                        Ok(Some(status)) => {
+                            // A process that the command started in the background can keep the
+                            // output open after the command exits, so don't wait for it past the
+                            // deadline, or for long if there is none.
+                            let output_deadline =
+                                deadline.unwrap_or_else(|| Instant::now() + OUTPUT_WAIT);
+                            let output = output_rx
+                                .recv_timeout(
+                                    output_deadline.saturating_duration_since(Instant::now()),
+                                )
+                                .ok();
+                            if output.is_none() {
+                                log::warn!(
+                                    "{LP} cmd-async exited but its output is still open: {:?}",
+                                    cmd_async.cmd
+                                );
+                            }
+                            break (status.code(), output);
+                        }
+                        Ok(None) if deadline.is_some_and(|d| Instant::now() >= d) => {
+                            log::warn!("{LP} killing cmd-async after timeout: {:?}", cmd_async.cmd);
+                            let _ = child.kill();
+                            let _ = child.wait();
+                            break (None, None);
+                        }
+                        Ok(None) => std::thread::sleep(Duration::from_millis(10)),
+                        Err(e) => {
+                            log::error!("{LP} failed to wait for cmd-async: {e}");
+                            break (None, None);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                log::error!("Failed to execute program {:?}: {e}", cmd.get_program());
+                (None, None)
+            }
+        };
+        log::info!(
+            "cmd-async {:?} finished with exit code {code:?}",
+            cmd_async.cmd
+        );
+        let output = output.map(|o| {
+            let output = String::from_utf8_lossy(&o);
+            let output = output.strip_suffix('\n').unwrap_or(&output);
+            output.strip_suffix('\r').unwrap_or(output).to_owned()
+        });
+        let _ = tx.send(CmdAsyncResult {
+            var: cmd_async.var.clone(),
+            output,
+            vkey: cmd_async.vkey_for_exit_code(code).map(str::to_owned),
+        });
+        if let Some(wakeup) = wakeup {
+            let _ = wakeup.send(KeyEvent::new(OsCode::KEY_RESERVED, KeyValue::WakeUp));
+        }
+    });
+}
+
+#[cfg(feature = "simulated_output")]
+fn run_cmd_async_in_thread(
+    cmd_async: CmdAsync,
+    env: [(&'static str, String); 2],
+    tx: mpsc::Sender<CmdAsyncResult>,
+    wakeup: Option<mpsc::SyncSender<KeyEvent>>,
+) {
+    println!("cmd-async:{:?} env:{env:?}", cmd_async.cmd);
+    let _ = tx.send(CmdAsyncResult {
+        var: cmd_async.var.clone(),
+        output: Some(String::new()),
+        vkey: cmd_async.vkey_for_exit_code(Some(0)).map(str::to_owned),
+    });
+    if let Some(wakeup) = wakeup {
+        let _ = wakeup.send(KeyEvent::new(OsCode::KEY_RESERVED, KeyValue::WakeUp));
+    }
+}
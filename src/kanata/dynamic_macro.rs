@@ -1,11 +1,23 @@
 use std::collections::VecDeque;
+use std::path::Path;
 
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+use anyhow::anyhow;
+use anyhow::{bail, Result};
 use kanata_keyberon::layout::Event;
 use kanata_parser::cfg::ReplayDelayBehaviour;
 use kanata_parser::keys::OsCode;
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+use kanata_parser::keys::{key_name, str_to_oscode};
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+use kanata_tcp_protocol::{DynamicMacro, DynamicMacroEvent, KeyEventValue};
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+use once_cell::sync::Lazy;
 use rustc_hash::FxHashMap as HashMap;
 use rustc_hash::FxHashSet as HashSet;
 
+use super::file_writer::*;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DynamicMacroItem {
     Press((OsCode, u16)),
@@ -17,6 +29,64 @@ pub struct DynamicMacroReplayState {
     active_macros: HashSet<u16>,
     delay_remaining: u16,
     macro_items: VecDeque<DynamicMacroItem>,
+    /// Playback speeds in percent of the recorded speed, with the innermost nested macro last.
+    speeds: Vec<u16>,
+    /// Remaining repetitions and items of the active macros that are played more than once.
+    repeats: HashMap<u16, (u16, Vec<DynamicMacroItem>)>,
+}
+
+impl DynamicMacroReplayState {
+    fn new() -> Self {
+        Self {
+            active_macros: HashSet::default(),
+            delay_remaining: 0,
+            macro_items: VecDeque::new(),
+            speeds: vec![],
+            repeats: HashMap::default(),
+        }
+    }
+
+    fn speed(&self) -> u16 {
+        self.speeds.last().copied().unwrap_or(100)
+    }
+
+    /// Plays the items of the macro next, `count` times at `speed` percent.
+    fn start_macro(&mut self, macro_id: u16, items: &[DynamicMacroItem], count: u16, speed: u16) {
+        self.active_macros.insert(macro_id);
+        self.speeds.push(speed);
+        if count > 1 {
+            self.repeats.insert(macro_id, (count - 1, items.to_vec()));
+        }
+        self.queue_macro(macro_id, items);
+    }
+
+    fn queue_macro(&mut self, macro_id: u16, items: &[DynamicMacroItem]) {
+        self.macro_items
+            .push_front(DynamicMacroItem::EndMacro(macro_id));
+        for item in items.iter().copied().rev() {
+            self.macro_items.push_front(item);
+        }
+    }
+
+    fn end_macro(&mut self, macro_id: u16) {
+        if let Some((remaining, items)) = self.repeats.get_mut(&macro_id) {
+            if *remaining > 0 {
+                *remaining -= 1;
+                log::debug!("repeating macro {macro_id}, {remaining} repetitions left after this");
+                let items = items.clone();
+                self.queue_macro(macro_id, &items);
+                return;
+            }
+            self.repeats.remove(&macro_id);
+        }
+        self.active_macros.remove(&macro_id);
+        self.speeds.pop();
+    }
+}
+
+/// Scales a delay of a macro for playback at `speed` percent of the recorded speed.
+fn scale_delay(delay: u16, speed: u16) -> u16 {
+    (u32::from(delay) * 100 / u32::from(speed.max(1))).min(u16::MAX.into()) as u16
 }
 
 pub struct DynamicMacroRecordState {
@@ -41,6 +111,10 @@ impl DynamicMacroRecordState {
         }
     }
 
+    pub fn macro_id(&self) -> u16 {
+        self.starting_macro_id
+    }
+
     fn add_release_for_all_unreleased_presses(&mut self) {
         let mut pressed_oscs = HashSet::default();
         for item in self.macro_items.iter() {
@@ -112,7 +186,8 @@ pub fn tick_replay_state(
     if let Some(state) = replay_state {
         state.delay_remaining = state.delay_remaining.saturating_sub(1);
         if state.delay_remaining == 0 {
-            state.delay_remaining = 5;
+            let speed = state.speed();
+            state.delay_remaining = scale_delay(5, speed).max(1);
             match state.macro_items.pop_front() {
                 None => {
                     *replay_state = None;
@@ -125,6 +200,7 @@ pub fn tick_replay_state(
                         let delay = match replay_behaviour.delay {
                             ReplayDelayBehaviour::Constant => 0,
                             ReplayDelayBehaviour::Recorded => {
+                                let delay = scale_delay(delay, speed);
                                 state.delay_remaining = delay;
                                 delay
                             }
@@ -136,6 +212,7 @@ pub fn tick_replay_state(
                         let delay = match replay_behaviour.delay {
                             ReplayDelayBehaviour::Constant => 0,
                             ReplayDelayBehaviour::Recorded => {
+                                let delay = scale_delay(delay, speed);
                                 state.delay_remaining = delay;
                                 delay
                             }
@@ -143,7 +220,7 @@ pub fn tick_replay_state(
                         Some(ReplayEvent(event, delay))
                     }
                     DynamicMacroItem::EndMacro(macro_id) => {
-                        state.active_macros.remove(&macro_id);
+                        state.end_macro(macro_id);
                         None
                     }
                 },
@@ -281,19 +358,27 @@ pub fn play_macro(
     macro_id: u16,
     replay_state: &mut Option<DynamicMacroReplayState>,
     recorded_macros: &HashMap<u16, Vec<DynamicMacroItem>>,
+) {
+    play_macro_repeat(macro_id, 1, None, replay_state, recorded_macros);
+}
+
+/// Plays the macro `count` times. The speed is in percent of the recorded speed; if it is `None`,
+/// a macro nested in another macro is played at the speed of the outer macro.
+pub fn play_macro_repeat(
+    macro_id: u16,
+    count: u16,
+    speed: Option<u16>,
+    replay_state: &mut Option<DynamicMacroReplayState>,
+    recorded_macros: &HashMap<u16, Vec<DynamicMacroItem>>,
 ) {
     match replay_state {
         None => {
             log::info!("replaying macro {macro_id}");
             *replay_state = recorded_macros.get(&macro_id).map(|macro_items| {
-                let mut active_macros = HashSet::default();
-                active_macros.insert(macro_id);
                 log::debug!("playing macro {macro_items:?}");
-                DynamicMacroReplayState {
-                    active_macros,
-                    delay_remaining: 0,
-                    macro_items: macro_items.clone().into(),
-                }
+                let mut state = DynamicMacroReplayState::new();
+                state.start_macro(macro_id, macro_items, count, speed.unwrap_or(100));
+                state
             });
         }
         Some(state) => {
@@ -302,14 +387,305 @@ pub fn play_macro(
             } else if let Some(items) = recorded_macros.get(&macro_id) {
                 log::debug!("prepending macro {macro_id} items to current replay");
                 log::debug!("playing macro {items:?}");
-                state.active_macros.insert(macro_id);
-                state
-                    .macro_items
-                    .push_front(DynamicMacroItem::EndMacro(macro_id));
-                for item in items.iter().copied().rev() {
-                    state.macro_items.push_front(item);
+                let speed = speed.unwrap_or(state.speed());
+                state.start_macro(macro_id, items, count, speed);
+            }
+        }
+    }
+}
+
+/// Pauses of a dynamic macro that are shorter than this many ms are not written to the
+/// configuration text of the macro.
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+const CFG_TEXT_MIN_DELAY: u32 = 100;
+
+/// Modifiers with their prefix in the keys of a `macro` action.
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+const MOD_PREFIXES: &[(OsCode, &str)] = &[
+    (OsCode::KEY_LEFTSHIFT, "S-"),
+    (OsCode::KEY_RIGHTSHIFT, "RS-"),
+    (OsCode::KEY_LEFTCTRL, "C-"),
+    (OsCode::KEY_RIGHTCTRL, "RC-"),
+    (OsCode::KEY_LEFTALT, "A-"),
+    (OsCode::KEY_RIGHTALT, "RA-"),
+    (OsCode::KEY_LEFTMETA, "M-"),
+    (OsCode::KEY_RIGHTMETA, "RM-"),
+];
+
+/// Returns the macro as a `macro` action for the configuration.
+///
+/// Keys are written as taps with the modifiers that are held at the time, e.g. `S-a`, so
+/// overlapping presses of other keys are written in the order they are pressed. Pauses of at
+/// least [`CFG_TEXT_MIN_DELAY`] ms are kept as delays. Returns `None` if a key of the macro has
+/// no name in the configuration.
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+pub fn macro_cfg_text(items: &[DynamicMacroItem]) -> Option<String> {
+    // Held modifiers and whether another key was pressed while they were held.
+    let mut held_mods: Vec<(OsCode, bool)> = vec![];
+    let mut parts: Vec<String> = vec![];
+    let mut delay: u32 = 0;
+    let push_tap = |parts: &mut Vec<String>, delay: &mut u32, prefix: String, key: OsCode| {
+        if *delay >= CFG_TEXT_MIN_DELAY {
+            parts.push(delay.to_string());
+        }
+        *delay = 0;
+        let name = macro_key_name(key)?;
+        parts.push(format!("{prefix}{name}"));
+        Some(())
+    };
+    for item in items.iter() {
+        match *item {
+            DynamicMacroItem::Press((key, key_delay)) => {
+                if MOD_PREFIXES.iter().any(|(m, _)| *m == key) {
+                    if !held_mods.iter().any(|(m, _)| *m == key) {
+                        held_mods.push((key, false));
+                    }
+                } else {
+                    let prefix: String = held_mods
+                        .iter_mut()
+                        .map(|(m, used)| {
+                            *used = true;
+                            mod_prefix(*m)
+                        })
+                        .collect();
+                    push_tap(&mut parts, &mut delay, prefix, key)?;
+                }
+                delay += u32::from(key_delay);
+            }
+            DynamicMacroItem::Release((key, key_delay)) => {
+                if let Some(idx) = held_mods.iter().position(|(m, _)| *m == key) {
+                    // A modifier that is released without pressing another key is a tap.
+                    if !held_mods.remove(idx).1 {
+                        push_tap(&mut parts, &mut delay, String::new(), key)?;
+                    }
                 }
+                delay += u32::from(key_delay);
             }
+            DynamicMacroItem::EndMacro(_) => {}
         }
     }
+    Some(format!("(macro {})", parts.join(" ")))
+}
+
+/// Returns the name of the key in a `macro` action, where a bare number is a delay, so digits
+/// are written as e.g. `Digit1`. Returns `None` if the key has no name in the configuration.
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+fn macro_key_name(key: OsCode) -> Option<String> {
+    let name = key_name(key);
+    let name = match name.parse::<u16>() {
+        Ok(_) => format!("Digit{name}"),
+        Err(_) => name,
+    };
+    (str_to_oscode(&name) == Some(key)).then_some(name)
+}
+
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+fn mod_prefix(key: OsCode) -> &'static str {
+    MOD_PREFIXES
+        .iter()
+        .find(|(m, _)| *m == key)
+        .map(|(_, prefix)| *prefix)
+        .unwrap_or_default()
+}
+
+/// Returns the name of the key for dynamic macro events.
+///
+/// This is the name of the key in the configuration if it has one that is read back as the same
+/// key, otherwise the name of the [`OsCode`] variant, e.g. `KEY_F13`.
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+fn event_key_name(key: OsCode) -> String {
+    let name = key_name(key);
+    match str_to_oscode(&name) == Some(key) {
+        true => name,
+        false => format!("{key:?}"),
+    }
+}
+
+/// Keys by the names of their [`OsCode`] variants, for keys without a configuration name.
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+static OSCODES_BY_VARIANT_NAME: Lazy<HashMap<String, OsCode>> = Lazy::new(|| {
+    (0..=OsCode::KEY_MAX as u16)
+        .filter_map(OsCode::from_u16)
+        .map(|key| (format!("{key:?}"), key))
+        .collect()
+});
+
+/// Returns the key of a name written by [`event_key_name`].
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+fn event_key(name: &str) -> Option<OsCode> {
+    str_to_oscode(name).or_else(|| OSCODES_BY_VARIANT_NAME.get(name).copied())
+}
+
+/// Returns the macro as a list of key events, e.g. for TCP clients.
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+pub fn macro_to_dynamic_macro(id: u16, items: &[DynamicMacroItem]) -> DynamicMacro {
+    let events = items
+        .iter()
+        .filter_map(|item| {
+            let (key, action, delay) = match *item {
+                DynamicMacroItem::Press((key, delay)) => (key, KeyEventValue::Press, delay),
+                DynamicMacroItem::Release((key, delay)) => (key, KeyEventValue::Release, delay),
+                DynamicMacroItem::EndMacro(_) => return None,
+            };
+            Some(DynamicMacroEvent {
+                key: event_key_name(key),
+                action,
+                delay,
+            })
+        })
+        .collect();
+    DynamicMacro {
+        id,
+        events,
+        cfg_text: macro_cfg_text(items),
+    }
+}
+
+/// Returns all macros as lists of key events, ordered by id.
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+pub fn macros_to_dynamic_macros(macros: &HashMap<u16, Vec<DynamicMacroItem>>) -> Vec<DynamicMacro> {
+    let mut macros: Vec<DynamicMacro> = macros
+        .iter()
+        .map(|(id, items)| macro_to_dynamic_macro(*id, items))
+        .collect();
+    macros.sort_by_key(|m| m.id);
+    macros
+}
+
+/// Converts key events to the items of a macro.
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+pub fn dynamic_macro_events_to_macro(
+    events: &[DynamicMacroEvent],
+) -> Result<Vec<DynamicMacroItem>> {
+    events
+        .iter()
+        .map(|event| {
+            let key = event_key(&event.key).ok_or_else(|| anyhow!("unknown key: {}", event.key))?;
+            Ok(match event.action {
+                KeyEventValue::Press => DynamicMacroItem::Press((key, event.delay)),
+                KeyEventValue::Release => DynamicMacroItem::Release((key, event.delay)),
+                KeyEventValue::Repeat => bail!("dynamic macros cannot contain repeat events"),
+            })
+        })
+        .collect()
+}
+
+/// Checks that every key of the macro is released after it is pressed, so that playing the macro
+/// does not leave keys held.
+#[cfg(any(feature = "json", feature = "tcp_server"))]
+pub fn check_macro_releases(items: &[DynamicMacroItem]) -> Result<()> {
+    let mut pressed = HashSet::default();
+    for item in items {
+        match item {
+            DynamicMacroItem::Press((key, _)) => {
+                if !pressed.insert(*key) {
+                    bail!(
+                        "{} is pressed twice without a release",
+                        event_key_name(*key)
+                    );
+                }
+            }
+            // A recording starts with the release of the key that started it, so releases
+            // without a press are allowed.
+            DynamicMacroItem::Release((key, _)) => {
+                pressed.remove(key);
+            }
+            DynamicMacroItem::EndMacro(_) => {}
+        }
+    }
+    if let Some(key) = pressed.into_iter().next() {
+        bail!("{} is pressed but never released", event_key_name(key));
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "json"))]
+pub fn load_macros(_path: &Path) -> Result<HashMap<u16, Vec<DynamicMacroItem>>> {
+    bail!("dynamic-macro-file requires kanata to be built with the json feature")
+}
+
+/// Reads the dynamic macros that were saved to the file. A missing file has no macros.
+#[cfg(feature = "json")]
+pub fn load_macros(path: &Path) -> Result<HashMap<u16, Vec<DynamicMacroItem>>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::default()),
+        Err(e) => bail!("could not read {}: {e}", path.display()),
+    };
+    let macros: Vec<DynamicMacro> = serde_json::from_str(&text)?;
+    macros
+        .into_iter()
+        .map(|m| {
+            let items = dynamic_macro_events_to_macro(&m.events)
+                .and_then(|items| check_macro_releases(&items).map(|()| items))
+                .map_err(|e| anyhow!("dynamic macro {}: {e}", m.id))?;
+            Ok((m.id, items))
+        })
+        .collect()
+}
+
+/// Returns the dynamic macros saved to the file from `dynamic-macro-file`, or no macros if it is
+/// not set or cannot be read.
+pub fn load_dynamic_macros(path: Option<&str>) -> HashMap<u16, Vec<DynamicMacroItem>> {
+    let Some(path) = path else {
+        return HashMap::default();
+    };
+    match load_macros(Path::new(path)) {
+        Ok(macros) => {
+            log::info!("loaded {} dynamic macros from {path}", macros.len());
+            macros
+        }
+        Err(e) => {
+            log::error!("could not load dynamic macros from {path}: {e}");
+            HashMap::default()
+        }
+    }
+}
+
+/// Store a recorded macro and save all macros to the file from `dynamic-macro-file`, if set.
+pub fn store_macro(
+    macros: &mut HashMap<u16, Vec<DynamicMacroItem>>,
+    file: Option<&Path>,
+    macro_id: u16,
+    items: Vec<DynamicMacroItem>,
+) {
+    macros.insert(macro_id, items);
+    save_dynamic_macros(file, macros);
+}
+
+/// Queues all macros to be written to the file from `dynamic-macro-file`, if set.
+pub fn save_dynamic_macros(file: Option<&Path>, macros: &HashMap<u16, Vec<DynamicMacroItem>>) {
+    if let Some(path) = file {
+        match macros_json(macros) {
+            Ok(json) => write_in_background(path, json),
+            Err(e) => log::error!("could not save dynamic macros: {e}"),
+        }
+    }
+}
+
+/// Writes all macros to the file from `dynamic-macro-file`, if set, and waits until they are
+/// written.
+pub fn save_dynamic_macros_and_wait(
+    file: Option<&Path>,
+    macros: &HashMap<u16, Vec<DynamicMacroItem>>,
+) {
+    if let Some(path) = file {
+        match macros_json(macros) {
+            Ok(json) => write_and_wait(path, json),
+            Err(e) => log::error!("could not save dynamic macros: {e}"),
+        }
+    }
+}
+
+#[cfg(not(feature = "json"))]
+fn macros_json(_macros: &HashMap<u16, Vec<DynamicMacroItem>>) -> Result<String> {
+    bail!("dynamic-macro-file requires kanata to be built with the json feature")
+}
+
+/// Returns the file contents for all dynamic macros, ordered by id.
+#[cfg(feature = "json")]
+fn macros_json(macros: &HashMap<u16, Vec<DynamicMacroItem>>) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&macros_to_dynamic_macros(
+        macros,
+    ))?)
 }
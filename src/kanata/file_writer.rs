@@ -0,0 +1,82 @@
+//! Writing of the files that kanata keeps up to date while it runs, such as `statistics-file`.
+//!
+//! Files are written by a background thread so that the processing loop does not wait for the
+//! disk. Every write goes to a temporary file next to the file that then replaces it, so that a
+//! write that is interrupted, e.g. by kanata exiting, does not leave a truncated file behind.
+
+use once_cell::sync::Lazy;
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender};
+use std::time::Duration;
+
+/// How long [`write_and_wait`] waits for the background thread.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+struct FileWrite {
+    path: PathBuf,
+    contents: String,
+    /// Notified once the file is written.
+    done: Option<Sender<()>>,
+}
+
+static WRITER: Lazy<Sender<FileWrite>> = Lazy::new(|| {
+    let (tx, rx) = channel::<FileWrite>();
+    std::thread::spawn(move || {
+        for write in rx {
+            if let Err(e) = write_atomically(&write.path, &write.contents) {
+                log::error!("could not write {}: {e}", write.path.display());
+            }
+            if let Some(done) = write.done {
+                let _ = done.send(());
+            }
+        }
+    });
+    tx
+});
+
+/// Queues the contents to be written to the file by the background thread.
+pub fn write_in_background(path: &Path, contents: String) {
+    send(path, contents, None);
+}
+
+/// Writes the contents to the file and waits until it is written, e.g. when kanata exits.
+///
+/// The write goes through the background thread anyway so that it cannot be overtaken by an
+/// earlier write that is still queued.
+pub fn write_and_wait(path: &Path, contents: String) {
+    let (done_tx, done_rx) = channel();
+    send(path, contents, Some(done_tx));
+    if done_rx.recv_timeout(WAIT_TIMEOUT).is_err() {
+        log::error!("timed out writing {}", path.display());
+    }
+}
+
+fn send(path: &Path, contents: String, done: Option<Sender<()>>) {
+    let write = FileWrite {
+        path: path.to_owned(),
+        contents,
+        done,
+    };
+    if WRITER.send(write).is_err() {
+        log::error!(
+            "could not write {}: the writer thread stopped",
+            path.display()
+        );
+    }
+}
+
+/// Writes the contents to a temporary file and renames it to `path`.
+fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    let tmp = with_suffix(path, ".tmp");
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Returns the path with the suffix appended to its file name, e.g. `stats.json.tmp`.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
@@ -0,0 +1,459 @@
+//! Diagnosis of hold-tap decisions, enabled with `hold-tap-analysis-file` in `defcfg`.
+//!
+//! Every decision of a hold-tap action on a `defsrc` key is recorded together with the input
+//! events around it. Decisions that were likely not what the user intended are counted as
+//! misfires, and a report with the counts per key, suggestions and the most recent decisions is
+//! written to the file periodically and when kanata exits.
+
+use kanata_keyberon::action::{Action, HoldTapConfig};
+use kanata_parser::keys::OsCode;
+use kanata_tcp_protocol::HoldTapResolution;
+
+use instant::Instant;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::file_writer::*;
+
+/// Interval between writes of the report.
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+/// A backspace within this time after the release of a key that resolved to hold suggests that
+/// the hold was not intended.
+const CORRECTION_WINDOW: Duration = Duration::from_secs(1);
+/// Number of recent input events that are kept to find the events before a decision.
+const RECENT_EVENTS_LEN: usize = 64;
+/// Number of events that are recorded per decision.
+const DECISION_EVENTS_LEN: usize = 32;
+/// Number of decisions that are listed in the report.
+const REPORT_DECISIONS_LEN: usize = 200;
+
+/// The variant and timeout of a hold-tap action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HoldTapInfo {
+    pub variant: &'static str,
+    pub timeout: u16,
+}
+
+impl HoldTapInfo {
+    /// Returns the info of the action if it is a hold-tap action.
+    pub fn of<T: std::fmt::Debug>(action: &Action<T>) -> Option<Self> {
+        let Action::HoldTap(ht) = action else {
+            return None;
+        };
+        let variant = match ht.config {
+            HoldTapConfig::Default => "tap-hold",
+            HoldTapConfig::HoldOnOtherKeyPress => "tap-hold-press",
+            HoldTapConfig::PermissiveHold => "tap-hold-release",
+            HoldTapConfig::OppositeHand(_) => "tap-hold-opposite-hand",
+            _ => "custom tap-hold",
+        };
+        Some(Self {
+            variant,
+            timeout: ht.timeout,
+        })
+    }
+}
+
+/// Kinds of decisions that were likely not intended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Misfire {
+    /// Resolved to hold, then the next key pressed after the release was backspace.
+    Corrected,
+    /// Resolved to hold because of another key that was still held when the hold-tap key was
+    /// released, which is typical for fast typing rather than for using the hold action.
+    Rolled,
+    /// Resolved to hold by the timeout and released without pressing any other key.
+    HeldAlone,
+}
+
+impl Misfire {
+    fn description(self) -> &'static str {
+        match self {
+            Misfire::Corrected => "followed by backspace",
+            Misfire::Rolled => "rolled into the next key",
+            Misfire::HeldAlone => "held past the timeout without another key",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct InputRecord {
+    key: OsCode,
+    pressed: bool,
+    time: Instant,
+    /// The hold-tap action of the key if it is a press of a hold-tap key.
+    hold_tap: Option<HoldTapInfo>,
+}
+
+#[derive(Debug)]
+struct Decision {
+    key: OsCode,
+    hold_tap: Option<HoldTapInfo>,
+    resolution: HoldTapResolution,
+    press_time: Instant,
+    decision_time: Instant,
+    release_time: Option<Instant>,
+    /// Input events from the press of the key up to the first press after its release.
+    events: Vec<InputRecord>,
+}
+
+impl Decision {
+    /// Returns the misfire kind of a finished decision, if it looks like one.
+    fn misfire(&self) -> Option<Misfire> {
+        if self.resolution == HoldTapResolution::Tap {
+            return None;
+        }
+        let release = self.release_time?;
+        let others_while_held: Vec<&InputRecord> = self
+            .events
+            .iter()
+            .filter(|e| e.key != self.key && e.time <= release)
+            .collect();
+        let next_press = self
+            .events
+            .iter()
+            .find(|e| e.pressed && e.time > release && e.key != self.key);
+        if next_press.is_some_and(|e| {
+            e.key == OsCode::KEY_BACKSPACE && e.time.duration_since(release) <= CORRECTION_WINDOW
+        }) {
+            return Some(Misfire::Corrected);
+        }
+        if self.resolution == HoldTapResolution::Hold {
+            let first_press = others_while_held.iter().find(|e| e.pressed)?;
+            let released_while_held = others_while_held
+                .iter()
+                .any(|e| e.key == first_press.key && !e.pressed);
+            return (!released_while_held).then_some(Misfire::Rolled);
+        }
+        (!others_while_held.iter().any(|e| e.pressed)).then_some(Misfire::HeldAlone)
+    }
+
+    fn describe(&self, misfire: Option<Misfire>) -> String {
+        let mut out = format!("{}", self.key);
+        if let Some(info) = self.hold_tap {
+            let _ = write!(out, " ({} {} ms)", info.variant, info.timeout);
+        }
+        let resolution = match self.resolution {
+            HoldTapResolution::Tap => "tap",
+            HoldTapResolution::Hold => "hold",
+            HoldTapResolution::Timeout => "hold by timeout",
+        };
+        let _ = write!(
+            out,
+            ": {resolution} after {} ms;",
+            millis_between(self.press_time, self.decision_time)
+        );
+        for e in self.events.iter() {
+            let arrow = if e.pressed { '↓' } else { '↑' };
+            let _ = write!(
+                out,
+                " {}{arrow}+{}",
+                e.key,
+                millis_between(self.press_time, e.time)
+            );
+        }
+        if let Some(misfire) = misfire {
+            let _ = write!(out, "; likely misfire: {}", misfire.description());
+        }
+        out
+    }
+}
+
+/// Decision counts of a key.
+#[derive(Debug, Default)]
+struct KeySummary {
+    hold_tap: Option<HoldTapInfo>,
+    taps: u64,
+    holds: u64,
+    timeouts: u64,
+    corrected: u64,
+    rolled: u64,
+    held_alone: u64,
+    /// The longest time that the key was held alone after its timeout, in ms.
+    max_held_alone: u64,
+}
+
+impl KeySummary {
+    fn suggestions(&self) -> Vec<String> {
+        let mut suggestions = vec![];
+        let variant = self.hold_tap.map(|info| info.variant).unwrap_or_default();
+        if self.rolled > 0 && variant == "tap-hold-press" {
+            suggestions.push("use tap-hold-release so that rolls resolve to tap".to_owned());
+        } else if self.rolled > 0 || self.corrected > 0 {
+            suggestions.push("use a longer timeout".to_owned());
+        }
+        if self.held_alone > 0 {
+            // Round up to the next 10 ms above the longest hold.
+            let timeout = (self.max_held_alone / 10 + 1) * 10;
+            suggestions.push(format!(
+                "taps were held up to {} ms, consider a timeout above {timeout} ms",
+                self.max_held_alone
+            ));
+        }
+        suggestions
+    }
+}
+
+/// Returns the milliseconds from `earlier` to `later`.
+fn millis_between(earlier: Instant, later: Instant) -> u64 {
+    later.duration_since(earlier).as_millis() as u64
+}
+
+pub struct HoldTapAnalyzer {
+    path: PathBuf,
+    recent: VecDeque<InputRecord>,
+    /// Decisions whose key is still held or that wait for the next press after the release.
+    open: Vec<Decision>,
+    /// The most recent finished decisions with their misfire kind.
+    finished: VecDeque<(Decision, Option<Misfire>)>,
+    summaries: BTreeMap<String, KeySummary>,
+    last_save: Instant,
+    /// Whether the report changed since it was last written.
+    changed: bool,
+}
+
+impl HoldTapAnalyzer {
+    pub fn new(path: &str) -> Self {
+        log::info!("writing hold-tap analysis to {path}");
+        Self {
+            path: PathBuf::from(path),
+            recent: VecDeque::new(),
+            open: vec![],
+            finished: VecDeque::new(),
+            summaries: BTreeMap::new(),
+            last_save: Instant::now(),
+            changed: false,
+        }
+    }
+
+    /// Returns the analyzer for a reloaded configuration, keeping the current one if the file
+    /// did not change.
+    pub fn reconfigure(prev: Option<Self>, path: Option<&str>) -> Option<Self> {
+        match (prev, path) {
+            (Some(prev), Some(path)) if prev.path == Path::new(path) => Some(prev),
+            (prev, path) => {
+                if let Some(mut prev) = prev {
+                    prev.save();
+                }
+                path.map(Self::new)
+            }
+        }
+    }
+
+    /// Record an input event of a `defsrc` key at `now`. For presses, `hold_tap` is the hold-tap
+    /// action of the key on the active layer, if it has one.
+    pub fn record_event(
+        &mut self,
+        key: OsCode,
+        pressed: bool,
+        hold_tap: Option<HoldTapInfo>,
+        now: Instant,
+    ) {
+        let record = InputRecord {
+            key,
+            pressed,
+            time: now,
+            hold_tap,
+        };
+        if self.recent.len() == RECENT_EVENTS_LEN {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(record);
+
+        let mut i = 0;
+        while i < self.open.len() {
+            let decision = &mut self.open[i];
+            if decision.events.len() < DECISION_EVENTS_LEN {
+                decision.events.push(record);
+            }
+            match (decision.release_time, pressed) {
+                (None, false) if key == decision.key => decision.release_time = Some(now),
+                (Some(_), true) => {
+                    let decision = self.open.remove(i);
+                    self.finish(decision, now);
+                    continue;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    /// Record the decision of the hold-tap action on the key at `now`, `ticks` after its press.
+    pub fn record_decision(
+        &mut self,
+        key: OsCode,
+        resolution: HoldTapResolution,
+        ticks: u16,
+        now: Instant,
+    ) {
+        let press = self.recent.iter().rposition(|e| e.key == key && e.pressed);
+        let (press_time, hold_tap, events) = match press {
+            Some(idx) => (
+                self.recent[idx].time,
+                self.recent[idx].hold_tap,
+                self.recent
+                    .iter()
+                    .skip(idx)
+                    .take(DECISION_EVENTS_LEN)
+                    .copied()
+                    .collect(),
+            ),
+            None => (
+                now.checked_sub(Duration::from_millis(ticks.into()))
+                    .unwrap_or(now),
+                None,
+                vec![],
+            ),
+        };
+        // The key may already be released, e.g. if it was tapped.
+        let release_time = events
+            .iter()
+            .find(|e| e.key == key && !e.pressed)
+            .map(|e| e.time);
+        self.open.push(Decision {
+            key,
+            hold_tap,
+            resolution,
+            press_time,
+            decision_time: now,
+            release_time,
+            events,
+        });
+    }
+
+    /// Finish the decisions whose correction window is over at `now` and write the report if it
+    /// is due.
+    pub fn tick(&mut self, now: Instant) {
+        let (done, open): (Vec<Decision>, Vec<Decision>) =
+            std::mem::take(&mut self.open).into_iter().partition(|d| {
+                d.release_time
+                    .is_some_and(|release| now.duration_since(release) > CORRECTION_WINDOW)
+            });
+        self.open = open;
+        for decision in done {
+            self.finish(decision, now);
+        }
+        if now.duration_since(self.last_save) >= SAVE_INTERVAL {
+            self.save();
+        }
+    }
+
+    fn finish(&mut self, decision: Decision, now: Instant) {
+        let misfire = decision.misfire();
+        if let Some(misfire) = misfire {
+            log::debug!(
+                "likely hold-tap misfire: {}",
+                decision.describe(Some(misfire))
+            );
+        }
+        let summary = self.summaries.entry(decision.key.to_string()).or_default();
+        summary.hold_tap = decision.hold_tap.or(summary.hold_tap);
+        match decision.resolution {
+            HoldTapResolution::Tap => summary.taps += 1,
+            HoldTapResolution::Hold => summary.holds += 1,
+            HoldTapResolution::Timeout => summary.timeouts += 1,
+        }
+        match misfire {
+            Some(Misfire::Corrected) => summary.corrected += 1,
+            Some(Misfire::Rolled) => summary.rolled += 1,
+            Some(Misfire::HeldAlone) => {
+                summary.held_alone += 1;
+                let release = decision.release_time.unwrap_or(now);
+                let held = millis_between(decision.press_time, release);
+                summary.max_held_alone = summary.max_held_alone.max(held);
+            }
+            None => {}
+        }
+        if self.finished.len() == REPORT_DECISIONS_LEN {
+            self.finished.pop_front();
+        }
+        self.finished.push_back((decision, misfire));
+        self.changed = true;
+    }
+
+    /// Returns the text of the report.
+    pub fn report(&self) -> String {
+        let mut out = String::from("Hold-tap analysis written by kanata\n\n");
+        let header = [
+            "key",
+            "variant",
+            "timeout",
+            "tap",
+            "hold",
+            "timed out",
+            "backspace",
+            "rolled",
+            "alone",
+        ];
+        let mut rows: Vec<Vec<String>> = vec![header.iter().map(|h| h.to_string()).collect()];
+        for (key, s) in self.summaries.iter() {
+            rows.push(vec![
+                key.clone(),
+                s.hold_tap.map(|i| i.variant).unwrap_or("?").to_owned(),
+                s.hold_tap
+                    .map(|i| format!("{} ms", i.timeout))
+                    .unwrap_or_else(|| "?".to_owned()),
+                s.taps.to_string(),
+                s.holds.to_string(),
+                s.timeouts.to_string(),
+                s.corrected.to_string(),
+                s.rolled.to_string(),
+                s.held_alone.to_string(),
+            ]);
+        }
+        let mut widths = vec![0; header.len()];
+        for row in rows.iter() {
+            for (width, item) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(item.chars().count());
+            }
+        }
+        for row in rows.iter() {
+            let line: Vec<String> = row
+                .iter()
+                .zip(widths.iter())
+                .map(|(item, width)| format!("{item:width$}"))
+                .collect();
+            let _ = writeln!(out, "{}", line.join("  ").trim_end());
+        }
+
+        out.push_str("\nSuggestions:\n");
+        let mut any_suggestion = false;
+        for (key, summary) in self.summaries.iter() {
+            for suggestion in summary.suggestions() {
+                any_suggestion = true;
+                let _ = writeln!(out, "- {key}: {suggestion}");
+            }
+        }
+        if !any_suggestion {
+            out.push_str("- none\n");
+        }
+
+        out.push_str("\nRecent decisions, with input events in ms since the press:\n");
+        for (decision, misfire) in self.finished.iter().rev() {
+            let _ = writeln!(out, "- {}", decision.describe(*misfire));
+        }
+        out
+    }
+
+    /// Queue the report to be written to the file if it changed.
+    pub fn save(&mut self) {
+        if let Some(report) = self.changed_report() {
+            write_in_background(&self.path, report);
+        }
+    }
+
+    /// Write the report to the file if it changed and wait until it is written.
+    pub fn save_and_wait(&mut self) {
+        if let Some(report) = self.changed_report() {
+            write_and_wait(&self.path, report);
+        }
+    }
+
+    fn changed_report(&mut self) -> Option<String> {
+        self.last_save = Instant::now();
+        std::mem::take(&mut self.changed).then(|| self.report())
+    }
+}
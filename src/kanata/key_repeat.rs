@@ -4,8 +4,10 @@ impl Kanata {
     /// This compares the active keys in the keyberon layout against the potential key outputs for
     /// corresponding physical key in the configuration. If any of keyberon active keys match any
     /// potential physical key output, write the repeat event to the OS.
-    pub(super) fn handle_repeat(&mut self, event: &KeyEvent) -> Result<()> {
-        let ret = self.handle_repeat_actual(event);
+    ///
+    /// `coord` is the layout coordinate of the event's key, as returned by `input_coord`.
+    pub(super) fn handle_repeat(&mut self, event: &KeyEvent, coord: (u8, u16)) -> Result<()> {
+        let ret = self.handle_repeat_actual(event, coord);
         // The cur_keys Vec is re-used for processing, for efficiency reasons to avoid allocation.
         // Unlike prev_keys which has useful info for the next call to handle_time_ticks, cur_keys
         // can be reused and cleared — it just needs to be empty for the next handle_time_ticks
@@ -14,7 +16,11 @@ impl Kanata {
         ret
     }
 
-    pub(super) fn handle_repeat_actual(&mut self, event: &KeyEvent) -> Result<()> {
+    pub(super) fn handle_repeat_actual(
+        &mut self,
+        event: &KeyEvent,
+        coord: (u8, u16),
+    ) -> Result<()> {
         if let Some(state) = self.sequence_state.get_active() {
             // While in non-visible sequence mode, don't send key repeats. I can't imagine it's a
             // helpful use case for someone trying to type in a sequence that they want to rely on
@@ -30,9 +36,31 @@ impl Kanata {
                 return Ok(());
             }
         }
-        self.cur_keys.extend(self.layout.bm().keycodes());
+        if coord.0 == FAKE_KEY_ROW {
+            // The potential outputs are only known for physical keys. A defdevice key has its own
+            // coordinate instead, so repeat the key that is held down at that coordinate.
+            let held_key = self.layout.b().states.iter().rev().find_map(|s| match s {
+                State::NormalKey {
+                    keycode, coord: c, ..
+                } if *c == coord => Some(*keycode),
+                _ => None,
+            });
+            if let Some(kc) = held_key {
+                log::debug!("repeat    {kc:?}");
+                if let Err(e) = write_key(&mut self.kbd_out, kc.into(), KeyValue::Repeat) {
+                    bail!("could not write key {e:?}");
+                }
+            }
+            return Ok(());
+        }
+        let layout = self.layout.bm();
+        self.cur_keys.extend(layout.keycodes());
+        let override_ctx = OverrideContext {
+            layer: layout.current_layer(),
+            kb_states: &layout.states,
+        };
         self.overrides
-            .override_keys(&mut self.cur_keys, &mut self.override_states);
+            .override_keys(&mut self.cur_keys, &mut self.override_states, override_ctx);
 
         // Prioritize checking the active layer in case a layer-while-held is active.
         let active_held_layers = self.layout.bm().trans_resolution_layer_order();
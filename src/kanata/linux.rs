@@ -27,6 +27,7 @@ impl Kanata {
             k.include_names.clone(),
             k.exclude_names.clone(),
             k.device_detect_mode,
+            k.defdevices.clone(),
         ) {
             Ok(kbd_in) => kbd_in,
             Err(e) => {
@@ -40,11 +41,13 @@ impl Kanata {
         drop(k);
 
         loop {
+            handle_device_changes(&kanata, &tx, kbd_in.take_device_changes())?;
+
             let events = kbd_in.read().map_err(|e| anyhow!("failed read: {}", e))?;
             log::trace!("event count: {}\nevents:\n{events:?}", events.len());
 
-            for in_event in events.iter().copied() {
-                let key_event = match KeyEvent::try_from(in_event) {
+            for (in_event, defdevice_idx) in events.iter().copied() {
+                let mut key_event = match KeyEvent::try_from(in_event) {
                     Ok(ev) => ev,
                     _ => {
                         // Pass-through non-key and non-scroll events
@@ -66,24 +69,29 @@ impl Kanata {
 
                 if key_event.value == KeyValue::Tap {
                     // Scroll event for sure. Only scroll events produce Tap.
-                    if !handle_scroll(&kanata, in_event, key_event.code, &events)? {
-                        continue;
+                    for _ in 0..handle_scroll(&kanata, in_event, key_event.code, &events)? {
+                        if let Err(e) = tx.try_send(key_event) {
+                            bail!("failed to send on channel: {}", e)
+                        }
                     }
-                } else {
-                    // Handle normal keypresses.
-                    // Check if this keycode is mapped in the configuration.
-                    // If it hasn't been mapped, send it immediately.
-                    if !MAPPED_KEYS.lock().contains(&key_event.code) {
-                        let mut kanata = kanata.lock();
-                        #[cfg(not(feature = "simulated_output"))]
-                        kanata
-                            .kbd_out
-                            .write_raw(in_event)
-                            .map_err(|e| anyhow!("failed write: {}", e))?;
-                        continue;
-                    };
+                    continue;
                 }
 
+                key_event.device = defdevice_idx;
+
+                // Handle normal keypresses.
+                // Check if this keycode is mapped in the configuration.
+                // If it hasn't been mapped, send it immediately.
+                if !MAPPED_KEYS.lock().contains(&key_event.code) {
+                    let mut kanata = kanata.lock();
+                    #[cfg(not(feature = "simulated_output"))]
+                    kanata
+                        .kbd_out
+                        .write_raw(in_event)
+                        .map_err(|e| anyhow!("failed write: {}", e))?;
+                    continue;
+                };
+
                 // Send key events to the processing loop
                 if let Err(e) = tx.try_send(key_event) {
                     bail!("failed to send on channel: {}", e)
@@ -92,6 +100,49 @@ impl Kanata {
         }
     }
 
+    /// Taps the `defdevice` action for an input device that was connected or disconnected and
+    /// notifies TCP clients about the change.
+    pub fn handle_device_change(&mut self, change: &DeviceChange) {
+        let defdevice = change
+            .defdevice
+            .and_then(|idx| self.defdevices.get(usize::from(idx)));
+        log::info!(
+            "device {}: {} ({})",
+            if change.connected {
+                "connected"
+            } else {
+                "disconnected"
+            },
+            change.name,
+            change.path
+        );
+        let column = defdevice.and_then(|defdevice| match change.connected {
+            true => defdevice.on_connect,
+            false => defdevice.on_disconnect,
+        });
+        if let Some(column) = column {
+            handle_fakekey_action(FakeKeyAction::Tap, self.layout.bm(), FAKE_KEY_ROW, column);
+        }
+        #[cfg(feature = "tcp_server")]
+        {
+            let name = change.name.clone();
+            let path = change.path.clone();
+            let defdevice = defdevice.map(|defdevice| defdevice.name.clone());
+            self.event_notifications.push(match change.connected {
+                true => ServerMessage::DeviceConnected {
+                    name,
+                    path,
+                    defdevice,
+                },
+                false => ServerMessage::DeviceDisconnected {
+                    name,
+                    path,
+                    defdevice,
+                },
+            });
+        }
+    }
+
     pub fn check_release_non_physical_shift(&mut self) -> Result<()> {
         Ok(())
     }
@@ -128,14 +179,44 @@ impl Kanata {
     }
 }
 
-/// Returns true if the scroll event should be sent to the processing loop, otherwise returns
-/// false.
+/// Handles device changes from the event loop and wakes up the processing loop so that the
+/// resulting actions and notifications are processed without waiting for an input event.
+fn handle_device_changes(
+    kanata: &Mutex<Kanata>,
+    tx: &Sender<KeyEvent>,
+    changes: Vec<DeviceChange>,
+) -> Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let mut k = kanata.lock();
+    for change in changes.iter() {
+        k.handle_device_change(change);
+    }
+    drop(k);
+    if let Err(e) = tx.try_send(KeyEvent::new(OsCode::KEY_RESERVED, KeyValue::WakeUp)) {
+        bail!("failed to send on channel: {}", e)
+    }
+    Ok(())
+}
+
+/// Returns the number of taps of a mapped wheel key for a scroll event of `value` notches: one tap
+/// for each notch, in either direction.
+pub(crate) fn wheel_taps(value: i32) -> u16 {
+    // Fast scrolling can report many notches at once. Limit the taps so that they don't overflow
+    // the channel to the processing loop.
+    const MAX_TAPS_PER_EVENT: u32 = 10;
+    value.unsigned_abs().clamp(1, MAX_TAPS_PER_EVENT) as u16
+}
+
+/// Returns the number of taps of the wheel key that should be sent to the processing loop. This is
+/// zero if the scroll event is not mapped, otherwise one tap for each notch that the wheel moved.
 fn handle_scroll(
     kanata: &Mutex<Kanata>,
     in_event: InputEvent,
     code: OsCode,
-    all_events: &[InputEvent],
-) -> Result<bool> {
+    all_events: &[(InputEvent, Option<u8>)],
+) -> Result<u16> {
     let direction: MWheelDirection = code.try_into().unwrap();
     let scroll_distance = in_event.value().unsigned_abs() as u16;
     match in_event.kind() {
@@ -143,7 +224,7 @@ fn handle_scroll(
             match axis_type {
                 RelativeAxisType::REL_WHEEL | RelativeAxisType::REL_HWHEEL => {
                     if MAPPED_KEYS.lock().contains(&code) {
-                        return Ok(true);
+                        return Ok(wheel_taps(in_event.value()));
                     }
                     // If we just used `write_raw` here, some of the scrolls issued by kanata would be
                     // REL_WHEEL_HI_RES + REL_WHEEL and some just REL_WHEEL and an issue like this one
@@ -156,7 +237,7 @@ fn handle_scroll(
                     // scroll event. In this scenario, the hi-res event should be used to call
                     // scroll, and not the normal event. Otherwise, too much scrolling will happen.
                     let mut kanata = kanata.lock();
-                    if !all_events.iter().any(|ev| {
+                    if !all_events.iter().any(|(ev, _)| {
                         matches!(
                             ev.kind(),
                             InputEventKind::RelAxis(
@@ -170,7 +251,7 @@ fn handle_scroll(
                             .scroll(direction, scroll_distance * HI_RES_SCROLL_UNITS_IN_LO_RES)
                             .map_err(|e| anyhow!("failed write: {}", e))?;
                     }
-                    Ok(false)
+                    Ok(0)
                 }
                 RelativeAxisType::REL_WHEEL_HI_RES | RelativeAxisType::REL_HWHEEL_HI_RES => {
                     if !MAPPED_KEYS.lock().contains(&code) {
@@ -184,7 +265,7 @@ fn handle_scroll(
                     }
                     // Kanata will not handle high resolution scroll events for now.
                     // Full notch scrolling only.
-                    Ok(false)
+                    Ok(0)
                 }
                 _ => unreachable!("expect to be handling a wheel event"),
             }
@@ -8,10 +8,10 @@ use log::{error, info};
 use parking_lot::Mutex;
 use std::sync::mpsc::{Receiver, SyncSender as Sender, TryRecvError};
 
-#[cfg(feature = "passthru_ahk")]
+#[cfg(any(feature = "passthru_ahk", feature = "tcp_server"))]
 use std::sync::mpsc::Sender as ASender;
 
-use kanata_keyberon::action::ReleasableState;
+use kanata_keyberon::action::{Action, ReleasableState};
 use kanata_keyberon::key_code::*;
 use kanata_keyberon::layout::{CustomEvent, Event, Layout, State};
 
@@ -21,28 +21,39 @@ use std::time;
 
 use crate::oskbd::{KeyEvent, *};
 #[cfg(feature = "tcp_server")]
-use crate::tcp_server::simple_sexpr_to_json_array;
-#[cfg(feature = "tcp_server")]
-use crate::SocketAddrWrapper;
+use crate::tcp_server::{config_diagnostic, is_subscribed, simple_sexpr_to_json_array};
 use crate::ValidatedArgs;
 use kanata_parser::cfg;
 use kanata_parser::cfg::list_actions::*;
 use kanata_parser::cfg::*;
 use kanata_parser::custom_action::*;
 pub use kanata_parser::keys::*;
-use kanata_tcp_protocol::ServerMessage;
+#[cfg(feature = "tcp_server")]
+use kanata_tcp_protocol::{ConfigDiagnostic, DynamicMacro, DynamicMacroEvent};
+use kanata_tcp_protocol::{HoldTapResolution, ServerMessage};
+#[cfg(feature = "tcp_server")]
+use kanata_tcp_protocol::{KanataState, KeyEventValue, SequenceCandidate, SubscriptionTopic};
 
 mod clipboard;
 use clipboard::*;
 
 mod dynamic_macro;
+pub use dynamic_macro::DynamicMacroItem;
 use dynamic_macro::*;
 
+mod file_writer;
+
 mod key_repeat;
 
 mod sequences;
 use sequences::*;
 
+mod hold_tap_analysis;
+pub(crate) use hold_tap_analysis::{HoldTapAnalyzer, HoldTapInfo};
+
+mod statistics;
+use statistics::*;
+
 pub mod cfg_forced;
 use cfg_forced::*;
 
@@ -58,6 +69,8 @@ pub use windows::*;
 
 #[cfg(target_os = "linux")]
 mod linux;
+#[cfg(all(test, target_os = "linux"))]
+pub(crate) use linux::wheel_taps;
 
 #[cfg(target_os = "macos")]
 mod macos;
@@ -149,6 +162,9 @@ pub struct Kanata {
     time_remainder: u128,
     /// Is true if a live reload was requested by the user and false otherwise.
     live_reload_requested: bool,
+    #[cfg(feature = "tcp_server")]
+    /// Receive the result of the next live reload, which is `None` if it succeeded.
+    live_reload_replies: Vec<ASender<Option<ConfigDiagnostic>>>,
     #[cfg(target_os = "linux")]
     /// Linux input paths in the user configuration.
     pub kbd_in_paths: Vec<String>,
@@ -194,6 +210,8 @@ pub struct Kanata {
     /// Determines what types of devices to grab based on autodetection mode.
     #[cfg(target_os = "linux")]
     pub device_detect_mode: DeviceDetectMode,
+    /// Devices with their own keys defined in `defdevice`.
+    pub defdevices: Vec<DefDevice>,
     /// Fake key actions that are waiting for a certain duration of keyboard idling.
     pub waiting_for_idle: HashSet<FakeKeyOnIdle>,
     /// Fake key actions that are being held and are pending release.
@@ -218,6 +236,11 @@ pub struct Kanata {
     dynamic_macro_max_presses: u16,
     /// Determines behaviour of replayed dynamic macros.
     dynamic_macro_replay_behaviour: ReplayBehaviour,
+    /// File that dynamic macros are saved to, from `dynamic-macro-file`.
+    dynamic_macro_file: Option<PathBuf>,
+    /// Whether the dynamic macros changed since they were loaded, so that they are written to
+    /// `dynamic_macro_file` again when kanata exits.
+    dynamic_macros_changed: bool,
     /// Keys that should be unmodded. If non-empty, any modifier should be cleared.
     unmodded_keys: Vec<KeyCode>,
     /// Modifiers to be cleared in case the above is non-empty.
@@ -226,13 +249,48 @@ pub struct Kanata {
     unshifted_keys: Vec<KeyCode>,
     /// Keep track of last pressed key for [`CustomAction::Repeat`].
     last_pressed_key: KeyCode,
-    #[cfg(feature = "tcp_server")]
+    #[cfg(feature = "cmd")]
+    /// Cmd variables and running commands of [`CustomAction::CmdAsync`].
+    cmd_async: CmdAsyncState,
+    #[cfg(any(feature = "tcp_server", feature = "cmd"))]
     /// Names of fake keys mapped to their index in the fake keys row
     pub virtual_keys: HashMap<String, usize>,
+    #[cfg(feature = "tcp_server")]
+    /// Text of the actions of `defseq` mapped to their index in the fake keys row.
+    pub sequence_action_names: HashMap<u16, String>,
+    #[cfg(feature = "tcp_server")]
+    /// The first column of the fake keys row that is not used by the configuration.
+    pub first_free_fake_key_column: usize,
+    #[cfg(feature = "tcp_server")]
+    /// Parser for actions sent by TCP clients.
+    pub action_expr_parser: ActionExprParser,
+    #[cfg(feature = "tcp_server")]
+    /// Whether TCP clients are allowed to execute arbitrary actions.
+    pub tcp_actions_enabled: bool,
+    #[cfg(feature = "tcp_server")]
+    /// Columns of the fake keys row used by the actions that TCP clients executed, keyed by the
+    /// text of the action.
+    action_expr_columns: HashMap<String, u16>,
     /// The maximum value of switch's key-timing item in the configuration.
     pub switch_max_key_timing: u16,
     #[cfg(feature = "tcp_server")]
-    tcp_server_address: Option<SocketAddrWrapper>,
+    /// Whether the server for TCP or IPC socket clients is running.
+    is_server_enabled: bool,
+    #[cfg(feature = "tcp_server")]
+    /// Notifications for TCP clients subscribed to event topics. These are queued during
+    /// processing and sent after each round of ticks.
+    event_notifications: Vec<ServerMessage>,
+    #[cfg(feature = "tcp_server")]
+    /// One-shot keys that were active on the previous tick, used to notify subscribed clients
+    /// when they change.
+    prev_oneshot_keys: Vec<(u8, u16)>,
+    #[cfg(feature = "tcp_server")]
+    /// State that was last sent to TCP clients, used to notify them when it changes.
+    prev_state: KanataState,
+    #[cfg(feature = "tcp_server")]
+    /// Sequence keys that were typed on the previous tick, or `None` if sequence mode was
+    /// inactive. Used to notify subscribed clients of the candidate sequences when it changes.
+    prev_sequence: Option<Vec<u16>>,
     #[cfg(all(target_os = "windows", feature = "gui"))]
     /// Various GUI-related options.
     pub gui_opts: CfgOptionsGui,
@@ -242,6 +300,12 @@ pub struct Kanata {
     pub macro_on_press_cancel_duration: u32,
     /// Stores user's saved clipboard contents.
     pub saved_clipboard_content: SavedClipboardData,
+    /// Records typing statistics. Is Some(...) if `statistics-file` is set in the configuration
+    /// and None otherwise.
+    pub statistics: Option<StatisticsRecorder>,
+    /// Records hold-tap decisions to find misfires. Is Some(...) if `hold-tap-analysis-file` is
+    /// set in the configuration and None otherwise.
+    pub hold_tap_analysis: Option<HoldTapAnalyzer>,
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -294,6 +358,26 @@ use once_cell::sync::Lazy;
 static MAPPED_KEYS: Lazy<Mutex<cfg::MappedKeys>> =
     Lazy::new(|| Mutex::new(cfg::MappedKeys::default()));
 
+/// The instance whose files are written by [`save_files_on_exit`].
+static RUNNING_KANATA: once_cell::sync::OnceCell<std::sync::Weak<Mutex<Kanata>>> =
+    once_cell::sync::OnceCell::new();
+
+/// Writes the files that kanata keeps up to date while it runs, such as `statistics-file`, and
+/// waits until they are written. Also removes the IPC socket. Called when kanata is about to exit.
+pub fn save_files_on_exit() {
+    #[cfg(all(feature = "tcp_server", unix))]
+    crate::tcp_server::remove_ipc_socket();
+    let Some(kanata) = RUNNING_KANATA.get().and_then(std::sync::Weak::upgrade) else {
+        return;
+    };
+    // The exit may be triggered while the processing loop holds the lock.
+    let locked = kanata.try_lock_for(time::Duration::from_secs(1));
+    match locked {
+        Some(mut k) => k.save_files(),
+        None => log::error!("could not write files before exiting: kanata is busy"),
+    }
+}
+
 impl Kanata {
     pub fn new(args: &ValidatedArgs) -> Result<Self> {
         let cfg = match cfg::new_from_file(&args.paths[0]) {
@@ -374,6 +458,8 @@ impl Kanata {
             last_tick: instant::Instant::now(),
             time_remainder: 0,
             live_reload_requested: false,
+            #[cfg(feature = "tcp_server")]
+            live_reload_replies: vec![],
             overrides: cfg.overrides,
             override_states: OverrideStates::new(),
             #[cfg(target_os = "macos")]
@@ -409,7 +495,7 @@ impl Kanata {
                 .windows_interception_keyboard_hwids_exclude,
             dynamic_macro_replay_state: None,
             dynamic_macro_record_state: None,
-            dynamic_macros: Default::default(),
+            dynamic_macros: load_dynamic_macros(cfg.options.dynamic_macro_file.as_deref()),
             log_layer_changes: get_forced_log_layer_changes()
                 .unwrap_or(cfg.options.log_layer_changes),
             caps_word: None,
@@ -420,6 +506,8 @@ impl Kanata {
             dynamic_macro_replay_behaviour: ReplayBehaviour {
                 delay: cfg.options.dynamic_macro_replay_delay_behaviour,
             },
+            dynamic_macro_file: cfg.options.dynamic_macro_file.as_deref().map(PathBuf::from),
+            dynamic_macros_changed: false,
             #[cfg(target_os = "linux")]
             x11_repeat_rate: cfg.options.linux_opts.linux_x11_repeat_delay_rate,
             #[cfg(target_os = "linux")]
@@ -428,6 +516,7 @@ impl Kanata {
                 .linux_opts
                 .linux_device_detect_mode
                 .expect("parser should default to some"),
+            defdevices: cfg.devices,
             waiting_for_idle: HashSet::default(),
             vkeys_pending_release: HashMap::default(),
             ticks_since_idle: 0,
@@ -436,22 +525,68 @@ impl Kanata {
             unmodded_mods: UnmodMods::empty(),
             unshifted_keys: vec![],
             last_pressed_key: KeyCode::No,
-            #[cfg(feature = "tcp_server")]
+            #[cfg(feature = "cmd")]
+            cmd_async: CmdAsyncState::new(),
+            #[cfg(any(feature = "tcp_server", feature = "cmd"))]
             virtual_keys: cfg.fake_keys,
+            #[cfg(feature = "tcp_server")]
+            sequence_action_names: cfg.sequence_action_names,
+            #[cfg(feature = "tcp_server")]
+            first_free_fake_key_column: cfg.first_free_fake_key_column,
+            #[cfg(feature = "tcp_server")]
+            action_expr_parser: cfg.action_expr_parser,
+            #[cfg(feature = "tcp_server")]
+            tcp_actions_enabled: cfg.options.enable_tcp_actions,
+            #[cfg(feature = "tcp_server")]
+            action_expr_columns: HashMap::default(),
             switch_max_key_timing: cfg.switch_max_key_timing,
             #[cfg(feature = "tcp_server")]
-            tcp_server_address: args.tcp_server_address.clone(),
+            is_server_enabled: args.is_server_enabled(),
+            #[cfg(feature = "tcp_server")]
+            event_notifications: vec![],
+            #[cfg(feature = "tcp_server")]
+            prev_state: KanataState::default(),
+            #[cfg(feature = "tcp_server")]
+            prev_oneshot_keys: vec![],
+            #[cfg(feature = "tcp_server")]
+            prev_sequence: None,
             #[cfg(all(target_os = "windows", feature = "gui"))]
             gui_opts: cfg.options.gui_opts,
             allow_hardware_repeat: cfg.options.allow_hardware_repeat,
             macro_on_press_cancel_duration: 0,
             saved_clipboard_content: Default::default(),
+            statistics: cfg
+                .options
+                .statistics_file
+                .as_deref()
+                .map(|path| StatisticsRecorder::new(path, &cfg.defsrc)),
+            hold_tap_analysis: cfg
+                .options
+                .hold_tap_analysis_file
+                .as_deref()
+                .map(HoldTapAnalyzer::new),
         })
     }
 
     /// Create a new configuration from a file, wrapped in an Arc<Mutex<_>>
     pub fn new_arc(args: &ValidatedArgs) -> Result<Arc<Mutex<Self>>> {
-        Ok(Arc::new(Mutex::new(Self::new(args)?)))
+        let kanata = Arc::new(Mutex::new(Self::new(args)?));
+        let _ = RUNNING_KANATA.set(Arc::downgrade(&kanata));
+        Ok(kanata)
+    }
+
+    /// Writes the files that are kept up to date while kanata runs and waits until they are
+    /// written.
+    pub fn save_files(&mut self) {
+        if std::mem::take(&mut self.dynamic_macros_changed) {
+            save_dynamic_macros_and_wait(self.dynamic_macro_file.as_deref(), &self.dynamic_macros);
+        }
+        if let Some(statistics) = &mut self.statistics {
+            statistics.save_and_wait();
+        }
+        if let Some(analysis) = &mut self.hold_tap_analysis {
+            analysis.save_and_wait();
+        }
     }
 
     pub fn new_from_str(cfg: &str, file_content: HashMap<String, String>) -> Result<Self> {
@@ -510,6 +645,8 @@ impl Kanata {
             last_tick: instant::Instant::now(),
             time_remainder: 0,
             live_reload_requested: false,
+            #[cfg(feature = "tcp_server")]
+            live_reload_replies: vec![],
             overrides: cfg.overrides,
             override_states: OverrideStates::new(),
             #[cfg(target_os = "macos")]
@@ -545,7 +682,7 @@ impl Kanata {
                 .windows_interception_keyboard_hwids_exclude,
             dynamic_macro_replay_state: None,
             dynamic_macro_record_state: None,
-            dynamic_macros: Default::default(),
+            dynamic_macros: load_dynamic_macros(cfg.options.dynamic_macro_file.as_deref()),
             log_layer_changes: get_forced_log_layer_changes()
                 .unwrap_or(cfg.options.log_layer_changes),
             caps_word: None,
@@ -556,6 +693,8 @@ impl Kanata {
             dynamic_macro_replay_behaviour: ReplayBehaviour {
                 delay: cfg.options.dynamic_macro_replay_delay_behaviour,
             },
+            dynamic_macro_file: cfg.options.dynamic_macro_file.as_deref().map(PathBuf::from),
+            dynamic_macros_changed: false,
             #[cfg(target_os = "linux")]
             x11_repeat_rate: cfg.options.linux_opts.linux_x11_repeat_delay_rate,
             #[cfg(target_os = "linux")]
@@ -564,6 +703,7 @@ impl Kanata {
                 .linux_opts
                 .linux_device_detect_mode
                 .expect("parser should default to some"),
+            defdevices: cfg.devices,
             waiting_for_idle: HashSet::default(),
             vkeys_pending_release: HashMap::default(),
             ticks_since_idle: 0,
@@ -572,16 +712,46 @@ impl Kanata {
             unmodded_mods: UnmodMods::empty(),
             unshifted_keys: vec![],
             last_pressed_key: KeyCode::No,
-            #[cfg(feature = "tcp_server")]
+            #[cfg(feature = "cmd")]
+            cmd_async: CmdAsyncState::new(),
+            #[cfg(any(feature = "tcp_server", feature = "cmd"))]
             virtual_keys: cfg.fake_keys,
+            #[cfg(feature = "tcp_server")]
+            sequence_action_names: cfg.sequence_action_names,
+            #[cfg(feature = "tcp_server")]
+            first_free_fake_key_column: cfg.first_free_fake_key_column,
+            #[cfg(feature = "tcp_server")]
+            action_expr_parser: cfg.action_expr_parser,
+            #[cfg(feature = "tcp_server")]
+            tcp_actions_enabled: cfg.options.enable_tcp_actions,
+            #[cfg(feature = "tcp_server")]
+            action_expr_columns: HashMap::default(),
             switch_max_key_timing: cfg.switch_max_key_timing,
             #[cfg(feature = "tcp_server")]
-            tcp_server_address: None,
+            is_server_enabled: false,
+            #[cfg(feature = "tcp_server")]
+            event_notifications: vec![],
+            #[cfg(feature = "tcp_server")]
+            prev_state: KanataState::default(),
+            #[cfg(feature = "tcp_server")]
+            prev_oneshot_keys: vec![],
+            #[cfg(feature = "tcp_server")]
+            prev_sequence: None,
             #[cfg(all(target_os = "windows", feature = "gui"))]
             gui_opts: cfg.options.gui_opts,
             allow_hardware_repeat: cfg.options.allow_hardware_repeat,
             macro_on_press_cancel_duration: 0,
             saved_clipboard_content: Default::default(),
+            statistics: cfg
+                .options
+                .statistics_file
+                .as_deref()
+                .map(|path| StatisticsRecorder::new(path, &cfg.defsrc)),
+            hold_tap_analysis: cfg
+                .options
+                .hold_tap_analysis_file
+                .as_deref()
+                .map(HoldTapAnalyzer::new),
         })
     }
 
@@ -595,11 +765,35 @@ impl Kanata {
         Ok(Arc::new(Mutex::new(k)))
     }
 
+    #[cfg(feature = "tcp_server")]
+    /// Requests a live reload of the file at `cfg_idx` in `cfg_paths`. The reload happens once
+    /// all keys are released, same as with the live reload actions, and its result is sent to
+    /// `reply`.
+    pub fn request_live_reload(
+        &mut self,
+        cfg_idx: usize,
+        reply: ASender<Option<ConfigDiagnostic>>,
+    ) {
+        self.cur_cfg_idx = cfg_idx;
+        self.live_reload_requested = true;
+        self.live_reload_replies.push(reply);
+    }
+
+    #[cfg(feature = "tcp_server")]
+    /// Sends the result of a live reload to the clients that requested it.
+    fn reply_to_live_reload_requests(&mut self, diagnostic: Option<ConfigDiagnostic>) {
+        for reply in self.live_reload_replies.drain(..) {
+            let _ = reply.send(diagnostic.clone());
+        }
+    }
+
     fn do_live_reload(&mut self, _tx: &Option<Sender<ServerMessage>>) -> Result<()> {
         let cfg = match cfg::new_from_file(&self.cfg_paths[self.cur_cfg_idx]) {
             Ok(c) => c,
             Err(e) => {
                 log::error!("{e:?}");
+                #[cfg(feature = "tcp_server")]
+                self.reply_to_live_reload_requests(Some(config_diagnostic(&e)));
                 bail!("failed to parse config file");
             }
         };
@@ -624,11 +818,44 @@ impl Kanata {
         self.dynamic_macro_replay_behaviour = ReplayBehaviour {
             delay: cfg.options.dynamic_macro_replay_delay_behaviour,
         };
+        let dynamic_macro_file = cfg.options.dynamic_macro_file.as_deref().map(PathBuf::from);
+        if dynamic_macro_file != self.dynamic_macro_file {
+            // Macros of the new file replace recorded macros with the same id, the other
+            // recorded macros are kept and saved to the new file.
+            self.dynamic_macros.extend(load_dynamic_macros(
+                cfg.options.dynamic_macro_file.as_deref(),
+            ));
+            self.dynamic_macro_file = dynamic_macro_file;
+            save_dynamic_macros(self.dynamic_macro_file.as_deref(), &self.dynamic_macros);
+            self.dynamic_macros_changed = true;
+        }
         self.switch_max_key_timing = cfg.switch_max_key_timing;
-        #[cfg(feature = "tcp_server")]
+        #[cfg(target_os = "linux")]
+        {
+            *PENDING_DEFDEVICES.lock() = Some(cfg.devices.clone());
+        }
+        self.defdevices = cfg.devices;
+        self.statistics = StatisticsRecorder::reconfigure(
+            self.statistics.take(),
+            cfg.options.statistics_file.as_deref(),
+            &cfg.defsrc,
+        );
+        self.hold_tap_analysis = HoldTapAnalyzer::reconfigure(
+            self.hold_tap_analysis.take(),
+            cfg.options.hold_tap_analysis_file.as_deref(),
+        );
+        #[cfg(any(feature = "tcp_server", feature = "cmd"))]
         {
             self.virtual_keys = cfg.fake_keys;
         }
+        #[cfg(feature = "tcp_server")]
+        {
+            self.sequence_action_names = cfg.sequence_action_names;
+            self.first_free_fake_key_column = cfg.first_free_fake_key_column;
+            self.action_expr_parser = cfg.action_expr_parser;
+            self.tcp_actions_enabled = cfg.options.enable_tcp_actions;
+            self.action_expr_columns.clear();
+        }
         #[cfg(target_os = "windows")]
         {
             self.windows_sync_keystates = cfg.options.windows_opts.sync_keystates;
@@ -656,6 +883,8 @@ impl Kanata {
         Kanata::set_repeat_rate(cfg.options.linux_opts.linux_x11_repeat_delay_rate)?;
         log::info!("Live reload successful");
         #[cfg(feature = "tcp_server")]
+        self.reply_to_live_reload_requests(None);
+        #[cfg(feature = "tcp_server")]
         if let Some(tx) = _tx {
             match tx.try_send(ServerMessage::ConfigFileReload {
                 new: self.cfg_paths[self.cur_cfg_idx]
@@ -701,16 +930,64 @@ impl Kanata {
     /// Update keyberon layout state for press/release, handle repeat separately
     pub fn handle_input_event(&mut self, event: &KeyEvent) -> Result<()> {
         log::debug!("process recv ev {event:?}");
-        let evc: u16 = event.code.into();
+        let (row, col) = self.input_coord(event);
+        #[cfg(feature = "tcp_server")]
+        if is_subscribed(SubscriptionTopic::InputEvents) {
+            let action = match event.value {
+                KeyValue::Press => Some(KeyEventValue::Press),
+                KeyValue::Release => Some(KeyEventValue::Release),
+                KeyValue::Repeat => Some(KeyEventValue::Repeat),
+                KeyValue::Tap | KeyValue::WakeUp => None,
+            };
+            if let Some(action) = action {
+                self.event_notifications.push(ServerMessage::InputEvent {
+                    key: key_name(event.code),
+                    action,
+                });
+            }
+        }
         self.ticks_since_idle = 0;
         let kbrn_ev = match event.value {
             KeyValue::Press => {
+                #[cfg(feature = "cmd")]
+                {
+                    self.cmd_async.last_pressed_key = event.code;
+                }
                 if let Some((macro_id, recorded_macro)) = record_press(
                     &mut self.dynamic_macro_record_state,
                     event.code,
                     self.dynamic_macro_max_presses,
                 ) {
-                    self.dynamic_macros.insert(macro_id, recorded_macro);
+                    store_macro(
+                        &mut self.dynamic_macros,
+                        self.dynamic_macro_file.as_deref(),
+                        macro_id,
+                        recorded_macro,
+                    );
+                    self.dynamic_macros_changed = true;
+                }
+                if let Some(statistics) = &mut self.statistics {
+                    if row == NORMAL_KEY_ROW {
+                        let layer = self.layout.b().current_layer();
+                        statistics.record_press(
+                            event.code,
+                            &self.layer_info[layer].name,
+                            instant::Instant::now(),
+                        );
+                    }
+                }
+                if let Some(analysis) = &mut self.hold_tap_analysis {
+                    if row == NORMAL_KEY_ROW {
+                        // Resolve transparent actions through the active layers like the layout.
+                        let layout = self.layout.b();
+                        let hold_tap = layout
+                            .trans_resolution_layer_order()
+                            .into_iter()
+                            .map(|layer| &layout.layers[usize::from(layer)][0][col as usize])
+                            .find(|action| !matches!(action, Action::Trans))
+                            .and_then(HoldTapInfo::of);
+                        analysis.record_event(event.code, true, hold_tap, instant::Instant::now());
+                    }
                 }
                 if self.macro_on_press_cancel_duration > 0 {
                     log::debug!("cancelling all macros: other press");
@@ -721,19 +998,24 @@ impl Kanata {
                         !matches!(s, State::FakeKey { .. } | State::RepeatingSequence { .. })
                     });
                 }
-                Event::Press(0, evc)
+                Event::Press(row, col)
             }
             KeyValue::Release => {
                 record_release(&mut self.dynamic_macro_record_state, event.code);
-                Event::Release(0, evc)
+                if let Some(analysis) = &mut self.hold_tap_analysis {
+                    if row == NORMAL_KEY_ROW {
+                        analysis.record_event(event.code, false, None, instant::Instant::now());
+                    }
+                }
+                Event::Release(row, col)
             }
             KeyValue::Repeat => {
-                let ret = self.handle_repeat(event);
+                let ret = self.handle_repeat(event, (row, col));
                 return ret;
             }
             KeyValue::Tap => {
-                self.layout.bm().event(Event::Press(0, evc));
-                self.layout.bm().event(Event::Release(0, evc));
+                self.layout.bm().event(Event::Press(row, col));
+                self.layout.bm().event(Event::Release(row, col));
                 return Ok(());
             }
             KeyValue::WakeUp => {
@@ -744,6 +1026,20 @@ impl Kanata {
         Ok(())
     }
 
+    /// Returns the layout coordinate of an input event. Keys of a `defdevice` have their own
+    /// coordinate in the fake key row; all other keys use the normal key row.
+    fn input_coord(&self, event: &KeyEvent) -> (u8, u16) {
+        #[cfg(target_os = "linux")]
+        if let Some(col) = event
+            .device
+            .and_then(|idx| self.defdevices.get(usize::from(idx)))
+            .and_then(|defdevice| defdevice.keys.get(&event.code))
+        {
+            return (FAKE_KEY_ROW, *col);
+        }
+        (NORMAL_KEY_ROW, event.code.into())
+    }
+
     /// Advance keyberon layout state and send events based on changes to its state.
     /// Returns the number of ticks that elapsed.
     fn handle_time_ticks(&mut self, tx: &Option<Sender<ServerMessage>>) -> Result<u16> {
@@ -770,6 +1066,11 @@ impl Kanata {
         };
 
         self.check_handle_layer_change(tx);
+        #[cfg(feature = "tcp_server")]
+        {
+            self.check_handle_state_change(tx);
+            self.send_event_notifications(tx);
+        }
 
         if self.live_reload_requested
             && ((self.prev_keys.is_empty() && self.cur_keys.is_empty())
@@ -787,6 +1088,13 @@ impl Kanata {
             self.live_reload_requested = false;
             if let Err(e) = self.do_live_reload(tx) {
                 log::error!("live reload failed {e}");
+                #[cfg(feature = "tcp_server")]
+                self.reply_to_live_reload_requests(Some(ConfigDiagnostic {
+                    msg: format!("live reload failed: {e}"),
+                    help: None,
+                    file: None,
+                    span: None,
+                }));
             }
         }
 
@@ -845,6 +1153,13 @@ impl Kanata {
 
     fn tick_states(&mut self, _tx: &Option<Sender<ServerMessage>>) -> Result<()> {
         self.live_reload_requested |= self.handle_keystate_changes(_tx)?;
+        if let Some(statistics) = &mut self.statistics {
+            let layer = self.layout.b().current_layer();
+            statistics.tick(layer, &self.layer_info[layer].name, instant::Instant::now());
+        }
+        if let Some(analysis) = &mut self.hold_tap_analysis {
+            analysis.tick(instant::Instant::now());
+        }
         self.handle_scrolling()?;
         self.handle_move_mouse()?;
         self.tick_sequence_state()?;
@@ -855,6 +1170,8 @@ impl Kanata {
         self.prev_keys.clear();
         self.prev_keys.append(&mut self.cur_keys);
         self.tick_held_vkeys();
+        #[cfg(feature = "cmd")]
+        self.tick_cmd_async();
         #[cfg(feature = "simulated_output")]
         {
             self.kbd_out.tick();
@@ -862,6 +1179,99 @@ impl Kanata {
         Ok(())
     }
 
+    #[cfg(feature = "tcp_server")]
+    /// Acts on the action expression sent by a TCP client as if it were the action of a virtual
+    /// key, and returns the error to send back if that is not possible.
+    ///
+    /// Every expression gets its own column in the fake keys row, after the columns used by the
+    /// configuration, so that releasing or toggling one expression does not affect the others.
+    pub fn execute_action_expr(
+        &mut self,
+        expr: &str,
+        fk_action: FakeKeyAction,
+    ) -> std::result::Result<(), Box<ServerMessage>> {
+        use kanata_tcp_protocol::ErrorCode;
+        if !self.tcp_actions_enabled {
+            return Err(Box::new(ServerMessage::error(
+                ErrorCode::ActionsDisabled,
+                "to execute actions you must put in defcfg: danger-enable-tcp-actions yes",
+            )));
+        }
+        let action = self.action_expr_parser.parse(expr).map_err(|e| {
+            log::warn!("tcp server received an invalid action: {expr}");
+            let diagnostic = config_diagnostic(&e);
+            Box::new(ServerMessage::error(
+                ErrorCode::InvalidAction,
+                diagnostic.help.unwrap_or(diagnostic.msg),
+            ))
+        })?;
+        log::info!("tcp server execute action: {expr},{fk_action:?}");
+        let held_column = self
+            .action_expr_columns
+            .get(expr)
+            .copied()
+            .filter(|column| states_has_coord(&self.layout.b().states, FAKE_KEY_ROW, *column));
+        let fk_action = match (fk_action, held_column) {
+            (FakeKeyAction::Release, _) | (FakeKeyAction::Toggle, Some(_)) => {
+                if let Some(column) = self.action_expr_columns.remove(expr) {
+                    self.layout.bm().event(Event::Release(FAKE_KEY_ROW, column));
+                }
+                return Ok(());
+            }
+            (FakeKeyAction::Toggle, None) => FakeKeyAction::Press,
+            (fk_action, _) => fk_action,
+        };
+        let column = self.action_expr_column(expr).ok_or_else(|| {
+            Box::new(ServerMessage::error(
+                ErrorCode::ActionFailed,
+                "no free virtual key is available for the action; release other actions first",
+            ))
+        })?;
+        handle_action_expr(action, fk_action, self.layout.bm(), FAKE_KEY_ROW, column);
+        Ok(())
+    }
+
+    #[cfg(feature = "tcp_server")]
+    /// Returns the column of the expression, or a free column for it if it has none.
+    fn action_expr_column(&mut self, expr: &str) -> Option<u16> {
+        if let Some(column) = self.action_expr_columns.get(expr) {
+            return Some(*column);
+        }
+        let layout = self.layout.b();
+        let in_use = |column: u16| layout_uses_coord(layout, FAKE_KEY_ROW, column);
+        // Columns of actions that were tapped or have been released are free again.
+        self.action_expr_columns.retain(|_, column| in_use(*column));
+        let columns = &self.action_expr_columns;
+        let column = (self.first_free_fake_key_column..kanata_parser::layers::KEYS_IN_ROW)
+            .map(|column| column as u16)
+            .find(|column| !in_use(*column) && !columns.values().any(|c| c == column))?;
+        self.action_expr_columns.insert(expr.to_owned(), column);
+        Some(column)
+    }
+
+    #[cfg(feature = "cmd")]
+    /// Taps the virtual keys chosen by the exit codes of finished `cmd-async` commands.
+    fn tick_cmd_async(&mut self) {
+        for name in self.cmd_async.take_finished() {
+            match self.virtual_keys.get(&name) {
+                Some(y) => handle_fakekey_action(
+                    FakeKeyAction::Tap,
+                    self.layout.bm(),
+                    FAKE_KEY_ROW,
+                    *y as u16,
+                ),
+                None => log::warn!("cmd-async: virtual key {name} no longer exists"),
+            }
+        }
+    }
+
+    #[cfg(feature = "cmd")]
+    /// Sets the channel of the processing loop, which is woken up when a `cmd-async` command
+    /// finishes.
+    pub fn set_cmd_async_wakeup_channel(&mut self, tx: Sender<KeyEvent>) {
+        self.cmd_async.set_wakeup_channel(tx);
+    }
+
     fn handle_scrolling(&mut self) -> Result<()> {
         if let Some(scroll_state) = &mut self.scroll_state {
             if scroll_state.ticks_until_scroll == 0 {
@@ -986,8 +1396,20 @@ impl Kanata {
         if let Some(state) = self.sequence_state.get_active() {
             state.ticks_until_timeout -= 1;
             if state.ticks_until_timeout == 0 {
-                log::debug!("sequence timeout; exiting sequence state");
-                cancel_sequence(state, &mut self.kbd_out)?;
+                if let Some(((i, j), seq_type)) = state.pending_match {
+                    log::debug!("sequence timeout; activating the completed sequence");
+                    do_successful_sequence_termination(
+                        &mut self.kbd_out,
+                        state,
+                        self.layout.bm(),
+                        i,
+                        j,
+                        seq_type,
+                    )?;
+                } else {
+                    log::debug!("sequence timeout; exiting sequence state");
+                    cancel_sequence(state, &mut self.kbd_out)?;
+                }
             }
         }
         Ok(())
@@ -1083,8 +1505,14 @@ impl Kanata {
             cur_keys.extend(self.unshifted_keys.iter());
         }
 
+        let override_ctx = OverrideContext {
+            layer: layout.current_layer(),
+            kb_states: &layout.states,
+        };
         self.overrides
-            .override_keys(cur_keys, &mut self.override_states);
+            .override_keys(cur_keys, &mut self.override_states, override_ctx);
+        self.override_states
+            .handle_action_changes(|event| layout.event(event));
         mark_overridden_nonmodkeys_for_eager_erasure(&self.override_states, &mut layout.states);
         if self.override_release_on_activation {
             for removed in self.override_states.removed_oscs() {
@@ -1153,6 +1581,8 @@ impl Kanata {
             if let Err(e) = release_key(&mut self.kbd_out, k.into()) {
                 bail!("failed to release key: {:?}", e);
             }
+            #[cfg(feature = "tcp_server")]
+            queue_output_event(&mut self.event_notifications, k, KeyEventValue::Release);
         }
 
         if cur_keys.is_empty() && !self.prev_keys.is_empty() {
@@ -1173,6 +1603,9 @@ impl Kanata {
                             EndSequenceType::Overlap,
                         )?;
                     }
+                    HasValueWithDescendants(coord) => {
+                        state.pending_match = Some((coord, EndSequenceType::Overlap));
+                    }
                     NotInTrie => {
                         // Overwrite overlapped with non-overlapped tracking
                         state.overlapped_sequence.clear();
@@ -1223,6 +1656,8 @@ impl Kanata {
                 if let Err(e) = press_key(&mut self.kbd_out, k.into()) {
                     bail!("failed to press key: {:?}", e);
                 }
+                #[cfg(feature = "tcp_server")]
+                queue_output_event(&mut self.event_notifications, k, KeyEventValue::Press);
             }
         }
 
@@ -1462,23 +1897,21 @@ impl Kanata {
                             #[cfg(feature = "cmd")]
                             {
                                 let cmd = _cmd.clone();
-                                // Maybe improvement in the future:
-                                // A delay here, as in KeyAction::Delay, will pause the entire
-                                // state machine loop. That is _probably_ OK, but ideally this
-                                // would be done in a separate thread or somehow
-                                for key_action in keys_for_cmd_output(&cmd) {
-                                    match key_action {
-                                        KeyAction::Press(osc) => press_key(&mut self.kbd_out, osc)?,
-                                        KeyAction::Release(osc) => {
-                                            release_key(&mut self.kbd_out, osc)?
-                                        }
-                                        KeyAction::Delay(delay) => std::thread::sleep(
-                                            std::time::Duration::from_millis(u64::from(delay)),
-                                        ),
-                                    }
-                                }
+                                output_cmd_keys(&mut self.kbd_out, keys_for_cmd_output(&cmd))?;
                             }
                         }
+                        CustomAction::CmdAsync(_cmd_async) => {
+                            #[cfg(feature = "cmd")]
+                            self.cmd_async
+                                .run(_cmd_async, &self.layer_info[layout.current_layer()].name);
+                        }
+                        CustomAction::VarOutputKeys(_var) => {
+                            #[cfg(feature = "cmd")]
+                            output_cmd_keys(
+                                &mut self.kbd_out,
+                                keys_for_text(self.cmd_async.var(_var)),
+                            )?;
+                        }
                         CustomAction::PushMessage(_message) => {
                             log::debug!("Action push-msg");
                             #[cfg(feature = "tcp_server")]
@@ -1497,8 +1930,8 @@ impl Kanata {
                                 }
                             }
                             #[cfg(feature = "tcp_server")]
-                            if self.tcp_server_address.is_none() {
-                                log::warn!("{} was used, but TCP server is not running. did you specify a port?", PUSH_MESSAGE);
+                            if !self.is_server_enabled {
+                                log::warn!("{} was used, but TCP server is not running. did you specify a port or IPC socket?", PUSH_MESSAGE);
                             }
                             #[cfg(not(feature = "tcp_server"))]
                             log::warn!(
@@ -1569,7 +2002,13 @@ impl Kanata {
                                 begin_record_macro(*macro_id, &mut self.dynamic_macro_record_state)
                             {
                                 log::debug!("saving macro {prev_recorded_macro:?}");
-                                self.dynamic_macros.insert(macro_id, prev_recorded_macro);
+                                store_macro(
+                                    &mut self.dynamic_macros,
+                                    self.dynamic_macro_file.as_deref(),
+                                    macro_id,
+                                    prev_recorded_macro,
+                                );
+                                self.dynamic_macros_changed = true;
                             }
                         }
                         CustomAction::DynamicMacroRecordStop(num_actions_to_remove) => {
@@ -1578,7 +2017,13 @@ impl Kanata {
                                 *num_actions_to_remove,
                             ) {
                                 log::debug!("saving macro {prev_recorded_macro:?}");
-                                self.dynamic_macros.insert(macro_id, prev_recorded_macro);
+                                store_macro(
+                                    &mut self.dynamic_macros,
+                                    self.dynamic_macro_file.as_deref(),
+                                    macro_id,
+                                    prev_recorded_macro,
+                                );
+                                self.dynamic_macros_changed = true;
                             }
                         }
                         CustomAction::DynamicMacroPlay(macro_id) => {
@@ -1588,6 +2033,19 @@ impl Kanata {
                                 &self.dynamic_macros,
                             );
                         }
+                        CustomAction::DynamicMacroPlayRepeat {
+                            macro_id,
+                            count,
+                            speed,
+                        } => {
+                            play_macro_repeat(
+                                *macro_id,
+                                *count,
+                                Some(*speed),
+                                &mut self.dynamic_macro_replay_state,
+                                &self.dynamic_macros,
+                            );
+                        }
                         CustomAction::CancelMacroOnNextPress(duration) => {
                             self.macro_on_press_cancel_duration = *duration;
                         }
@@ -1635,6 +2093,10 @@ impl Kanata {
                         CustomAction::ClipboardCmdSet(cmd_params) => {
                             clpb_cmd_set(cmd_params);
                         }
+                        CustomAction::ClipboardVarSet(_var) => {
+                            #[cfg(feature = "cmd")]
+                            clpb_set(self.cmd_async.var(_var));
+                        }
                         CustomAction::ClipboardSave(id) => {
                             clpb_save(*id, &mut self.saved_clipboard_content);
                         }
@@ -1786,18 +2248,291 @@ impl Kanata {
             _ => {}
         };
 
+        let resolutions: Vec<_> = self.layout.bm().hold_tap_resolutions.drain(..).collect();
+        if let Some(statistics) = &mut self.statistics {
+            for res in resolutions.iter() {
+                if let (NORMAL_KEY_ROW, Some(key), Some(resolution)) = (
+                    res.coord.0,
+                    OsCode::from_u16(res.coord.1),
+                    hold_tap_resolution(res.action),
+                ) {
+                    statistics.record_hold_tap(key, resolution);
+                }
+            }
+        }
+        if let Some(analysis) = &mut self.hold_tap_analysis {
+            for res in resolutions.iter() {
+                if let (NORMAL_KEY_ROW, Some(key), Some(resolution)) = (
+                    res.coord.0,
+                    OsCode::from_u16(res.coord.1),
+                    hold_tap_resolution(res.action),
+                ) {
+                    analysis.record_decision(key, resolution, res.ticks, instant::Instant::now());
+                }
+            }
+        }
+        #[cfg(feature = "tcp_server")]
+        {
+            self.queue_layout_event_notifications(resolutions);
+            self.queue_sequence_notifications();
+        }
         self.check_release_non_physical_shift()?;
         Ok(live_reload_requested)
     }
 
     #[cfg(feature = "tcp_server")]
-    pub fn change_layer(&mut self, layer_name: String) {
+    /// Queues notifications for hold-tap resolutions and one-shot changes in the keyberon layout
+    /// for subscribed clients.
+    fn queue_layout_event_notifications(
+        &mut self,
+        resolutions: Vec<kanata_keyberon::layout::HoldTapResolution>,
+    ) {
+        if is_subscribed(SubscriptionTopic::HoldTap) {
+            for res in resolutions {
+                let Some(resolution) = hold_tap_resolution(res.action) else {
+                    continue;
+                };
+                let key = self.coord_name(res.coord);
+                self.event_notifications
+                    .push(ServerMessage::HoldTapResolved {
+                        key,
+                        resolution,
+                        ticks: res.ticks,
+                    });
+            }
+        }
+        if is_subscribed(SubscriptionTopic::OneShot) {
+            let layout = self.layout.b();
+            if !layout.oneshot.keys.iter().eq(self.prev_oneshot_keys.iter()) {
+                self.prev_oneshot_keys.clear();
+                self.prev_oneshot_keys
+                    .extend(layout.oneshot.keys.iter().copied());
+                let active = self
+                    .prev_oneshot_keys
+                    .iter()
+                    .map(|coord| self.coord_name(*coord))
+                    .collect();
+                self.event_notifications
+                    .push(ServerMessage::OneShotChange { active });
+            }
+        }
+    }
+
+    #[cfg(feature = "tcp_server")]
+    /// Queues the sequences that can still be completed for subscribed clients when sequence
+    /// mode starts, ends or a key is typed in it.
+    fn queue_sequence_notifications(&mut self) {
+        if !is_subscribed(SubscriptionTopic::Sequences) {
+            return;
+        }
+        let state = &self.sequence_state;
+        let sequence = state.is_active().then_some(&state.sequence);
+        if sequence == self.prev_sequence.as_ref() {
+            return;
+        }
+        let mut candidates: Vec<SequenceCandidate> = vec![];
+        if let Some(sequence) = sequence {
+            let typed = sequence
+                .iter()
+                .filter(|k| **k != KEY_OVERLAP_MARKER && !is_held_sequence_modifier(**k))
+                .count();
+            for (keys, coord) in self.sequences.iter_prefix(sequence) {
+                // Overlapping keys have a sequence for every order; list only one of them.
+                let name = self.coord_name(*coord);
+                if !candidates.iter().any(|c| c.name == name) {
+                    candidates.push(SequenceCandidate {
+                        name,
+                        keys: sequence_keys_text(&keys),
+                        typed,
+                    });
+                }
+            }
+        }
+        self.prev_sequence = sequence.cloned();
+        self.event_notifications
+            .push(ServerMessage::SequenceCandidates { candidates });
+    }
+
+    #[cfg(feature = "tcp_server")]
+    /// Returns the name of the defsrc key, virtual key or `defseq` action at the layout
+    /// coordinate.
+    fn coord_name(&self, (x, y): (u8, u16)) -> String {
+        match x {
+            NORMAL_KEY_ROW => OsCode::from_u16(y).map(key_name),
+            FAKE_KEY_ROW => self
+                .virtual_keys
+                .iter()
+                .find(|(_, idx)| **idx == usize::from(y))
+                .map(|(name, _)| name.clone())
+                .or_else(|| {
+                    self.defdevices.iter().find_map(|defdevice| {
+                        defdevice
+                            .keys
+                            .iter()
+                            .find(|(_, col)| **col == y)
+                            .map(|(osc, _)| format!("{}:{}", defdevice.name, key_name(*osc)))
+                    })
+                })
+                .or_else(|| self.sequence_action_names.get(&y).cloned()),
+            _ => None,
+        }
+        .unwrap_or_else(|| format!("{x},{y}"))
+    }
+
+    #[cfg(feature = "tcp_server")]
+    fn send_event_notifications(&mut self, tx: &Option<Sender<ServerMessage>>) {
+        match tx {
+            Some(tx) => {
+                let mut dropped = 0;
+                for msg in self.event_notifications.drain(..) {
+                    match tx.try_send(msg) {
+                        Ok(_) => {}
+                        Err(std::sync::mpsc::TrySendError::Full(_)) => dropped += 1,
+                        Err(error) => {
+                            log::error!("could not send event notification: {}", error);
+                        }
+                    }
+                }
+                if dropped > 0 {
+                    // Blocking here would stall key processing on slow clients.
+                    log::warn!(
+                        "dropped {dropped} event notifications: notification channel is full"
+                    );
+                }
+            }
+            None => self.event_notifications.clear(),
+        }
+    }
+
+    #[cfg(feature = "tcp_server")]
+    /// Returns a snapshot of the user-visible state for TCP clients.
+    pub fn state(&self) -> KanataState {
+        let layout = self.layout.b();
+        let mut pressed_virtual_keys = vec![];
+        for (x, y) in layout.states.iter().filter_map(State::coord) {
+            if x != FAKE_KEY_ROW {
+                continue;
+            }
+            if let Some((name, _)) = self
+                .virtual_keys
+                .iter()
+                .find(|(_, idx)| **idx == usize::from(y))
+            {
+                if !pressed_virtual_keys.contains(name) {
+                    pressed_virtual_keys.push(name.clone());
+                }
+            }
+        }
+        pressed_virtual_keys.sort();
+        KanataState {
+            layer: self.layer_info[layout.current_layer()].name.clone(),
+            pressed_virtual_keys,
+            caps_word: self.caps_word.is_some(),
+            dynamic_macro_recording: self
+                .dynamic_macro_record_state
+                .as_ref()
+                .map(|state| state.macro_id()),
+            sequence: self.sequence_state.is_active().then(|| {
+                self.sequence_state
+                    .raw_oscs
+                    .iter()
+                    .map(|osc| key_name(*osc))
+                    .collect()
+            }),
+        }
+    }
+
+    #[cfg(feature = "tcp_server")]
+    /// Notifies subscribed TCP clients if the state returned by [`Kanata::state`] changed.
+    fn check_handle_state_change(&mut self, tx: &Option<Sender<ServerMessage>>) {
+        let Some(tx) = tx else {
+            return;
+        };
+        if !is_subscribed(SubscriptionTopic::State) {
+            return;
+        }
+        let state = self.state();
+        if state == self.prev_state {
+            return;
+        }
+        self.prev_state = state.clone();
+        if let Err(error) = tx.try_send(ServerMessage::StateChanged { state }) {
+            log::error!("could not send event notification: {}", error);
+        }
+    }
+
+    #[cfg(feature = "tcp_server")]
+    /// Sets the default layer. Returns false if no layer with the name exists.
+    pub fn change_layer(&mut self, layer_name: &str) -> bool {
         for (i, l) in self.layer_info.iter().enumerate() {
             if l.name == layer_name {
                 self.layout.bm().set_default_layer(i);
-                return;
+                return true;
             }
         }
+        false
+    }
+
+    #[cfg(feature = "tcp_server")]
+    /// Returns all dynamic macros ordered by id.
+    pub fn dynamic_macro_list(&self) -> Vec<DynamicMacro> {
+        macros_to_dynamic_macros(&self.dynamic_macros)
+    }
+
+    #[cfg(feature = "tcp_server")]
+    /// Returns the dynamic macro as a list of key events.
+    pub fn dynamic_macro(&self, macro_id: u16) -> Option<DynamicMacro> {
+        self.dynamic_macros
+            .get(&macro_id)
+            .map(|items| macro_to_dynamic_macro(macro_id, items))
+    }
+
+    #[cfg(feature = "tcp_server")]
+    /// Converts the key events of a dynamic macro from a TCP client to the items of the macro and
+    /// checks that every key is released. Done before taking the lock for
+    /// [`Kanata::set_dynamic_macro`], since looking up the key names is comparatively slow.
+    pub fn dynamic_macro_from_events(
+        events: &[DynamicMacroEvent],
+    ) -> Result<Vec<DynamicMacroItem>> {
+        let items = dynamic_macro_events_to_macro(events)?;
+        check_macro_releases(&items)?;
+        Ok(items)
+    }
+
+    #[cfg(feature = "tcp_server")]
+    /// Replaces or adds the dynamic macro and saves the macros to `dynamic-macro-file`, if set.
+    /// Returns an error if the macro has more presses than `dynamic-macro-max-presses` allows.
+    pub fn set_dynamic_macro(&mut self, macro_id: u16, items: Vec<DynamicMacroItem>) -> Result<()> {
+        let presses = items
+            .iter()
+            .filter(|item| matches!(item, DynamicMacroItem::Press(_)))
+            .count();
+        if presses > usize::from(self.dynamic_macro_max_presses) {
+            bail!(
+                "the macro has {presses} presses, more than dynamic-macro-max-presses ({})",
+                self.dynamic_macro_max_presses
+            );
+        }
+        store_macro(
+            &mut self.dynamic_macros,
+            self.dynamic_macro_file.as_deref(),
+            macro_id,
+            items,
+        );
+        self.dynamic_macros_changed = true;
+        Ok(())
+    }
+
+    #[cfg(feature = "tcp_server")]
+    /// Deletes the dynamic macro. Returns false if no macro with the id exists.
+    pub fn delete_dynamic_macro(&mut self, macro_id: u16) -> bool {
+        if self.dynamic_macros.remove(&macro_id).is_none() {
+            return false;
+        }
+        log::info!("deleted dynamic macro {macro_id}");
+        save_dynamic_macros(self.dynamic_macro_file.as_deref(), &self.dynamic_macros);
+        self.dynamic_macros_changed = true;
+        true
     }
 
     #[allow(unused_variables)]
@@ -1845,12 +2580,18 @@ impl Kanata {
                     }
                     Ok(event) => {
                         let notification = event.as_bytes();
+                        let topic = event.topic();
                         let mut clients = clients.lock();
                         let mut stale_clients = vec![];
                         for (id, client) in &mut *clients {
-                            match client.write_all(&notification) {
+                            if let Some(topic) = topic {
+                                if !client.subscriptions.contains(&topic) {
+                                    continue;
+                                }
+                            }
+                            match client.stream.write_all(&notification) {
                                 Ok(_) => {
-                                    log::debug!("layer change notification sent");
+                                    log::debug!("event notification sent");
                                 }
                                 Err(e) => {
                                     log::warn!(
@@ -1866,6 +2607,9 @@ impl Kanata {
                             log::warn!("removing disconnected tcp client: {id}");
                             clients.remove(id);
                         }
+                        if !stale_clients.is_empty() {
+                            crate::tcp_server::update_subscribed_topics(&clients);
+                        }
                     }
                 }
             }
@@ -2150,6 +2894,16 @@ impl Kanata {
             .next()
             .map(|he| he.ticks_since_occurrence >= k.switch_max_key_timing)
             .unwrap_or(true);
+        // tap-hold-require-prior-idle counts the ticks between presses, so the ticks must not
+        // pause before the latest press is older than the required idle time.
+        let passed_prior_idle_check = k
+            .layout
+            .b()
+            .historical_inputs
+            .iter_hevents()
+            .find(|he| he.event.0 == NORMAL_KEY_ROW)
+            .map(|he| he.ticks_since_occurrence >= k.layout.b().tap_hold_require_prior_idle)
+            .unwrap_or(true);
         let chordsv2_accepts_chords = k
             .layout
             .b()
@@ -2157,7 +2911,11 @@ impl Kanata {
             .as_ref()
             .map(|cv2| cv2.accepts_chords_chv2())
             .unwrap_or(true);
-        is_idle && !counting_idle_ticks && passed_max_switch_timing_check && chordsv2_accepts_chords
+        is_idle
+            && !counting_idle_ticks
+            && passed_max_switch_timing_check
+            && passed_prior_idle_check
+            && chordsv2_accepts_chords
     }
 
     pub fn is_idle(&self) -> bool {
@@ -2194,12 +2952,41 @@ impl Kanata {
     }
 }
 
+#[cfg(feature = "tcp_server")]
+fn queue_output_event(notifications: &mut Vec<ServerMessage>, k: &KeyCode, action: KeyEventValue) {
+    if is_subscribed(SubscriptionTopic::OutputEvents) {
+        let osc: OsCode = k.into();
+        notifications.push(ServerMessage::OutputEvent {
+            key: key_name(osc),
+            action,
+        });
+    }
+}
+
 #[test]
 fn test_unmodmods_bits() {
     assert_eq!(UnmodMods::empty().bits(), 0u8);
     assert_eq!(UnmodMods::all().bits(), 255u8);
 }
 
+#[cfg(feature = "cmd")]
+fn output_cmd_keys(kbd_out: &mut KbdOut, keys: impl Iterator<Item = KeyAction>) -> Result<()> {
+    // Maybe improvement in the future:
+    // A delay here, as in KeyAction::Delay, will pause the entire
+    // state machine loop. That is _probably_ OK, but ideally this
+    // would be done in a separate thread or somehow
+    for key_action in keys {
+        match key_action {
+            KeyAction::Press(osc) => press_key(kbd_out, osc)?,
+            KeyAction::Release(osc) => release_key(kbd_out, osc)?,
+            KeyAction::Delay(delay) => {
+                std::thread::sleep(std::time::Duration::from_millis(u64::from(delay)))
+            }
+        }
+    }
+    Ok(())
+}
+
 #[cfg(feature = "cmd")]
 fn run_multi_cmd(cmds: Vec<(Option<log::Level>, Option<log::Level>, Vec<String>)>) {
     std::thread::spawn(move || {
@@ -2299,6 +3086,8 @@ fn check_for_exit(_event: &KeyEvent) {
         if IS_ESC_PRESSED.load(SeqCst) && IS_SPC_PRESSED.load(SeqCst) && IS_LCL_PRESSED.load(SeqCst)
         {
             log::info!("{EXIT_MSG}");
+            #[cfg(not(target_os = "linux"))]
+            save_files_on_exit();
             #[cfg(all(target_os = "windows", feature = "gui"))]
             {
                 #[cfg(not(feature = "interception_driver"))]
@@ -2324,6 +3113,19 @@ fn check_for_exit(_event: &KeyEvent) {
     }
 }
 
+/// Returns the resolution of a hold-tap action as reported to statistics and TCP clients.
+fn hold_tap_resolution(
+    action: kanata_keyberon::layout::WaitingAction,
+) -> Option<HoldTapResolution> {
+    use kanata_keyberon::layout::WaitingAction;
+    match action {
+        WaitingAction::Tap => Some(HoldTapResolution::Tap),
+        WaitingAction::Hold => Some(HoldTapResolution::Hold),
+        WaitingAction::Timeout => Some(HoldTapResolution::Timeout),
+        WaitingAction::NoOp => None,
+    }
+}
+
 fn update_kbd_out(_cfg: &CfgOptions, _kbd_out: &KbdOut) -> Result<()> {
     #[cfg(all(not(feature = "simulated_output"), target_os = "linux"))]
     {
@@ -2357,6 +3159,55 @@ pub fn handle_fakekey_action<'a, const C: usize, const R: usize, T>(
     };
 }
 
+/// Acts on `action` as if it were the action of a fake key at `(x, y)`.
+#[cfg(feature = "tcp_server")]
+fn handle_action_expr<'a, const C: usize, const R: usize, T>(
+    action: &'a kanata_keyberon::action::Action<'a, T>,
+    fk_action: FakeKeyAction,
+    layout: &mut Layout<'a, C, R, T>,
+    x: u8,
+    y: u16,
+) where
+    T: 'a + std::fmt::Debug + Copy,
+{
+    let press = |layout: &mut Layout<'a, C, R, T>| {
+        layout.action_queue.push_back(Some(((x, y), 0, action)));
+    };
+    match fk_action {
+        FakeKeyAction::Press => press(layout),
+        FakeKeyAction::Release => layout.event(Event::Release(x, y)),
+        FakeKeyAction::Tap => {
+            press(layout);
+            layout.event(Event::Release(x, y));
+        }
+        FakeKeyAction::Toggle => match states_has_coord(&layout.states, x, y) {
+            true => layout.event(Event::Release(x, y)),
+            false => press(layout),
+        },
+    };
+}
+
+/// Returns whether the layout has a state, a queued action or a queued event at `(x, y)`.
+#[cfg(feature = "tcp_server")]
+fn layout_uses_coord<'a, const C: usize, const R: usize, T>(
+    layout: &Layout<'a, C, R, T>,
+    x: u8,
+    y: u16,
+) -> bool
+where
+    T: 'a + std::fmt::Debug + Copy,
+{
+    states_has_coord(&layout.states, x, y)
+        || layout
+            .action_queue
+            .iter()
+            .any(|queued| matches!(queued, Some((coord, _, _)) if *coord == (x, y)))
+        || layout
+            .queue
+            .iter()
+            .any(|queued| queued.event().coord() == (x, y))
+}
+
 fn states_has_coord<T>(states: &[State<T>], x: u8, y: u16) -> bool {
     states.iter().any(|s| match s {
         State::NormalKey { coord, .. }
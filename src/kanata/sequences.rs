@@ -1,5 +1,8 @@
 use super::*;
 
+#[cfg(feature = "tcp_server")]
+use kanata_parser::keys::key_name;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SequenceActivity {
     Inactive,
@@ -30,6 +33,10 @@ pub struct SequenceState {
     pub activity: SequenceActivity,
     /// Counter to reduce number of backspaces typed.
     noerase_count: u16,
+    /// The longest completed sequence that is also the beginning of longer sequences. It
+    /// activates when the sequence times out or when a key does not continue any sequence,
+    /// unless a longer sequence is completed first.
+    pub(super) pending_match: Option<((u8, u16), EndSequenceType)>,
 }
 
 impl SequenceState {
@@ -43,6 +50,7 @@ impl SequenceState {
             sequence_timeout: 0,
             activity: Inactive,
             noerase_count: 0,
+            pending_match: None,
         }
     }
 
@@ -56,6 +64,7 @@ impl SequenceState {
         self.overlapped_sequence.clear();
         self.activity = Active;
         self.noerase_count = 0;
+        self.pending_match = None;
     }
 
     pub fn is_active(&self) -> bool {
@@ -87,6 +96,7 @@ pub(super) fn get_mod_mask_for_cur_keys(cur_keys: &[KeyCode]) -> u16 {
         .fold(0, |a, v| a | mod_mask_for_keycode(v))
 }
 
+#[derive(Debug, Clone, Copy)]
 pub(super) enum EndSequenceType {
     Standard,
     Overlap,
@@ -103,7 +113,6 @@ pub(super) fn do_sequence_press_logic(
 ) -> Result<(), anyhow::Error> {
     state.ticks_until_timeout = state.sequence_timeout;
     let osc = OsCode::from(*k);
-    state.raw_oscs.push(osc);
     use kanata_parser::trie::GetOrDescendentExistsResult::*;
     let pushed_into_seq = {
         // Transform to OsCode and convert modifiers other than altgr/ralt
@@ -117,6 +126,15 @@ pub(super) fn do_sequence_press_logic(
         });
         base | mod_mask
     };
+    if let Some(((i, j), seq_type)) = state.pending_match {
+        // Like a timeout, a key that does not continue any sequence ends the sequence. The key
+        // is not typed.
+        if !continues_sequence(state, sequences, pushed_into_seq) {
+            log::debug!("{k:?} does not continue a sequence; activating the completed sequence");
+            return do_successful_sequence_termination(kbd_out, state, layout, i, j, seq_type);
+        }
+    }
+    state.raw_oscs.push(osc);
     match state.sequence_input_mode {
         SequenceInputMode::VisibleBackspaced => {
             press_key(kbd_out, osc)?;
@@ -265,10 +283,47 @@ pub(super) fn do_sequence_press_logic(
                 EndSequenceType::Standard,
             )?;
         }
+    } else if state.is_active() {
+        // If the sequence is complete but it is also the beginning of longer sequences, it
+        // replaces the shorter completed sequence and waits for the next key or the timeout.
+        // Otherwise the shorter completed sequence keeps waiting.
+        match (res_overlapped, res) {
+            (HasValueWithDescendants(coord), _) => {
+                state.pending_match = Some((coord, EndSequenceType::Overlap));
+            }
+            (_, HasValueWithDescendants(coord)) => {
+                state.pending_match = Some((coord, EndSequenceType::Standard));
+            }
+            _ => {}
+        }
     }
     Ok(())
 }
 
+/// Returns whether appending the key to the current sequence, as a standard or overlapping key,
+/// begins or completes any sequence.
+fn continues_sequence(
+    state: &SequenceState,
+    sequences: &kanata_parser::trie::Trie<(u8, u16)>,
+    pushed_into_seq: u16,
+) -> bool {
+    use kanata_parser::trie::GetOrDescendentExistsResult::*;
+    let standard = state.sequence.iter().copied();
+    let overlapped = state.overlapped_sequence.iter().copied();
+    [
+        standard
+            .clone()
+            .chain([pushed_into_seq])
+            .collect::<Vec<_>>(),
+        standard.chain([pushed_into_seq & MASK_KEYCODES]).collect(),
+        overlapped
+            .chain([(pushed_into_seq & MASK_KEYCODES) | KEY_OVERLAP_MARKER])
+            .collect(),
+    ]
+    .iter()
+    .any(|seq| sequences.get_or_descendant_exists(seq) != NotInTrie)
+}
+
 use kanata_keyberon::key_code::KeyCode::*;
 
 pub(super) fn do_successful_sequence_termination(
@@ -369,3 +424,50 @@ pub(super) fn cancel_sequence(state: &mut SequenceState, kbd_out: &mut KbdOut) -
 pub(super) fn add_noerase(state: &mut SequenceState, noerase_count: u16) {
     state.noerase_count += noerase_count;
 }
+
+#[cfg(feature = "tcp_server")]
+/// Returns the keys of a sequence as they are written in `defseq`, e.g. `g S-s O-(a b)`.
+pub(super) fn sequence_keys_text(keys: &[u16]) -> String {
+    let mut parts: Vec<String> = vec![];
+    let mut in_overlap = false;
+    for k in keys.iter().copied() {
+        if k == KEY_OVERLAP_MARKER {
+            if let (true, Some(last)) = (in_overlap, parts.last_mut()) {
+                last.push(')');
+            }
+            in_overlap = false;
+            continue;
+        }
+        if is_held_sequence_modifier(k) {
+            continue;
+        }
+        let osc = OsCode::from(k & MASK_KEYCODES);
+        let mut part = String::new();
+        if k & KEY_OVERLAP_MARKER != 0 && !in_overlap {
+            part.push_str("O-(");
+            in_overlap = true;
+        }
+        let mods = [
+            (LCtrl, "C-"),
+            (LShift, "S-"),
+            (LAlt, "A-"),
+            (RAlt, "RA-"),
+            (LGui, "M-"),
+        ];
+        for (mod_key, prefix) in mods {
+            if k & mod_mask_for_keycode(mod_key) != 0 {
+                part.push_str(prefix);
+            }
+        }
+        part.push_str(&key_name(osc));
+        parts.push(part);
+    }
+    parts.join(" ")
+}
+
+#[cfg(feature = "tcp_server")]
+/// Returns whether the sequence element is a modifier pressed as part of a modded key, e.g.
+/// the `lsft` in `S-a`. These are shown as prefixes of the keys they modify.
+pub(super) fn is_held_sequence_modifier(k: u16) -> bool {
+    k & MASK_MODDED & !KEY_OVERLAP_MARKER != 0 && OsCode::from(k & MASK_KEYCODES).is_modifier()
+}
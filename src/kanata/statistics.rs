@@ -0,0 +1,202 @@
+//! Typing statistics, recorded if `statistics-file` is set in `defcfg`.
+//!
+//! The statistics are loaded from the file on startup, so that they accumulate across runs, and
+//! written back to it periodically and when kanata exits.
+
+use kanata_parser::cfg::DefsrcKey;
+use kanata_parser::keys::{key_name, OsCode};
+use kanata_tcp_protocol::{HoldTapResolution, TypingStatistics};
+use rustc_hash::FxHashSet as HashSet;
+
+use super::file_writer::*;
+
+use instant::Instant;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Interval between writes of the statistics file.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+/// Two key presses that are further apart than this are not counted as a bigram.
+const BIGRAM_MAX_GAP: Duration = Duration::from_secs(1);
+
+pub struct StatisticsRecorder {
+    path: PathBuf,
+    statistics: TypingStatistics,
+    /// Keys of `defsrc`. Presses of other keys are not counted.
+    defsrc: HashSet<OsCode>,
+    /// The layer that was active on the previous tick.
+    prev_layer: Option<usize>,
+    /// The previous key press and its time, used for bigrams.
+    prev_press: Option<(OsCode, Instant)>,
+    last_save: Instant,
+    /// Whether the statistics changed since they were last written.
+    changed: bool,
+}
+
+impl StatisticsRecorder {
+    /// Create a recorder that writes to the file at `path`, starting from the statistics that
+    /// the file already contains.
+    pub fn new(path: &str, defsrc: &[DefsrcKey]) -> Self {
+        let path = PathBuf::from(path);
+        let statistics = load_statistics(&path);
+        log::info!("recording typing statistics to {}", path.display());
+        Self {
+            path,
+            statistics,
+            defsrc: defsrc.iter().map(|k| k.code).collect(),
+            prev_layer: None,
+            prev_press: None,
+            last_save: Instant::now(),
+            changed: false,
+        }
+    }
+
+    /// Returns the recorder for a reloaded configuration, keeping the current one if the file
+    /// did not change.
+    pub fn reconfigure(
+        prev: Option<Self>,
+        path: Option<&str>,
+        defsrc: &[DefsrcKey],
+    ) -> Option<Self> {
+        match (prev, path) {
+            (Some(mut prev), Some(path)) if prev.path == Path::new(path) => {
+                prev.defsrc = defsrc.iter().map(|k| k.code).collect();
+                prev.prev_press = None;
+                Some(prev)
+            }
+            (prev, path) => {
+                if let Some(mut prev) = prev {
+                    prev.save();
+                }
+                path.map(|path| Self::new(path, defsrc))
+            }
+        }
+    }
+
+    pub fn statistics(&self) -> &TypingStatistics {
+        &self.statistics
+    }
+
+    /// Count a press of the input key at `now` while `layer` is active.
+    pub fn record_press(&mut self, key: OsCode, layer: &str, now: Instant) {
+        if !self.defsrc.contains(&key) {
+            return;
+        }
+        self.changed = true;
+        *self
+            .statistics
+            .key_presses
+            .entry(key_name(key))
+            .or_default() += 1;
+        self.statistics
+            .layers
+            .entry(layer.to_owned())
+            .or_default()
+            .key_presses += 1;
+        if let Some((prev, prev_time)) = self.prev_press {
+            if now.duration_since(prev_time) <= BIGRAM_MAX_GAP {
+                *self
+                    .statistics
+                    .bigrams
+                    .entry(format!("{} {}", key_name(prev), key_name(key)))
+                    .or_default() += 1;
+            }
+        }
+        self.prev_press = Some((key, now));
+    }
+
+    /// Count the resolution of a hold-tap action on the input key.
+    pub fn record_hold_tap(&mut self, key: OsCode, resolution: HoldTapResolution) {
+        if !self.defsrc.contains(&key) {
+            return;
+        }
+        self.changed = true;
+        let counts = self.statistics.hold_taps.entry(key_name(key)).or_default();
+        match resolution {
+            HoldTapResolution::Tap => counts.tap += 1,
+            HoldTapResolution::Hold => counts.hold += 1,
+            HoldTapResolution::Timeout => counts.timeout += 1,
+        }
+    }
+
+    /// Count a change to `layer` and write the file if it is due at `now`.
+    pub fn tick(&mut self, layer: usize, layer_name: &str, now: Instant) {
+        if self.prev_layer != Some(layer) {
+            self.prev_layer = Some(layer);
+            self.changed = true;
+            self.statistics
+                .layers
+                .entry(layer_name.to_owned())
+                .or_default()
+                .activations += 1;
+        }
+        if now.duration_since(self.last_save) >= SAVE_INTERVAL {
+            self.save();
+        }
+    }
+
+    /// Queue the statistics to be written to the file if they changed.
+    pub fn save(&mut self) {
+        if let Some(json) = self.changed_json() {
+            write_in_background(&self.path, json);
+        }
+    }
+
+    /// Write the statistics to the file if they changed and wait until they are written.
+    pub fn save_and_wait(&mut self) {
+        if let Some(json) = self.changed_json() {
+            write_and_wait(&self.path, json);
+        }
+    }
+
+    fn changed_json(&mut self) -> Option<String> {
+        self.last_save = Instant::now();
+        if !std::mem::take(&mut self.changed) {
+            return None;
+        }
+        #[cfg(feature = "json")]
+        {
+            Some(
+                serde_json::to_string_pretty(&self.statistics)
+                    .expect("statistics can be serialized"),
+            )
+        }
+        #[cfg(not(feature = "json"))]
+        None
+    }
+}
+
+/// Reads the statistics from the file. A file that cannot be read is moved aside rather than
+/// overwritten, so that the statistics in it are not lost.
+#[cfg(feature = "json")]
+fn load_statistics(path: &Path) -> TypingStatistics {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return TypingStatistics::default(),
+        Err(e) => {
+            log::error!("could not read statistics from {}: {e}", path.display());
+            return back_up(path);
+        }
+    };
+    serde_json::from_str(&text).unwrap_or_else(|e| {
+        log::error!("could not read statistics from {}: {e}", path.display());
+        back_up(path)
+    })
+}
+
+#[cfg(not(feature = "json"))]
+fn load_statistics(_path: &Path) -> TypingStatistics {
+    log::error!("statistics-file requires kanata to be built with the json feature");
+    TypingStatistics::default()
+}
+
+/// Moves the file to a backup next to it and returns empty statistics to start over with.
+#[cfg(feature = "json")]
+fn back_up(path: &Path) -> TypingStatistics {
+    let backup = with_suffix(path, ".bak");
+    match std::fs::rename(path, &backup) {
+        Ok(()) => log::warn!("moved the statistics to {}", backup.display()),
+        Err(e) => log::error!("could not move the statistics to {}: {e}", backup.display()),
+    }
+    TypingStatistics::default()
+}
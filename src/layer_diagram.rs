@@ -0,0 +1,591 @@
+//! Rendering of the layers of a configuration as keyboard diagrams, e.g. for cheat sheets.
+//!
+//! Keys are placed in the shape of `defsrc`: every line of `defsrc` is a row of the diagram, and
+//! keys that start at the same column on different lines are drawn in the same column. Each key
+//! shows what it does when tapped and, for `tap-hold*` and layer keys, what it does when held.
+//! Actions without a short description are shown by their alias name if they have one.
+
+use kanata_keyberon::action::Action;
+use kanata_parser::cfg::format::grid_columns;
+use kanata_parser::cfg::list_actions::*;
+use kanata_parser::cfg::Cfg;
+use kanata_parser::custom_action::{Btn, CustomAction, MWheelDirection, MoveDirection};
+use kanata_parser::keys::{key_name, OsCode};
+use rustc_hash::FxHashMap as HashMap;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use std::fmt::Write;
+
+/// Output formats of [`render_layers`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DiagramFormat {
+    /// A single SVG image with all layers.
+    Svg,
+    /// An HTML page with an SVG image per layer.
+    Html,
+    /// Plain text for terminals and code comments.
+    Ascii,
+}
+
+/// Render every layer of the configuration in the given format.
+pub fn render_layers(cfg: &Cfg, format: DiagramFormat) -> String {
+    let diagram = Diagram::new(cfg);
+    match format {
+        DiagramFormat::Svg => diagram.svg(),
+        DiagramFormat::Html => diagram.html(),
+        DiagramFormat::Ascii => diagram.ascii(),
+    }
+}
+
+/// What a key does, shown on its tap and hold lines.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Legend {
+    tap: String,
+    hold: String,
+    transparent: bool,
+}
+
+/// A key placed on the diagram grid.
+struct GridKey {
+    row: usize,
+    column: usize,
+    code: OsCode,
+}
+
+struct Diagram {
+    layer_names: Vec<String>,
+    keys: Vec<GridKey>,
+    row_count: usize,
+    /// Legends of every key, per layer, in the order of `keys`.
+    legends: Vec<Vec<Legend>>,
+    /// Width of the widest legend of each grid column, in characters.
+    widths: Vec<usize>,
+}
+
+impl Diagram {
+    fn new(cfg: &Cfg) -> Self {
+        let key_columns: Vec<usize> = cfg.defsrc.iter().map(|k| k.column).collect();
+        let (columns, key_grid_columns) = grid_columns(&key_columns);
+        let keys: Vec<GridKey> = cfg
+            .defsrc
+            .iter()
+            .zip(key_grid_columns)
+            .map(|(k, column)| GridKey {
+                row: k.row,
+                column,
+                code: k.code,
+            })
+            .collect();
+
+        let mut aliases: Vec<_> = cfg.aliases.iter().collect();
+        aliases.sort_by_key(|(name, _)| *name);
+        let mut alias_names = HashMap::default();
+        for (name, action) in aliases {
+            if let Some(address) = action_address(action) {
+                // Use the first name of actions with multiple aliases.
+                alias_names.entry(address).or_insert(name.as_str());
+            }
+        }
+        let layer_names: Vec<String> = cfg.layer_info.iter().map(|l| l.name.clone()).collect();
+        let names = Names {
+            layers: &layer_names,
+            aliases: alias_names,
+        };
+        let layers = cfg.layout.b().layers;
+        let legends: Vec<Vec<Legend>> = layers
+            .iter()
+            .take(layer_names.len())
+            .map(|layer| {
+                keys.iter()
+                    .map(|k| names.legend(&layer[0][usize::from(k.code)]))
+                    .collect()
+            })
+            .collect();
+
+        let mut widths = vec![0; columns.len()];
+        for layer in legends.iter() {
+            for (key, legend) in keys.iter().zip(layer) {
+                let width = &mut widths[key.column];
+                *width = (*width).max(legend.tap.width()).max(legend.hold.width());
+            }
+        }
+        Self {
+            layer_names,
+            row_count: keys.iter().map(|k| k.row + 1).max().unwrap_or(0),
+            keys,
+            legends,
+            widths,
+        }
+    }
+
+    fn ascii(&self) -> String {
+        // Boxes of neighbouring keys share their borders.
+        let mut positions = vec![0];
+        for width in self.widths.iter() {
+            positions.push(positions.last().expect("not empty") + width + 3);
+        }
+        let mut out = String::new();
+        for (name, legends) in self.layer_names.iter().zip(self.legends.iter()) {
+            let mut canvas = Canvas::new(
+                self.row_count * 3 + 1,
+                *positions.last().expect("not empty") + 1,
+            );
+            for (key, legend) in self.keys.iter().zip(legends) {
+                let (x0, x1) = (positions[key.column], positions[key.column + 1]);
+                let y0 = key.row * 3;
+                canvas.draw_box(x0, y0, x1, y0 + 3);
+                canvas.write(x0 + 2, y0 + 1, &legend.tap);
+                canvas.write(x0 + 2, y0 + 2, &legend.hold);
+            }
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            let _ = writeln!(out, "{name}");
+            out.push_str(&canvas.to_string());
+        }
+        out
+    }
+
+    fn html(&self) -> String {
+        let mut out = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>kanata layers</title>\n</head>\n<body>\n",
+        );
+        for (name, legends) in self.layer_names.iter().zip(self.legends.iter()) {
+            let _ = writeln!(out, "<h2>{}</h2>", xml_escape(name));
+            let (width, height) = self.svg_layer_size();
+            let _ = writeln!(
+                out,
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\">"
+            );
+            out.push_str(SVG_STYLE);
+            self.svg_layer(&mut out, legends, 0);
+            out.push_str("</svg>\n");
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    fn svg(&self) -> String {
+        let (width, layer_height) = self.svg_layer_size();
+        let height = (layer_height + SVG_TITLE_HEIGHT) * self.layer_names.len();
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\">\n"
+        );
+        out.push_str(SVG_STYLE);
+        for (i, (name, legends)) in self.layer_names.iter().zip(self.legends.iter()).enumerate() {
+            let y = i * (layer_height + SVG_TITLE_HEIGHT);
+            let _ = writeln!(
+                out,
+                "<text class=\"title\" x=\"0\" y=\"{}\">{}</text>",
+                y + SVG_TITLE_HEIGHT - 8,
+                xml_escape(name)
+            );
+            self.svg_layer(&mut out, legends, y + SVG_TITLE_HEIGHT);
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+
+    fn svg_positions(&self) -> Vec<usize> {
+        let mut positions = vec![0];
+        for width in self.widths.iter() {
+            positions.push(positions.last().expect("not empty") + svg_key_width(*width) + SVG_GAP);
+        }
+        positions
+    }
+
+    fn svg_layer_size(&self) -> (usize, usize) {
+        let width = self
+            .svg_positions()
+            .last()
+            .expect("not empty")
+            .saturating_sub(SVG_GAP);
+        let height = (self.row_count * (SVG_KEY_HEIGHT + SVG_GAP)).saturating_sub(SVG_GAP);
+        (width, height)
+    }
+
+    fn svg_layer(&self, out: &mut String, legends: &[Legend], y: usize) {
+        let positions = self.svg_positions();
+        for (key, legend) in self.keys.iter().zip(legends) {
+            let x = positions[key.column];
+            let key_y = y + key.row * (SVG_KEY_HEIGHT + SVG_GAP);
+            let width = svg_key_width(self.widths[key.column]);
+            let class = if legend.transparent {
+                "key trans"
+            } else {
+                "key"
+            };
+            let _ = writeln!(
+                out,
+                "<rect class=\"{class}\" x=\"{x}\" y=\"{key_y}\" width=\"{width}\" height=\"{SVG_KEY_HEIGHT}\" rx=\"4\"/>"
+            );
+            for (text, class, offset) in [(&legend.tap, "tap", 18), (&legend.hold, "hold", 36)] {
+                if text.is_empty() {
+                    continue;
+                }
+                let _ = writeln!(
+                    out,
+                    "<text class=\"{class}\" x=\"{}\" y=\"{}\">{}</text>",
+                    x + width / 2,
+                    key_y + offset,
+                    xml_escape(text)
+                );
+            }
+        }
+    }
+}
+
+const SVG_KEY_HEIGHT: usize = 44;
+const SVG_GAP: usize = 4;
+const SVG_TITLE_HEIGHT: usize = 32;
+const SVG_STYLE: &str = "<style>
+.key { fill: #f4f4f4; stroke: #555; }
+.trans { fill: #fff; stroke: #bbb; stroke-dasharray: 3 2; }
+text { font-family: monospace; text-anchor: middle; }
+.tap { font-size: 12px; }
+.hold { font-size: 10px; fill: #666; }
+.title { font-size: 16px; font-weight: bold; text-anchor: start; }
+</style>
+";
+
+fn svg_key_width(legend_width: usize) -> usize {
+    (legend_width * 8 + 12).max(SVG_KEY_HEIGHT)
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// An action of the layout, see [`kanata_parser::cfg::KanataLayout::b`].
+pub(crate) type LayoutAction<'a> = Action<'a, &'a &'a [&'a CustomAction]>;
+
+/// Names used in legends.
+struct Names<'a> {
+    layers: &'a [String],
+    /// Alias names by the [`action_address`] of their action.
+    aliases: HashMap<usize, &'a str>,
+}
+
+impl Names<'_> {
+    fn legend(&self, action: &LayoutAction<'_>) -> Legend {
+        let legend = self.describe(action);
+        Legend {
+            tap: truncate(legend.tap),
+            hold: truncate(legend.hold),
+            transparent: legend.transparent,
+        }
+    }
+
+    fn describe(&self, action: &LayoutAction<'_>) -> Legend {
+        let tap = |tap: String| Legend {
+            tap,
+            ..Default::default()
+        };
+        match action {
+            Action::Trans => Legend {
+                transparent: true,
+                ..Default::default()
+            },
+            Action::NoOp => tap("XX".into()),
+            Action::KeyCode(kc) => tap(key_name(OsCode::from(*kc))),
+            Action::MultipleKeyCodes(kcs) => tap(chord_name(kcs)),
+            Action::Layer(idx) => Legend {
+                hold: self.layer_name(*idx),
+                ..Default::default()
+            },
+            Action::DefaultLayer(idx) => tap(format!("to:{}", self.layer_name(*idx))),
+            Action::HoldTap(ht) => Legend {
+                tap: self.describe(&ht.tap).summary(),
+                hold: self.describe(&ht.hold).summary(),
+                transparent: false,
+            },
+            Action::OneShot(os) => tap(format!("os:{}", self.describe(os.action).summary())),
+            Action::Repeat => tap("rpt".into()),
+            Action::Custom(cas) => match cas {
+                [CustomAction::Unicode(c)] => tap(c.to_string()),
+                _ => tap(self.alias_or(action, || custom_action_names(cas))),
+            },
+            // Show the keys output by a multi action, since the other actions within it are
+            // usually only there to support the key.
+            Action::MultipleActions(acs) => {
+                let keys: Vec<String> = acs
+                    .iter()
+                    .filter(|ac| matches!(ac, Action::KeyCode(_) | Action::MultipleKeyCodes(_)))
+                    .map(|ac| self.describe(ac).tap)
+                    .collect();
+                match keys.len() {
+                    1 | 2 => tap(keys.join("+")),
+                    _ => tap(self.alias_or(action, || "multi".into())),
+                }
+            }
+            Action::TapDance(_) => tap(self.alias_or(action, || "tap-dance".into())),
+            Action::Chords(_) => tap(self.alias_or(action, || "chord".into())),
+            Action::Sequence { .. } | Action::RepeatableSequence { .. } => {
+                tap(self.alias_or(action, || "macro".into()))
+            }
+            _ => tap(self.alias_or(action, || "...".into())),
+        }
+    }
+
+    fn layer_name(&self, idx: usize) -> String {
+        self.layers.get(idx).cloned().unwrap_or_default()
+    }
+
+    /// Returns the name of the alias for the action, or the fallback if there is none.
+    fn alias_or(&self, action: &LayoutAction<'_>, fallback: impl FnOnce() -> String) -> String {
+        action_address(action)
+            .and_then(|address| self.aliases.get(&address))
+            .map(|name| format!("@{name}"))
+            .unwrap_or_else(fallback)
+    }
+}
+
+/// Returns the address of the data that the action refers to, if any. Layer keys that use an
+/// alias hold a copy of the alias action, so both refer to the same data.
+fn action_address(action: &LayoutAction<'_>) -> Option<usize> {
+    let address = match action {
+        Action::MultipleKeyCodes(kcs) => std::ptr::from_ref(*kcs) as usize,
+        Action::MultipleActions(acs) => std::ptr::from_ref(*acs) as usize,
+        Action::Sequence { events } | Action::RepeatableSequence { events } => {
+            std::ptr::from_ref(*events) as usize
+        }
+        Action::HoldTap(ht) => std::ptr::from_ref(*ht) as usize,
+        Action::Custom(cas) => std::ptr::from_ref(*cas) as usize,
+        Action::OneShot(os) => std::ptr::from_ref(*os) as usize,
+        Action::TapDance(td) => std::ptr::from_ref(*td) as usize,
+        Action::Chords(chords) => std::ptr::from_ref(*chords) as usize,
+        Action::Fork(fork) => std::ptr::from_ref(*fork) as usize,
+        Action::Switch(switch) => std::ptr::from_ref(*switch) as usize,
+        _ => return None,
+    };
+    Some(address)
+}
+
+/// Legends wider than this are cut off.
+const MAX_LEGEND_WIDTH: usize = 12;
+
+fn truncate(text: String) -> String {
+    if text.width() <= MAX_LEGEND_WIDTH {
+        return text;
+    }
+    let mut width = 0;
+    let mut truncated: String = text
+        .chars()
+        .take_while(|c| {
+            width += c.width().unwrap_or(0);
+            width < MAX_LEGEND_WIDTH
+        })
+        .collect();
+    truncated.push('…');
+    truncated
+}
+
+impl Legend {
+    /// A single line description, used for actions within other actions.
+    fn summary(self) -> String {
+        match (self.tap.is_empty(), self.hold.is_empty()) {
+            (false, _) => self.tap,
+            (true, false) => self.hold,
+            (true, true) if self.transparent => "_".into(),
+            (true, true) => String::new(),
+        }
+    }
+}
+
+pub(crate) fn custom_action_names(cas: &[&CustomAction]) -> String {
+    cas.iter()
+        .map(|ca| custom_action_name(ca))
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
+/// Returns the configuration name of the action that the custom action is parsed from.
+fn custom_action_name(ca: &CustomAction) -> String {
+    use CustomAction::*;
+    let name = match ca {
+        Cmd(_) => CMD,
+        CmdLog(..) => CMD_LOG,
+        CmdOutputKeys(_) => CMD_OUTPUT_KEYS,
+        CmdAsync(_) => CMD_ASYNC,
+        ClipboardVarSet(_) => CLIPBOARD_VAR_SET,
+        VarOutputKeys(_) => VAR_OUTPUT_KEYS,
+        PushMessage(_) => PUSH_MESSAGE,
+        Unicode(_) => UNICODE,
+        Mouse(btn) => match btn {
+            Btn::Left => "mlft",
+            Btn::Right => "mrgt",
+            Btn::Mid => "mmid",
+            Btn::Forward => "mfwd",
+            Btn::Backward => "mbck",
+        },
+        MouseTap(btn) => match btn {
+            Btn::Left => "mltp",
+            Btn::Right => "mrtp",
+            Btn::Mid => "mmtp",
+            Btn::Forward => "mftp",
+            Btn::Backward => "mbtp",
+        },
+        FakeKey { .. } => ON_PRESS,
+        FakeKeyOnRelease { .. } => ON_RELEASE,
+        FakeKeyOnIdle(_) => ON_IDLE,
+        FakeKeyHoldForDuration(_) => HOLD_FOR_DURATION,
+        Delay(_) => ON_PRESS_DELAY,
+        DelayOnRelease(_) => ON_RELEASE_DELAY,
+        MWheel { direction, .. } => match direction {
+            MWheelDirection::Up => MWHEEL_UP,
+            MWheelDirection::Down => MWHEEL_DOWN,
+            MWheelDirection::Left => MWHEEL_LEFT,
+            MWheelDirection::Right => MWHEEL_RIGHT,
+        },
+        MWheelNotch { direction } => match direction {
+            MWheelDirection::Up => "mwu",
+            MWheelDirection::Down => "mwd",
+            MWheelDirection::Left => "mwl",
+            MWheelDirection::Right => "mwr",
+        },
+        MoveMouse { direction, .. } => match direction {
+            MoveDirection::Up => MOVEMOUSE_UP,
+            MoveDirection::Down => MOVEMOUSE_DOWN,
+            MoveDirection::Left => MOVEMOUSE_LEFT,
+            MoveDirection::Right => MOVEMOUSE_RIGHT,
+        },
+        MoveMouseAccel { direction, .. } => match direction {
+            MoveDirection::Up => MOVEMOUSE_ACCEL_UP,
+            MoveDirection::Down => MOVEMOUSE_ACCEL_DOWN,
+            MoveDirection::Left => MOVEMOUSE_ACCEL_LEFT,
+            MoveDirection::Right => MOVEMOUSE_ACCEL_RIGHT,
+        },
+        MoveMouseSpeed { .. } => MOVEMOUSE_SPEED,
+        SequenceCancel => "scnl",
+        SequenceLeader(..) => "sldr",
+        SequenceNoerase(_) => SEQUENCE_NOERASE,
+        LiveReload => "lrld",
+        LiveReloadNext => "lrld-next",
+        LiveReloadPrev => "lrld-prev",
+        LiveReloadNum(_) => LIVE_RELOAD_NUM,
+        LiveReloadFile(_) => LIVE_RELOAD_FILE,
+        Repeat => "rpt",
+        CancelMacroOnRelease => MACRO_RELEASE_CANCEL,
+        CancelMacroOnNextPress(_) => MACRO_CANCEL_ON_NEXT_PRESS,
+        DynamicMacroRecord(_) => DYNAMIC_MACRO_RECORD,
+        DynamicMacroRecordStop(_) => "dynamic-macro-record-stop",
+        DynamicMacroPlay(_) => DYNAMIC_MACRO_PLAY,
+        DynamicMacroPlayRepeat { .. } => DYNAMIC_MACRO_PLAY_REPEAT,
+        SendArbitraryCode(_) => ARBITRARY_CODE,
+        CapsWord(_) => CAPS_WORD,
+        SetMouse { .. } => SETMOUSE,
+        Unmodded { .. } => UNMOD,
+        Unshifted { .. } => UNSHIFT,
+        ReverseReleaseOrder => "reverse-release-order",
+        ClipboardSet(_) => CLIPBOARD_SET,
+        ClipboardCmdSet(_) => CLIPBOARD_CMD_SET,
+        ClipboardSave(_) => CLIPBOARD_SAVE,
+        ClipboardRestore(_) => CLIPBOARD_RESTORE,
+        ClipboardSaveSet(..) => CLIPBOARD_SAVE_SET,
+        ClipboardSaveCmdSet(..) => CLIPBOARD_SAVE_CMD_SET,
+        ClipboardSaveSwap(..) => CLIPBOARD_SAVE_SWAP,
+    };
+    name.to_owned()
+}
+
+/// Returns the name of a chord such as `C-S-a`, using the same modifier prefixes as the
+/// configuration.
+fn chord_name(kcs: &[kanata_keyberon::key_code::KeyCode]) -> String {
+    let mut name = String::new();
+    let mut keys = vec![];
+    for kc in kcs {
+        let osc = OsCode::from(*kc);
+        let prefix = match osc {
+            OsCode::KEY_LEFTCTRL => "C-",
+            OsCode::KEY_RIGHTCTRL => "RC-",
+            OsCode::KEY_LEFTSHIFT => "S-",
+            OsCode::KEY_RIGHTSHIFT => "RS-",
+            OsCode::KEY_LEFTALT => "A-",
+            OsCode::KEY_RIGHTALT => "AG-",
+            OsCode::KEY_LEFTMETA => "M-",
+            OsCode::KEY_RIGHTMETA => "RM-",
+            _ => {
+                keys.push(key_name(osc));
+                continue;
+            }
+        };
+        name.push_str(prefix);
+    }
+    match keys.is_empty() {
+        // Only modifiers, e.g. `(lctl lsft)`.
+        true => kcs
+            .iter()
+            .map(|kc| key_name(OsCode::from(*kc)))
+            .collect::<Vec<_>>()
+            .join("+"),
+        false => name + &keys.join("+"),
+    }
+}
+
+/// A grid of characters for drawing ASCII diagrams.
+struct Canvas {
+    /// Each cell holds one character, or is empty if it is covered by a preceding wide character.
+    cells: Vec<Vec<String>>,
+}
+
+impl Canvas {
+    fn new(height: usize, width: usize) -> Self {
+        Self {
+            cells: vec![vec![" ".to_owned(); width]; height],
+        }
+    }
+
+    /// Draw a line character, turning crossings of lines into corners.
+    fn draw(&mut self, x: usize, y: usize, c: char) {
+        let cell = &mut self.cells[y][x];
+        *cell = match (cell.as_str(), c) {
+            (" ", c) => c.to_string(),
+            (existing, c) if existing == c.to_string() => existing.to_owned(),
+            _ => "+".to_owned(),
+        };
+    }
+
+    fn draw_box(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        for x in x0..=x1 {
+            for y in [y0, y1] {
+                self.draw(x, y, if x == x0 || x == x1 { '+' } else { '-' });
+            }
+        }
+        for y in y0 + 1..y1 {
+            for x in [x0, x1] {
+                self.draw(x, y, '|');
+            }
+        }
+    }
+
+    fn write(&mut self, x: usize, y: usize, text: &str) {
+        let mut x = x;
+        for c in text.chars() {
+            let width = c.width().unwrap_or(0);
+            if width == 0 {
+                // Combining characters belong to the preceding character.
+                if let Some(prev) = x.checked_sub(1) {
+                    self.cells[y][prev].push(c);
+                }
+                continue;
+            }
+            self.cells[y][x] = c.to_string();
+            for covered in 1..width {
+                self.cells[y][x + covered] = String::new();
+            }
+            x += width;
+        }
+    }
+}
+
+impl std::fmt::Display for Canvas {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in self.cells.iter() {
+            writeln!(f, "{}", line.concat().trim_end())?;
+        }
+        Ok(())
+    }
+}
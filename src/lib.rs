@@ -3,9 +3,14 @@ use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
 
+#[cfg(feature = "json")]
+pub mod cfg_dump;
+pub mod export;
 #[cfg(all(target_os = "windows", feature = "gui"))]
 pub mod gui;
+pub mod import;
 pub mod kanata;
+pub mod layer_diagram;
 pub mod oskbd;
 pub mod tcp_server;
 #[cfg(test)]
@@ -20,11 +25,29 @@ pub struct ValidatedArgs {
     pub paths: Vec<CfgPath>,
     #[cfg(feature = "tcp_server")]
     pub tcp_server_address: Option<SocketAddrWrapper>,
+    #[cfg(all(feature = "tcp_server", unix))]
+    pub ipc_socket_path: Option<PathBuf>,
     #[cfg(target_os = "linux")]
     pub symlink_path: Option<String>,
     pub nodelay: bool,
 }
 
+impl ValidatedArgs {
+    /// Returns true if the server for [`kanata_tcp_protocol`] messages should be started, either
+    /// on a TCP port or on an IPC socket.
+    pub fn is_server_enabled(&self) -> bool {
+        #[cfg(all(feature = "tcp_server", unix))]
+        if self.ipc_socket_path.is_some() {
+            return true;
+        }
+        #[cfg(feature = "tcp_server")]
+        if self.tcp_server_address.is_some() {
+            return true;
+        }
+        false
+    }
+}
+
 pub fn default_cfg() -> Vec<PathBuf> {
     let mut cfgs = Vec::new();
 
@@ -52,6 +52,15 @@ kanata.kbd in the current working directory and
         verbatim_doc_comment
     )]
     tcp_server_address: Option<SocketAddrWrapper>,
+
+    /// Path of a Unix domain socket to serve the same messages as the TCP server
+    /// on. The socket is accessible by the owner and group of the kanata
+    /// process; use the permissions of its directory to restrict it further.
+    /// Can be used together with --port.
+    #[cfg(all(feature = "tcp_server", unix))]
+    #[arg(long, value_name = "PATH", verbatim_doc_comment)]
+    ipc_socket: Option<PathBuf>,
+
     /// Path for the symlink pointing to the newly-created device. If blank, no
     /// symlink will be created.
     #[cfg(target_os = "linux")]
@@ -89,10 +98,40 @@ kanata.kbd in the current working directory and
     #[arg(short, long, verbatim_doc_comment)]
     wait_device_ms: Option<u64>,
 
-    /// Validate configuration file and exit
+    /// Validate configuration file and exit. Together with --fmt, check that
+    /// the configuration files are formatted instead.
     #[arg(long, verbatim_doc_comment)]
     check: bool,
 
+    /// Format the configuration files in place and exit. Together with
+    /// --check, report the files that are not formatted without changing
+    /// them and exit with a non-zero code if there are any.
+    #[arg(long, verbatim_doc_comment)]
+    fmt: bool,
+
+    /// Print the configuration file after expansion of includes, templates,
+    /// variables and conditionals in the given format and exit.
+    #[cfg(feature = "json")]
+    #[arg(long, value_name = "FORMAT", verbatim_doc_comment)]
+    dump_cfg: Option<cfg_dump::DumpFormat>,
+
+    /// Convert the layers of the configuration file to a keymap of keyboard
+    /// firmware in the given format, print it and exit. Actions that could
+    /// not be converted are reported on stderr.
+    #[arg(long, value_name = "FORMAT", verbatim_doc_comment)]
+    export: Option<export::ExportFormat>,
+
+    /// Convert a KMonad configuration (.kbd) or a QMK keymap (keymap.c or
+    /// keymap.json) to a kanata configuration, print it and exit. Constructs
+    /// that could not be converted are reported on stderr.
+    #[arg(long, value_name = "FILE", verbatim_doc_comment)]
+    import: Option<PathBuf>,
+
+    /// Print diagrams of the layers of the configuration file in the given
+    /// format and exit.
+    #[arg(long, value_name = "FORMAT", verbatim_doc_comment)]
+    layer_diagram: Option<layer_diagram::DiagramFormat>,
+
     /// Log layer changes even if the configuration file has set the defcfg
     /// option to false. Useful if you are experimenting with a new
     /// configuration but want to default to no logging.
@@ -116,6 +155,40 @@ mod cli {
 
         let cfg_paths = args.cfg.unwrap_or_else(default_cfg);
 
+        // Handled before logging is initialized so that only the output is printed to stdout.
+        if let Some(path) = args.import {
+            let status = match import::import_file(&path) {
+                Ok(conversion) => {
+                    print!("{}", conversion.cfg);
+                    for warning in conversion.warnings.iter() {
+                        eprintln!("warning: {warning}");
+                    }
+                    0
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    1
+                }
+            };
+            std::process::exit(status);
+        }
+        #[cfg(feature = "json")]
+        if let Some(format) = args.dump_cfg {
+            print_from_cfg(&cfg_paths, |cfg| cfg_dump::dump_cfg(cfg, format));
+        }
+        if let Some(format) = args.export {
+            print_from_cfg(&cfg_paths, |cfg| {
+                let export = export::export(cfg, format);
+                for warning in export.warnings.iter() {
+                    eprintln!("warning: {warning}");
+                }
+                export.keymap
+            });
+        }
+        if let Some(format) = args.layer_diagram {
+            print_from_cfg(&cfg_paths, |cfg| layer_diagram::render_layers(cfg, format));
+        }
+
         let log_lvl = match (args.debug, args.trace, args.quiet) {
             (_, true, false) => LevelFilter::Trace,
             (true, false, false) => LevelFilter::Debug,
@@ -156,6 +229,18 @@ mod cli {
             bail!("No config files provided\nFor more info, pass the `-h` or `--help` flags.");
         }
 
+        if args.fmt {
+            let status = match format_cfg_files(&cfg_paths, args.check) {
+                Ok(true) => 0,
+                Ok(false) => 1,
+                Err(e) => {
+                    log::error!("{e:?}");
+                    1
+                }
+            };
+            std::process::exit(status);
+        }
+
         if args.check {
             log::info!("validating config only and exiting");
             let status = match cfg::new_from_file(&cfg_paths[0]) {
@@ -183,12 +268,55 @@ mod cli {
             paths: cfg_paths,
             #[cfg(feature = "tcp_server")]
             tcp_server_address: args.tcp_server_address,
+            #[cfg(all(feature = "tcp_server", unix))]
+            ipc_socket_path: args.ipc_socket,
             #[cfg(target_os = "linux")]
             symlink_path: args.symlink_path,
             nodelay: args.nodelay,
         })
     }
 
+    /// Print the output of `render` for the first configuration file and exit.
+    fn print_from_cfg(cfg_paths: &[PathBuf], render: impl Fn(&cfg::Cfg) -> String) -> ! {
+        let status = match cfg_paths.first().map(|p| cfg::new_from_file(p)) {
+            Some(Ok(cfg)) => {
+                print!("{}", render(&cfg));
+                0
+            }
+            Some(Err(e)) => {
+                eprintln!("{e:?}");
+                1
+            }
+            None => {
+                eprintln!("No config files provided");
+                1
+            }
+        };
+        std::process::exit(status);
+    }
+
+    /// Format the files in place, or only check that they are formatted if `check` is true.
+    /// Returns whether all files were already formatted when checking.
+    fn format_cfg_files(paths: &[PathBuf], check: bool) -> Result<bool> {
+        let mut all_formatted = true;
+        for path in paths {
+            let text = std::fs::read_to_string(path)?;
+            let formatted = cfg::format::format_cfg(&text)
+                .map_err(|e| anyhow::anyhow!("could not format {}: {}", path.display(), e.msg))?;
+            if formatted == text {
+                continue;
+            }
+            if check {
+                log::error!("{} is not formatted", path.display());
+                all_formatted = false;
+            } else {
+                std::fs::write(path, formatted)?;
+                log::info!("formatted {}", path.display());
+            }
+        }
+        Ok(all_formatted)
+    }
+
     pub(crate) fn main_impl() -> Result<()> {
         let args = cli_init()?;
         let kanata_arc = Kanata::new_arc(&args)?;
@@ -205,18 +333,11 @@ mod cli {
         // keyboard events while also maintaining `tick()` calls to keyberon.
 
         let (tx, rx) = std::sync::mpsc::sync_channel(100);
+        #[cfg(feature = "cmd")]
+        kanata_arc.lock().set_cmd_async_wakeup_channel(tx.clone());
 
-        let (server, ntx, nrx) = if let Some(address) = {
-            #[cfg(feature = "tcp_server")]
-            {
-                args.tcp_server_address
-            }
-            #[cfg(not(feature = "tcp_server"))]
-            {
-                None::<SocketAddrWrapper>
-            }
-        } {
-            let mut server = TcpServer::new(address.into_inner(), tx.clone());
+        let (server, ntx, nrx) = if args.is_server_enabled() {
+            let mut server = TcpServer::new(&args, tx.clone());
             server.start(kanata_arc.clone());
             let (ntx, nrx) = std::sync::mpsc::sync_channel(100);
             (Some(server), Some(ntx), Some(nrx))
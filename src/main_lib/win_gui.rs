@@ -152,18 +152,11 @@ fn main_impl() -> Result<()> {
     // while also maintaining `tick()` calls to keyberon.
 
     let (tx, rx) = std::sync::mpsc::sync_channel(100);
+    #[cfg(feature = "cmd")]
+    kanata_arc.lock().set_cmd_async_wakeup_channel(tx.clone());
 
-    let (server, ntx, nrx) = if let Some(address) = {
-        #[cfg(feature = "tcp_server")]
-        {
-            args.tcp_server_address
-        }
-        #[cfg(not(feature = "tcp_server"))]
-        {
-            None::<SocketAddrWrapper>
-        }
-    } {
-        let mut server = TcpServer::new(address.into_inner(), tx.clone());
+    let (server, ntx, nrx) = if args.is_server_enabled() {
+        let mut server = TcpServer::new(&args, tx.clone());
         server.start(kanata_arc.clone());
         let (ntx, nrx) = std::sync::mpsc::sync_channel(100);
         (Some(server), Some(ntx), Some(nrx))
@@ -23,8 +23,8 @@ use std::thread;
 
 use super::*;
 use crate::{kanata::CalculatedMouseMove, oskbd::KeyEvent};
-use kanata_parser::cfg::DeviceDetectMode;
 use kanata_parser::cfg::UnicodeTermination;
+use kanata_parser::cfg::{DefDevice, DeviceDetectMode};
 use kanata_parser::custom_action::*;
 use kanata_parser::keys::*;
 
@@ -40,6 +40,21 @@ pub struct KbdIn {
     include_names: Option<Vec<String>>,
     exclude_names: Option<Vec<String>>,
     device_detect_mode: DeviceDetectMode,
+    defdevices: Vec<DefDevice>,
+    /// Index into `defdevices` for registered devices that belong to a `defdevice`.
+    defdevice_idxs: HashMap<Token, u8>,
+    /// Devices that were registered or removed since the last call to `take_device_changes`.
+    device_changes: Vec<DeviceChange>,
+}
+
+/// An input device that was registered or removed by [`KbdIn`].
+#[derive(Debug, Clone)]
+pub struct DeviceChange {
+    pub name: String,
+    pub path: String,
+    pub connected: bool,
+    /// Index of the `defdevice` that the input device belongs to, if any.
+    pub defdevice: Option<u8>,
 }
 
 const INOTIFY_TOKEN_VALUE: usize = 0;
@@ -47,6 +62,11 @@ const INOTIFY_TOKEN: Token = Token(INOTIFY_TOKEN_VALUE);
 
 pub static WAIT_DEVICE_MS: AtomicU64 = AtomicU64::new(200);
 
+/// `defdevice` items of a live reloaded configuration, which [`KbdIn`] applies to its
+/// registered devices on its next read.
+pub static PENDING_DEFDEVICES: parking_lot::Mutex<Option<Vec<DefDevice>>> =
+    parking_lot::const_mutex(None);
+
 impl KbdIn {
     pub fn new(
         dev_paths: &[String],
@@ -54,6 +74,7 @@ impl KbdIn {
         include_names: Option<Vec<String>>,
         exclude_names: Option<Vec<String>>,
         device_detect_mode: DeviceDetectMode,
+        defdevices: Vec<DefDevice>,
     ) -> Result<Self, io::Error> {
         let poll = Poll::new()?;
 
@@ -101,6 +122,9 @@ impl KbdIn {
             include_names,
             exclude_names,
             device_detect_mode,
+            defdevices,
+            defdevice_idxs: HashMap::default(),
+            device_changes: vec![],
         };
 
         for (device, dev_path) in devices.into_iter() {
@@ -130,11 +154,27 @@ impl KbdIn {
         self.poll
             .registry()
             .register(&mut SourceFd(&fd), tok, Interest::READABLE)?;
+        let defdevice = defdevice_index(&self.defdevices, &dev, &path).map(|idx| {
+            log::info!(
+                "{path} is mapped by defdevice {}",
+                self.defdevices[idx].name
+            );
+            self.defdevice_idxs.insert(tok, idx as u8);
+            idx as u8
+        });
+        self.device_changes.push(DeviceChange {
+            name: dev.name().unwrap_or("").to_owned(),
+            path: path.clone(),
+            connected: true,
+            defdevice,
+        });
         self.devices.insert(tok, (dev, path));
         Ok(())
     }
 
-    pub fn read(&mut self) -> Result<Vec<InputEvent>, io::Error> {
+    /// Read events from the input devices. Each event is returned along with the index of the
+    /// `defdevice` that its input device belongs to, if any.
+    pub fn read(&mut self) -> Result<Vec<(InputEvent, Option<u8>)>, io::Error> {
         let mut input_events = vec![];
         loop {
             log::trace!("polling");
@@ -144,15 +184,18 @@ impl KbdIn {
                 return Ok(vec![]);
             }
 
+            self.apply_pending_defdevices();
+
             const EVENT_LIMIT: usize = 48;
 
             let mut do_rediscover = false;
             for event in &self.events {
                 if let Some((device, _)) = self.devices.get_mut(&event.token()) {
+                    let defdevice_idx = self.defdevice_idxs.get(&event.token()).copied();
                     if let Err(e) = device.fetch_events().map(|evs| {
                         evs.into_iter()
                             .take(EVENT_LIMIT)
-                            .for_each(|ev| input_events.push(ev))
+                            .for_each(|ev| input_events.push((ev, defdevice_idx)))
                     }) {
                         // Currently the kind() is uncategorized... not helpful, need to match
                         // on os error. code 19 is ENODEV, "no such device".
@@ -161,8 +204,15 @@ impl KbdIn {
                                 self.poll
                                     .registry()
                                     .deregister(&mut SourceFd(&device.as_raw_fd()))?;
-                                if let Some((_, path)) = self.devices.remove(&event.token()) {
+                                let defdevice = self.defdevice_idxs.remove(&event.token());
+                                if let Some((device, path)) = self.devices.remove(&event.token()) {
                                     log::warn!("removing kbd device: {path}");
+                                    self.device_changes.push(DeviceChange {
+                                        name: device.name().unwrap_or("").to_owned(),
+                                        path: path.clone(),
+                                        connected: false,
+                                        defdevice,
+                                    });
                                     if let Some(ref mut missing) = self.missing_device_paths {
                                         missing.push(path);
                                     }
@@ -184,12 +234,33 @@ impl KbdIn {
                 log::info!("watch found file changes, looking for new devices");
                 self.rediscover_devices()?;
             }
-            if !input_events.is_empty() {
+            if !input_events.is_empty() || !self.device_changes.is_empty() {
                 return Ok(input_events);
             }
         }
     }
 
+    /// Maps the registered devices to the `defdevice` items of a live reload, if there was one
+    /// since the last call.
+    fn apply_pending_defdevices(&mut self) {
+        let Some(defdevices) = PENDING_DEFDEVICES.lock().take() else {
+            return;
+        };
+        self.defdevice_idxs.clear();
+        for (tok, (dev, path)) in &self.devices {
+            if let Some(idx) = defdevice_index(&defdevices, dev, path) {
+                log::info!("{path} is mapped by defdevice {}", defdevices[idx].name);
+                self.defdevice_idxs.insert(*tok, idx as u8);
+            }
+        }
+        self.defdevices = defdevices;
+    }
+
+    /// Returns the devices that were registered or removed since the last call.
+    pub fn take_device_changes(&mut self) -> Vec<DeviceChange> {
+        std::mem::take(&mut self.device_changes)
+    }
+
     fn rediscover_devices(&mut self) -> Result<(), io::Error> {
         // This function is kinda ugly but the borrow checker doesn't like all this mutation.
         let mut paths_registered = vec![];
@@ -249,6 +320,20 @@ impl KbdIn {
     }
 }
 
+/// Returns the index of the first `defdevice` that lists the name or path of the input device.
+/// Paths are also compared after resolving symlinks, so that e.g. `/dev/input/by-id/` paths match.
+fn defdevice_index(defdevices: &[DefDevice], dev: &Device, path: &str) -> Option<usize> {
+    let name = dev.name().unwrap_or("");
+    let canonical_path = fs::canonicalize(path).ok();
+    defdevices.iter().position(|defdevice| {
+        defdevice.device_names.iter().any(|n| n == name)
+            || defdevice.device_paths.iter().any(|p| {
+                p == path
+                    || (canonical_path.is_some() && fs::canonicalize(p).ok() == canonical_path)
+            })
+    })
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 enum DeviceType {
     Keyboard,
@@ -321,10 +406,10 @@ impl TryFrom<InputEvent> for KeyEvent {
     fn try_from(item: InputEvent) -> Result<Self, Self::Error> {
         use OsCode::*;
         match item.kind() {
-            evdev::InputEventKind::Key(k) => Ok(Self {
-                code: OsCode::from_u16(k.0).ok_or(())?,
-                value: KeyValue::from(item.value()),
-            }),
+            evdev::InputEventKind::Key(k) => Ok(Self::new(
+                OsCode::from_u16(k.0).ok_or(())?,
+                KeyValue::from(item.value()),
+            )),
             evdev::InputEventKind::RelAxis(axis_type) => {
                 let dist = item.value();
                 let code: OsCode = match axis_type {
@@ -344,10 +429,7 @@ impl TryFrom<InputEvent> for KeyEvent {
                     }
                     _ => return Err(()),
                 };
-                Ok(KeyEvent {
-                    code,
-                    value: KeyValue::Tap,
-                })
+                Ok(KeyEvent::new(code, KeyValue::Tap))
             }
             _ => Err(()),
         }
@@ -754,12 +836,14 @@ fn handle_signals(symlink: Option<Symlink>) {
         if let Some(signal) = (&mut signals).into_iter().next() {
             match signal {
                 SIGINT | SIGTERM => {
+                    crate::kanata::save_files_on_exit();
                     drop(symlink);
                     signal_hook::low_level::emulate_default_handler(signal)
                         .expect("run original sighandlers");
                     unreachable!();
                 }
                 SIGTSTP => {
+                    crate::kanata::save_files_on_exit();
                     drop(symlink);
                     log::warn!("got SIGTSTP, exiting instead of pausing so keyboards don't hang");
                     std::process::exit(SIGTSTP);
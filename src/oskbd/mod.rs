@@ -105,12 +105,20 @@ use kanata_parser::keys::OsCode;
 pub struct KeyEvent {
     pub code: OsCode,
     pub value: KeyValue,
+    /// Index of the `defdevice` that the input device of this event belongs to, if any.
+    #[cfg(target_os = "linux")]
+    pub device: Option<u8>,
 }
 
 #[allow(dead_code, unused)]
 impl KeyEvent {
     pub fn new(code: OsCode, value: KeyValue) -> Self {
-        Self { code, value }
+        Self {
+            code,
+            value,
+            #[cfg(target_os = "linux")]
+            device: None,
+        }
     }
 }
 
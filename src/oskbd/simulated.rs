@@ -325,6 +325,11 @@ impl Outputs {
         }
     }
 
+    /// Returns the number of ticks since the last output event.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
     fn push(&mut self, event: impl AsRef<str>) {
         if self.ticks > 0 {
             self.events.push(format!("t:{}ms", self.ticks));
@@ -476,13 +481,13 @@ impl InputEvent {
 impl TryFrom<InputEvent> for KeyEvent {
     type Error = ();
     fn try_from(item: InputEvent) -> Result<Self, Self::Error> {
-        Ok(Self {
-            code: OsCode::from_u16(item.code as u16).ok_or(())?,
-            value: match item.up {
+        Ok(Self::new(
+            OsCode::from_u16(item.code as u16).ok_or(())?,
+            match item.up {
                 true => KeyValue::Release,
                 false => KeyValue::Press,
             },
-        })
+        ))
     }
 }
 
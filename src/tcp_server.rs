@@ -1,9 +1,11 @@
 use crate::oskbd::*;
 use crate::Kanata;
+use crate::ValidatedArgs;
 
 #[cfg(feature = "tcp_server")]
 use kanata_tcp_protocol::*;
 use parking_lot::Mutex;
+#[cfg(feature = "tcp_server")]
 use std::net::SocketAddr;
 use std::sync::mpsc::SyncSender as Sender;
 use std::sync::Arc;
@@ -11,14 +13,88 @@ use std::sync::Arc;
 #[cfg(feature = "tcp_server")]
 type HashMap<K, V> = rustc_hash::FxHashMap<K, V>;
 #[cfg(feature = "tcp_server")]
+type HashSet<T> = rustc_hash::FxHashSet<T>;
+#[cfg(feature = "tcp_server")]
 use kanata_parser::cfg::SimpleSExpr;
 #[cfg(feature = "tcp_server")]
-use std::io::Write;
+use std::io::{Read, Write};
 #[cfg(feature = "tcp_server")]
 use std::net::{TcpListener, TcpStream};
+#[cfg(all(feature = "tcp_server", unix))]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(all(feature = "tcp_server", unix))]
+use std::path::Path;
+#[cfg(feature = "tcp_server")]
+use std::path::PathBuf;
+
+#[cfg(feature = "tcp_server")]
+use std::sync::atomic::{AtomicU8, Ordering::SeqCst};
+#[cfg(feature = "tcp_server")]
+use std::time::Duration;
 
+/// How long a [`ClientMessage::Reload`] waits for the reload, which happens once all keys are
+/// released or after a second of kanata being busy.
 #[cfg(feature = "tcp_server")]
-pub type Connections = Arc<Mutex<HashMap<String, TcpStream>>>;
+const RELOAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A client connected over TCP or over the IPC socket.
+#[cfg(feature = "tcp_server")]
+pub enum ClientStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+#[cfg(feature = "tcp_server")]
+impl ClientStream {
+    pub fn try_clone(&self) -> std::io::Result<Self> {
+        match self {
+            ClientStream::Tcp(s) => s.try_clone().map(ClientStream::Tcp),
+            #[cfg(unix)]
+            ClientStream::Unix(s) => s.try_clone().map(ClientStream::Unix),
+        }
+    }
+}
+
+#[cfg(feature = "tcp_server")]
+impl Read for ClientStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            ClientStream::Tcp(s) => s.read(buf),
+            #[cfg(unix)]
+            ClientStream::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+#[cfg(feature = "tcp_server")]
+impl Write for ClientStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            ClientStream::Tcp(s) => s.write(buf),
+            #[cfg(unix)]
+            ClientStream::Unix(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            ClientStream::Tcp(s) => s.flush(),
+            #[cfg(unix)]
+            ClientStream::Unix(s) => s.flush(),
+        }
+    }
+}
+
+#[cfg(feature = "tcp_server")]
+pub struct Connection {
+    pub stream: ClientStream,
+    /// Event topics that this client will receive messages for.
+    pub subscriptions: HashSet<SubscriptionTopic>,
+}
+
+#[cfg(feature = "tcp_server")]
+pub type Connections = Arc<Mutex<HashMap<String, Connection>>>;
 
 #[cfg(not(feature = "tcp_server"))]
 pub type Connections = ();
@@ -36,9 +112,45 @@ fn to_action(val: FakeKeyActionMessage) -> FakeKeyAction {
     }
 }
 
+/// Bitmask of the topics that at least one connected client is subscribed to. This lets the
+/// processing loop skip building event messages that no client would receive.
+#[cfg(feature = "tcp_server")]
+static SUBSCRIBED_TOPICS: AtomicU8 = AtomicU8::new(0);
+
+#[cfg(feature = "tcp_server")]
+fn topic_bit(topic: SubscriptionTopic) -> u8 {
+    1 << (topic as u8)
+}
+
+/// Returns true if any connected client is subscribed to the topic.
+#[cfg(feature = "tcp_server")]
+pub fn is_subscribed(topic: SubscriptionTopic) -> bool {
+    SUBSCRIBED_TOPICS.load(SeqCst) & topic_bit(topic) != 0
+}
+
+/// Recomputes the topics that clients are subscribed to. Must be called after adding or
+/// removing subscriptions or connections.
+#[cfg(feature = "tcp_server")]
+pub fn update_subscribed_topics(connections: &HashMap<String, Connection>) {
+    let topics = connections
+        .values()
+        .flat_map(|conn| conn.subscriptions.iter())
+        .fold(0, |topics, topic| topics | topic_bit(*topic));
+    SUBSCRIBED_TOPICS.store(topics, SeqCst);
+}
+
+#[cfg(feature = "tcp_server")]
+fn remove_connection(connections: &Connections, addr: &str) {
+    let mut connections = connections.lock();
+    connections.remove(addr);
+    update_subscribed_topics(&connections);
+}
+
 #[cfg(feature = "tcp_server")]
 pub struct TcpServer {
-    pub address: SocketAddr,
+    pub address: Option<SocketAddr>,
+    #[cfg(unix)]
+    pub ipc_socket_path: Option<PathBuf>,
     pub connections: Connections,
     pub wakeup_channel: Sender<KeyEvent>,
 }
@@ -50,213 +162,456 @@ pub struct TcpServer {
 
 impl TcpServer {
     #[cfg(feature = "tcp_server")]
-    pub fn new(address: SocketAddr, wakeup_channel: Sender<KeyEvent>) -> Self {
+    pub fn new(args: &ValidatedArgs, wakeup_channel: Sender<KeyEvent>) -> Self {
         Self {
-            address,
+            address: args.tcp_server_address.clone().map(|a| a.into_inner()),
+            #[cfg(unix)]
+            ipc_socket_path: args.ipc_socket_path.clone(),
             connections: Arc::new(Mutex::new(HashMap::default())),
             wakeup_channel,
         }
     }
 
     #[cfg(not(feature = "tcp_server"))]
-    pub fn new(_address: SocketAddr, _wakeup_channel: Sender<KeyEvent>) -> Self {
+    pub fn new(_args: &ValidatedArgs, _wakeup_channel: Sender<KeyEvent>) -> Self {
         Self { connections: () }
     }
 
     #[cfg(feature = "tcp_server")]
     pub fn start(&mut self, kanata: Arc<Mutex<Kanata>>) {
-        use kanata_parser::cfg::FAKE_KEY_ROW;
+        if let Some(address) = self.address {
+            let listener = TcpListener::bind(address).expect("TCP server starts");
+            let connections = self.connections.clone();
+            let wakeup_channel = self.wakeup_channel.clone();
+            let kanata = kanata.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            let addr = stream
+                                .peer_addr()
+                                .expect("incoming conn has known address")
+                                .to_string();
+                            serve_client(
+                                ClientStream::Tcp(stream),
+                                addr,
+                                &kanata,
+                                &connections,
+                                &wakeup_channel,
+                            );
+                        }
+                        Err(_) => log::error!("not able to accept client connection"),
+                    }
+                }
+            });
+        }
+
+        #[cfg(unix)]
+        if let Some(path) = self.ipc_socket_path.clone() {
+            let listener = bind_ipc_socket(&path).expect("IPC socket server starts");
+            let connections = self.connections.clone();
+            let wakeup_channel = self.wakeup_channel.clone();
+            std::thread::spawn(move || {
+                // Unix socket clients are unnamed, so number them to tell them apart.
+                for (client_num, stream) in listener.incoming().enumerate() {
+                    match stream {
+                        Ok(stream) => serve_client(
+                            ClientStream::Unix(stream),
+                            format!("{}#{client_num}", path.display()),
+                            &kanata,
+                            &connections,
+                            &wakeup_channel,
+                        ),
+                        Err(_) => log::error!("not able to accept client connection"),
+                    }
+                }
+            });
+        }
+    }
+
+    #[cfg(not(feature = "tcp_server"))]
+    pub fn start(&mut self, _kanata: Arc<Mutex<Kanata>>) {}
+}
 
-        use crate::kanata::handle_fakekey_action;
+/// The IPC socket that [`remove_ipc_socket`] removes when kanata exits.
+#[cfg(all(feature = "tcp_server", unix))]
+static IPC_SOCKET_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
 
-        let listener = TcpListener::bind(self.address).expect("TCP server starts");
+/// Binds a Unix domain socket at `path` that is only accessible by its owner and group.
+///
+/// Fails if another process is listening on `path`.
+#[cfg(all(feature = "tcp_server", unix))]
+pub(crate) fn bind_ipc_socket(path: &Path) -> std::io::Result<UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
 
-        let connections = self.connections.clone();
-        let wakeup_channel = self.wakeup_channel.clone();
+    // A socket left behind by a previous run would make binding fail. Only remove the file if
+    // it is a socket that nothing listens on, so that neither a mistyped path nor starting
+    // kanata twice can take something away from its owner.
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AddrInUse,
+                    format!("another process is listening on {}", path.display()),
+                ));
+            }
+            log::info!("removing stale IPC socket {}", path.display());
+            std::fs::remove_file(path)?;
+        }
+    }
+    // Bind in a directory that only this user can enter and restrict the permissions there,
+    // so that the socket is never reachable by other users, then link it into place. Unlike a
+    // rename, linking fails instead of replacing a file that exists at `path`.
+    let private_dir = path.with_file_name(format!(".kanata-{}", std::process::id()));
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&private_dir)?;
+    let private_path = private_dir.join("socket");
+    let listener = UnixListener::bind(&private_path).and_then(|listener| {
+        std::fs::set_permissions(&private_path, std::fs::Permissions::from_mode(0o660))?;
+        std::fs::hard_link(&private_path, path)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_file(&private_path);
+    std::fs::remove_dir(&private_dir)?;
+    let listener = listener?;
+    *IPC_SOCKET_PATH.lock() = Some(path.to_owned());
+    log::info!("listening on IPC socket {}", path.display());
+    Ok(listener)
+}
 
-        std::thread::spawn(move || {
-            for stream in listener.incoming() {
-                match stream {
-                    Ok(mut stream) => {
+/// Removes the IPC socket, if kanata is listening on one. Called when kanata is about to exit.
+#[cfg(all(feature = "tcp_server", unix))]
+pub(crate) fn remove_ipc_socket() {
+    if let Some(path) = IPC_SOCKET_PATH.lock().take() {
+        if let Err(e) = std::fs::remove_file(&path) {
+            log::warn!("could not remove IPC socket {}: {e}", path.display());
+        }
+    }
+}
+
+/// Registers a newly connected client and starts a thread that handles its messages.
+#[cfg(feature = "tcp_server")]
+fn serve_client(
+    mut stream: ClientStream,
+    addr: String,
+    kanata: &Arc<Mutex<Kanata>>,
+    connections: &Connections,
+    wakeup_channel: &Sender<KeyEvent>,
+) {
+    {
+        let k = kanata.lock();
+        log::info!(
+            "new client connection, sending initial LayerChange event to inform them of current layer"
+        );
+        if let Err(e) = stream.write(
+            &ServerMessage::LayerChange {
+                new: k.layer_info[k.layout.b().current_layer()].name.clone(),
+            }
+            .as_bytes(),
+        ) {
+            log::warn!("failed to write to stream, dropping it: {e:?}");
+            return;
+        }
+    }
+
+    connections.lock().insert(
+        addr.clone(),
+        Connection {
+            stream: stream.try_clone().expect("stream is clonable"),
+            subscriptions: HashSet::default(),
+        },
+    );
+    let reader =
+        serde_json::Deserializer::from_reader(stream.try_clone().expect("stream is clonable"))
+            .into_iter::<ClientRequest>();
+
+    log::info!("listening for incoming messages {addr}");
+
+    let connections = connections.clone();
+    let kanata = kanata.clone();
+    let wakeup_channel = wakeup_channel.clone();
+    std::thread::spawn(move || {
+        for v in reader {
+            match v {
+                Ok(ClientRequest { request_id, msg }) => {
+                    // Older versions did not report errors for these messages, so clients that
+                    // do not send a request id don't expect a reply to them.
+                    let errors_are_silent = matches!(
+                        msg,
+                        ClientMessage::ChangeLayer { .. } | ClientMessage::SetMouse { .. }
+                    );
+                    let response =
+                        handle_client_message(msg, &kanata, &connections, &addr, &wakeup_channel);
+                    // Requests with an id always get a response so that the
+                    // client knows the request was processed.
+                    let response = match (response, request_id) {
+                        (Some(ServerMessage::Error { msg, .. }), None) if errors_are_silent => {
+                            log::warn!("tcp client {addr} request failed: {msg}");
+                            None
+                        }
+                        (Some(msg), _) => Some(msg),
+                        (None, Some(_)) => Some(ServerMessage::Success {}),
+                        (None, None) => None,
+                    };
+                    if let Some(msg) = response {
+                        if let Err(e) =
+                            stream.write_all(&ServerResponse { request_id, msg }.as_bytes())
                         {
-                            let k = kanata.lock();
-                            log::info!(
-                                "new client connection, sending initial LayerChange event to inform them of current layer"
-                            );
-                            if let Err(e) = stream.write(
-                                &ServerMessage::LayerChange {
-                                    new: k.layer_info[k.layout.b().current_layer()].name.clone(),
-                                }
-                                .as_bytes(),
-                            ) {
-                                log::warn!("failed to write to stream, dropping it: {e:?}");
-                                continue;
-                            }
+                            log::error!("stream write error: {e}");
+                            break;
                         }
-
-                        let addr = stream
-                            .peer_addr()
-                            .expect("incoming conn has known address")
-                            .to_string();
-
-                        connections.lock().insert(
-                            addr.clone(),
-                            stream.try_clone().expect("stream is clonable"),
-                        );
-                        let reader = serde_json::Deserializer::from_reader(
-                            stream.try_clone().expect("stream is clonable"),
+                    }
+                    wake_up(&wakeup_channel);
+                }
+                Err(e) => {
+                    log::warn!("client sent an invalid message, disconnecting them. Err: {e:?}");
+                    // Ignore write result because we're about to disconnect
+                    // the client anyway.
+                    let _ = stream.write_all(
+                        &ServerMessage::error(
+                            ErrorCode::InvalidMessage,
+                            "disconnecting - you sent an invalid message",
                         )
-                        .into_iter::<ClientMessage>();
-
-                        log::info!("listening for incoming messages {addr}");
-
-                        let connections = connections.clone();
-                        let kanata = kanata.clone();
-                        let wakeup_channel = wakeup_channel.clone();
-                        std::thread::spawn(move || {
-                            for v in reader {
-                                match v {
-                                    Ok(event) => {
-                                        match event {
-                                            ClientMessage::ChangeLayer { new } => {
-                                                kanata.lock().change_layer(new);
-                                            }
-                                            ClientMessage::RequestLayerNames {} => {
-                                                let msg = ServerMessage::LayerNames {
-                                                    names: kanata
-                                                        .lock()
-                                                        .layer_info
-                                                        .iter()
-                                                        .map(|info| info.name.clone())
-                                                        .collect::<Vec<_>>(),
-                                                };
-                                                match stream.write_all(&msg.as_bytes()) {
-                                                    Ok(_) => {}
-                                                    Err(err) => log::error!(
-                                                        "server could not send response: {err}"
-                                                    ),
-                                                }
-                                            }
-                                            ClientMessage::ActOnFakeKey { name, action } => {
-                                                let mut k = kanata.lock();
-                                                let index = match k.virtual_keys.get(&name) {
-                                                    Some(index) => Some(*index as u16),
-                                                    None => {
-                                                        if let Err(e) = stream.write_all(
-                                                            &ServerMessage::Error {
-                                                                msg: format!(
-                                                                "unknown virtual/fake key: {name}"
-                                                            ),
-                                                            }
-                                                            .as_bytes(),
-                                                        ) {
-                                                            log::error!("stream write error: {e}");
-                                                            connections.lock().remove(&addr);
-                                                            break;
-                                                        }
-                                                        continue;
-                                                    }
-                                                };
-                                                if let Some(index) = index {
-                                                    log::info!("tcp server fake-key action: {name},{action:?}");
-                                                    handle_fakekey_action(
-                                                        to_action(action),
-                                                        k.layout.bm(),
-                                                        FAKE_KEY_ROW,
-                                                        index,
-                                                    );
-                                                }
-                                                drop(k);
-                                            }
-                                            ClientMessage::SetMouse { x, y } => {
-                                                log::info!(
-                                                    "tcp server SetMouse action: x {x} y {y}"
-                                                );
-                                                match kanata.lock().kbd_out.set_mouse(x, y) {
-                                                    Ok(_) => {
-                                                        log::info!("sucessfully did set mouse position to: x {x} y {y}");
-                                                        // Optionally send a success message to the
-                                                        // client
-                                                    }
-                                                    Err(e) => {
-                                                        log::error!(
-                                                            "Failed to set mouse position: {}",
-                                                            e
-                                                        );
-                                                        // Implement any error handling logic here,
-                                                        // such as sending an error response to
-                                                        // the client
-                                                    }
-                                                }
-                                            }
-                                            ClientMessage::RequestCurrentLayerInfo {} => {
-                                                let mut k = kanata.lock();
-                                                let cur_layer = k.layout.bm().current_layer();
-                                                let msg = ServerMessage::CurrentLayerInfo {
-                                                    name: k.layer_info[cur_layer].name.clone(),
-                                                    cfg_text: k.layer_info[cur_layer]
-                                                        .cfg_text
-                                                        .clone(),
-                                                };
-                                                drop(k);
-                                                match stream.write_all(&msg.as_bytes()) {
-                                                Ok(_) => {}
-                                                Err(err) => log::error!(
-                                                    "Error writing response to RequestCurrentLayerInfo: {err}"
-                                                ),
-                                            }
-                                            }
-                                            ClientMessage::RequestCurrentLayerName {} => {
-                                                let mut k = kanata.lock();
-                                                let cur_layer = k.layout.bm().current_layer();
-                                                let msg = ServerMessage::CurrentLayerName {
-                                                    name: k.layer_info[cur_layer].name.clone(),
-                                                };
-                                                drop(k);
-                                                match stream.write_all(&msg.as_bytes()) {
-                                                Ok(_) => {}
-                                                Err(err) => log::error!(
-                                                    "Error writing response to RequestCurrentLayerName: {err}"
-                                                ),
-                                            }
-                                            }
-                                        }
-                                        use kanata_parser::keys::*;
-                                        wakeup_channel
-                                            .send(KeyEvent {
-                                                code: OsCode::KEY_RESERVED,
-                                                value: KeyValue::WakeUp,
-                                            })
-                                            .expect("write key event");
-                                    }
-                                    Err(e) => {
-                                        log::warn!(
-                                        "client sent an invalid message, disconnecting them. Err: {e:?}"
-                                    );
-                                        // Ignore write result because we're about to disconnect
-                                        // the client anyway.
-                                        let _ = stream.write_all(
-                                            &ServerMessage::Error {
-                                                msg: "disconnecting - you sent an invalid message"
-                                                    .into(),
-                                            }
-                                            .as_bytes(),
-                                        );
-                                        connections.lock().remove(&addr);
-                                        break;
-                                    }
-                                }
+                        .as_bytes(),
+                    );
+                    break;
+                }
+            }
+        }
+        // The client disconnected, either by closing the connection or because of an error.
+        log::info!("tcp client {addr} disconnected");
+        remove_connection(&connections, &addr);
+    });
+}
+
+/// Wakes up the processing loop so that it handles the changes made by a client message.
+#[cfg(feature = "tcp_server")]
+fn wake_up(wakeup_channel: &Sender<KeyEvent>) {
+    use kanata_parser::keys::*;
+    wakeup_channel
+        .send(KeyEvent::new(OsCode::KEY_RESERVED, KeyValue::WakeUp))
+        .expect("write key event");
+}
+
+/// Processes a message from the client at `addr` and returns the response to send back, if any.
+#[cfg(feature = "tcp_server")]
+fn handle_client_message(
+    msg: ClientMessage,
+    kanata: &Arc<Mutex<Kanata>>,
+    connections: &Connections,
+    addr: &str,
+    wakeup_channel: &Sender<KeyEvent>,
+) -> Option<ServerMessage> {
+    use crate::kanata::handle_fakekey_action;
+    use kanata_parser::cfg::FAKE_KEY_ROW;
+
+    match msg {
+        ClientMessage::Hello {
+            protocol_version,
+            capabilities,
+        } => {
+            log::info!(
+                "tcp client {addr} hello: protocol version {protocol_version}, capabilities {capabilities:?}"
+            );
+            Some(ServerMessage::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+            })
+        }
+        ClientMessage::ChangeLayer { new } => match kanata.lock().change_layer(&new) {
+            true => None,
+            false => Some(ServerMessage::error(
+                ErrorCode::UnknownLayer,
+                format!("unknown layer: {new}"),
+            )),
+        },
+        ClientMessage::RequestLayerNames {} => Some(ServerMessage::LayerNames {
+            names: kanata
+                .lock()
+                .layer_info
+                .iter()
+                .map(|info| info.name.clone())
+                .collect::<Vec<_>>(),
+        }),
+        ClientMessage::ActOnFakeKey { name, action } => {
+            let mut k = kanata.lock();
+            match k.virtual_keys.get(&name) {
+                Some(index) => {
+                    let index = *index as u16;
+                    log::info!("tcp server fake-key action: {name},{action:?}");
+                    handle_fakekey_action(to_action(action), k.layout.bm(), FAKE_KEY_ROW, index);
+                    None
+                }
+                None => Some(ServerMessage::error(
+                    ErrorCode::UnknownVirtualKey,
+                    format!("unknown virtual/fake key: {name}"),
+                )),
+            }
+        }
+        ClientMessage::ExecuteAction { expr, action } => kanata
+            .lock()
+            .execute_action_expr(&expr, to_action(action))
+            .err()
+            .map(|e| *e),
+        ClientMessage::SetMouse { x, y } => {
+            log::info!("tcp server SetMouse action: x {x} y {y}");
+            match kanata.lock().kbd_out.set_mouse(x, y) {
+                Ok(_) => {
+                    log::info!("sucessfully did set mouse position to: x {x} y {y}");
+                    None
+                }
+                Err(e) => {
+                    log::error!("Failed to set mouse position: {}", e);
+                    Some(ServerMessage::error(
+                        ErrorCode::ActionFailed,
+                        format!("failed to set mouse position: {e}"),
+                    ))
+                }
+            }
+        }
+        ClientMessage::RequestCurrentLayerInfo {} => {
+            let mut k = kanata.lock();
+            let cur_layer = k.layout.bm().current_layer();
+            Some(ServerMessage::CurrentLayerInfo {
+                name: k.layer_info[cur_layer].name.clone(),
+                cfg_text: k.layer_info[cur_layer].cfg_text.clone(),
+            })
+        }
+        ClientMessage::RequestState {} => Some(ServerMessage::State {
+            state: kanata.lock().state(),
+        }),
+        ClientMessage::RequestStatistics {} => match &kanata.lock().statistics {
+            Some(statistics) => Some(ServerMessage::Statistics {
+                statistics: statistics.statistics().clone(),
+            }),
+            None => Some(ServerMessage::error(
+                ErrorCode::StatisticsDisabled,
+                "to record statistics you must put in defcfg: statistics-file <path>",
+            )),
+        },
+        ClientMessage::RequestDynamicMacros {} => Some(ServerMessage::DynamicMacros {
+            macros: kanata.lock().dynamic_macro_list(),
+        }),
+        ClientMessage::RequestDynamicMacro { id } => match kanata.lock().dynamic_macro(id) {
+            Some(dynamic_macro) => Some(ServerMessage::DynamicMacro { dynamic_macro }),
+            None => Some(ServerMessage::error(
+                ErrorCode::UnknownDynamicMacro,
+                format!("unknown dynamic macro: {id}"),
+            )),
+        },
+        ClientMessage::SetDynamicMacro { id, events } => {
+            let set = Kanata::dynamic_macro_from_events(&events)
+                .and_then(|items| kanata.lock().set_dynamic_macro(id, items));
+            match set {
+                Ok(()) => {
+                    log::info!("tcp server set dynamic macro {id}");
+                    None
+                }
+                Err(e) => Some(ServerMessage::error(
+                    ErrorCode::InvalidDynamicMacro,
+                    format!("invalid dynamic macro {id}: {e}"),
+                )),
+            }
+        }
+        ClientMessage::DeleteDynamicMacro { id } => match kanata.lock().delete_dynamic_macro(id) {
+            true => None,
+            false => Some(ServerMessage::error(
+                ErrorCode::UnknownDynamicMacro,
+                format!("unknown dynamic macro: {id}"),
+            )),
+        },
+        ClientMessage::RequestCurrentLayerName {} => {
+            let mut k = kanata.lock();
+            let cur_layer = k.layout.bm().current_layer();
+            Some(ServerMessage::CurrentLayerName {
+                name: k.layer_info[cur_layer].name.clone(),
+            })
+        }
+        ClientMessage::Subscribe { topics } => {
+            log::info!("tcp server subscribe: {topics:?}");
+            let mut conns = connections.lock();
+            if let Some(conn) = conns.get_mut(addr) {
+                conn.subscriptions.extend(topics);
+            }
+            update_subscribed_topics(&conns);
+            None
+        }
+        ClientMessage::Reload { path } => {
+            let (cfg_idx, cfg_path) = {
+                let k = kanata.lock();
+                match path {
+                    Some(path) => {
+                        let path = PathBuf::from(path);
+                        match k.cfg_paths.iter().position(|p| *p == path) {
+                            Some(idx) => (idx, path),
+                            None => {
+                                return Some(ServerMessage::error(
+                                    ErrorCode::UnknownConfigFile,
+                                    format!(
+                                        "{} was not passed as an argument to kanata",
+                                        path.display()
+                                    ),
+                                ));
                             }
-                        });
+                        }
                     }
-                    Err(_) => log::error!("not able to accept client connection"),
+                    None => (k.cur_cfg_idx, k.cfg_paths[k.cur_cfg_idx].clone()),
                 }
+            };
+            log::info!(
+                "tcp server requested live reload of file: {}",
+                cfg_path.display()
+            );
+            // The reload runs in the processing loop once all keys are released. Wait for it so
+            // that the client gets the result of the reload that was actually applied.
+            let (reply_tx, reply_rx) = std::sync::mpsc::channel();
+            kanata.lock().request_live_reload(cfg_idx, reply_tx);
+            wake_up(wakeup_channel);
+            let Ok(diagnostic) = reply_rx.recv_timeout(RELOAD_TIMEOUT) else {
+                return Some(ServerMessage::error(
+                    ErrorCode::Other,
+                    "timed out waiting for the live reload; it happens once all keys are released",
+                ));
+            };
+            Some(ServerMessage::ConfigReloadResult {
+                path: cfg_path.display().to_string(),
+                diagnostic,
+            })
+        }
+        ClientMessage::Unsubscribe { topics } => {
+            log::info!("tcp server unsubscribe: {topics:?}");
+            let mut conns = connections.lock();
+            if let Some(conn) = conns.get_mut(addr) {
+                conn.subscriptions.retain(|topic| !topics.contains(topic));
             }
-        });
+            update_subscribed_topics(&conns);
+            None
+        }
     }
+}
 
-    #[cfg(not(feature = "tcp_server"))]
-    pub fn start(&mut self, _kanata: Arc<Mutex<Kanata>>) {}
+/// Converts a configuration parse error into its protocol representation.
+#[cfg(feature = "tcp_server")]
+pub(crate) fn config_diagnostic(e: &miette::Report) -> ConfigDiagnostic {
+    let label = e.labels().and_then(|mut labels| labels.next());
+    let contents = label.as_ref().and_then(|label| {
+        e.source_code()
+            .and_then(|src| src.read_span(label.inner(), 0, 0).ok())
+    });
+    ConfigDiagnostic {
+        msg: e.to_string(),
+        help: e.help().map(|help| help.to_string()),
+        file: contents
+            .as_ref()
+            .and_then(|c| c.name().map(|name| name.to_string())),
+        span: label.zip(contents).map(|(label, contents)| DiagnosticSpan {
+            offset: label.offset(),
+            length: label.len(),
+            line: contents.line(),
+            column: contents.column(),
+        }),
+    }
 }
 
 #[cfg(feature = "tcp_server")]
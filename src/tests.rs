@@ -109,6 +109,10 @@ fn parse_home_row_mods() {
         "./cfg_samples/home-row-mod-advanced.kbd",
     ))
     .unwrap();
+    new_from_file(&std::path::PathBuf::from(
+        "./cfg_samples/home-row-mod-opposite-hand.kbd",
+    ))
+    .unwrap();
 }
 
 #[test]
@@ -137,3 +141,376 @@ fn sizeof_state() {
         2 * std::mem::size_of::<usize>()
     );
 }
+
+#[test]
+#[cfg(feature = "tcp_server")]
+fn config_diagnostic_has_error_location() {
+    init_log();
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let e = new_from_str("(defsrc a)\n(deflayer base notakey)", Default::default())
+        .map(|_| ())
+        .unwrap_err();
+    let diagnostic = crate::tcp_server::config_diagnostic(&e);
+    assert!(diagnostic.help.unwrap().contains("notakey"));
+    assert_eq!(diagnostic.file.as_deref(), Some("configuration"));
+    let span = diagnostic.span.unwrap();
+    assert_eq!((span.line, span.column), (1, 15));
+    assert_eq!((span.offset, span.length), (26, 7));
+}
+
+#[test]
+#[cfg(all(feature = "tcp_server", unix))]
+fn ipc_socket_replaces_only_stale_socket() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixStream;
+
+    init_log();
+    let path = std::env::temp_dir().join(format!("kanata-test-{}.sock", std::process::id()));
+    let listener = crate::tcp_server::bind_ipc_socket(&path).unwrap();
+    drop(listener);
+    // The socket file is left behind, as it would be if kanata was killed.
+    assert!(path.exists());
+    let listener = crate::tcp_server::bind_ipc_socket(&path).unwrap();
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o660);
+    UnixStream::connect(&path).unwrap();
+    listener.accept().unwrap();
+    // A socket that is listened on is not taken over.
+    crate::tcp_server::bind_ipc_socket(&path)
+        .map(|_| ())
+        .unwrap_err();
+    UnixStream::connect(&path).unwrap();
+    crate::tcp_server::remove_ipc_socket();
+    assert!(!path.exists());
+
+    // Regular files are never removed.
+    std::fs::write(&path, "not a socket").unwrap();
+    crate::tcp_server::bind_ipc_socket(&path)
+        .map(|_| ())
+        .unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn wheel_taps_follow_scroll_distance() {
+    use crate::kanata::wheel_taps;
+
+    assert_eq!(wheel_taps(1), 1);
+    assert_eq!(wheel_taps(-1), 1);
+    assert_eq!(wheel_taps(3), 3);
+    assert_eq!(wheel_taps(-4), 4);
+    // A mapped wheel key is tapped at least once, and a burst of notches is capped.
+    assert_eq!(wheel_taps(0), 1);
+    assert_eq!(wheel_taps(25), 10);
+    assert_eq!(wheel_taps(i32::MIN), 10);
+}
+
+#[test]
+fn layer_diagram_ascii() {
+    use crate::layer_diagram::*;
+    init_log();
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let cfg = new_from_str(
+        "(defsrc a b c)
+         (defalias h (tap-hold 200 200 a lctl))
+         (deflayer base @h (one-shot 500 lsft) (layer-while-held nav))
+         (deflayer nav _ XX C-c)",
+        Default::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        render_layers(&cfg, DiagramFormat::Ascii),
+        "base
++------+---------+-----+
+| a    | os:lsft |     |
+| lctl |         | nav |
++------+---------+-----+
+
+nav
++------+---------+-----+
+|      | XX      | C-c |
+|      |         |     |
++------+---------+-----+
+"
+    );
+    let svg = render_layers(&cfg, DiagramFormat::Svg);
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.contains(">os:lsft</text>"));
+}
+
+#[test]
+fn layer_diagram_names_actions() {
+    use crate::layer_diagram::*;
+    init_log();
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    // Only the alias itself is named, not an equal action written in a layer.
+    let cfg = new_from_str(
+        "(defsrc a b c)
+         (defalias m (macro a b))
+         (deflayer base @m (macro a b) (mwheel-up 50 120))",
+        Default::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        render_layers(&cfg, DiagramFormat::Ascii),
+        "base
++----+-------+-----------+
+| @m | macro | mwheel-up |
+|    |       |           |
++----+-------+-----------+
+"
+    );
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn dump_cfg_json_resolves_templates_and_variables() {
+    use crate::cfg_dump::*;
+    init_log();
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let cfg = new_from_str(
+        "(defvar tt 200 ht (tap-hold $tt $tt a lctl))
+         (deftemplate two (x) $x $x)
+         (defsrc a b c)
+         (defalias h $ht)
+         (deflayer base (t! two @h) (layer-while-held nav))
+         (deflayermap (nav) a C-c)
+         (defvirtualkeys vk a)
+         (defseq vk (a b) (macro c) (b c))",
+        Default::default(),
+    )
+    .unwrap();
+    let json: serde_json::Value = serde_json::from_str(&dump_cfg(&cfg, DumpFormat::Json)).unwrap();
+    assert_eq!(
+        json["aliases"]["h"],
+        serde_json::json!(["tap-hold", "200", "200", "a", "lctl"])
+    );
+    assert_eq!(
+        json["layers"],
+        serde_json::json!([
+            {
+                "name": "base",
+                "actions": {
+                    "a": ["tap-hold", "200", "200", "a", "lctl"],
+                    "b": ["tap-hold", "200", "200", "a", "lctl"],
+                    "c": ["layer-while-held", "nav"],
+                },
+            },
+            {"name": "nav", "actions": {"a": "C-c"}},
+        ])
+    );
+    assert_eq!(
+        json["sequences"],
+        serde_json::json!([
+            {"target": "vk", "keys": ["a", "b"]},
+            {"target": ["macro", "c"], "keys": ["b", "c"]},
+        ])
+    );
+}
+
+#[test]
+fn import_kmonad() {
+    use crate::import::*;
+    init_log();
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let conversion = import(
+        "(defcfg fallthrough true cmp-seq ralt)
+         (defsrc caps a s)
+         (defalias
+           xcp (tap-hold-next 400 esc lctl)
+           cpy (around lctl (around lsft c))
+           hi #(h i P50 !))
+         (deflayer base @xcp @cpy (layer-toggle other))
+         (deflayer other _ @hi (layer-add x))",
+        ImportFormat::Kmonad,
+    )
+    .unwrap();
+    assert_eq!(
+        conversion.warnings,
+        [
+            "defcfg option cmp-seq was not converted",
+            "Action layer-add was replaced by XX",
+        ]
+    );
+    for expected in [
+        "process-unmapped-keys yes",
+        "xcp (tap-hold-press 400 400 esc lctl)",
+        "cpy (multi lctl lsft c)",
+        "hi (macro h i 50 S-1)",
+        "@xcp @cpy (layer-while-held other)",
+        "_    @hi  XX",
+    ] {
+        assert!(conversion.cfg.contains(expected), "{}", conversion.cfg);
+    }
+}
+
+#[test]
+fn import_kmonad_symbols() {
+    use crate::import::*;
+    init_log();
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let conversion = import(
+        "(defsrc 1 2 3 4)
+         (defalias sym (layer-toggle symbols))
+         (deflayer base @sym 2 3 4)
+         (deflayer symbols _ @ # ~)",
+        ImportFormat::Kmonad,
+    )
+    .unwrap();
+    assert!(conversion.warnings.is_empty(), "{:?}", conversion.warnings);
+    assert!(
+        conversion.cfg.contains("_    S-2 S-3 S-grv"),
+        "{}",
+        conversion.cfg
+    );
+    // Alias references are kept.
+    assert!(conversion.cfg.contains("@sym 2"), "{}", conversion.cfg);
+}
+
+#[test]
+fn import_qmk_keymap_c() {
+    use crate::import::*;
+    init_log();
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let conversion = import(
+        "#include \"keymaps.h\"
+         enum layers { _BASE, _NAV };
+         #define HOME_A LGUI_T(KC_A)
+         const uint16_t PROGMEM keymaps[][MATRIX_ROWS][MATRIX_COLS] = {
+             [_BASE] = LAYOUT(
+                 KC_TAB, HOME_A, KC_B, // comment
+                 LT(_NAV, KC_SPC), MO(_NAV)
+             ),
+             [_NAV] = LAYOUT(
+                 _______, KC_EXLM, LCTL(KC_C),
+                 KC_P1, _______
+             )
+         };",
+        ImportFormat::QmkC,
+    )
+    .unwrap();
+    assert_eq!(
+        conversion.warnings,
+        ["1 key position(s) without a key to tap or with a duplicate key in the first layer were left out"]
+    );
+    for expected in [
+        "(defsrc\n  tab                                           a                         b\n  spc\n)",
+        "  tab                                           (tap-hold 200 200 a lmet) b",
+        "  (tap-hold 200 200 spc (layer-while-held nav))",
+        "  _                                             S-1                       C-c\n  kp1\n)",
+    ] {
+        assert!(conversion.cfg.contains(expected), "{}", conversion.cfg);
+    }
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn import_qmk_keymap_json() {
+    use crate::import::*;
+    init_log();
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let conversion = import(
+        r#"{
+            "keyboard": "example",
+            "layout": "LAYOUT_split_1x2_1",
+            "layers": [
+                ["KC_Q", "KC_W", "KC_E", "KC_R", "LT(1, KC_SPC)"],
+                ["KC_KP_1", "KC_P2", "_______", "MO(1)", "KC_TRNS"]
+            ]
+        }"#,
+        ImportFormat::QmkJson,
+    )
+    .unwrap();
+    assert_eq!(conversion.warnings, Vec::<String>::new());
+    for expected in [
+        "(defsrc\n  q                                                w   e r\n  spc\n)",
+        "\n  (tap-hold 200 200 spc (layer-while-held layer1))\n)",
+        "\n  kp1    ",
+        " kp2 _ (layer-while-held layer1)\n  _\n)",
+    ] {
+        assert!(conversion.cfg.contains(expected), "{}", conversion.cfg);
+    }
+}
+
+#[test]
+fn export_keymaps() {
+    use crate::export::*;
+    init_log();
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let cfg = new_from_str(
+        "(defsrc caps a s
+                 spc)
+         (deflayer base
+           (one-shot 500 lsft) (tap-hold 200 200 a lctl) (tap-dance 200 (s C-s))
+           (tap-hold 180 180 spc (layer-while-held nav)))
+         (deflayer nav (layer-switch base) C-S-a (unicode x) _)",
+        Default::default(),
+    )
+    .unwrap();
+
+    let qmk = export(&cfg, ExportFormat::QmkJson);
+    assert!(
+        qmk.keymap.contains(
+            r#""OSM(MOD_LSFT)", "MT(MOD_LCTL, KC_A)", "KC_S",
+      "LT(1, KC_SPC)""#
+        ),
+        "{}",
+        qmk.keymap
+    );
+    assert!(
+        qmk.keymap.contains(
+            r#""DF(0)", "LCTL(LSFT(KC_A))", "KC_NO",
+      "KC_TRNS""#
+        ),
+        "{}",
+        qmk.keymap
+    );
+    assert_eq!(
+        qmk.warnings,
+        [
+            "Layer nav, key s: unicode has no firmware equivalent and was exported as no-op",
+            "Hold-tap keys use the tapping term of the firmware (200 ms unless configured) \
+             instead of their timeouts of 180 ms",
+            "Layer base, key s: tap dance needs C code in QMK, exported as its first action",
+        ]
+    );
+
+    let zmk = export(&cfg, ExportFormat::Zmk);
+    for expected in [
+        "bindings = <&kp S>, <&kp LC(S)>;",
+        "&sk LSHFT   &mt LCTRL A   &td0",
+        "&to 0       &kp LC(LS(A)) &none",
+        "&lt 1 SPACE",
+    ] {
+        assert!(zmk.keymap.contains(expected), "{}", zmk.keymap);
+    }
+    assert_eq!(zmk.warnings.len(), 2);
+}
@@ -0,0 +1,18 @@
+use super::*;
+
+#[test]
+fn cmd_async_taps_vkey_for_exit_code() {
+    let result = simulate(
+        "
+(defcfg danger-enable-cmd yes)
+(defsrc a b)
+(deflayer base
+  (cmd-async (on-success ok) (on-failure fail) (cmd true))
+  (cmd-async (on-exit 0 zero) (on-success ok) (cmd true)))
+(defvirtualkeys ok x fail y zero z)
+",
+        "d:a t:10 u:a t:10 d:b t:10 u:b t:10",
+    )
+    .to_ascii();
+    assert_eq!("t:1ms dn:X t:1ms up:X t:19ms dn:Z t:1ms up:Z", result);
+}
@@ -0,0 +1,106 @@
+use super::*;
+
+fn event(k: &mut Kanata, key: &str, value: KeyValue, device: Option<u8>) {
+    input_event(k, key, value, device);
+    k.tick_ms(10, &None).unwrap();
+}
+
+#[test]
+fn defdevice_keys_are_mapped_separately() {
+    let (_lk, mut k) = new_kanata(
+        "
+(defsrc kp1 kp2)
+(deflayer base 1 (layer-while-held nav))
+(deflayer nav 2 _)
+(defdevice pad
+  (names pad)
+  (defsrc kp1)
+  (deflayer base a)
+  (deflayer nav b))
+",
+    );
+
+    event(&mut k, "kp1", KeyValue::Press, None);
+    event(&mut k, "kp1", KeyValue::Release, None);
+    event(&mut k, "kp1", KeyValue::Press, Some(0));
+    event(&mut k, "kp1", KeyValue::Release, Some(0));
+    // Layer state is shared between the devices.
+    event(&mut k, "kp2", KeyValue::Press, None);
+    event(&mut k, "kp1", KeyValue::Press, Some(0));
+    event(&mut k, "kp1", KeyValue::Release, Some(0));
+    event(&mut k, "kp2", KeyValue::Release, None);
+    // Unknown devices fall back to the main defsrc.
+    event(&mut k, "kp1", KeyValue::Press, Some(1));
+    event(&mut k, "kp1", KeyValue::Release, Some(1));
+
+    assert_eq!(
+        "out:↓Kb1 out:↑Kb1 out:↓A out:↑A out:↓B out:↑B out:↓Kb1 out:↑Kb1",
+        k.kbd_out.outputs.events.join("\n").to_spaces().no_time()
+    );
+}
+
+#[test]
+fn defdevice_connect_and_disconnect_actions() {
+    let (_lk, mut k) = new_kanata(
+        "
+(defsrc a)
+(deflayer laptop b)
+(deflayer external c)
+(defdevice ext
+  (names ext)
+  (on-connect (layer-switch external))
+  (on-disconnect (layer-switch laptop)))
+",
+    );
+
+    let change = |connected, defdevice| crate::oskbd::DeviceChange {
+        name: "ext".into(),
+        path: "/dev/input/event9".into(),
+        connected,
+        defdevice,
+    };
+    k.handle_device_change(&change(true, Some(0)));
+    k.tick_ms(10, &None).unwrap();
+    event(&mut k, "a", KeyValue::Press, None);
+    event(&mut k, "a", KeyValue::Release, None);
+    k.handle_device_change(&change(false, Some(0)));
+    k.tick_ms(10, &None).unwrap();
+    event(&mut k, "a", KeyValue::Press, None);
+    event(&mut k, "a", KeyValue::Release, None);
+    // Devices without a defdevice do not trigger any action.
+    k.handle_device_change(&change(true, None));
+    k.tick_ms(10, &None).unwrap();
+    event(&mut k, "a", KeyValue::Press, None);
+    event(&mut k, "a", KeyValue::Release, None);
+
+    assert_eq!(
+        "out:↓C out:↑C out:↓B out:↑B out:↓B out:↑B",
+        k.kbd_out.outputs.events.join("\n").to_spaces().no_time()
+    );
+}
+
+#[test]
+fn defdevice_keys_repeat_their_own_output() {
+    let (_lk, mut k) = new_kanata(
+        "
+(defsrc kp1)
+(deflayer base 1)
+(defdevice pad
+  (names pad)
+  (defsrc kp1)
+  (deflayer base a))
+",
+    );
+
+    event(&mut k, "kp1", KeyValue::Press, Some(0));
+    event(&mut k, "kp1", KeyValue::Repeat, Some(0));
+    event(&mut k, "kp1", KeyValue::Release, Some(0));
+    // Nothing is repeated once the key is released.
+    event(&mut k, "kp1", KeyValue::Repeat, Some(0));
+
+    // The simulated output shows repeats as presses.
+    assert_eq!(
+        "out:↓A out:↓A out:↑A",
+        k.kbd_out.outputs.events.join("\n").to_spaces().no_time()
+    );
+}
@@ -0,0 +1,213 @@
+use super::*;
+
+fn macro_file_cfg(path: &std::path::Path) -> String {
+    format!(
+        "
+(defcfg dynamic-macro-file \"{}\")
+(defsrc a b lsft f1 f2 f3)
+(deflayer base a b lsft (dynamic-macro-record 1) dynamic-macro-record-stop (dynamic-macro-play 1))
+",
+        path.display()
+    )
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn dynamic_macros_are_saved_and_loaded() {
+    let path =
+        std::env::temp_dir().join(format!("kanata-dynamic-macros-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let cfg = macro_file_cfg(&path);
+    let (lk, mut k) = new_kanata(&cfg);
+    sim_input(
+        &mut k,
+        "d:f1 t:10 u:f1 t:10 d:lsft t:10 d:a t:10 u:a t:10 u:lsft t:200 d:b t:10 u:b t:10 \
+         d:f2 t:10 u:f2 t:10",
+    );
+    // The macros are written in the background; exiting waits for the write.
+    k.save_files();
+    drop((lk, k));
+
+    let saved = std::fs::read_to_string(&path).expect("macros are saved");
+    let macros: Vec<kanata_tcp_protocol::DynamicMacro> = serde_json::from_str(&saved).unwrap();
+    assert_eq!(macros.len(), 1);
+    assert_eq!(macros[0].id, 1);
+    assert_eq!(macros[0].cfg_text.as_deref(), Some("(macro S-a 220 b)"));
+    let events: Vec<String> = macros[0]
+        .events
+        .iter()
+        .map(|e| format!("{:?}:{}:{}", e.action, e.key, e.delay))
+        .collect();
+    assert_eq!(
+        events,
+        [
+            "Release:f1:10",
+            "Press:lsft:10",
+            "Press:a:10",
+            "Release:a:10",
+            "Release:lsft:200",
+            "Press:b:10",
+            "Release:b:10",
+        ]
+    );
+
+    // The macro is loaded on the next start.
+    let result = simulate(cfg.as_str(), "d:f3 t:10 u:f3 t:500").to_ascii();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(
+        "t:11ms dn:LShift t:10ms dn:A t:10ms up:A t:10ms up:LShift t:200ms dn:B t:10ms up:B",
+        result
+    );
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn dynamic_macro_cfg_text_plays_the_recorded_events() {
+    let path = std::env::temp_dir().join(format!(
+        "kanata-dynamic-macro-cfg-{}.json",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let cfg = macro_file_cfg(&path)
+        .replace("(defsrc a b", "(defsrc 1 0")
+        .replace("(deflayer base a b", "(deflayer base 1 0");
+    let (lk, mut k) = new_kanata(&cfg);
+    sim_input(
+        &mut k,
+        "d:f1 t:10 u:f1 t:10 d:1 t:10 u:1 t:10 d:lsft t:10 d:0 t:10 u:0 t:10 u:lsft t:10 \
+         d:f2 t:10 u:f2 t:10",
+    );
+    k.save_files();
+    drop((lk, k));
+    let saved = std::fs::read_to_string(&path).expect("macros are saved");
+    let _ = std::fs::remove_file(&path);
+    let macros: Vec<kanata_tcp_protocol::DynamicMacro> = serde_json::from_str(&saved).unwrap();
+    assert_eq!(
+        macros[0].cfg_text.as_deref(),
+        Some("(macro Digit1 S-Digit0)")
+    );
+
+    // The configuration text is read back as the recorded keys rather than as delays.
+    let cfg = format!(
+        "(defsrc f1) (deflayer base {})",
+        macros[0].cfg_text.as_ref().unwrap()
+    );
+    let result = simulate(cfg.as_str(), "d:f1 t:10 u:f1 t:100")
+        .to_ascii()
+        .no_time();
+    assert_eq!("dn:Kb1 up:Kb1 dn:LShift dn:Kb0 up:Kb0 up:LShift", result);
+}
+
+#[test]
+fn dynamic_macro_play_repeat() {
+    let cfg = "
+(defsrc a b f1 f2 f3)
+(deflayer base a b (dynamic-macro-record 1) (dynamic-macro-play-repeat 1 3 100)
+ (dynamic-macro-play-repeat 1 1 200))
+";
+    let record = "d:f1 t:10 u:f1 t:10 d:a t:40 u:a t:40 d:b t:40 u:b t:40 d:f1 t:10 u:f1 t:10";
+    let result = simulate(cfg, &format!("{record} d:f2 t:10 u:f2 t:1000")).to_ascii();
+    assert_eq!(
+        "t:20ms dn:A t:40ms up:A t:40ms dn:B t:40ms up:B \
+         t:71ms dn:A t:40ms up:A t:40ms dn:B t:40ms up:B \
+         t:55ms dn:A t:40ms up:A t:40ms dn:B t:40ms up:B \
+         t:55ms dn:A t:40ms up:A t:40ms dn:B t:40ms up:B",
+        result
+    );
+    let result = simulate(cfg, &format!("{record} d:f3 t:10 u:f3 t:1000")).to_ascii();
+    assert_eq!(
+        "t:20ms dn:A t:40ms up:A t:40ms dn:B t:40ms up:B \
+         t:66ms dn:A t:20ms up:A t:20ms dn:B t:20ms up:B",
+        result
+    );
+}
+
+#[cfg(feature = "tcp_server")]
+#[test]
+fn dynamic_macros_can_be_set_and_deleted() {
+    use kanata_tcp_protocol::{DynamicMacroEvent, KeyEventValue};
+
+    init_log();
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut k = Kanata::new_from_str(
+        "
+(defcfg dynamic-macro-max-presses 3)
+(defsrc f1)
+(deflayer base (dynamic-macro-play 2))
+",
+        Default::default(),
+    )
+    .expect("failed to parse cfg");
+    let event = |key: &str, action, delay| DynamicMacroEvent {
+        key: key.into(),
+        action,
+        delay,
+    };
+    let set = |k: &mut Kanata, events: &[DynamicMacroEvent]| {
+        Kanata::dynamic_macro_from_events(events).and_then(|items| k.set_dynamic_macro(2, items))
+    };
+
+    assert!(k.dynamic_macro_list().is_empty());
+    assert!(set(&mut k, &[event("a", KeyEventValue::Press, 10)]).is_err());
+    assert!(set(&mut k, &[event("nokey", KeyEventValue::Press, 10)]).is_err());
+    // The limit of dynamic-macro-max-presses applies to macros set over TCP too.
+    let taps: Vec<DynamicMacroEvent> = (0..4)
+        .flat_map(|_| {
+            [
+                event("a", KeyEventValue::Press, 10),
+                event("a", KeyEventValue::Release, 10),
+            ]
+        })
+        .collect();
+    assert!(set(&mut k, &taps).is_err());
+    set(
+        &mut k,
+        &[
+            event("lsft", KeyEventValue::Press, 0),
+            event("a", KeyEventValue::Press, 10),
+            event("a", KeyEventValue::Release, 0),
+            event("lsft", KeyEventValue::Release, 300),
+            event("b", KeyEventValue::Press, 10),
+            event("b", KeyEventValue::Release, 0),
+        ],
+    )
+    .expect("valid macro");
+    let dynamic_macro = k.dynamic_macro(2).expect("macro is set");
+    assert_eq!(dynamic_macro.cfg_text.as_deref(), Some("(macro S-a 310 b)"));
+    assert_eq!(k.dynamic_macro_list(), [dynamic_macro]);
+
+    let f1 = str_to_oscode("f1").unwrap();
+    k.handle_input_event(&KeyEvent::new(f1, KeyValue::Press))
+        .unwrap();
+    k.handle_input_event(&KeyEvent::new(f1, KeyValue::Release))
+        .unwrap();
+    k.tick_ms(1000, &None).unwrap();
+    assert_eq!(
+        "t:2ms dn:LShift t:1ms dn:A t:9ms up:A t:1ms up:LShift t:300ms dn:B t:10ms up:B",
+        k.kbd_out.outputs.events.join("\n").to_ascii()
+    );
+
+    assert!(k.delete_dynamic_macro(2));
+    assert!(!k.delete_dynamic_macro(2));
+    assert_eq!(k.dynamic_macro(2), None);
+
+    // Mouse buttons are written with their configuration names.
+    let tap = |key: &str| {
+        [
+            event(key, KeyEventValue::Press, 10),
+            event(key, KeyEventValue::Release, 10),
+        ]
+    };
+    set(&mut k, &tap("mbck")).expect("valid macro");
+    let dynamic_macro = k.dynamic_macro(2).expect("macro is set");
+    assert_eq!(dynamic_macro.events[0].key, "mbck");
+    assert_eq!(dynamic_macro.cfg_text.as_deref(), Some("(macro mbck)"));
+    // Keys without a configuration name cannot be written as a macro action.
+    set(&mut k, &tap("BTN_TRIGGER")).expect("valid macro");
+    let dynamic_macro = k.dynamic_macro(2).expect("macro is set");
+    assert_eq!(dynamic_macro.events[0].key, "BTN_TRIGGER");
+    assert_eq!(dynamic_macro.cfg_text, None);
+}
@@ -0,0 +1,128 @@
+use super::*;
+
+#[test]
+fn hold_tap_analysis_reports_misfires() {
+    use crate::kanata::{HoldTapAnalyzer, HoldTapInfo};
+    use kanata_parser::keys::OsCode::*;
+    use kanata_tcp_protocol::HoldTapResolution::*;
+
+    let path = std::env::temp_dir().join(format!("kanata-hold-tap-{}.txt", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut analysis = HoldTapAnalyzer::new(path.to_str().unwrap());
+    // Times are given explicitly because the analysis uses wall-clock time, which does not
+    // advance with simulated ticks.
+    let start = instant::Instant::now();
+    let at = |ms| start + std::time::Duration::from_millis(ms);
+    let info = |variant, timeout| Some(HoldTapInfo { variant, timeout });
+    let a = info("tap-hold-press", 200);
+
+    // Fast roll from a to s: tap-hold-press resolves to hold although s is released last.
+    analysis.record_event(KEY_A, true, a, at(0));
+    analysis.record_event(KEY_S, true, None, at(20));
+    analysis.record_decision(KEY_A, Hold, 20, at(20));
+    analysis.record_event(KEY_A, false, None, at(40));
+    analysis.record_event(KEY_S, false, None, at(60));
+    analysis.tick(at(1560));
+    // A normal tap of a.
+    analysis.record_event(KEY_A, true, a, at(1560));
+    analysis.record_event(KEY_A, false, None, at(1610));
+    analysis.record_decision(KEY_A, Tap, 50, at(1610));
+    analysis.tick(at(3110));
+    // d is held past its timeout without pressing another key.
+    analysis.record_event(KEY_D, true, info("tap-hold", 150), at(3110));
+    analysis.record_decision(KEY_D, Timeout, 150, at(3260));
+    analysis.record_event(KEY_D, false, None, at(3340));
+    analysis.tick(at(4840));
+    // f is held past its timeout and the result is deleted right away.
+    analysis.record_event(KEY_F, true, info("tap-hold-release", 200), at(4840));
+    analysis.record_decision(KEY_F, Timeout, 200, at(5040));
+    analysis.record_event(KEY_F, false, None, at(5090));
+    analysis.record_event(KEY_BACKSPACE, true, None, at(5140));
+    analysis.record_event(KEY_BACKSPACE, false, None, at(5160));
+    analysis.tick(at(6660));
+
+    let report = analysis.report();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(
+        &lines[2..8],
+        &[
+            "key  variant           timeout  tap  hold  timed out  backspace  rolled  alone",
+            "A    tap-hold-press    200 ms   1    1     0          0          1       0",
+            "D    tap-hold          150 ms   0    0     1          0          0       1",
+            "F    tap-hold-release  200 ms   0    0     1          1          0       0",
+            "",
+            "Suggestions:",
+        ]
+    );
+    assert!(report.contains("- A: use tap-hold-release so that rolls resolve to tap\n"));
+    assert!(report.contains("- D: taps were held up to 230 ms, consider a timeout above 240 ms\n"));
+    assert!(report.contains("- F: use a longer timeout\n"));
+    assert!(report.contains(
+        "- A (tap-hold-press 200 ms): hold after 20 ms; A↓+0 S↓+20 A↑+40 S↑+60; \
+         likely misfire: rolled into the next key\n"
+    ));
+    assert!(report.contains("likely misfire: followed by backspace\n"));
+
+    // The report is written to the file.
+    analysis.save_and_wait();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), report);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn hold_tap_analysis_records_decisions_of_the_layout() {
+    let path = std::env::temp_dir().join(format!("kanata-hold-tap-kb-{}.txt", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let cfg = format!(
+        "
+(defcfg hold-tap-analysis-file \"{}\")
+(defsrc a s)
+(deflayer base (tap-hold-press 200 200 a lctl) s)
+",
+        path.display()
+    );
+    let (_lk, mut k) = new_kanata(&cfg);
+
+    sim_input(
+        &mut k,
+        "d:a t:20 d:s t:20 u:a t:20 u:s t:20 d:a t:50 u:a t:50 d:s",
+    );
+
+    let report = k.hold_tap_analysis.as_ref().unwrap().report();
+    assert!(
+        report.contains("\nA    tap-hold-press  200 ms   1    1     0          0          1 "),
+        "{report}"
+    );
+
+    // The report is written to the file.
+    k.save_files();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), report);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn hold_tap_analysis_resolves_transparent_keys_through_active_layers() {
+    let path =
+        std::env::temp_dir().join(format!("kanata-hold-tap-trans-{}.txt", std::process::id()));
+    let cfg = format!(
+        "
+(defcfg hold-tap-analysis-file \"{}\")
+(defsrc a s d)
+(deflayer base a (layer-while-held mid) _)
+(deflayer mid (tap-hold 200 200 a lctl) _ (layer-while-held top))
+(deflayer top _ _ _)
+",
+        path.display()
+    );
+    let (_lk, mut k) = new_kanata(&cfg);
+
+    // The transparent key of top resolves to the hold-tap of mid, not to a of the base layer.
+    // The press of a on the base layer at the end finishes the decision.
+    sim_input(
+        &mut k,
+        "d:s t:10 d:d t:10 d:a t:50 u:a t:50 u:d t:10 u:s t:10 d:a t:10",
+    );
+
+    let report = k.hold_tap_analysis.as_ref().unwrap().report();
+    assert!(report.contains("\nA    tap-hold  200 ms "), "{report}");
+}
@@ -11,11 +11,18 @@ use crate::{
 };
 
 use rustc_hash::FxHashMap;
+use std::sync::MutexGuard;
 
 mod block_keys_tests;
 mod capsword_sim_tests;
 mod chord_sim_tests;
+#[cfg(feature = "cmd")]
+mod cmd_sim_tests;
+#[cfg(target_os = "linux")]
+mod defdevice_sim_tests;
 mod delay_tests;
+mod dynamic_macro_sim_tests;
+mod hold_tap_analysis_sim_tests;
 mod layer_sim_tests;
 mod macro_sim_tests;
 mod oneshot_tests;
@@ -23,7 +30,13 @@ mod override_tests;
 mod release_sim_tests;
 mod repeat_sim_tests;
 mod seq_sim_tests;
+#[cfg(feature = "tcp_server")]
+mod state_sim_tests;
+mod statistics_sim_tests;
 mod switch_sim_tests;
+mod tap_hold_sim_tests;
+#[cfg(feature = "tcp_server")]
+mod tcp_action_sim_tests;
 mod template_sim_tests;
 mod unicode_sim_tests;
 mod unmod_sim_tests;
@@ -46,44 +59,53 @@ fn simulate_with_file_content<S: AsRef<str>>(
         Err(poisoned) => poisoned.into_inner(),
     };
     let mut k = Kanata::new_from_str(cfg.as_ref(), file_content).expect("failed to parse cfg");
-    for pair in sim.as_ref().split_whitespace() {
+    sim_input(&mut k, sim.as_ref());
+    drop(_lk);
+    k.kbd_out.outputs.events.join("\n")
+}
+
+/// Parses the configuration for a test that inspects the [`Kanata`] state between inputs.
+/// Configurations are not parsed by other tests until the returned guard is dropped.
+fn new_kanata(cfg: &str) -> (MutexGuard<'static, ()>, Kanata) {
+    init_log();
+    let lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let k = Kanata::new_from_str(cfg, Default::default()).expect("failed to parse cfg");
+    (lk, k)
+}
+
+/// Runs simulated input in the format of [`simulate`].
+fn sim_input(k: &mut Kanata, sim: &str) {
+    for pair in sim.split_whitespace() {
         match pair.split_once(':') {
             Some((kind, val)) => match kind {
                 "t" => {
                     let tick = str::parse::<u128>(val).expect("valid num for tick");
                     k.tick_ms(tick, &None).unwrap();
                 }
-                "d" => {
-                    let key_code = str_to_oscode(val).expect("valid keycode");
-                    k.handle_input_event(&KeyEvent {
-                        code: key_code,
-                        value: KeyValue::Press,
-                    })
-                    .expect("input handles fine");
-                }
-                "u" => {
-                    let key_code = str_to_oscode(val).expect("valid keycode");
-                    k.handle_input_event(&KeyEvent {
-                        code: key_code,
-                        value: KeyValue::Release,
-                    })
-                    .expect("input handles fine");
-                }
-                "r" => {
-                    let key_code = str_to_oscode(val).expect("valid keycode");
-                    k.handle_input_event(&KeyEvent {
-                        code: key_code,
-                        value: KeyValue::Repeat,
-                    })
-                    .expect("input handles fine");
-                }
+                "d" => input_event(k, val, KeyValue::Press, None),
+                "u" => input_event(k, val, KeyValue::Release, None),
+                "r" => input_event(k, val, KeyValue::Repeat, None),
                 _ => panic!("invalid item {pair}"),
             },
             None => panic!("invalid item {pair}"),
         }
     }
-    drop(_lk);
-    k.kbd_out.outputs.events.join("\n")
+}
+
+/// Handles an input event of the key, from the `defdevice` with the index if one is given.
+fn input_event(k: &mut Kanata, key: &str, value: KeyValue, device: Option<u8>) {
+    #[allow(unused_mut)]
+    let mut event = KeyEvent::new(str_to_oscode(key).expect("valid keycode"), value);
+    #[cfg(target_os = "linux")]
+    {
+        event.device = device;
+    }
+    #[cfg(not(target_os = "linux"))]
+    assert!(device.is_none(), "defdevice is only supported on Linux");
+    k.handle_input_event(&event).expect("input handles fine");
 }
 
 #[allow(unused)]
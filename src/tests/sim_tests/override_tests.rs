@@ -68,3 +68,117 @@ fn override_eagerly_releases() {
         result
     );
 }
+
+#[test]
+fn override_restricted_to_layers() {
+    let result = simulate(
+        "
+(defoverrides
+ (lsft a (layers other)) (b)
+)
+(defsrc a b)
+(deflayer base a (layer-while-held other))
+(deflayer other a b)
+        ",
+        "d:lsft t:10 d:a t:10 u:a t:10 d:b t:10 d:a t:10 u:a t:10 u:b t:10 u:lsft t:10",
+    )
+    .to_ascii()
+    .no_time();
+    assert_eq!(
+        "dn:LShift dn:A up:A up:LShift dn:B up:B dn:LShift up:LShift",
+        result
+    );
+}
+
+#[test]
+fn override_with_negative_mods() {
+    let result = simulate(
+        "
+(defoverrides
+ (lsft a (negative-mods lctl)) (b)
+)
+(defsrc a)
+(deflayer base a)
+        ",
+        "d:lsft t:10 d:a t:10 u:a t:10 d:lctl t:10 d:a t:10 u:a t:10 u:lctl u:lsft t:10",
+    )
+    .to_ascii()
+    .no_time();
+    assert_eq!(
+        "dn:LShift up:LShift dn:B up:B dn:LShift dn:LCtrl dn:A up:A up:LCtrl up:LShift",
+        result
+    );
+}
+
+#[test]
+fn override_outputs_action() {
+    let result = simulate(
+        "
+(defoverrides
+ (lsft a) (macro h i)
+ (lctl b) (layer-while-held other)
+)
+(defsrc a b c)
+(deflayer base a b c)
+(deflayer other a b x)
+        ",
+        "d:lsft t:10 d:a t:50 u:a t:10 u:lsft t:10 \
+         d:lctl t:10 d:b t:10 d:c t:10 u:c t:10 u:b t:10 d:c t:10 u:c t:10 u:lctl t:10",
+    )
+    .to_ascii()
+    .no_time();
+    assert_eq!(
+        "dn:LShift up:LShift dn:H up:H dn:I up:I dn:LShift up:LShift \
+         dn:LCtrl up:LCtrl dn:X up:X dn:LCtrl dn:C up:C up:LCtrl",
+        result
+    );
+}
+
+#[test]
+fn override_disabled_by_vkey() {
+    let result = simulate(
+        "
+(defvirtualkeys no-overrides nop0)
+(defoverrides
+ (lsft a (disabled-by-vkey no-overrides)) (b)
+)
+(defsrc a c)
+(deflayer base a (on-press toggle-vkey no-overrides))
+        ",
+        "d:lsft t:10 d:a t:10 u:a t:10 u:lsft t:10 d:c t:10 u:c t:10 \
+         d:lsft t:10 d:a t:10 u:a t:10 u:lsft t:10",
+    )
+    .to_ascii()
+    .no_time();
+    assert_eq!(
+        "dn:LShift up:LShift dn:B up:B dn:LShift up:LShift dn:LShift dn:A up:A up:LShift",
+        result
+    );
+}
+
+#[test]
+fn override_action_stays_active_while_input_key_is_held() {
+    let cfg = "
+(defoverrides
+ (lctl a) (macro h i)
+ (lsft b) (layer-while-held other)
+)
+(defsrc a b c o)
+(deflayer base a b c (one-shot 2000 lctl))
+(deflayer other a b x o)
+        ";
+    // The one-shot modifier is released as soon as a is pressed, but a stays overridden until it
+    // is released and is not output itself.
+    let result = simulate(cfg, "d:o t:10 u:o t:10 d:a t:50 u:a t:10 d:a t:10 u:a t:10")
+        .to_ascii()
+        .no_time();
+    assert_eq!("dn:LCtrl up:LCtrl dn:H up:H dn:I up:I dn:A up:A", result);
+    // The layer stays active after shift is released, until b is released.
+    let result = simulate(
+        cfg,
+        "d:lsft t:10 d:b t:10 u:lsft t:10 d:c t:10 u:c t:10 u:b t:10 d:c t:10 u:c t:10",
+    )
+    .to_ascii()
+    .no_time();
+    assert_eq!("dn:LShift up:LShift dn:X up:X dn:C up:C", result);
+}
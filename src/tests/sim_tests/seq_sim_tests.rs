@@ -236,3 +236,108 @@ fn noerase() {
         result,
     );
 }
+
+#[test]
+fn sequence_outputs_action() {
+    let result = simulate(
+        "(defsrc 0 a b)
+         (deflayer base sldr a b)
+         (defseq
+           (macro h i) (a b)
+           @bye (b a))
+         (defalias bye (macro b y e))
+        ",
+        "d:0 u:0 t:10 d:a u:a t:10 d:b u:b t:50 d:0 u:0 t:10 d:b u:b t:10 d:a u:a t:50",
+    )
+    .no_time()
+    .to_ascii();
+    assert_eq!(
+        "up:A up:B dn:H up:H dn:I up:I up:B up:A dn:B up:B dn:Y up:Y dn:E up:E",
+        result
+    );
+}
+
+#[test]
+fn sequence_prefix_activates_on_timeout() {
+    let result = simulate(
+        "(defcfg sequence-timeout 100)
+         (defsrc 0 a b c)
+         (deflayer base sldr a b c)
+         (defseq
+           x (a b)
+           y (a b c))
+        ",
+        "d:0 u:0 t:10 d:a u:a t:10 d:b u:b t:200 \
+         d:0 u:0 t:10 d:a u:a t:10 d:b u:b t:10 d:c u:c t:200",
+    )
+    .no_time()
+    .to_ascii();
+    assert_eq!("up:A up:B dn:X up:X up:A up:B up:C dn:Y up:Y", result);
+}
+
+#[test]
+fn sequence_prefix_activates_after_continuing_keys() {
+    let cfg = "(defcfg sequence-timeout 100)
+         (defsrc 0 a b c d e)
+         (deflayer base sldr a b c d e)
+         (defseq
+           (macro x) (a b)
+           (macro y) (a b c d))
+        ";
+    // The completed a b activates on timeout after c continued the longer sequence.
+    let result = simulate(cfg, "d:0 u:0 t:10 d:a u:a t:10 d:b u:b t:10 d:c u:c t:200")
+        .no_time()
+        .to_ascii();
+    assert_eq!("up:A up:B up:C dn:X up:X", result);
+    // It also activates on a key that continues no sequence.
+    let result = simulate(
+        cfg,
+        "d:0 u:0 t:10 d:a u:a t:10 d:b u:b t:10 d:c u:c t:10 d:e u:e t:50 d:e u:e t:10",
+    )
+    .no_time()
+    .to_ascii();
+    assert_eq!("up:A up:B up:C up:E dn:X up:X dn:E up:E", result);
+    // The longer sequence still activates when it is completed.
+    let result = simulate(
+        cfg,
+        "d:0 u:0 t:10 d:a u:a t:10 d:b u:b t:10 d:c u:c t:10 d:d u:d t:200",
+    )
+    .no_time()
+    .to_ascii();
+    assert_eq!("up:A up:B up:C up:D dn:Y up:Y", result);
+}
+
+#[test]
+fn sequence_prefix_activates_on_other_key() {
+    let result = simulate(
+        "(defsrc 0 a b c)
+         (deflayer base sldr a b c)
+         (defseq
+           x (a b)
+           y (a b a b))
+        ",
+        "d:0 u:0 t:10 d:a u:a t:10 d:b u:b t:10 d:c u:c t:50 d:c u:c t:10",
+    )
+    .no_time()
+    .to_ascii();
+    // The c that ends the sequence is not typed.
+    assert_eq!("up:A up:B up:C dn:X up:X dn:C up:C", result);
+}
+
+#[test]
+fn sequence_prefix_swallows_held_ending_key() {
+    let result = simulate(
+        "(defsrc 0 a b c)
+         (deflayer base sldr a b c)
+         (defseq
+           (macro x y) (a b)
+           z (a b a b))
+        ",
+        "d:0 u:0 t:10 d:a u:a t:10 d:b u:b t:10 d:c t:100 u:c t:10 d:c u:c t:10",
+    )
+    .no_time()
+    .to_ascii();
+    // The c that ends the sequence is not typed, neither while the action runs nor when it is
+    // released. Only the next press of c is typed.
+    assert_eq!("up:A up:B dn:X up:X dn:Y up:Y up:C dn:C up:C", result);
+}
@@ -0,0 +1,43 @@
+use super::*;
+
+#[test]
+fn state_reflects_vkeys_caps_word_macro_recording_and_sequence() {
+    let (_lk, mut k) = new_kanata(
+        "
+(defsrc 1 2 3 4 a b)
+(deflayer base (on-press toggle-vkey held) (caps-word 1000) (dynamic-macro-record 5) sldr a b)
+(deflayer other _ 2 3 4 a b)
+(defvirtualkeys held (layer-while-held other) unused a)
+(defseq unused (a b c))
+",
+    );
+
+    let state = k.state();
+    assert_eq!(state.layer, "base");
+    assert!(state.pressed_virtual_keys.is_empty());
+    assert!(!state.caps_word);
+    assert_eq!(state.dynamic_macro_recording, None);
+    assert_eq!(state.sequence, None);
+
+    sim_input(&mut k, "d:1 t:10 u:1 t:10");
+    let state = k.state();
+    assert_eq!(state.layer, "other");
+    assert_eq!(state.pressed_virtual_keys, vec!["held".to_string()]);
+    sim_input(&mut k, "d:1 t:10 u:1 t:10");
+    assert_eq!(k.state().layer, "base");
+
+    sim_input(&mut k, "d:2 t:10 u:2 t:10");
+    assert!(k.state().caps_word);
+    k.tick_ms(1000, &None).unwrap();
+    assert!(!k.state().caps_word);
+
+    sim_input(&mut k, "d:3 t:10 u:3 t:10");
+    assert_eq!(k.state().dynamic_macro_recording, Some(5));
+
+    sim_input(&mut k, "d:4 t:10 u:4 t:10");
+    assert_eq!(k.state().sequence, Some(vec![]));
+    sim_input(&mut k, "d:a t:10 u:a t:10");
+    assert_eq!(k.state().sequence, Some(vec!["a".to_string()]));
+    k.tick_ms(1000, &None).unwrap();
+    assert_eq!(k.state().sequence, None);
+}
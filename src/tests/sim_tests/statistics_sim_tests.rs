@@ -0,0 +1,83 @@
+use super::*;
+
+use kanata_parser::keys::OsCode;
+
+#[test]
+#[cfg(feature = "json")]
+fn statistics_count_presses_layers_hold_taps_and_bigrams() {
+    let path = std::env::temp_dir().join(format!("kanata-statistics-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let cfg = format!(
+        "
+(defcfg statistics-file \"{}\")
+(defsrc a s d)
+(deflayer base (tap-hold 200 200 a lctl) s (layer-while-held other))
+(deflayer other b s _)
+",
+        path.display()
+    );
+    let (_lk, mut k) = new_kanata(&cfg);
+
+    // Tap of the hold-tap, then s right after it.
+    sim_input(&mut k, "d:a t:50 u:a t:50 d:s t:50 u:s t:50");
+    // Hold of the hold-tap by its timeout.
+    sim_input(&mut k, "d:a t:300 u:a t:50");
+    // s on the other layer; the unmapped key f is not counted.
+    sim_input(&mut k, "d:d t:50 d:s t:50 u:s t:50 u:d t:50 d:f t:50");
+
+    let stats = k.statistics.as_ref().unwrap().statistics().clone();
+    assert_eq!(stats.key_presses["a"], 2);
+    assert_eq!(stats.key_presses["s"], 2);
+    assert_eq!(stats.key_presses["d"], 1);
+    assert!(!stats.key_presses.contains_key("f"));
+    assert_eq!(stats.layers["base"].key_presses, 4);
+    assert_eq!(stats.layers["base"].activations, 2);
+    assert_eq!(stats.layers["other"].key_presses, 1);
+    assert_eq!(stats.layers["other"].activations, 1);
+    assert_eq!(stats.hold_taps["a"].tap, 1);
+    assert_eq!(stats.hold_taps["a"].timeout, 1);
+    assert_eq!(stats.bigrams["a s"], 1);
+    assert_eq!(stats.bigrams["s a"], 1);
+    assert_eq!(stats.bigrams["a d"], 1);
+    assert_eq!(stats.bigrams["d s"], 1);
+
+    // The statistics are written to the file and loaded again on the next start.
+    k.save_files();
+    let k = Kanata::new_from_str(&cfg, Default::default()).expect("failed to parse cfg");
+    assert_eq!(*k.statistics.as_ref().unwrap().statistics(), stats);
+
+    // A file that cannot be read is kept as a backup instead of being overwritten.
+    let backup = path.with_extension("json.bak");
+    let _ = std::fs::remove_file(&backup);
+    std::fs::write(&path, "{ not json").unwrap();
+    let k = Kanata::new_from_str(&cfg, Default::default()).expect("failed to parse cfg");
+    assert_eq!(
+        *k.statistics.as_ref().unwrap().statistics(),
+        Default::default()
+    );
+    assert_eq!(std::fs::read_to_string(&backup).unwrap(), "{ not json");
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&backup);
+}
+
+#[test]
+fn statistics_bigrams_are_presses_close_in_time() {
+    let (_lk, mut k) = new_kanata(
+        "
+(defcfg statistics-file \"kanata-statistics-not-written.json\")
+(defsrc a s)
+(deflayer base a s)
+",
+    );
+    let statistics = k.statistics.as_mut().unwrap();
+    let start = instant::Instant::now();
+    let after = |ms| start + std::time::Duration::from_millis(ms);
+    statistics.record_press(OsCode::KEY_A, "base", after(0));
+    statistics.record_press(OsCode::KEY_S, "base", after(1000));
+    // Presses more than a second apart are not a bigram, even if no time passed in between
+    // for the processing loop.
+    statistics.record_press(OsCode::KEY_A, "base", after(2001));
+    let stats = statistics.statistics();
+    assert_eq!(stats.bigrams["a s"], 1);
+    assert!(!stats.bigrams.contains_key("s a"));
+}
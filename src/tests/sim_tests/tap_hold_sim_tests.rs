@@ -0,0 +1,106 @@
+use super::*;
+
+static OPPOSITE_HAND_CFG: &str = "
+(defsrc a s d j k)
+(defhands
+  (left a s d)
+  (right j k))
+(deflayer base (tap-hold-opposite-hand 200 200 a lctl) s d j k)
+";
+
+#[test]
+fn opposite_hand_same_hand_roll_taps() {
+    let result = simulate(OPPOSITE_HAND_CFG, "d:a t:20 d:s t:20 u:a t:20 u:s t:20").to_ascii();
+    assert_eq!("t:20ms dn:A t:6ms dn:S t:14ms up:A t:20ms up:S", result);
+}
+
+#[test]
+fn opposite_hand_same_hand_chord_taps() {
+    let result = simulate(OPPOSITE_HAND_CFG, "d:a t:20 d:s t:20 u:s t:20 u:a t:20").to_ascii();
+    assert_eq!("t:20ms dn:A t:6ms dn:S t:14ms up:S t:20ms up:A", result);
+}
+
+#[test]
+fn opposite_hand_chord_holds() {
+    let result = simulate(OPPOSITE_HAND_CFG, "d:a t:20 d:j t:20 u:j t:20 u:a t:20").to_ascii();
+    assert_eq!(
+        "t:40ms dn:LCtrl t:6ms dn:J t:1ms up:J t:13ms up:LCtrl",
+        result
+    );
+}
+
+#[test]
+fn opposite_hand_roll_taps() {
+    let result = simulate(OPPOSITE_HAND_CFG, "d:a t:20 d:j t:20 u:a t:20 u:j t:20").to_ascii();
+    assert_eq!("t:40ms dn:A t:6ms dn:J t:1ms up:A t:13ms up:J", result);
+}
+
+#[test]
+fn opposite_hand_timeout_holds() {
+    let result = simulate(OPPOSITE_HAND_CFG, "d:a t:250 d:s t:20 u:s u:a t:20").to_ascii();
+    assert_eq!(
+        "t:200ms dn:LCtrl t:50ms dn:S t:20ms up:S t:1ms up:LCtrl",
+        result
+    );
+}
+
+#[test]
+fn opposite_hand_requires_defhands() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let cfg = "
+(defsrc a j)
+(deflayer base (tap-hold-opposite-hand 200 200 a lctl) j)
+";
+    assert!(Kanata::new_from_str(cfg, Default::default()).is_err());
+    let cfg = "
+(defsrc a j)
+(defhands (left a f) (right j))
+(deflayer base (tap-hold-opposite-hand 200 200 a lctl) j)
+";
+    assert!(Kanata::new_from_str(cfg, Default::default()).is_err());
+}
+
+static REQUIRE_PRIOR_IDLE_CFG: &str = "
+(defcfg tap-hold-require-prior-idle 100)
+(defsrc a s)
+(deflayer base (tap-hold-press 200 200 a lctl) s)
+";
+
+#[test]
+fn require_prior_idle_taps_while_typing() {
+    let result = simulate(
+        REQUIRE_PRIOR_IDLE_CFG,
+        "d:s t:20 u:s t:30 d:a t:20 d:s t:20 u:s u:a t:20",
+    )
+    .to_ascii();
+    assert_eq!(
+        "dn:S t:20ms up:S t:30ms dn:A t:20ms dn:S t:20ms up:S t:1ms up:A",
+        result
+    );
+}
+
+#[test]
+fn require_prior_idle_keeps_ticking_until_idle() {
+    let (_lk, mut k) = new_kanata(REQUIRE_PRIOR_IDLE_CFG);
+    sim_input(&mut k, "d:s t:20 u:s t:50");
+    // Blocking would stop counting the time since the press of s.
+    assert!(!k.can_block_update_idle_waiting(0));
+    sim_input(&mut k, "t:50");
+    assert!(k.can_block_update_idle_waiting(0));
+}
+
+#[test]
+fn require_prior_idle_holds_after_idle() {
+    let result = simulate(
+        REQUIRE_PRIOR_IDLE_CFG,
+        "d:s t:20 u:s t:100 d:a t:20 d:s t:20 u:s u:a t:20",
+    )
+    .to_ascii();
+    assert_eq!(
+        "dn:S t:20ms up:S t:120ms dn:LCtrl t:6ms dn:S t:14ms up:S t:1ms up:LCtrl",
+        result
+    );
+}
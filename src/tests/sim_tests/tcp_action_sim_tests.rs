@@ -0,0 +1,89 @@
+use super::*;
+
+use kanata_parser::custom_action::FakeKeyAction::{self, *};
+use kanata_tcp_protocol::{ErrorCode, ServerMessage};
+
+const CFG: &str = "
+(defcfg danger-enable-tcp-actions yes)
+(defsrc a)
+(deflayer base a)
+(deflayer other b)
+(defvirtualkeys vk c)
+";
+
+fn execute(k: &mut Kanata, expr: &str, action: FakeKeyAction) {
+    k.execute_action_expr(expr, action)
+        .unwrap_or_else(|e| panic!("{expr} {action:?} failed: {e:?}"));
+    sim_input(k, "t:10");
+}
+
+fn outputs(k: &Kanata) -> String {
+    k.kbd_out.outputs.events.join("\n").no_time().to_ascii()
+}
+
+fn error_code(result: std::result::Result<(), Box<ServerMessage>>) -> ErrorCode {
+    match result.map_err(|e| *e) {
+        Err(ServerMessage::Error { code, .. }) => code,
+        other => panic!("expected an error, got {other:?}"),
+    }
+}
+
+#[test]
+fn tcp_actions_are_pressed_and_released_independently() {
+    let (_lk, mut k) = new_kanata(CFG);
+    execute(&mut k, "x", Press);
+    execute(&mut k, "y", Press);
+    execute(&mut k, "y", Release);
+    assert_eq!("dn:X dn:Y up:Y", outputs(&k));
+    execute(&mut k, "x", Release);
+    assert_eq!("dn:X dn:Y up:Y up:X", outputs(&k));
+    // Releasing an action that is not held does nothing.
+    execute(&mut k, "x", Release);
+    assert_eq!("dn:X dn:Y up:Y up:X", outputs(&k));
+}
+
+#[test]
+fn tcp_actions_are_tapped_and_toggled_independently() {
+    let (_lk, mut k) = new_kanata(CFG);
+    execute(&mut k, "x", Toggle);
+    execute(&mut k, "y", Tap);
+    execute(&mut k, "(layer-while-held other)", Toggle);
+    sim_input(&mut k, "d:a t:10 u:a t:10");
+    execute(&mut k, "(layer-while-held other)", Toggle);
+    sim_input(&mut k, "d:a t:10 u:a t:10");
+    assert_eq!("dn:X dn:Y up:Y dn:B up:B dn:A up:A", outputs(&k));
+    execute(&mut k, "x", Toggle);
+    assert_eq!("dn:X dn:Y up:Y dn:B up:B dn:A up:A up:X", outputs(&k));
+}
+
+#[test]
+fn tcp_actions_report_errors() {
+    let (_lk, mut k) = new_kanata(CFG);
+    assert_eq!(
+        error_code(k.execute_action_expr("(bogus-action)", Tap)),
+        ErrorCode::InvalidAction
+    );
+    // Every held action uses its own virtual key, so they run out eventually. Pretend the
+    // configuration leaves only two of them free.
+    k.first_free_fake_key_column = kanata_parser::layers::KEYS_IN_ROW - 2;
+    execute(&mut k, "x", Press);
+    execute(&mut k, "y", Press);
+    assert_eq!(
+        error_code(k.execute_action_expr("z", Press)),
+        ErrorCode::ActionFailed
+    );
+    // Released and tapped actions don't keep their virtual key.
+    execute(&mut k, "x", Release);
+    execute(&mut k, "z", Tap);
+    execute(&mut k, "a", Tap);
+    execute(&mut k, "y", Release);
+    assert_eq!("dn:X dn:Y up:X dn:Z up:Z dn:A up:A up:Y", outputs(&k));
+
+    // The configuration parse lock is still held by this test.
+    let mut k =
+        Kanata::new_from_str("(defsrc a) (deflayer base a)", Default::default()).expect("parses");
+    assert_eq!(
+        error_code(k.execute_action_expr("x", Tap)),
+        ErrorCode::ActionsDisabled
+    );
+}
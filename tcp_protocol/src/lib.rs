@@ -1,15 +1,218 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::str::FromStr;
 
+/// Version of the protocol implemented by this crate. Clients can learn the version of the
+/// server they are connected to by sending [`ClientMessage::Hello`].
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Optional protocol features that the server supports, sent in [`ServerMessage::Hello`].
+pub const CAPABILITIES: &[&str] = &[
+    "request-id",
+    "error-codes",
+    "subscribe",
+    "execute-action",
+    "device-events",
+    "statistics",
+    "dynamic-macros",
+    "sequence-candidates",
+];
+
+/// A message from the server to its clients.
+///
+/// Keys in all messages are named as in the configuration, e.g. `"a"` or `"lsft"`.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ServerMessage {
-    LayerChange { new: String },
-    LayerNames { names: Vec<String> },
-    CurrentLayerInfo { name: String, cfg_text: String },
-    ConfigFileReload { new: String },
-    CurrentLayerName { name: String },
-    MessagePush { message: serde_json::Value },
-    Error { msg: String },
+    LayerChange {
+        new: String,
+    },
+    LayerNames {
+        names: Vec<String>,
+    },
+    CurrentLayerInfo {
+        name: String,
+        cfg_text: String,
+    },
+    ConfigFileReload {
+        new: String,
+    },
+    CurrentLayerName {
+        name: String,
+    },
+    MessagePush {
+        message: serde_json::Value,
+    },
+    /// Sent in response to a failed request that has an id. Requests without an id only get
+    /// this for an unknown virtual key in [`ClientMessage::ActOnFakeKey`] and for invalid
+    /// messages, as in protocol versions before request ids.
+    Error {
+        msg: String,
+        #[serde(default)]
+        code: ErrorCode,
+    },
+    /// Response to a request that has no other response, sent only if the request has an id.
+    Success {},
+    /// Response to [`ClientMessage::Reload`], sent once the reload has run. If `diagnostic` is
+    /// `None`, the configuration was reloaded successfully.
+    ConfigReloadResult {
+        path: String,
+        diagnostic: Option<ConfigDiagnostic>,
+    },
+    /// Response to [`ClientMessage::Hello`].
+    Hello {
+        protocol_version: u32,
+        capabilities: Vec<String>,
+    },
+    /// A key event read from an input device, before any remapping.
+    /// Requires a subscription to [`SubscriptionTopic::InputEvents`].
+    InputEvent {
+        key: String,
+        action: KeyEventValue,
+    },
+    /// A key event that kanata sent to the OS.
+    /// Requires a subscription to [`SubscriptionTopic::OutputEvents`].
+    OutputEvent {
+        key: String,
+        action: KeyEventValue,
+    },
+    /// A tap-hold key was resolved to one of its actions after waiting for `ticks` ms.
+    /// Requires a subscription to [`SubscriptionTopic::HoldTap`].
+    HoldTapResolved {
+        key: String,
+        resolution: HoldTapResolution,
+        ticks: u16,
+    },
+    /// The set of active one-shot keys changed.
+    /// Requires a subscription to [`SubscriptionTopic::OneShot`].
+    OneShotChange {
+        active: Vec<String>,
+    },
+    /// The sequences that can still be completed changed: sequence mode started or a key was
+    /// typed in it. The list is empty when sequence mode ends.
+    /// Requires a subscription to [`SubscriptionTopic::Sequences`].
+    SequenceCandidates {
+        candidates: Vec<SequenceCandidate>,
+    },
+    /// Response to [`ClientMessage::RequestState`].
+    State {
+        state: KanataState,
+    },
+    /// Any part of [`KanataState`] changed.
+    /// Requires a subscription to [`SubscriptionTopic::State`].
+    StateChanged {
+        state: KanataState,
+    },
+    /// Response to [`ClientMessage::RequestStatistics`].
+    Statistics {
+        statistics: TypingStatistics,
+    },
+    /// Response to [`ClientMessage::RequestDynamicMacros`], ordered by id.
+    DynamicMacros {
+        macros: Vec<DynamicMacro>,
+    },
+    /// Response to [`ClientMessage::RequestDynamicMacro`].
+    DynamicMacro {
+        dynamic_macro: DynamicMacro,
+    },
+    /// An input device was connected. `defdevice` is the name of the `defdevice` that the device
+    /// belongs to, if any.
+    DeviceConnected {
+        name: String,
+        path: String,
+        defdevice: Option<String>,
+    },
+    /// An input device was disconnected.
+    DeviceDisconnected {
+        name: String,
+        path: String,
+        defdevice: Option<String>,
+    },
+}
+
+/// Snapshot of the user-visible state of kanata.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KanataState {
+    /// Name of the active layer.
+    pub layer: String,
+    /// Names of the virtual keys that are currently pressed.
+    pub pressed_virtual_keys: Vec<String>,
+    pub caps_word: bool,
+    /// The id of the dynamic macro being recorded, if recording.
+    pub dynamic_macro_recording: Option<u16>,
+    /// Keys typed so far in the active sequence, or `None` if no sequence is active.
+    pub sequence: Option<Vec<String>>,
+}
+
+/// Typing statistics, collected if `statistics-file` is set in `defcfg`. Keys are named as in
+/// `defsrc`, e.g. `"a"` or `"lsft"`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypingStatistics {
+    /// Number of presses of every `defsrc` key.
+    #[serde(default)]
+    pub key_presses: BTreeMap<String, u64>,
+    /// Usage of every layer, by layer name.
+    #[serde(default)]
+    pub layers: BTreeMap<String, LayerStatistics>,
+    /// Resolutions of the hold-tap actions, by the `defsrc` key that they are on.
+    #[serde(default)]
+    pub hold_taps: BTreeMap<String, HoldTapStatistics>,
+    /// Number of times that a key was pressed right after another one, keyed by the names of
+    /// both keys separated by a space, e.g. `"t h"`.
+    #[serde(default)]
+    pub bigrams: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayerStatistics {
+    /// Number of times that the layer became the active layer.
+    #[serde(default)]
+    pub activations: u64,
+    /// Number of `defsrc` key presses while the layer was active.
+    #[serde(default)]
+    pub key_presses: u64,
+}
+
+/// The number of times that hold-tap actions were resolved to each [`HoldTapResolution`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HoldTapStatistics {
+    #[serde(default)]
+    pub tap: u64,
+    #[serde(default)]
+    pub hold: u64,
+    #[serde(default)]
+    pub timeout: u64,
+}
+
+/// A sequence of `defseq` that can be completed from the keys typed so far.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SequenceCandidate {
+    /// The virtual key name or action that the sequence activates.
+    pub name: String,
+    /// All keys of the sequence, e.g. `"g S-s t"`.
+    pub keys: String,
+    /// The number of keys of the sequence that have been typed.
+    pub typed: usize,
+}
+
+/// A dynamic macro as a list of key events. Keys are named as in the configuration, e.g.
+/// `"lsft"`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DynamicMacro {
+    pub id: u16,
+    pub events: Vec<DynamicMacroEvent>,
+    /// The macro as a `macro` action for the configuration, or `None` if a key of the macro has
+    /// no name in the configuration. Ignored when the macro is read.
+    #[serde(default)]
+    pub cfg_text: Option<String>,
+}
+
+/// A key press or release of a dynamic macro, followed by `delay` ms before the next event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DynamicMacroEvent {
+    pub key: String,
+    pub action: KeyEventValue,
+    #[serde(default)]
+    pub delay: u16,
 }
 
 impl ServerMessage {
@@ -18,30 +221,219 @@ impl ServerMessage {
         msg.push(b'\n');
         msg
     }
+
+    pub fn error(code: ErrorCode, msg: impl Into<String>) -> Self {
+        ServerMessage::Error {
+            msg: msg.into(),
+            code,
+        }
+    }
+
+    /// Returns the topic that a client must be subscribed to for receiving this message, or
+    /// `None` if it is sent to all clients.
+    pub fn topic(&self) -> Option<SubscriptionTopic> {
+        match self {
+            ServerMessage::InputEvent { .. } => Some(SubscriptionTopic::InputEvents),
+            ServerMessage::OutputEvent { .. } => Some(SubscriptionTopic::OutputEvents),
+            ServerMessage::HoldTapResolved { .. } => Some(SubscriptionTopic::HoldTap),
+            ServerMessage::OneShotChange { .. } => Some(SubscriptionTopic::OneShot),
+            ServerMessage::SequenceCandidates { .. } => Some(SubscriptionTopic::Sequences),
+            ServerMessage::StateChanged { .. } => Some(SubscriptionTopic::State),
+            _ => None,
+        }
+    }
+}
+
+/// A server message sent in response to a [`ClientRequest`]. The message fields are flattened
+/// alongside `request_id`, so a response without an id is identical to a plain [`ServerMessage`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServerResponse {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
+    #[serde(flatten)]
+    pub msg: ServerMessage,
+}
+
+impl ServerResponse {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut msg = serde_json::to_vec(self).expect("ServerResponse should serialize");
+        msg.push(b'\n');
+        msg
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum ErrorCode {
+    #[default]
+    Other,
+    InvalidMessage,
+    UnknownLayer,
+    UnknownVirtualKey,
+    UnknownConfigFile,
+    ActionFailed,
+    /// [`ClientMessage::ExecuteAction`] is not enabled in the configuration.
+    ActionsDisabled,
+    /// The expression in [`ClientMessage::ExecuteAction`] is not a valid action.
+    InvalidAction,
+    /// [`ClientMessage::RequestStatistics`] was sent but statistics are not enabled in the
+    /// configuration.
+    StatisticsDisabled,
+    /// No dynamic macro has the requested id.
+    UnknownDynamicMacro,
+    /// The events of [`ClientMessage::SetDynamicMacro`] are not a valid dynamic macro.
+    InvalidDynamicMacro,
+}
+
+/// A configuration parse error.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigDiagnostic {
+    pub msg: String,
+    pub help: Option<String>,
+    /// The file that contains the error. This may be an included file rather than the main
+    /// configuration file.
+    pub file: Option<String>,
+    pub span: Option<DiagnosticSpan>,
+}
+
+/// Location of an error within a file. Line and column are zero-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiagnosticSpan {
+    pub offset: usize,
+    pub length: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A client message with an optional id. If the id is present, the server echoes it in its
+/// response. Plain [`ClientMessage`] JSON is also a valid `ClientRequest` without an id.
+///
+/// Without an id, errors of `ChangeLayer` and `SetMouse` are not sent, as in versions before
+/// request ids. All other errors are sent.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
+    #[serde(flatten)]
+    pub msg: ClientMessage,
+}
+
+impl FromStr for ClientRequest {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ClientMessage {
+    /// Asks the server for its protocol version and capabilities.
+    Hello {
+        protocol_version: u32,
+        #[serde(default)]
+        capabilities: Vec<String>,
+    },
     ChangeLayer {
         new: String,
     },
     RequestLayerNames {},
     RequestCurrentLayerInfo {},
     RequestCurrentLayerName {},
+    RequestState {},
     ActOnFakeKey {
         name: String,
         action: FakeKeyActionMessage,
     },
+    /// Parses `expr` as an action, the same way as an alias definition in the configuration, and
+    /// acts on it as if it were a virtual key. Requires `danger-enable-tcp-actions` in `defcfg`.
+    ///
+    /// Every expression acts like its own virtual key, so `Release` and `Toggle` act on the
+    /// action that was pressed with the same expression text. The number of actions held at
+    /// the same time is limited by the free virtual keys.
+    ExecuteAction {
+        expr: String,
+        #[serde(default)]
+        action: FakeKeyActionMessage,
+    },
     SetMouse {
         x: u16,
         y: u16,
     },
+    Subscribe {
+        topics: Vec<SubscriptionTopic>,
+    },
+    Unsubscribe {
+        topics: Vec<SubscriptionTopic>,
+    },
+    /// Live reloads the configuration file at `path`, which must be one of the files kanata was
+    /// started with. Reloads the current configuration file if `path` is `None`. The reload
+    /// happens once all keys are released; the response is sent after it.
+    Reload {
+        #[serde(default)]
+        path: Option<String>,
+    },
+    /// Requests the typing statistics. Requires `statistics-file` in `defcfg`.
+    RequestStatistics {},
+    /// Requests all recorded dynamic macros.
+    RequestDynamicMacros {},
+    /// Requests the dynamic macro with the id.
+    RequestDynamicMacro {
+        id: u16,
+    },
+    /// Replaces the dynamic macro with the id, or adds it if it does not exist. The macro is
+    /// saved to `dynamic-macro-file`, if set. Every press in `events` should have a release, and
+    /// there cannot be more presses than `dynamic-macro-max-presses` allows.
+    SetDynamicMacro {
+        id: u16,
+        events: Vec<DynamicMacroEvent>,
+    },
+    /// Deletes the dynamic macro with the id.
+    DeleteDynamicMacro {
+        id: u16,
+    },
+}
+
+/// Streams of events that are only sent to clients that subscribe to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum SubscriptionTopic {
+    InputEvents,
+    OutputEvents,
+    HoldTap,
+    OneShot,
+    Sequences,
+    State,
+}
+
+impl SubscriptionTopic {
+    pub const ALL: [SubscriptionTopic; 6] = [
+        SubscriptionTopic::InputEvents,
+        SubscriptionTopic::OutputEvents,
+        SubscriptionTopic::HoldTap,
+        SubscriptionTopic::OneShot,
+        SubscriptionTopic::Sequences,
+        SubscriptionTopic::State,
+    ];
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum KeyEventValue {
+    Press,
+    Release,
+    Repeat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum HoldTapResolution {
+    Tap,
+    Hold,
+    Timeout,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub enum FakeKeyActionMessage {
     Press,
     Release,
+    #[default]
     Tap,
     Toggle,
 }
@@ -53,3 +445,55 @@ impl FromStr for ClientMessage {
         serde_json::from_str(s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_without_id_is_plain_client_message() {
+        let req: ClientRequest = r#"{"ChangeLayer":{"new":"base"}}"#.parse().unwrap();
+        assert_eq!(req.request_id, None);
+        assert!(matches!(req.msg, ClientMessage::ChangeLayer { new } if new == "base"));
+    }
+
+    #[test]
+    fn request_id_is_echoed_in_response() {
+        let req: ClientRequest = r#"{"request_id":7,"RequestLayerNames":{}}"#.parse().unwrap();
+        assert_eq!(req.request_id, Some(7));
+        assert!(matches!(req.msg, ClientMessage::RequestLayerNames {}));
+
+        let resp = ServerResponse {
+            request_id: req.request_id,
+            msg: ServerMessage::error(ErrorCode::UnknownLayer, "unknown layer: x"),
+        };
+        assert_eq!(
+            serde_json::to_string(&resp).unwrap(),
+            r#"{"request_id":7,"Error":{"msg":"unknown layer: x","code":"UnknownLayer"}}"#
+        );
+    }
+
+    #[test]
+    fn response_without_id_is_plain_server_message() {
+        let resp = ServerResponse {
+            request_id: None,
+            msg: ServerMessage::LayerChange { new: "base".into() },
+        };
+        assert_eq!(
+            resp.as_bytes(),
+            ServerMessage::LayerChange { new: "base".into() }.as_bytes()
+        );
+    }
+
+    #[test]
+    fn error_without_code_parses() {
+        let msg: ServerMessage = serde_json::from_str(r#"{"Error":{"msg":"oops"}}"#).unwrap();
+        assert!(matches!(
+            msg,
+            ServerMessage::Error {
+                code: ErrorCode::Other,
+                ..
+            }
+        ));
+    }
+}